@@ -0,0 +1,52 @@
+//! Compares how tightly each packing algorithm fills pages with a mix of
+//! sprite sizes, and how long it takes.
+//!
+//! cargo bench --features fixtures --bench occupancy
+
+extern crate texture_atlas;
+
+use std::time::Instant;
+
+use texture_atlas::{PackAlgorithm, TileAtlasBuilder};
+use texture_atlas::fixtures;
+
+/// Sizes from a fixed seed, so every run packs the same frames.
+fn frame_sizes(count: usize) -> Vec<(u32, u32)> {
+    let mut state: u32 = 0x2545_f491;
+    let mut next = move || {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (state >> 16) % 7
+    };
+
+    (0..count).map(|_| (8 << (next() % 5), 8 << (next() % 5))).collect()
+}
+
+fn main() {
+    let sizes = frame_sizes(400);
+
+    for &algorithm in [PackAlgorithm::Skyline, PackAlgorithm::MaxRects, PackAlgorithm::Guillotine,
+                       PackAlgorithm::Shelf].iter() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(512);
+        builder.set_pack_algorithm(algorithm);
+        for (i, &(w, h)) in sizes.iter().enumerate() {
+            builder.add_frame_image(&format!("frame{}", i), fixtures::tile_sheet(1, 1, (w, h)), (w, h)).unwrap();
+        }
+
+        let start = Instant::now();
+        let atlas = builder.build_cpu(None).unwrap();
+        let elapsed = start.elapsed();
+
+        let used: u64 = atlas.frames().values().map(|frame| frame.rect().w() as u64 * frame.rect().h() as u64).sum();
+        let total: u64 = (0..atlas.passes())
+            .map(|page| {
+                let (w, h) = atlas.get_page_dimensions(page);
+                w as u64 * h as u64
+            })
+            .sum();
+
+        println!("{:?}: {} pages, {:.1}% occupied, packed in {}.{:03}s",
+                 algorithm, atlas.passes(), 100.0 * used as f64 / total as f64,
+                 elapsed.as_secs(), elapsed.subsec_nanos() / 1_000_000);
+    }
+}
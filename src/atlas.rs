@@ -0,0 +1,200 @@
+use AtlasRect;
+use backend::PageTexture;
+use ids::PageId;
+use texture_atlas::TextureAtlas;
+use tile_atlas::{self, TileAtlas};
+
+/// Where a frame lies on its page, in the same top-left based UV
+/// coordinates as `DrawInfo`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameUv {
+    pub page: PageId,
+    pub uv_offset: (f32, f32),
+    pub uv_scale: [f32; 2],
+    /// Whether the frame lies on the page turned a quarter turn clockwise.
+    /// See `AtlasRect::rotated`.
+    pub rotated: bool,
+}
+
+impl FrameUv {
+    /// See `DrawInfo::uv_corners`.
+    pub fn uv_corners(&self) -> [(f32, f32); 4] {
+        tile_atlas::uv_corners(self.uv_offset, self.uv_scale, self.rotated)
+    }
+}
+
+/// A page as exported metadata describes it, so consumers can tell which
+/// image a frame's rect lies on without relying on the order of files.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PageInfo {
+    pub page: PageId,
+    /// The file the page was saved as, if it was saved.
+    pub file_name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What `TileAtlas` and `TextureAtlas` have in common: pages, and frames
+/// looked up by key. For tools like debug viewers and exporters that should
+/// work with either.
+pub trait Atlas {
+    type Texture: PageTexture;
+
+    fn page_count(&self) -> usize;
+
+    fn page(&self, page: PageId) -> &Self::Texture;
+
+    /// Gets the page and area of the frame added under `key`.
+    fn frame_area(&self, key: &str) -> Option<(PageId, &AtlasRect)>;
+
+    /// Gets the key of every frame, in no particular order.
+    fn frame_keys(&self) -> Vec<&str>;
+
+    /// Gets the file name a page was saved under, if it was saved.
+    fn page_name(&self, page: PageId) -> Option<&str> {
+        let _ = page;
+        None
+    }
+
+    fn page_dimensions(&self, page: PageId) -> (u32, u32) {
+        self.page(page).dimensions()
+    }
+
+    /// Whether `frame_uv` insets its UVs by half a texel. See
+    /// `TileAtlas::set_half_texel_inset`.
+    fn half_texel_inset(&self) -> bool {
+        false
+    }
+
+    fn page_info(&self, page: PageId) -> PageInfo {
+        let (width, height) = self.page_dimensions(page);
+        PageInfo {
+            page: page,
+            file_name: self.page_name(page).map(|name| name.to_string()),
+            width: width,
+            height: height,
+        }
+    }
+
+    /// Gets the page info of the page the frame added under `key` is on.
+    fn frame_page_info(&self, key: &str) -> Option<PageInfo> {
+        self.frame_area(key).map(|(page, _)| self.page_info(page))
+    }
+
+    fn frame_uv(&self, key: &str) -> Option<FrameUv> {
+        self.frame_area(key).map(|(page, rect)| {
+            let (w, h) = self.page_dimensions(page);
+            let uv_offset = (rect.x() as f32 / w as f32, rect.y() as f32 / h as f32);
+            let uv_scale = [rect.w() as f32 / w as f32, rect.h() as f32 / h as f32];
+            let (uv_offset, uv_scale) = if self.half_texel_inset() {
+                tile_atlas::inset_uvs(uv_offset, uv_scale, (w, h))
+            } else {
+                (uv_offset, uv_scale)
+            };
+
+            FrameUv {
+                page: page,
+                uv_offset: uv_offset,
+                uv_scale: uv_scale,
+                rotated: rect.rotated(),
+            }
+        })
+    }
+}
+
+impl<T: PageTexture> Atlas for TileAtlas<T> {
+    type Texture = T;
+
+    fn page_count(&self) -> usize {
+        self.passes()
+    }
+
+    fn page(&self, page: PageId) -> &T {
+        self.get_texture(page)
+    }
+
+    fn page_name(&self, PageId(page): PageId) -> Option<&str> {
+        self.page_names().get(page).map(|name| name.as_str())
+    }
+
+    fn half_texel_inset(&self) -> bool {
+        TileAtlas::half_texel_inset(self)
+    }
+
+    fn frame_area(&self, key: &str) -> Option<(PageId, &AtlasRect)> {
+        self.frames().get(key).map(|frame| (PageId(frame.texture_idx()), frame.rect()))
+    }
+
+    fn frame_keys(&self) -> Vec<&str> {
+        self.frames().keys().map(|key| key.as_str()).collect()
+    }
+}
+
+impl<T: PageTexture> Atlas for TextureAtlas<T> {
+    type Texture = T;
+
+    fn page_count(&self) -> usize {
+        1
+    }
+
+    fn page(&self, page: PageId) -> &T {
+        assert_eq!(page, PageId(0), "A TextureAtlas only has page 0");
+        self.get_texture()
+    }
+
+    fn frame_area(&self, key: &str) -> Option<(PageId, &AtlasRect)> {
+        self.frames().get(key).map(|rect| (PageId(0), rect))
+    }
+
+    fn frame_keys(&self) -> Vec<&str> {
+        self.frames().keys().map(|key| key.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas::TileAtlasBuilder;
+
+    fn covered_area<A: Atlas>(atlas: &A) -> u32 {
+        atlas.frame_keys().iter()
+            .map(|key| atlas.frame_area(key).unwrap().1)
+            .map(|rect| rect.w() * rect.h())
+            .sum()
+    }
+
+    #[test]
+    fn test_generic_atlas() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(16, 16), (16, 16)).unwrap();
+        let tiles = builder.build(&MemoryBackend, None).unwrap();
+        let view = tiles.texture_atlas_view(0);
+
+        assert_eq!(covered_area(&tiles), 48 * 24 + 16 * 16);
+        assert_eq!(covered_area(&view), covered_area(&tiles));
+        assert_eq!(tiles.frame_uv("a"), view.frame_uv("a"));
+
+        let (w, _) = tiles.page_dimensions(PageId(0));
+        assert_eq!(tiles.frame_uv("a").unwrap().uv_scale[0], 48.0 / w as f32);
+        assert!(tiles.frame_uv("c").is_none());
+    }
+
+    #[test]
+    fn test_page_info() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        let tiles = builder.build(&MemoryBackend, None).unwrap();
+        let (w, h) = tiles.page_dimensions(PageId(0));
+
+        let info = tiles.frame_page_info("a").unwrap();
+        assert_eq!(info, PageInfo { page: PageId(0), file_name: None, width: w, height: h });
+        assert!(tiles.frame_page_info("b").is_none());
+
+        let named = tiles.with_page_names(vec!["0123456789abcdef.png".to_string()]);
+        assert_eq!(named.page_info(PageId(0)).file_name, Some("0123456789abcdef.png".to_string()));
+    }
+}
@@ -0,0 +1,670 @@
+//! Texture upload, kept apart from packing so atlases can be drawn with
+//! renderers other than glium. Atlases are generic over the texture type of
+//! the `GpuBackend` they were uploaded with.
+//!
+//! Backends for glium and wgpu are available behind the features of the
+//! same names.
+
+use std::borrow::Cow;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use image::{DynamicImage, GenericImage, ImageBuffer};
+
+use block_compress::{self, CompressedPage};
+use error::{AtlasError, AtlasResult};
+
+/// A page's pixels, ready to be uploaded.
+#[derive(Clone, Copy)]
+pub struct RawPage<'a> {
+    /// RGBA, 8 bits per channel, with rows from top to bottom.
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+}
+
+#[cfg(feature = "glium")]
+impl<'a> RawPage<'a> {
+    /// Copies the pixels into a glium image, with rows from bottom to top as
+    /// glium expects.
+    pub fn to_glium_image(&self) -> ::glium::texture::RawImage2d<'static, u8> {
+        ::glium::texture::RawImage2d::from_raw_rgba_reversed(self.data.to_vec(), (self.width, self.height))
+    }
+}
+
+/// A texture holding one page of an atlas.
+pub trait PageTexture {
+    /// Gets the width and height of the page in pixels.
+    fn dimensions(&self) -> (u32, u32);
+}
+
+/// Creates textures from pages. Implemented for every glium `Facade` with
+/// the `glium` feature, so a display can be passed anywhere a backend is
+/// asked for. Pages created that way are `CompressedSrgbTexture2d`s; wrap
+/// the display in `SrgbFormat`, `LinearFormat` or `CompressedLinearFormat`
+/// to pick another format.
+pub trait GpuBackend {
+    type Texture: PageTexture;
+
+    fn create_texture(&self, page: RawPage) -> AtlasResult<Self::Texture>;
+
+    /// Creates a texture from a block compressed page. Backends that can't
+    /// upload compressed data as it is decompress it first, which saves no
+    /// video memory but still draws the same.
+    fn create_compressed_texture(&self, page: &CompressedPage) -> AtlasResult<Self::Texture> {
+        let rgba = block_compress::decompress(page)?.to_rgba();
+        self.create_texture(RawPage {
+            data: &rgba,
+            width: page.width,
+            height: page.height,
+        })
+    }
+
+    /// Creates a texture for a page along with its mip levels below the full
+    /// size, each half the size of the one before. The default ignores
+    /// `mipmaps`, for backends that generate their own.
+    fn create_texture_with_mipmaps(&self, page: RawPage, mipmaps: &[RawPage]) -> AtlasResult<Self::Texture> {
+        let _ = mipmaps;
+        self.create_texture(page)
+    }
+
+    /// Overwrites the area of `texture` with its top-left corner at `(x, y)`
+    /// with `pixels`, in the same top-left based coordinates as `RawPage`.
+    fn write_texture(&self, texture: &mut Self::Texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()>;
+
+    /// Creates a page texture to be filled in later with `stream_texture`.
+    /// The default uploads a page of transparent pixels.
+    fn create_empty_texture(&self, width: u32, height: u32) -> AtlasResult<Self::Texture> {
+        let data = vec![0; width as usize * height as usize * 4];
+        self.create_texture(RawPage {
+            data: &data,
+            width: width,
+            height: height,
+        })
+    }
+
+    /// Like `write_texture`, but for backends that can hand the pixels off
+    /// to the GPU without waiting for the copy to finish. The default calls
+    /// `write_texture`.
+    fn stream_texture(&self, texture: &mut Self::Texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+        self.write_texture(texture, x, y, pixels)
+    }
+
+    /// Whether textures keep their rows from bottom to top, as OpenGL's do,
+    /// so that pages are flipped on the way in. True for the glium backends.
+    fn flips_rows(&self) -> bool {
+        false
+    }
+}
+
+/// Uploads through another backend with each page's top row first in the
+/// texture, undoing the flip of backends that `flips_rows`, so `DrawInfo`
+/// UVs can be sampled as they are. See `TileAtlasBuilder::set_flip_y`.
+pub struct Unflipped<'a, B: 'a>(pub &'a B);
+
+impl<'a, B: GpuBackend> GpuBackend for Unflipped<'a, B> {
+    type Texture = B::Texture;
+
+    fn create_texture(&self, page: RawPage) -> AtlasResult<B::Texture> {
+        if !self.0.flips_rows() {
+            return self.0.create_texture(page);
+        }
+        let flipped = flip_rows(page);
+        self.0.create_texture(RawPage { data: &flipped, ..page })
+    }
+
+    fn create_compressed_texture(&self, page: &CompressedPage) -> AtlasResult<B::Texture> {
+        if !self.0.flips_rows() {
+            return self.0.create_compressed_texture(page);
+        }
+        match block_compress::flip_vertical(page) {
+            Some(flipped) => self.0.create_compressed_texture(&flipped),
+            None => {
+                let rgba = block_compress::decompress(page)?.to_rgba();
+                self.create_texture(RawPage {
+                    data: &rgba,
+                    width: page.width,
+                    height: page.height,
+                })
+            },
+        }
+    }
+
+    fn create_texture_with_mipmaps(&self, page: RawPage, mipmaps: &[RawPage]) -> AtlasResult<B::Texture> {
+        if !self.0.flips_rows() {
+            return self.0.create_texture_with_mipmaps(page, mipmaps);
+        }
+        let flipped = flip_rows(page);
+        let levels: Vec<_> = mipmaps.iter().map(|&level| flip_rows(level)).collect();
+        let raw_levels: Vec<_> = mipmaps.iter().zip(levels.iter())
+            .map(|(level, data)| RawPage { data: data, ..*level })
+            .collect();
+        self.0.create_texture_with_mipmaps(RawPage { data: &flipped, ..page }, &raw_levels)
+    }
+
+    fn write_texture(&self, texture: &mut B::Texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+        if !self.0.flips_rows() {
+            return self.0.write_texture(texture, x, y, pixels);
+        }
+        let y = flipped_y(texture, y, &pixels)?;
+        let flipped = flip_rows(pixels);
+        self.0.write_texture(texture, x, y, RawPage { data: &flipped, ..pixels })
+    }
+
+    fn create_empty_texture(&self, width: u32, height: u32) -> AtlasResult<B::Texture> {
+        self.0.create_empty_texture(width, height)
+    }
+
+    fn stream_texture(&self, texture: &mut B::Texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+        if !self.0.flips_rows() {
+            return self.0.stream_texture(texture, x, y, pixels);
+        }
+        let y = flipped_y(texture, y, &pixels)?;
+        let flipped = flip_rows(pixels);
+        self.0.stream_texture(texture, x, y, RawPage { data: &flipped, ..pixels })
+    }
+}
+
+/// Gets `pixels` with their rows in the opposite order.
+fn flip_rows(pixels: RawPage) -> Vec<u8> {
+    pixels.data.chunks(pixels.width as usize * 4).rev()
+        .flat_map(|row| row.iter().cloned())
+        .collect()
+}
+
+/// Gets where a write at `y` has to go for it to land there once the page is
+/// flipped.
+fn flipped_y<T: PageTexture>(texture: &T, y: u32, pixels: &RawPage) -> AtlasResult<u32> {
+    let (_, page_height) = texture.dimensions();
+    if y + pixels.height > page_height {
+        return Err(AtlasError::Texture("Write lies outside of the page".to_string()));
+    }
+    Ok(page_height - y - pixels.height)
+}
+
+#[cfg(feature = "glium")]
+fn glium_image(pixels: RawPage) -> ::glium::texture::RawImage2d<u8> {
+    pixels.to_glium_image()
+}
+
+/// Fills a pixel buffer object with `pixels`, rows from bottom to top, so a
+/// texture can be written from it without the driver copying from main
+/// memory first.
+#[cfg(feature = "glium")]
+fn glium_pixel_buffer<F: ::glium::backend::Facade>(facade: &F, pixels: RawPage)
+                                                   -> ::glium::texture::pixel_buffer::PixelBuffer<(u8, u8, u8, u8)> {
+    let row_bytes = pixels.width as usize * 4;
+    let texels: Vec<_> = pixels.data.chunks(row_bytes).rev()
+        .flat_map(|row| row.chunks(4))
+        .map(|p| (p[0], p[1], p[2], p[3]))
+        .collect();
+
+    let buffer = ::glium::texture::pixel_buffer::PixelBuffer::new_empty(facade, texels.len());
+    buffer.write(&texels);
+    buffer
+}
+
+#[cfg(feature = "glium")]
+fn glium_rect(page_height: u32, x: u32, y: u32, pixels: &RawPage) -> ::glium::Rect {
+    ::glium::Rect {
+        left: x,
+        bottom: page_height - (y + pixels.height),
+        width: pixels.width,
+        height: pixels.height,
+    }
+}
+
+#[cfg(feature = "glium")]
+impl PageTexture for ::Texture2d {
+    fn dimensions(&self) -> (u32, u32) {
+        ::Texture2d::dimensions(self)
+    }
+}
+
+#[cfg(feature = "glium")]
+impl<F: ::glium::backend::Facade> GpuBackend for F {
+    type Texture = ::Texture2d;
+
+    fn create_texture(&self, page: RawPage) -> AtlasResult<Self::Texture> {
+        ::Texture2d::new(self, glium_image(page)).map_err(|e| AtlasError::Texture(format!("{:?}", e)))
+    }
+
+    fn create_compressed_texture(&self, page: &CompressedPage) -> AtlasResult<Self::Texture> {
+        use glium::texture::{CompressedMipmapsOption, CompressedSrgbFormat};
+
+        let format = match page.format {
+            block_compress::BlockFormat::Bc1 => CompressedSrgbFormat::S3tcDxt1Alpha,
+            block_compress::BlockFormat::Bc3 => CompressedSrgbFormat::S3tcDxt5Alpha,
+        };
+
+        // glium expects rows from bottom to top. Blocks can only be flipped
+        // as they are when no block hangs over the bottom edge.
+        let flipped = match block_compress::flip_vertical(page) {
+            Some(flipped) => flipped,
+            None          => return upload_page(self, &block_compress::decompress(page)?),
+        };
+
+        ::Texture2d::with_compressed_data(self, &flipped.data, page.width, page.height, format,
+                                          CompressedMipmapsOption::NoMipmap)
+            .map_err(|e| AtlasError::Texture(format!("{:?}", e)))
+    }
+
+    fn write_texture(&self, texture: &mut Self::Texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+        let (_, page_height) = ::Texture2d::dimensions(texture);
+        texture.write(glium_rect(page_height, x, y, &pixels), glium_image(pixels));
+        Ok(())
+    }
+
+    fn stream_texture(&self, texture: &mut Self::Texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+        let (_, page_height) = ::Texture2d::dimensions(texture);
+        let rect = glium_rect(page_height, x, y, &pixels);
+        let buffer = glium_pixel_buffer(self, pixels);
+        texture.main_level().raw_upload_from_pixel_buffer(buffer.as_slice(),
+                                                          rect.left..rect.left + rect.width,
+                                                          rect.bottom..rect.bottom + rect.height,
+                                                          0..1);
+        Ok(())
+    }
+
+    fn flips_rows(&self) -> bool {
+        true
+    }
+}
+
+/// Creates glium pages as uncompressed sRGB textures, which keeps block
+/// compression from smearing small pixel art tiles.
+#[cfg(feature = "glium")]
+pub struct SrgbFormat<'a, F: 'a>(pub &'a F);
+
+/// Creates glium pages as uncompressed linear textures.
+#[cfg(feature = "glium")]
+pub struct LinearFormat<'a, F: 'a>(pub &'a F);
+
+/// Creates glium pages as compressed linear textures.
+#[cfg(feature = "glium")]
+pub struct CompressedLinearFormat<'a, F: 'a>(pub &'a F);
+
+macro_rules! glium_format {
+    ($format:ident, $texture:ty) => {
+        #[cfg(feature = "glium")]
+        impl PageTexture for $texture {
+            fn dimensions(&self) -> (u32, u32) {
+                <$texture>::dimensions(self)
+            }
+        }
+
+        #[cfg(feature = "glium")]
+        impl<'a, F: ::glium::backend::Facade> GpuBackend for $format<'a, F> {
+            type Texture = $texture;
+
+            fn create_texture(&self, page: RawPage) -> AtlasResult<$texture> {
+                <$texture>::new(self.0, glium_image(page)).map_err(|e| AtlasError::Texture(format!("{:?}", e)))
+            }
+
+            fn write_texture(&self, texture: &mut $texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+                let (_, page_height) = <$texture>::dimensions(texture);
+                texture.write(glium_rect(page_height, x, y, &pixels), glium_image(pixels));
+                Ok(())
+            }
+
+            fn stream_texture(&self, texture: &mut $texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+                let (_, page_height) = <$texture>::dimensions(texture);
+                let rect = glium_rect(page_height, x, y, &pixels);
+                let buffer = glium_pixel_buffer(self.0, pixels);
+                texture.main_level().raw_upload_from_pixel_buffer(buffer.as_slice(),
+                                                                  rect.left..rect.left + rect.width,
+                                                                  rect.bottom..rect.bottom + rect.height,
+                                                                  0..1);
+                Ok(())
+            }
+
+            fn flips_rows(&self) -> bool {
+                true
+            }
+        }
+    }
+}
+
+glium_format!(SrgbFormat, ::glium::texture::SrgbTexture2d);
+glium_format!(LinearFormat, ::glium::texture::Texture2d);
+glium_format!(CompressedLinearFormat, ::glium::texture::CompressedTexture2d);
+
+/// Uploads pages into `wgpu::Texture`s on `device` through `queue`.
+///
+/// wgpu textures have their origin at the top left, so unlike with glium,
+/// `DrawInfo` UVs can be used as they are.
+#[cfg(feature = "wgpu")]
+pub struct WgpuBackend<'a> {
+    pub device: &'a ::wgpu::Device,
+    pub queue: &'a ::wgpu::Queue,
+}
+
+#[cfg(feature = "wgpu")]
+impl PageTexture for ::wgpu::Texture {
+    fn dimensions(&self) -> (u32, u32) {
+        let size = self.size();
+        (size.width, size.height)
+    }
+}
+
+#[cfg(feature = "wgpu")]
+impl<'a> GpuBackend for WgpuBackend<'a> {
+    type Texture = ::wgpu::Texture;
+
+    fn create_texture(&self, page: RawPage) -> AtlasResult<::wgpu::Texture> {
+        self.create_texture_with_mipmaps(page, &[])
+    }
+
+    fn create_texture_with_mipmaps(&self, page: RawPage, mipmaps: &[RawPage]) -> AtlasResult<::wgpu::Texture> {
+        let texture = self.device.create_texture(&wgpu_page_descriptor(&page, 1 + mipmaps.len() as u32));
+        self.write_level(&texture, 0, 0, 0, page);
+        for (level, mipmap) in mipmaps.iter().enumerate() {
+            self.write_level(&texture, level as u32 + 1, 0, 0, *mipmap);
+        }
+        Ok(texture)
+    }
+
+    fn write_texture(&self, texture: &mut ::wgpu::Texture, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+        check_wgpu_write(PageTexture::dimensions(texture), x, y, &pixels)?;
+        self.write_level(texture, 0, x, y, pixels);
+        Ok(())
+    }
+}
+
+/// Describes the texture a page of `levels` mip levels is created as.
+#[cfg(feature = "wgpu")]
+fn wgpu_page_descriptor(page: &RawPage, levels: u32) -> ::wgpu::TextureDescriptor<'static> {
+    ::wgpu::TextureDescriptor {
+        label: Some("texture_atlas page"),
+        size: ::wgpu::Extent3d {
+            width: page.width,
+            height: page.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: levels,
+        sample_count: 1,
+        dimension: ::wgpu::TextureDimension::D2,
+        format: ::wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: ::wgpu::TextureUsages::TEXTURE_BINDING | ::wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    }
+}
+
+/// Fails unless `pixels` written at `(x, y)` lie inside a page of
+/// `page_size`, which wgpu would otherwise fail validation for.
+#[cfg(feature = "wgpu")]
+fn check_wgpu_write((page_w, page_h): (u32, u32), x: u32, y: u32, pixels: &RawPage) -> AtlasResult<()> {
+    if x + pixels.width > page_w || y + pixels.height > page_h {
+        return Err(AtlasError::Texture("Write lies outside of the page".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "wgpu")]
+impl<'a> WgpuBackend<'a> {
+    fn write_level(&self, texture: &::wgpu::Texture, mip_level: u32, x: u32, y: u32, pixels: RawPage) {
+        self.queue.write_texture(::wgpu::ImageCopyTexture {
+                                     texture: texture,
+                                     mip_level: mip_level,
+                                     origin: ::wgpu::Origin3d { x: x, y: y, z: 0 },
+                                     aspect: ::wgpu::TextureAspect::All,
+                                 },
+                                 pixels.data,
+                                 ::wgpu::ImageDataLayout {
+                                     offset: 0,
+                                     bytes_per_row: Some(4 * pixels.width),
+                                     rows_per_image: Some(pixels.height),
+                                 },
+                                 ::wgpu::Extent3d {
+                                     width: pixels.width,
+                                     height: pixels.height,
+                                     depth_or_array_layers: 1,
+                                 });
+    }
+}
+
+impl PageTexture for DynamicImage {
+    fn dimensions(&self) -> (u32, u32) {
+        GenericImage::dimensions(self)
+    }
+}
+
+/// Lets atlases borrow pages owned by another atlas.
+impl<'a, T: PageTexture> PageTexture for &'a T {
+    fn dimensions(&self) -> (u32, u32) {
+        (**self).dimensions()
+    }
+}
+
+impl<T: PageTexture> PageTexture for Rc<T> {
+    fn dimensions(&self) -> (u32, u32) {
+        (**self).dimensions()
+    }
+}
+
+impl<T: PageTexture> PageTexture for Arc<T> {
+    fn dimensions(&self) -> (u32, u32) {
+        (**self).dimensions()
+    }
+}
+
+/// Creates each page by calling a closure, for texture types no backend
+/// knows about, like an engine's own texture handles or a glium texture
+/// type with a format of its own. With glium, `RawPage::to_glium_image`
+/// gives the closure something to pass to the texture's constructor.
+///
+/// The closure can only create pages, so atlases built with it can't
+/// `flush` pixels written with `write_pixels`.
+pub struct TextureFn<F>(pub F);
+
+impl<T, F> GpuBackend for TextureFn<F>
+    where T: PageTexture,
+          F: Fn(RawPage) -> AtlasResult<T>
+{
+    type Texture = T;
+
+    fn create_texture(&self, page: RawPage) -> AtlasResult<T> {
+        (self.0)(page)
+    }
+
+    fn write_texture(&self, _texture: &mut T, _x: u32, _y: u32, _pixels: RawPage) -> AtlasResult<()> {
+        Err(AtlasError::Texture("Pages created by a TextureFn can't be written to".to_string()))
+    }
+}
+
+/// A backend that keeps pages in main memory as images, for tools and tests
+/// that need an atlas but no renderer.
+pub struct MemoryBackend;
+
+impl GpuBackend for MemoryBackend {
+    type Texture = DynamicImage;
+
+    fn create_texture(&self, page: RawPage) -> AtlasResult<DynamicImage> {
+        match ImageBuffer::from_raw(page.width, page.height, page.data.to_vec()) {
+            Some(buffer) => Ok(DynamicImage::ImageRgba8(buffer)),
+            None         => Err(AtlasError::Texture("Page data is smaller than its size".to_string())),
+        }
+    }
+
+    fn write_texture(&self, texture: &mut DynamicImage, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+        let region = self.create_texture(pixels)?;
+        if !texture.copy_from(&region, x, y) {
+            return Err(AtlasError::Texture("Write lies outside of the page".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Gets a page's pixels as RGBA, without a copy if they already are.
+fn rgba_bytes(page: &DynamicImage) -> Cow<[u8]> {
+    match *page {
+        DynamicImage::ImageRgba8(ref rgba) => Cow::Borrowed(&**rgba),
+        ref other                          => Cow::Owned(other.to_rgba().into_raw()),
+    }
+}
+
+fn raw_page<'a>(page: &DynamicImage, data: &'a [u8]) -> RawPage<'a> {
+    let (width, height) = GenericImage::dimensions(page);
+    RawPage {
+        data: data,
+        width: width,
+        height: height,
+    }
+}
+
+/// Uploads a page with `backend`. Pages packed by this crate are already
+/// RGBA and go to the backend as they are.
+pub fn upload_page<B: GpuBackend>(backend: &B, page: &DynamicImage) -> AtlasResult<B::Texture> {
+    trace_span!("upload");
+    let rgba = rgba_bytes(page);
+    backend.create_texture(raw_page(page, &rgba))
+}
+
+/// Like `upload_page`, but passes along the page's mip levels.
+pub fn upload_page_with_mipmaps<B: GpuBackend>(backend: &B,
+                                               page: &DynamicImage,
+                                               mipmaps: &[DynamicImage]) -> AtlasResult<B::Texture> {
+    trace_span!("upload");
+    let rgba = rgba_bytes(page);
+    let levels: Vec<_> = mipmaps.iter().map(rgba_bytes).collect();
+    let raw_levels: Vec<_> = mipmaps.iter().zip(levels.iter())
+        .map(|(level, data)| raw_page(level, data))
+        .collect();
+
+    backend.create_texture_with_mipmaps(raw_page(page, &rgba), &raw_levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImage, Rgba};
+
+    use super::*;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_memory_backend() {
+        let mut image = DynamicImage::new_rgba8(48, 24);
+        image.put_pixel(30, 4, Rgba { data: [255, 0, 0, 255] });
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", image, (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (1, 0)).unwrap();
+
+        let cpu_atlas = builder.build_cpu(None).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        let page = atlas.get_texture(0);
+        assert_eq!(PageTexture::dimensions(page), GenericImage::dimensions(&cpu_atlas.pages()[0]));
+        assert_eq!(page.raw_pixels(), cpu_atlas.pages()[0].raw_pixels());
+        assert_eq!(atlas.get_tile_draw_info(0, 0).uv_offset, cpu_atlas.get_tile_draw_info(0, 0).uv_offset);
+    }
+
+    /// Keeps rows from bottom to top, like a GL backend.
+    struct BottomUp;
+
+    impl GpuBackend for BottomUp {
+        type Texture = DynamicImage;
+
+        fn create_texture(&self, page: RawPage) -> AtlasResult<DynamicImage> {
+            Ok(MemoryBackend.create_texture(page)?.flipv())
+        }
+
+        fn write_texture(&self, texture: &mut DynamicImage, x: u32, y: u32, pixels: RawPage) -> AtlasResult<()> {
+            let (_, page_height) = GenericImage::dimensions(texture);
+            let region = MemoryBackend.create_texture(pixels)?.flipv();
+            assert!(texture.copy_from(&region, x, page_height - y - pixels.height));
+            Ok(())
+        }
+
+        fn flips_rows(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_texture_fn() {
+        struct Handle(usize, (u32, u32));
+
+        impl PageTexture for Handle {
+            fn dimensions(&self) -> (u32, u32) {
+                self.1
+            }
+        }
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 48), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(48, 48), (24, 24)).unwrap();
+        builder.add_tile("b", 0, (1, 1)).unwrap();
+
+        let created = ::std::cell::Cell::new(0);
+        let backend = TextureFn(|page: RawPage| {
+            created.set(created.get() + 1);
+            Ok(Rc::new(Handle(created.get(), (page.width, page.height))))
+        });
+        let mut atlas = builder.build(&backend, None).unwrap();
+
+        assert_eq!(created.get(), 2);
+        assert_eq!(atlas.get_texture(atlas.get_tile_texture_idx(0)).dimensions(), (48, 48));
+        assert_eq!(atlas.get_tile_draw_info(0, 0).uv_offset, (0.5, 0.5));
+
+        atlas.write_pixels(0, 0, 0, &DynamicImage::new_rgba8(1, 1)).unwrap();
+        assert!(atlas.flush(&backend).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "glium")]
+    fn test_glium_rows() {
+        // every glium page format uploads through these, so this covers them
+        // without a GL context.
+        let data = [255, 0, 0, 255, 0, 0, 255, 255];
+        let pixels = RawPage { data: &data, width: 1, height: 2 };
+        let rect = glium_rect(16, 3, 4, &pixels);
+        assert_eq!((rect.left, rect.bottom, rect.width, rect.height), (3, 10, 1, 2));
+
+        let image = glium_image(pixels);
+        assert_eq!((image.width, image.height), (1, 2));
+        assert_eq!(&image.data[..], &[0, 0, 255, 255, 255, 0, 0, 255][..]);
+    }
+
+    #[test]
+    #[cfg(feature = "wgpu")]
+    fn test_wgpu_pages() {
+        // covers what doesn't need a device: the textures asked for and the
+        // writes let through to the queue.
+        let data = [0; 4 * 8 * 4];
+        let page = RawPage { data: &data, width: 8, height: 4 };
+        let descriptor = wgpu_page_descriptor(&page, 3);
+        assert_eq!((descriptor.size.width, descriptor.size.height, descriptor.mip_level_count), (8, 4, 3));
+        assert_eq!(descriptor.format, ::wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert!(descriptor.usage.contains(::wgpu::TextureUsages::COPY_DST));
+
+        assert!(check_wgpu_write((16, 16), 8, 12, &page).is_ok());
+        assert!(check_wgpu_write((16, 16), 9, 0, &page).is_err());
+        assert!(check_wgpu_write((16, 16), 0, 13, &page).is_err());
+    }
+
+    #[test]
+    fn test_flip_y() {
+        let red = Rgba { data: [255, 0, 0, 255] };
+        let mut image = DynamicImage::new_rgba8(24, 24);
+        image.put_pixel(3, 0, red);
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sprite", image, (24, 24)).unwrap();
+        builder.add_tile("sprite", 0, (0, 0)).unwrap();
+        let flipped = builder.build(&BottomUp, None).unwrap();
+        let (_, h) = PageTexture::dimensions(flipped.get_texture(0));
+        assert_eq!(flipped.get_texture(0).get_pixel(3, h - 1), red);
+
+        builder.set_flip_y(false);
+        let mut atlas = builder.build(&BottomUp, None).unwrap();
+        assert_eq!(atlas.get_texture(0).get_pixel(3, 0), red);
+
+        let mut dot = DynamicImage::new_rgba8(1, 2);
+        dot.put_pixel(0, 1, red);
+        atlas.write_pixels(0, 10, 4, &dot).unwrap();
+        atlas.flush(&BottomUp).unwrap();
+        assert_eq!(atlas.get_texture(0).get_pixel(10, 5), red);
+    }
+}
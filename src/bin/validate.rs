@@ -0,0 +1,39 @@
+//! Checks the UV invariants of every tile in the atlas configs given on the
+//! command line, exiting with an error if any tile breaks them.
+//!
+//! cargo run --features validate --bin validate -- data/tiles.toml
+
+extern crate texture_atlas;
+
+use std::env;
+use std::process;
+
+use texture_atlas::validate;
+
+fn main() {
+    let mut failed = false;
+
+    for filename in env::args().skip(1) {
+        match validate::validate_config(&filename) {
+            Ok(violations) => {
+                for v in violations.iter() {
+                    println!("{}: tile {} ({}), animation frame {}: {} ({:?} to {:?})",
+                             filename, v.tile, v.frame, v.animation_frame, v.reason, v.uv_min, v.uv_max);
+                }
+                if violations.is_empty() {
+                    println!("{}: ok", filename);
+                } else {
+                    failed = true;
+                }
+            },
+            Err(e) => {
+                println!("{}: {}", filename, e);
+                failed = true;
+            },
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,361 @@
+//! A CPU encoder and decoder for the BC1 and BC3 (DXT1 and DXT5) block
+//! formats, for packing pages that can tolerate some loss into a fraction
+//! of the video memory. Endpoints are picked from each block's bounding
+//! box, which is quick but not the best quality an encoder could get.
+
+use std::cmp;
+
+use image::{DynamicImage, GenericImage, ImageBuffer, Rgba};
+
+use error::{AtlasError, AtlasResult};
+
+/// Which block format to compress pages to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// 8 bytes per 4x4 block, with one bit of alpha. Pixels less than half
+    /// opaque become fully transparent.
+    Bc1,
+    /// 16 bytes per 4x4 block, with full alpha.
+    Bc3,
+}
+
+impl BlockFormat {
+    pub fn block_size(&self) -> usize {
+        match *self {
+            BlockFormat::Bc1 => 8,
+            BlockFormat::Bc3 => 16,
+        }
+    }
+
+    /// Gets the extension of the cache files pages in this format are
+    /// saved as.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            BlockFormat::Bc1 => "bc1",
+            BlockFormat::Bc3 => "bc3",
+        }
+    }
+}
+
+/// A page compressed to a block format, as blocks from left to right and
+/// top to bottom.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CompressedPage {
+    pub format: BlockFormat,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl CompressedPage {
+    /// Checks that `data` holds exactly the blocks covering the page, as a
+    /// page read from a damaged cache might not.
+    pub fn check(&self) -> AtlasResult<()> {
+        let blocks = ((self.width + 3) / 4) as usize * ((self.height + 3) / 4) as usize;
+        let expected = blocks * self.format.block_size();
+        if self.data.len() != expected {
+            return Err(AtlasError::Cache(format!("A {}x{} {:?} page takes {} bytes, but has {}",
+                                                 self.width, self.height, self.format, expected, self.data.len())));
+        }
+        Ok(())
+    }
+}
+
+pub fn compress(image: &DynamicImage, format: BlockFormat) -> CompressedPage {
+    trace_span!("compress");
+    let rgba = image.to_rgba();
+    let (width, height) = rgba.dimensions();
+    let (blocks_x, blocks_y) = ((width + 3) / 4, (height + 3) / 4);
+    let mut data = Vec::with_capacity((blocks_x * blocks_y) as usize * format.block_size());
+
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let mut block = [[0u8; 4]; 16];
+            for (i, pixel) in block.iter_mut().enumerate() {
+                // blocks hanging over the edge repeat the last row or column.
+                let x = cmp::min(bx * 4 + i as u32 % 4, width - 1);
+                let y = cmp::min(by * 4 + i as u32 / 4, height - 1);
+                *pixel = rgba.get_pixel(x, y).data;
+            }
+
+            match format {
+                BlockFormat::Bc1 => encode_color_block(&block, true, &mut data),
+                BlockFormat::Bc3 => {
+                    encode_alpha_block(&block, &mut data);
+                    encode_color_block(&block, false, &mut data);
+                },
+            }
+        }
+    }
+
+    CompressedPage {
+        format: format,
+        width: width,
+        height: height,
+        data: data,
+    }
+}
+
+/// Decodes a page back to pixels, failing with `AtlasError::Cache` if its
+/// data doesn't hold every block.
+pub fn decompress(page: &CompressedPage) -> AtlasResult<DynamicImage> {
+    page.check()?;
+
+    let blocks_x = (page.width + 3) / 4;
+    let block_size = page.format.block_size();
+
+    let buffer = ImageBuffer::from_fn(page.width, page.height, |x, y| {
+        let offset = ((y / 4) * blocks_x + x / 4) as usize * block_size;
+        let block = &page.data[offset..offset + block_size];
+        let i = ((y % 4) * 4 + x % 4) as usize;
+
+        let pixel = match page.format {
+            BlockFormat::Bc1 => decode_color(block, i, true),
+            BlockFormat::Bc3 => {
+                let mut pixel = decode_color(&block[8..], i, false);
+                pixel[3] = decode_alpha(&block[..8], i);
+                pixel
+            },
+        };
+        Rgba { data: pixel }
+    });
+
+    Ok(DynamicImage::ImageRgba8(buffer))
+}
+
+/// Flips a page upside down by reordering its blocks and their rows,
+/// without re-encoding anything. Returns `None` if the page's height isn't a
+/// multiple of 4, since the padding rows of the last block row would end up
+/// at the top.
+pub fn flip_vertical(page: &CompressedPage) -> Option<CompressedPage> {
+    if page.height % 4 != 0 {
+        return None;
+    }
+
+    let block_size = page.format.block_size();
+    let row_len = ((page.width + 3) / 4) as usize * block_size;
+    let mut data = Vec::with_capacity(page.data.len());
+
+    for row in page.data.chunks(row_len).rev() {
+        for block in row.chunks(block_size) {
+            let color = match page.format {
+                BlockFormat::Bc1 => block,
+                BlockFormat::Bc3 => {
+                    data.extend_from_slice(&flip_alpha_block(&block[..8]));
+                    &block[8..]
+                },
+            };
+            // each byte of the color indices is one row of the block.
+            data.extend_from_slice(&[color[0], color[1], color[2], color[3],
+                                     color[7], color[6], color[5], color[4]]);
+        }
+    }
+
+    Some(CompressedPage {
+        format: page.format,
+        width: page.width,
+        height: page.height,
+        data: data,
+    })
+}
+
+fn flip_alpha_block(block: &[u8]) -> [u8; 8] {
+    let mut indices = 0u64;
+    for (byte, &value) in block[2..8].iter().enumerate() {
+        indices |= (value as u64) << (byte * 8);
+    }
+
+    // each 12 bits of the alpha indices are one row of the block.
+    let mut flipped = 0u64;
+    for row in 0..4 {
+        flipped |= ((indices >> (row * 12)) & 0xfff) << ((3 - row) * 12);
+    }
+
+    let mut out = [block[0], block[1], 0, 0, 0, 0, 0, 0];
+    for (byte, value) in out[2..].iter_mut().enumerate() {
+        *value = (flipped >> (byte * 8)) as u8;
+    }
+    out
+}
+
+fn to_565(c: [u8; 4]) -> u16 {
+    (c[0] as u16 >> 3) << 11 | (c[1] as u16 >> 2) << 5 | c[2] as u16 >> 3
+}
+
+fn from_565(c: u16) -> [u8; 4] {
+    let r = (c >> 11) as u8 & 0x1f;
+    let g = (c >> 5) as u8 & 0x3f;
+    let b = c as u8 & 0x1f;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+}
+
+fn mix(a: [u8; 4], b: [u8; 4], wa: u32, wb: u32) -> [u8; 4] {
+    let total = wa + wb;
+    let m = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb) / total) as u8;
+    [m(0), m(1), m(2), 255]
+}
+
+/// Builds the four colors an endpoint pair encodes. Without `four_colors`,
+/// the last one is transparent black.
+fn palette(c0: u16, c1: u16, four_colors: bool) -> [[u8; 4]; 4] {
+    let (a, b) = (from_565(c0), from_565(c1));
+    if four_colors {
+        [a, b, mix(a, b, 2, 1), mix(a, b, 1, 2)]
+    } else {
+        [a, b, mix(a, b, 1, 1), [0, 0, 0, 0]]
+    }
+}
+
+fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    (0..3).map(|i| {
+        let d = a[i] as i32 - b[i] as i32;
+        (d * d) as u32
+    }).sum()
+}
+
+/// Appends the 8 byte color half of a block. With `punch_through`, pixels
+/// less than half opaque are encoded as transparent.
+fn encode_color_block(block: &[[u8; 4]; 16], punch_through: bool, out: &mut Vec<u8>) {
+    let transparent = |p: &[u8; 4]| punch_through && p[3] < 128;
+
+    let mut min = [255u8; 4];
+    let mut max = [0u8; 4];
+    for pixel in block.iter().filter(|p| !transparent(*p)) {
+        for (i, &value) in pixel.iter().take(3).enumerate() {
+            min[i] = cmp::min(min[i], value);
+            max[i] = cmp::max(max[i], value);
+        }
+    }
+
+    let (mut c0, mut c1) = (to_565(max), to_565(min));
+    if min[0] > max[0] {
+        // every pixel is transparent.
+        c0 = 0;
+        c1 = 0;
+    }
+
+    // the order of the endpoints picks the mode: four colors when the first
+    // is greater, three and transparency otherwise.
+    let four_colors = !block.iter().any(|p| transparent(p));
+    if (four_colors && c0 < c1) || (!four_colors && c0 > c1) {
+        let t = c0;
+        c0 = c1;
+        c1 = t;
+    }
+
+    let colors = palette(c0, c1, four_colors && c0 != c1);
+    let mut indices = 0u32;
+    for (i, pixel) in block.iter().enumerate() {
+        let index = if transparent(pixel) {
+            3
+        } else if c0 == c1 {
+            0
+        } else {
+            let candidates = if four_colors { 4 } else { 3 };
+            (0..candidates).min_by_key(|&j| distance(*pixel, colors[j])).unwrap()
+        };
+        indices |= (index as u32) << (i * 2);
+    }
+
+    out.extend_from_slice(&[c0 as u8, (c0 >> 8) as u8, c1 as u8, (c1 >> 8) as u8,
+                            indices as u8, (indices >> 8) as u8, (indices >> 16) as u8, (indices >> 24) as u8]);
+}
+
+fn decode_color(block: &[u8], i: usize, punch_through: bool) -> [u8; 4] {
+    let c0 = block[0] as u16 | (block[1] as u16) << 8;
+    let c1 = block[2] as u16 | (block[3] as u16) << 8;
+    let indices = block[4] as u32 | (block[5] as u32) << 8 | (block[6] as u32) << 16 | (block[7] as u32) << 24;
+
+    let colors = palette(c0, c1, c0 > c1 || !punch_through);
+    colors[(indices >> (i * 2)) as usize & 3]
+}
+
+/// Gets the eight alpha values an endpoint pair encodes.
+fn alpha_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let mut alphas = [a0, a1, 0, 0, 0, 0, 0, 0];
+    for (i, alpha) in alphas.iter_mut().enumerate().skip(2) {
+        let i = i as u32;
+        *alpha = (((8 - i) * a0 as u32 + (i - 1) * a1 as u32) / 7) as u8;
+    }
+    alphas
+}
+
+/// Appends the 8 byte alpha half of a BC3 block.
+fn encode_alpha_block(block: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+    let a0 = block.iter().map(|p| p[3]).max().unwrap();
+    let a1 = block.iter().map(|p| p[3]).min().unwrap();
+    let alphas = alpha_palette(a0, a1);
+
+    let mut indices = 0u64;
+    for (i, pixel) in block.iter().enumerate() {
+        let index = if a0 == a1 {
+            0
+        } else {
+            (0..8).min_by_key(|&j| (alphas[j] as i32 - pixel[3] as i32).abs()).unwrap()
+        };
+        indices |= (index as u64) << (i * 3);
+    }
+
+    out.push(a0);
+    out.push(a1);
+    for byte in 0..6 {
+        out.push((indices >> (byte * 8)) as u8);
+    }
+}
+
+fn decode_alpha(block: &[u8], i: usize) -> u8 {
+    let mut indices = 0u64;
+    for (byte, &value) in block[2..8].iter().enumerate() {
+        indices |= (value as u64) << (byte * 8);
+    }
+    alpha_palette(block[0], block[1])[(indices >> (i * 3)) as usize & 7]
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImage, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut image = DynamicImage::new_rgba8(6, 4);
+        for (x, y, _) in image.clone().pixels() {
+            let alpha = if x < 3 { 255 } else { 0 };
+            image.put_pixel(x, y, Rgba { data: [200, 40 * y as u8, 10, alpha] });
+        }
+
+        for &format in &[BlockFormat::Bc1, BlockFormat::Bc3] {
+            let page = compress(&image, format);
+            assert_eq!(page.data.len(), 2 * format.block_size());
+
+            let decoded = decompress(&page).unwrap();
+            assert_eq!(decoded.dimensions(), (6, 4));
+            for (x, y, pixel) in image.pixels() {
+                let out = decoded.get_pixel(x, y);
+                assert_eq!(out.data[3], pixel.data[3]);
+                if pixel.data[3] == 255 {
+                    for (&a, &b) in out.data.iter().zip(pixel.data.iter()).take(3) {
+                        assert!((a as i32 - b as i32).abs() <= 32);
+                    }
+                }
+            }
+
+            let flipped = decompress(&flip_vertical(&page).unwrap()).unwrap();
+            for (x, y, pixel) in decoded.pixels() {
+                assert_eq!(flipped.get_pixel(x, 3 - y), pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut page = compress(&DynamicImage::new_rgba8(8, 8), BlockFormat::Bc3);
+        assert!(page.check().is_ok());
+        page.data.pop();
+        match decompress(&page) {
+            Err(AtlasError::Cache(..)) => (),
+            _ => panic!("expected a truncated page to be rejected"),
+        }
+    }
+}
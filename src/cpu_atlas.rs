@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use image::{DynamicImage, GenericImage};
+
+use AtlasRect;
+use atlas::PageInfo;
+use backend::{self, GpuBackend, PageTexture, Unflipped};
+use block_compress::CompressedPage;
+use error::{AtlasError, AtlasResult};
+use ids::{PageId, TileId};
+use sample_match::{self, TileMatch};
+use sampler::SamplerSettings;
+use streaming_upload::StreamingUpload;
+use tile_atlas::{self, AtlasFrame, DrawInfo, Pick, StalePages, TileAtlas, TileIndex};
+
+/// A packed tile atlas whose pages are still in main memory.
+///
+/// A `TileAtlas` owns textures created on whichever `Facade` it was built
+/// with. Textures are usable from any context that shares objects with that
+/// one, so an atlas built on a loader context can be drawn from a render
+/// context created with it as a shared context. For contexts that don't
+/// share objects, keep the `CpuAtlas` around and `upload` it once per
+/// context; uploading doesn't repack anything.
+///
+/// The tile queries work the same as on `TileAtlas`, so code doing tile
+/// math can be tested against real atlas metadata without a GPU. It's
+/// `Send`, so it can be packed on one thread and uploaded on another.
+pub struct CpuAtlas {
+    locations: HashMap<TileIndex, String>,
+    frames: HashMap<String, AtlasFrame>,
+    pages: Vec<DynamicImage>,
+    compressed: Vec<Option<CompressedPage>>,
+    mipmaps: Vec<Vec<DynamicImage>>,
+    page_names: Vec<String>,
+    sampler: SamplerSettings,
+    flip_y: bool,
+    half_texel_inset: bool,
+    stale_pages: StalePages,
+}
+
+impl CpuAtlas {
+    pub fn new(locations: HashMap<TileIndex, String>,
+               frames: HashMap<String, AtlasFrame>,
+               pages: Vec<DynamicImage>) -> Self {
+        CpuAtlas {
+            locations: locations,
+            frames: frames,
+            pages: pages,
+            compressed: Vec::new(),
+            mipmaps: Vec::new(),
+            page_names: Vec::new(),
+            sampler: SamplerSettings::default(),
+            flip_y: true,
+            half_texel_inset: false,
+            stale_pages: StalePages::RemoveFolder,
+        }
+    }
+
+    /// Attaches block compressed copies of some or all of the pages, in page
+    /// order, which `upload` then uploads instead of the pages themselves.
+    pub fn with_compressed_pages(mut self, compressed: Vec<Option<CompressedPage>>) -> Self {
+        assert!(compressed.is_empty() || compressed.len() == self.pages.len());
+        self.compressed = compressed;
+        self
+    }
+
+    /// Gets the block compressed copy of a page, if it was compressed.
+    pub fn compressed_page<P: Into<PageId>>(&self, page: P) -> Option<&CompressedPage> {
+        let PageId(page) = page.into();
+        self.compressed.get(page).and_then(|compressed| compressed.as_ref())
+    }
+
+    /// Attaches the mip levels below each page, which `upload` then passes to
+    /// the backend along with the pages.
+    pub fn with_mipmaps(mut self, mipmaps: Vec<Vec<DynamicImage>>) -> Self {
+        assert!(mipmaps.is_empty() || mipmaps.len() == self.pages.len());
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    /// Gets the mip levels below the full size of a page, or nothing if the
+    /// atlas wasn't built with mipmaps.
+    pub fn mipmaps<P: Into<PageId>>(&self, page: P) -> &[DynamicImage] {
+        let PageId(page) = page.into();
+        self.mipmaps.get(page).map_or(&[], |levels| levels.as_slice())
+    }
+
+    /// Gets how many mip levels the pages have, counting the page itself.
+    pub fn mip_levels(&self) -> u32 {
+        self.mipmaps.iter().map(|levels| levels.len() as u32 + 1).max().unwrap_or(1)
+    }
+
+    /// Sets the sampler settings the uploaded atlas gets.
+    pub fn with_sampler(mut self, sampler: SamplerSettings) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn sampler(&self) -> SamplerSettings {
+        self.sampler
+    }
+
+    /// Sets whether pages are flipped for backends that keep rows from
+    /// bottom to top. See `TileAtlasBuilder::set_flip_y`.
+    pub fn with_flip_y(mut self, flip_y: bool) -> Self {
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn flip_y(&self) -> bool {
+        self.flip_y
+    }
+
+    /// Sets whether reported UVs are inset by half a texel. See
+    /// `TileAtlas::set_half_texel_inset`.
+    pub fn with_half_texel_inset(mut self, inset: bool) -> Self {
+        self.half_texel_inset = inset;
+        self
+    }
+
+    pub fn half_texel_inset(&self) -> bool {
+        self.half_texel_inset
+    }
+
+    /// Sets what `save_to_cache` does with pages already in the cache
+    /// folder. See `TileAtlasBuilder::set_stale_pages`.
+    pub fn with_stale_pages(mut self, stale: StalePages) -> Self {
+        self.stale_pages = stale;
+        self
+    }
+
+    pub fn stale_pages(&self) -> StalePages {
+        self.stale_pages
+    }
+
+    /// Records the file names the pages were saved under, in page order.
+    pub fn with_page_names(mut self, page_names: Vec<String>) -> Self {
+        self.page_names = page_names;
+        self
+    }
+
+    /// Gets the file names the pages were saved under, or nothing if they
+    /// were never saved.
+    pub fn page_names(&self) -> &[String] {
+        &self.page_names
+    }
+
+    pub fn pages(&self) -> &[DynamicImage] {
+        &self.pages
+    }
+
+    pub fn page_info<P: Into<PageId>>(&self, page: P) -> PageInfo {
+        let PageId(page) = page.into();
+        let (width, height) = self.get_page_dimensions(page);
+        PageInfo {
+            page: PageId(page),
+            file_name: self.page_names.get(page).cloned(),
+            width: width,
+            height: height,
+        }
+    }
+
+    pub fn locations(&self) -> &HashMap<TileIndex, String> {
+        &self.locations
+    }
+
+    pub fn frames(&self) -> &HashMap<String, AtlasFrame> {
+        &self.frames
+    }
+
+    pub fn get_frame<I: Into<TileId>>(&self, tile_type: I) -> &AtlasFrame {
+        self.try_get_frame(tile_type).unwrap()
+    }
+
+    pub fn try_get_frame<I: Into<TileId>>(&self, tile_type: I) -> AtlasResult<&AtlasFrame> {
+        let TileId(tile_type) = tile_type.into();
+        self.locations.get(&tile_type)
+            .and_then(|key| self.frames.get(key))
+            .ok_or(AtlasError::MissingTile(tile_type))
+    }
+
+    pub fn get_page_dimensions<P: Into<PageId>>(&self, idx: P) -> (u32, u32) {
+        let PageId(idx) = idx.into();
+        self.pages.get(idx).unwrap().dimensions()
+    }
+
+    pub fn get_tile_draw_info<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> DrawInfo {
+        self.try_get_tile_draw_info(tile_type, msecs).unwrap()
+    }
+
+    pub fn try_get_tile_draw_info<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> AtlasResult<DrawInfo> {
+        let TileId(tile_type) = tile_type.into();
+        let frame = self.try_get_frame(tile_type)?;
+        let info = tile_atlas::frame_draw_info(&self.frames, frame, tile_type, msecs, &|idx| self.get_page_dimensions(idx));
+        if self.half_texel_inset {
+            Ok(tile_atlas::inset_draw_info(info, &|idx| self.get_page_dimensions(idx)))
+        } else {
+            Ok(info)
+        }
+    }
+
+    pub fn get_tile_texture_idx<I: Into<TileId>>(&self, tile_type: I) -> usize {
+        self.get_tile_draw_info(tile_type, 0).page
+    }
+
+    pub fn get_animation_pages<I: Into<TileId>>(&self, tile_type: I) -> Vec<usize> {
+        let TileId(tile_type) = tile_type.into();
+        tile_atlas::animation_pages(&self.frames, self.get_frame(tile_type), tile_type)
+    }
+
+    pub fn get_sprite_tex_ratio<I: Into<TileId>>(&self, tile_type: I) -> [f32; 2] {
+        self.get_tile_draw_info(tile_type, 0).uv_scale
+    }
+
+    pub fn get_tile_texture_size<I: Into<TileId>>(&self, tile_type: I) -> (u32, u32) {
+        self.get_tile_draw_info(tile_type, 0).pixel_size
+    }
+
+    pub fn get_texture_offset<I: Into<TileId>>(&self, tile_type: I) -> (f32, f32) {
+        self.get_texture_offset_at(tile_type, 0)
+    }
+
+    pub fn get_texture_offset_at<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> (f32, f32) {
+        self.get_tile_draw_info(tile_type, msecs).uv_offset
+    }
+
+    /// Gets the pages holding frames of a usage class, in page order.
+    pub fn pages_with_class(&self, class: &str) -> Vec<PageId> {
+        tile_atlas::pages_with_class(&self.frames, class)
+    }
+
+    /// Gets how many pixels of a page no frame covers. See
+    /// `TileAtlas::page_free_area`.
+    pub fn page_free_area<P: Into<PageId>>(&self, page: P) -> u64 {
+        let PageId(page) = page.into();
+        tile_atlas::page_free_area(&self.frames, page, self.get_page_dimensions(page))
+    }
+
+    /// Finds a `w` by `h` area on any page that no frame covers, without
+    /// reserving it. See `TileAtlas::find_free_rect`.
+    pub fn find_free_rect(&self, w: u32, h: u32) -> Option<(PageId, AtlasRect)> {
+        (0..self.passes())
+            .filter_map(|page| tile_atlas::find_free_rect(&self.frames, page, self.get_page_dimensions(page), w, h)
+                        .map(|rect| (PageId(page), rect)))
+            .next()
+    }
+
+    pub fn can_fit(&self, w: u32, h: u32) -> bool {
+        self.find_free_rect(w, h).is_some()
+    }
+
+    /// Finds the frame and tile drawn from a pixel of a page. See
+    /// `TileAtlas::pick`.
+    pub fn pick<P: Into<PageId>>(&self, page: P, pixel: (u32, u32)) -> Option<Pick> {
+        let PageId(page) = page.into();
+        tile_atlas::pick(&self.frames, page, pixel)
+    }
+
+    /// Like `pick`, at a UV coordinate measured from the page's top-left
+    /// corner.
+    pub fn pick_uv<P: Into<PageId>>(&self, page: P, uv: (f32, f32)) -> Option<Pick> {
+        let PageId(page) = page.into();
+        tile_atlas::pick(&self.frames, page, tile_atlas::uv_to_pixel(uv, self.get_page_dimensions(page)))
+    }
+
+    /// Finds the `limit` tiles that look most like `sample`, best first, to
+    /// track down where a piece of a screenshot was drawn from or which
+    /// tiles duplicate each other's art.
+    pub fn match_sample(&self, sample: &DynamicImage, limit: usize) -> Vec<TileMatch> {
+        sample_match::match_sample(&self.frames, &self.pages, sample, limit)
+    }
+
+    pub fn passes(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Draws `image` onto page `page` with its top-left corner at `(x, y)`.
+    /// The page's compressed copy, if any, is dropped rather than left
+    /// stale, so the page is uploaded uncompressed from then on.
+    pub fn write_pixels<P: Into<PageId>>(&mut self, page: P, x: u32, y: u32, image: &DynamicImage) -> AtlasResult<()> {
+        let PageId(page) = page.into();
+        let target = match self.pages.get_mut(page) {
+            Some(target) => target,
+            None         => return Err(AtlasError::Texture(format!("No page {}", page))),
+        };
+        if !target.copy_from(image, x, y) {
+            return Err(AtlasError::Texture(format!("A write at ({}, {}) lies outside of page {}", x, y, page)));
+        }
+
+        if let Some(compressed) = self.compressed.get_mut(page) {
+            *compressed = None;
+        }
+        Ok(())
+    }
+
+    /// Creates textures for every page with `backend`.
+    pub fn upload<B: GpuBackend>(&self, backend: &B) -> AtlasResult<TileAtlas<B::Texture>> {
+        Ok(TileAtlas::new(self.locations.clone(), self.frames.clone(), self.upload_pages(backend)?)
+           .with_sampler(self.sampler)
+           .with_page_names(self.page_names.clone())
+           .with_flip_y(self.flip_y)
+           .with_half_texel_inset(self.half_texel_inset)
+           .with_mip_levels(self.mip_levels()))
+    }
+
+    /// Like `upload`, but the atlas keeps this copy of its pages so it can
+    /// `reupload` them into a new context after the old one is lost.
+    pub fn upload_retained<B: GpuBackend>(self, backend: &B) -> AtlasResult<TileAtlas<B::Texture>> {
+        Ok(self.upload(backend)?.with_retained_pages(self))
+    }
+
+    /// Creates a texture for each page, in page order.
+    pub fn upload_pages<B: GpuBackend>(&self, backend: &B) -> AtlasResult<Vec<B::Texture>> {
+        let mut textures = Vec::with_capacity(self.pages.len());
+        for idx in 0..self.pages.len() {
+            textures.push(self.upload_page(backend, idx)?);
+        }
+        Ok(textures)
+    }
+
+    /// Creates a texture for one page, compressed or with mipmaps if the
+    /// atlas has them.
+    pub fn upload_page<B: GpuBackend, P: Into<PageId>>(&self, backend: &B, page: P) -> AtlasResult<B::Texture> {
+        let PageId(idx) = page.into();
+        if self.flip_y {
+            self.create_page_texture(backend, idx)
+        } else {
+            self.create_page_texture(&Unflipped(backend), idx)
+        }
+    }
+
+    fn create_page_texture<B: GpuBackend>(&self, backend: &B, idx: usize) -> AtlasResult<B::Texture> {
+        let page = match self.pages.get(idx) {
+            Some(page) => page,
+            None       => return Err(AtlasError::Texture(format!("No page {}", idx))),
+        };
+
+        match self.compressed_page(idx) {
+            Some(compressed) => backend.create_compressed_texture(compressed),
+            None if !self.mipmaps.is_empty() => backend::upload_page_with_mipmaps(backend, page, &self.mipmaps[idx]),
+            None => backend::upload_page(backend, page),
+        }
+    }
+
+    /// Starts uploading the pages a few rows at a time. See
+    /// `StreamingUpload`.
+    pub fn upload_streaming<T: PageTexture>(self, rows_per_step: u32) -> StreamingUpload<T> {
+        StreamingUpload::new(self, rows_per_step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use image::DynamicImage;
+
+    use backend::MemoryBackend;
+    use tile_atlas::{TileAtlasBuilder, TileKind};
+
+    #[test]
+    fn test_tile_math() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("wide", DynamicImage::new_rgba8(96, 24), (24, 24)).unwrap();
+        builder.add_frame_image("small", DynamicImage::new_rgba8(16, 16), (16, 16)).unwrap();
+        builder.add_tile_with_kind("wide", 0, (0, 0), TileKind::Animated(4, 100)).unwrap();
+        builder.add_tile("small", 1, (0, 0)).unwrap();
+        builder.add_tile_with_kind("wide", 2, (0, 0), TileKind::StridedAnimated(2, 100, 2)).unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.passes(), 1);
+        assert_eq!(atlas.get_tile_texture_size(0), (24, 24));
+        assert_eq!(atlas.get_tile_texture_size(1), (16, 16));
+
+        let (w, _) = atlas.get_page_dimensions(0);
+        let ratio = atlas.get_sprite_tex_ratio(0);
+        assert!((ratio[0] - 24.0 / w as f32).abs() < 1e-6);
+
+        let first = atlas.get_texture_offset_at(0, 0);
+        let second = atlas.get_texture_offset_at(0, 150);
+        assert!((second.0 - first.0 - ratio[0]).abs() < 1e-6);
+        assert_eq!(second.1, first.1);
+        assert_eq!(atlas.get_texture_offset_at(0, 400), first);
+
+        let strided = atlas.get_texture_offset_at(2, 100);
+        assert!((strided.0 - first.0 - 2.0 * ratio[0]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_pack_on_worker_thread() {
+        let packer = thread::spawn(|| {
+            let mut builder = TileAtlasBuilder::new();
+            builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+            builder.add_tile("a", 0, (0, 0)).unwrap();
+            builder.pack()
+        });
+
+        let packed = packer.join().unwrap().unwrap();
+        let atlas = packed.upload(&MemoryBackend).unwrap();
+        assert_eq!(atlas.passes(), 1);
+        assert_eq!(atlas.get_tile_texture_size(0), (24, 24));
+    }
+}
@@ -0,0 +1,169 @@
+//! Regions of a render target handed out at runtime, for decals, shadow
+//! maps or impostors drawn into the target rather than loaded as images.
+//! Allocation goes through `rect_packing`, so the regions are placed with
+//! the same algorithms and options as atlas pages.
+
+use std::collections::HashMap;
+
+use AtlasRect;
+use packing::PackerConfig;
+use rect_packing::RectAtlas;
+
+/// A region allocated in a `DecalAtlas`. Ids aren't reused, so one kept
+/// after its region was freed never finds another's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DecalId(u32);
+
+/// A region `DecalAtlas::defragment` moved, whose contents should be copied
+/// from `from` to `to` in the render target.
+#[derive(Clone)]
+pub struct DecalMove {
+    pub decal: DecalId,
+    pub from: AtlasRect,
+    pub to: AtlasRect,
+}
+
+/// Hands out rectangular regions of a render target of
+/// `max_width` by `max_height` pixels.
+pub struct DecalAtlas {
+    config: PackerConfig,
+    regions: RectAtlas<DecalId>,
+    next_id: u32,
+}
+
+impl DecalAtlas {
+    pub fn new(config: &PackerConfig) -> Self {
+        DecalAtlas {
+            config: *config,
+            regions: RectAtlas::new(config),
+            next_id: 0,
+        }
+    }
+
+    /// Gets the size of the render target the regions are in.
+    pub fn size(&self) -> (u32, u32) {
+        (self.config.max_width, self.config.max_height)
+    }
+
+    /// Finds room for a `w` by `h` region, or `None` if there is none left,
+    /// even in the space of freed regions. Defragmenting may then make room.
+    pub fn allocate(&mut self, w: u32, h: u32) -> Option<DecalId> {
+        let id = DecalId(self.next_id);
+        match self.regions.insert(id, w, h) {
+            Some(_) => {
+                self.next_id += 1;
+                Some(id)
+            },
+            None => None,
+        }
+    }
+
+    /// Gets where a region is in the render target.
+    pub fn region(&self, id: DecalId) -> Option<&AtlasRect> {
+        self.regions.placement(&id)
+    }
+
+    /// Gets every allocated region.
+    pub fn regions(&self) -> &HashMap<DecalId, AtlasRect> {
+        self.regions.placements()
+    }
+
+    /// Frees a region for later allocations, and gets where it was.
+    pub fn free(&mut self, id: DecalId) -> Option<AtlasRect> {
+        self.regions.remove(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Frees every region at once.
+    pub fn clear(&mut self) {
+        self.regions = RectAtlas::new(&self.config);
+    }
+
+    /// Packs every region again from scratch, largest first, which closes up
+    /// the gaps freed regions leave behind. Gets the regions that moved, so
+    /// their contents can be copied over, or `None` with nothing moved if
+    /// they no longer all fit packed in that order.
+    pub fn defragment(&mut self) -> Option<Vec<DecalMove>> {
+        let mut live: Vec<(DecalId, AtlasRect)> = self.regions.placements().iter()
+            .map(|(&id, rect)| (id, rect.clone()))
+            .collect();
+        // Ids break ties, so the layout doesn't depend on the order of the
+        // map.
+        live.sort_by(|a, b| {
+            let size = |rect: &AtlasRect| (rect.w as u64 * rect.h as u64, rect.w.max(rect.h));
+            size(&b.1).cmp(&size(&a.1)).then_with(|| a.0.cmp(&b.0))
+        });
+
+        let mut packed = RectAtlas::new(&self.config);
+        for &(id, ref rect) in live.iter() {
+            let (w, h) = rect.source_size();
+            if packed.insert(id, w, h).is_none() {
+                return None;
+            }
+        }
+
+        let moves = live.into_iter()
+            .filter_map(|(id, from)| {
+                let to = packed.placement(&id).unwrap().clone();
+                if (from.x, from.y, from.rotated) == (to.x, to.y, to.rotated) {
+                    None
+                } else {
+                    Some(DecalMove { decal: id, from: from, to: to })
+                }
+            })
+            .collect();
+
+        self.regions = packed;
+        Some(moves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packing::PackAlgorithm;
+
+    #[test]
+    fn test_decal_atlas() {
+        let config = PackerConfig {
+            algorithm: PackAlgorithm::Shelf,
+            max_width: 64,
+            max_height: 64,
+            ..PackerConfig::default()
+        };
+        let mut decals = DecalAtlas::new(&config);
+        let a = decals.allocate(32, 32).unwrap();
+        let b = decals.allocate(32, 32).unwrap();
+        let c = decals.allocate(64, 16).unwrap();
+        let d = decals.allocate(64, 16).unwrap();
+        assert!(decals.allocate(16, 16).is_none());
+        let at = |decals: &DecalAtlas, id| decals.region(id).map(|rect| (rect.x, rect.y));
+        assert_eq!(at(&decals, d), Some((0, 48)));
+
+        // b's place is reused, under a new id.
+        assert_eq!(decals.free(b).map(|rect| (rect.x, rect.y)), Some((32, 0)));
+        let e = decals.allocate(16, 16).unwrap();
+        assert!(e != b);
+        assert_eq!(at(&decals, e), Some((32, 0)));
+        assert!(decals.region(b).is_none());
+
+        decals.free(a);
+        decals.free(c);
+        let moves = decals.defragment().unwrap();
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves.iter().map(|m| (m.decal, m.to.x, m.to.y)).collect::<Vec<_>>(), vec![(d, 0, 0), (e, 0, 16)]);
+        assert_eq!((at(&decals, d), at(&decals, e)), (Some((0, 0)), Some((0, 16))));
+        assert_eq!(decals.len(), 2);
+
+        decals.clear();
+        assert!(decals.is_empty());
+        assert!(decals.allocate(64, 64).is_some());
+    }
+}
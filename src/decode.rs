@@ -0,0 +1,227 @@
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::Cursor;
+#[cfg(feature = "fs")]
+use std::io::Read;
+use std::path::Path;
+
+use image::{self, bmp, gif, jpeg, png, tga, tiff, webp, DynamicImage, GenericImage, ImageDecoder, ImageFormat};
+
+use error::{AtlasError, AtlasResult};
+
+const PNG_MAGIC: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Upper bounds on the size of images imported as frames, to keep corrupt
+/// or malicious files from exhausting memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Maximum size of the decoded RGBA pixel data.
+    pub max_bytes: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_width: 8192,
+            max_height: 8192,
+            max_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub fn allows(&self, width: u32, height: u32) -> bool {
+        width <= self.max_width &&
+            height <= self.max_height &&
+            width as u64 * height as u64 * 4 <= self.max_bytes
+    }
+}
+
+/// Imports the image at `path`. The format is detected from the file's
+/// contents, so a misnamed file still loads. The dimensions are read from
+/// the header and checked against `limits` before any pixel data is
+/// decoded, and checked again after decoding for formats whose header
+/// can't be read on its own.
+#[cfg(feature = "fs")]
+pub fn import_image(path: &Path, limits: Option<&DecodeLimits>) -> AtlasResult<DynamicImage> {
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+
+    decode_image(&data, path, limits)
+}
+
+/// Like `import_image`, for an image file already read into memory, such as
+/// one fetched by a browser build. `name` is used in errors, and its
+/// extension for formats that can't be detected from the contents.
+pub fn decode_image(data: &[u8], name: &Path, limits: Option<&DecodeLimits>) -> AtlasResult<DynamicImage> {
+    let format = match sniff_format(data).or_else(|| extension_format(name)) {
+        Some(format) => format,
+        None         => return Err(AtlasError::Image(name.to_path_buf(),
+                                                     "contents aren't a supported image format".to_string())),
+    };
+
+    if let Some(limits) = limits {
+        if let Some((width, height)) = header_dimensions(data, format) {
+            check_limits(name, width, height, limits)?;
+        }
+    }
+
+    let image = image::load_from_memory_with_format(data, format).map_err(|e| {
+        AtlasError::Image(name.to_path_buf(), e.to_string())
+    })?;
+
+    if let Some(limits) = limits {
+        let (width, height) = image.dimensions();
+        check_limits(name, width, height, limits)?;
+    }
+
+    Ok(image)
+}
+
+fn check_limits(path: &Path, width: u32, height: u32, limits: &DecodeLimits) -> AtlasResult<()> {
+    if !limits.allows(width, height) {
+        return Err(AtlasError::ImageTooLarge(path.to_path_buf(), (width, height), *limits));
+    }
+    Ok(())
+}
+
+/// Detects an image's format from its first bytes. TGA has no magic
+/// bytes, so it's the only format that can't be detected; see
+/// `extension_format`.
+pub fn sniff_format(data: &[u8]) -> Option<ImageFormat> {
+    if data.starts_with(&PNG_MAGIC) {
+        Some(ImageFormat::PNG)
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some(ImageFormat::JPEG)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some(ImageFormat::GIF)
+    } else if data.starts_with(b"BM") {
+        Some(ImageFormat::BMP)
+    } else if data.starts_with(&[0, 0, 1, 0]) {
+        Some(ImageFormat::ICO)
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some(ImageFormat::WEBP)
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some(ImageFormat::TIFF)
+    } else if data.starts_with(b"#?RADIANCE") {
+        Some(ImageFormat::HDR)
+    } else if data.len() >= 2 && data[0] == b'P' && data[1] >= b'1' && data[1] <= b'6' {
+        Some(ImageFormat::PPM)
+    } else {
+        None
+    }
+}
+
+/// Gets the format of images that `sniff_format` can't detect from the
+/// extension of `path`.
+fn extension_format(path: &Path) -> Option<ImageFormat> {
+    let extension = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => extension.to_lowercase(),
+        None            => return None,
+    };
+    match extension.as_str() {
+        "tga" => Some(ImageFormat::TGA),
+        _     => None,
+    }
+}
+
+/// Reads an image's dimensions from its header without decoding any pixel
+/// data, or returns `None` if the header can't be read.
+fn header_dimensions(data: &[u8], format: ImageFormat) -> Option<(u32, u32)> {
+    let reader = Cursor::new(data);
+    let dimensions = match format {
+        ImageFormat::PNG  => png::PNGDecoder::new(reader).dimensions(),
+        ImageFormat::JPEG => jpeg::JPEGDecoder::new(reader).dimensions(),
+        ImageFormat::GIF  => gif::Decoder::new(reader).dimensions(),
+        ImageFormat::BMP  => bmp::BMPDecoder::new(reader).dimensions(),
+        ImageFormat::TGA  => tga::TGADecoder::new(reader).dimensions(),
+        ImageFormat::WEBP => webp::WebpDecoder::new(reader).dimensions(),
+        ImageFormat::TIFF => tiff::TIFFDecoder::new(reader).and_then(|mut decoder| decoder.dimensions()),
+        _                 => return None,
+    };
+    dimensions.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "fs")]
+    use std::env;
+    #[cfg(feature = "fs")]
+    use std::fs::File;
+
+    use image::{self, DynamicImage};
+
+    use super::*;
+
+    #[test]
+    fn test_header_dimensions() {
+        let image = DynamicImage::new_rgb8(48, 24);
+        let limits = DecodeLimits { max_width: 32, ..DecodeLimits::default() };
+        for &format in [ImageFormat::PNG, ImageFormat::JPEG].iter() {
+            let mut data = Vec::new();
+            image.save(&mut data, format).unwrap();
+            assert_eq!(header_dimensions(&data, format), Some((48, 24)));
+
+            match decode_image(&data, Path::new("wide"), Some(&limits)) {
+                Err(AtlasError::ImageTooLarge(_, (48, 24), _)) => (),
+                _ => panic!("expected {:?} to be rejected by its header", format),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_missing_file() {
+        match import_image(Path::new("nonexistent.png"), None) {
+            Err(AtlasError::Io(ref path, _)) => assert_eq!(path, Path::new("nonexistent.png")),
+            _ => panic!("expected an I/O error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_sniff_format() {
+        let mut path = env::temp_dir();
+        path.push("texture_atlas_decode_sniff.jpg");
+        {
+            let mut file = File::create(&path).unwrap();
+            DynamicImage::new_rgba8(8, 8).save(&mut file, image::PNG).unwrap();
+        }
+
+        assert_eq!(import_image(&path, None).unwrap().dimensions(), (8, 8));
+        assert_eq!(sniff_format(b"GIF89a..."), Some(ImageFormat::GIF));
+        assert_eq!(sniff_format(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_tga() {
+        // An uncompressed true-color TGA, 2x1 with the origin at the top
+        // left, with pixels stored as BGR.
+        let mut data = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 1, 0, 24, 0x20];
+        data.extend_from_slice(&[0, 0, 255, 255, 0, 0]);
+        assert_eq!(sniff_format(&data), None);
+
+        let image = decode_image(&data, Path::new("tiles/door.TGA"), None).unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0).data, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).data, [0, 0, 255, 255]);
+
+        assert!(decode_image(&data, Path::new("tiles/door.png"), None).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = DecodeLimits {
+            max_width: 64,
+            max_height: 64,
+            max_bytes: 32 * 32 * 4,
+        };
+        assert!(limits.allows(32, 32));
+        assert!(!limits.allows(65, 1));
+        assert!(!limits.allows(64, 64));
+    }
+}
@@ -0,0 +1,228 @@
+//! Content hashing for cache checks, page file names and bundle manifests.
+//! Algorithms are picked by feature: `blake3`, `sha2` or the older `sha3`,
+//! preferred in that order when several are enabled.
+//!
+//! Recorded hashes are tagged with the algorithm that made them, as
+//! `algorithm:hex`, and are checked with that same algorithm if it's
+//! enabled, so caches stay valid when the preferred one changes. Untagged
+//! hashes were made by older versions with SHA3-256.
+
+#[cfg(feature = "blake3")]
+use blake3;
+#[cfg(feature = "sha3")]
+use crypto::digest::Digest as CryptoDigest;
+#[cfg(feature = "sha3")]
+use crypto::sha3::Sha3;
+#[cfg(feature = "sha2")]
+use sha2::{self, Digest as Sha2Digest};
+
+#[cfg(not(any(feature = "blake3", feature = "sha2", feature = "sha3")))]
+compile_error!("texture_atlas needs one of the blake3, sha2 or sha3 features for hashing");
+
+/// The algorithm new hashes are made with.
+#[cfg(feature = "blake3")]
+pub const DEFAULT_ALGORITHM: &'static str = "blake3";
+#[cfg(all(feature = "sha2", not(feature = "blake3")))]
+pub const DEFAULT_ALGORITHM: &'static str = "sha256";
+#[cfg(all(feature = "sha3", not(feature = "sha2"), not(feature = "blake3")))]
+pub const DEFAULT_ALGORITHM: &'static str = "sha3-256";
+
+/// What untagged hashes were made with.
+const LEGACY_ALGORITHM: &'static str = "sha3-256";
+
+/// A hash function being fed data.
+pub trait ContentDigest {
+    /// Bytes the function consumes at a time, which HMAC pads keys to.
+    fn block_size(&self) -> usize;
+    fn update(&mut self, data: &[u8]);
+    /// Gets the hash of everything given to `update`.
+    fn finish(&mut self) -> Vec<u8>;
+}
+
+#[cfg(feature = "blake3")]
+struct Blake3Digest(blake3::Hasher);
+
+#[cfg(feature = "blake3")]
+impl ContentDigest for Blake3Digest {
+    fn block_size(&self) -> usize {
+        64
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.0.finalize().as_bytes().to_vec()
+    }
+}
+
+#[cfg(feature = "sha2")]
+struct Sha256Digest(sha2::Sha256);
+
+#[cfg(feature = "sha2")]
+impl ContentDigest for Sha256Digest {
+    fn block_size(&self) -> usize {
+        64
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        Sha2Digest::update(&mut self.0, data);
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.0.clone().finalize().to_vec()
+    }
+}
+
+#[cfg(feature = "sha3")]
+struct Sha3Digest(Sha3);
+
+#[cfg(feature = "sha3")]
+impl ContentDigest for Sha3Digest {
+    fn block_size(&self) -> usize {
+        self.0.block_size()
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.input(data);
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let mut out = vec![0; self.0.output_bytes()];
+        self.0.result(&mut out);
+        out
+    }
+}
+
+/// Starts a hash with `algorithm`, or returns `None` if it isn't known or
+/// its feature is off.
+pub fn new_digest(algorithm: &str) -> Option<Box<dyn ContentDigest>> {
+    match algorithm {
+        #[cfg(feature = "blake3")]
+        "blake3"   => Some(Box::new(Blake3Digest(blake3::Hasher::new()))),
+        #[cfg(feature = "sha2")]
+        "sha256"   => Some(Box::new(Sha256Digest(sha2::Sha256::new()))),
+        #[cfg(feature = "sha3")]
+        "sha3-256" => Some(Box::new(Sha3Digest(Sha3::sha3_256()))),
+        _          => None,
+    }
+}
+
+fn default_digest() -> Box<dyn ContentDigest> {
+    new_digest(DEFAULT_ALGORITHM).unwrap()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hashes `parts` one after another with the default algorithm, untagged.
+/// For names, which only need to differ, not to be checked later.
+pub fn hash_hex(parts: &[&[u8]]) -> String {
+    let mut digest = default_digest();
+    for part in parts.iter() {
+        digest.update(part);
+    }
+    to_hex(&digest.finish())
+}
+
+/// Hashes `data` with the default algorithm, tagged with its name.
+pub fn tagged_hash(data: &[u8]) -> String {
+    format!("{}:{}", DEFAULT_ALGORITHM, hash_hex(&[data]))
+}
+
+/// Splits a recorded hash into its algorithm and hex digest.
+fn split_tag(recorded: &str) -> (&str, &str) {
+    match recorded.find(':') {
+        Some(i) => (&recorded[..i], &recorded[i + 1..]),
+        None    => (LEGACY_ALGORITHM, recorded),
+    }
+}
+
+/// Checks that `recorded`, from `tagged_hash`, is the hash of `data`. Always
+/// false if the algorithm it was made with isn't enabled.
+pub fn matches(recorded: &str, data: &[u8]) -> bool {
+    let (algorithm, hex) = split_tag(recorded);
+    match new_digest(algorithm) {
+        Some(mut digest) => {
+            digest.update(data);
+            constant_time_eq(to_hex(&digest.finish()).as_bytes(), hex.as_bytes())
+        },
+        None => false,
+    }
+}
+
+/// Computes the HMAC of `message` under `key` with `algorithm`.
+pub fn hmac(algorithm: &str, key: &[u8], message: &[u8]) -> Option<Vec<u8>> {
+    let (mut inner, mut outer) = match (new_digest(algorithm), new_digest(algorithm)) {
+        (Some(inner), Some(outer)) => (inner, outer),
+        _                          => return None,
+    };
+
+    let block_size = inner.block_size();
+    let mut key = if key.len() > block_size {
+        let mut digest = new_digest(algorithm).unwrap();
+        digest.update(key);
+        digest.finish()
+    } else {
+        key.to_vec()
+    };
+    key.resize(block_size, 0);
+
+    let inner_pad: Vec<u8> = key.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = key.iter().map(|b| b ^ 0x5c).collect();
+
+    inner.update(&inner_pad);
+    inner.update(message);
+    outer.update(&outer_pad);
+    outer.update(&inner.finish());
+    Some(outer.finish())
+}
+
+/// Signs `message` with the default algorithm, tagged with its name.
+pub fn tagged_hmac(key: &[u8], message: &[u8]) -> String {
+    format!("{}:{}", DEFAULT_ALGORITHM, to_hex(&hmac(DEFAULT_ALGORITHM, key, message).unwrap()))
+}
+
+/// Checks a signature from `tagged_hmac`, in time independent of where it
+/// first differs.
+pub fn verify_hmac(signature: &str, key: &[u8], message: &[u8]) -> bool {
+    let (algorithm, hex) = split_tag(signature);
+    match hmac(algorithm, key, message) {
+        Some(expected) => constant_time_eq(to_hex(&expected).as_bytes(), hex.as_bytes()),
+        None           => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tagged_hashes() {
+        let hash = tagged_hash(b"tiles");
+        assert!(hash.starts_with(DEFAULT_ALGORITHM));
+        assert!(matches(&hash, b"tiles"));
+        assert!(!matches(&hash, b"tiles!"));
+        assert!(!matches("md5:d41d8cd98f00b204e9800998ecf8427e", b""));
+
+        let signature = tagged_hmac(b"key", b"manifest");
+        assert!(verify_hmac(&signature, b"key", b"manifest"));
+        assert!(!verify_hmac(&signature, b"other key", b"manifest"));
+    }
+
+    #[test]
+    #[cfg(feature = "sha3")]
+    fn test_legacy_sha3() {
+        // SHA3-256 of the empty string, as older versions recorded it.
+        assert!(matches("a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a", b""));
+    }
+}
@@ -0,0 +1,109 @@
+//! Drawing atlas regions in egui, enabled with the `egui` feature. Each page
+//! is registered with the painter once, and tiles and frames are then looked
+//! up as the texture and UV rect `egui::Image` takes.
+
+use egui::{self, TextureId};
+
+use atlas::Atlas;
+use backend::PageTexture;
+use error::{AtlasError, AtlasResult};
+use ids::{PageId, TileId};
+use tile_atlas::TileAtlas;
+
+/// The egui textures of every page of an atlas.
+pub struct EguiTextures {
+    pages: Vec<TextureId>,
+}
+
+impl EguiTextures {
+    /// Registers every page of `atlas` by calling `register` with it, which
+    /// should hand the texture to the egui painter in use and return the id
+    /// it was given. With `egui_glium`, that's `Painter::register_native_texture`;
+    /// painters of other GL versions can be given the page's
+    /// `TileAtlas::gl_texture_id`.
+    pub fn register<T, F>(atlas: &TileAtlas<T>, mut register: F) -> Self
+        where T: PageTexture,
+              F: FnMut(PageId, &T) -> TextureId
+    {
+        let pages = (0..atlas.passes())
+            .map(|page| register(PageId(page), atlas.get_texture(page)))
+            .collect();
+
+        EguiTextures {
+            pages: pages,
+        }
+    }
+
+    pub fn page<P: Into<PageId>>(&self, page: P) -> TextureId {
+        let PageId(page) = page.into();
+        self.pages[page]
+    }
+
+    /// Gets the texture and UV rect of a tile as it's drawn `msecs` into its
+    /// animation. An `egui::Image` can't turn its UVs, so tiles of atlases
+    /// built with `set_allow_rotation` come out a quarter turn clockwise
+    /// unless the image is rotated back.
+    pub fn tile<T, I>(&self, atlas: &TileAtlas<T>, tile_type: I, msecs: u64) -> AtlasResult<(TextureId, egui::Rect)>
+        where T: PageTexture,
+              I: Into<TileId>
+    {
+        let info = atlas.try_get_tile_draw_info(tile_type, msecs)?;
+        Ok((self.page(info.page), uv_rect(atlas.flip_y(), info.uv_offset, info.uv_scale)))
+    }
+
+    /// Gets the texture and UV rect of the whole frame added under `key`.
+    pub fn frame<T: PageTexture>(&self, atlas: &TileAtlas<T>, key: &str) -> AtlasResult<(TextureId, egui::Rect)> {
+        let uv = atlas.frame_uv(key).ok_or_else(|| AtlasError::MissingFrame(key.to_string()))?;
+        Ok((self.page(uv.page), uv_rect(atlas.flip_y(), uv.uv_offset, uv.uv_scale)))
+    }
+}
+
+/// Turns a UV offset from the top left and a UV size into the rect egui
+/// samples. Pages of atlases that `flip_y` are stored bottom row first, so
+/// their rects are mirrored, with `min` below `max`, which draws the region
+/// upright.
+fn uv_rect(flip_y: bool, (u, v): (f32, f32), scale: [f32; 2]) -> egui::Rect {
+    if flip_y {
+        egui::Rect::from_min_max(egui::pos2(u, 1.0 - v), egui::pos2(u + scale[0], 1.0 - v - scale[1]))
+    } else {
+        egui::Rect::from_min_size(egui::pos2(u, v), egui::vec2(scale[0], scale[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_egui_uv_rects() {
+        for &flip_y in [false, true].iter() {
+            let mut builder = TileAtlasBuilder::new();
+            builder.set_flip_y(flip_y);
+            builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+            builder.add_tile("a", 0, (1, 0)).unwrap();
+            let atlas = builder.build(&MemoryBackend, None).unwrap();
+
+            let textures = EguiTextures::register(&atlas, |PageId(page), _| TextureId::User(page as u64 + 7));
+            let (texture, uv) = textures.tile(&atlas, 0, 0).unwrap();
+            let info = atlas.get_tile_draw_info(0, 0);
+            let (u, v) = info.uv_offset;
+            let (sw, sh) = (info.uv_scale[0], info.uv_scale[1]);
+            assert_eq!(texture, TextureId::User(7));
+            if flip_y {
+                assert_eq!((uv.min, uv.max), (egui::pos2(u, 1.0 - v), egui::pos2(u + sw, 1.0 - v - sh)));
+            } else {
+                assert_eq!((uv.min, uv.max), (egui::pos2(u, v), egui::pos2(u + sw, v + sh)));
+            }
+
+            let (w, h) = atlas.get_texture(0).dimensions();
+            let (_, uv) = textures.frame(&atlas, "a").unwrap();
+            assert_eq!(uv.width(), 48.0 / w as f32);
+            assert_eq!(uv.height().abs(), 24.0 / h as f32);
+            assert!(textures.frame(&atlas, "b").is_err());
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use bincode;
+use toml;
+
+use decode::DecodeLimits;
+use tile_atlas::TileIndex;
+
+#[derive(Debug)]
+pub enum AtlasError {
+    /// A file couldn't be read or written.
+    Io(PathBuf, io::Error),
+    /// A `[[maps]]` entry points at a file that doesn't exist. Holds the
+    /// missing file, the config it was listed in, if known, and the index of
+    /// the entry.
+    MissingMapFile(PathBuf, Option<PathBuf>, usize),
+    /// An image couldn't be decoded.
+    Image(PathBuf, String),
+    /// An image is larger than the configured decode limits.
+    ImageTooLarge(PathBuf, (u32, u32), DecodeLimits),
+    /// A frame couldn't be packed or exported.
+    Pack(String, String),
+    /// A frame is too large to fit on a page. Holds the frame, its size and
+    /// the page size.
+    FrameTooLarge(String, (u32, u32), u32),
+    /// A texture page couldn't be created on the GPU.
+    Texture(String),
+    /// An atlas config is malformed.
+    Config(String),
+    /// A value in an atlas config is missing or has the wrong type.
+    ConfigValue(ConfigDiagnostic),
+    /// An atlas config loaded with unknown keys rejected has one. The
+    /// diagnostic's `expected` lists the valid keys.
+    UnknownConfigKey(ConfigDiagnostic),
+    /// A cached atlas couldn't be read or written.
+    Cache(String),
+    /// No frame was added under the given key.
+    MissingFrame(String),
+    /// No tile was added with the given index.
+    MissingTile(TileIndex),
+    /// A tile was released more times than it was retained.
+    NotRetained(TileIndex),
+    /// A tile's offset, or one of its animation frames, reaches past the edge
+    /// of its frame. Holds the tile, the frame, the pixel range the tile
+    /// would cover and the size of the frame.
+    TileOutOfBounds(TileIndex, String, ((u32, u32), (u32, u32)), (u32, u32)),
+    /// An animated tile has no frames or no delay between them. Holds the
+    /// tile, its frame count and its delay.
+    InvalidAnimation(TileIndex, u64, u64),
+    /// A tile index was added more than once.
+    DuplicateTile(TileIndex),
+    /// A frame key was added more than once while duplicates are errors.
+    DuplicateFrame(String),
+    /// A path resolves outside of the allowed root.
+    PathEscapesRoot(String, PathBuf),
+    /// The operation was cancelled before it finished.
+    Cancelled,
+    /// Several errors collected while loading or building an atlas, in the
+    /// order they were found.
+    Multiple(Vec<AtlasError>),
+}
+
+pub type AtlasResult<T> = Result<T, AtlasError>;
+
+/// Where and why a config value failed to load.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigDiagnostic {
+    pub file: Option<PathBuf>,
+    /// Line and column, both starting at 1, of the offending key, or of the
+    /// header of its entry if the key is missing.
+    pub position: Option<(usize, usize)>,
+    /// The array of tables the value is in, like `maps`, and the index of
+    /// the entry in it.
+    pub entry: Option<(String, usize)>,
+    pub key: String,
+    pub expected: String,
+    /// The type of the value found instead, or `None` if it was missing.
+    pub found: Option<String>,
+}
+
+impl ConfigDiagnostic {
+    fn write_location(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        if let Some((line, column)) = self.position {
+            write!(f, "{}:{}:", line, column)?;
+        }
+        if self.file.is_some() || self.position.is_some() {
+            write!(f, " ")?;
+        }
+        if let Some((ref table, idx)) = self.entry {
+            write!(f, "[[{}]] entry {}: ", table, idx)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_location(f)?;
+        match self.found {
+            Some(ref found) => write!(f, "expected {} to be {}, found {}", self.key, self.expected, found),
+            None            => write!(f, "expected {} to be {}, but it's missing", self.key, self.expected),
+        }
+    }
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AtlasError::Io(ref path, ref err) =>
+                write!(f, "I/O error on {}: {}", path.display(), err),
+            AtlasError::MissingMapFile(ref path, ref config, index) => {
+                let config = config.as_ref()
+                    .map(|c| c.display().to_string())
+                    .unwrap_or_else(|| "<unnamed config>".to_string());
+                write!(f, "Map {} in {} points to {}, which doesn't exist", index, config, path.display())
+            },
+            AtlasError::Image(ref path, ref err) =>
+                write!(f, "Couldn't decode image {}: {}", path.display(), err),
+            AtlasError::ImageTooLarge(ref path, (w, h), ref limits) =>
+                write!(f, "Image {} is {}x{}, which exceeds the decode limits {:?}",
+                       path.display(), w, h, limits),
+            AtlasError::Pack(ref key, ref err) =>
+                write!(f, "Couldn't pack frame {}: {}", key, err),
+            AtlasError::FrameTooLarge(ref key, (w, h), page_size) =>
+                write!(f, "Frame {} is {}x{}, which doesn't fit on a {}x{} page; \
+                           raise the page size or split the image into smaller frames",
+                       key, w, h, page_size, page_size),
+            AtlasError::Texture(ref err) =>
+                write!(f, "Couldn't create texture: {}", err),
+            AtlasError::Config(ref err) =>
+                write!(f, "Invalid atlas config: {}", err),
+            AtlasError::ConfigValue(ref diagnostic) =>
+                write!(f, "Invalid atlas config: {}", diagnostic),
+            AtlasError::UnknownConfigKey(ref diagnostic) => {
+                write!(f, "Invalid atlas config: ")?;
+                diagnostic.write_location(f)?;
+                write!(f, "unknown key {}, expected {}", diagnostic.key, diagnostic.expected)
+            },
+            AtlasError::Cache(ref err) =>
+                write!(f, "Invalid atlas cache: {}", err),
+            AtlasError::MissingFrame(ref key) =>
+                write!(f, "No frame named {}", key),
+            AtlasError::MissingTile(index) =>
+                write!(f, "No tile with index {}", index),
+            AtlasError::NotRetained(index) =>
+                write!(f, "Tile {} was released without being retained", index),
+            AtlasError::TileOutOfBounds(index, ref key, (start, end), (w, h)) =>
+                write!(f, "Tile {} in {} covers pixels {:?} to {:?}, outside of the {}x{} frame",
+                       index, key, start, end, w, h),
+            AtlasError::InvalidAnimation(index, frame_count, delay) =>
+                write!(f, "Tile {} has an animation of {} frames {} ms apart, both must be above 0",
+                       index, frame_count, delay),
+            AtlasError::DuplicateTile(index) =>
+                write!(f, "Tile index {} was added twice", index),
+            AtlasError::DuplicateFrame(ref key) =>
+                write!(f, "Frame {} was added twice", key),
+            AtlasError::PathEscapesRoot(ref path, ref root) =>
+                write!(f, "Path {} escapes the allowed root {}", path, root.display()),
+            AtlasError::Cancelled =>
+                write!(f, "Cancelled"),
+            AtlasError::Multiple(ref errors) => {
+                write!(f, "{} errors:", errors.len())?;
+                for err in errors.iter() {
+                    write!(f, "\n  {}", err)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl Error for AtlasError {
+    fn description(&self) -> &str {
+        match *self {
+            AtlasError::Io(..) => "I/O error",
+            AtlasError::MissingMapFile(..) => "missing map file",
+            AtlasError::Image(..) => "image decoding error",
+            AtlasError::ImageTooLarge(..) => "image too large",
+            AtlasError::Pack(..) => "packing error",
+            AtlasError::FrameTooLarge(..) => "frame too large",
+            AtlasError::Texture(..) => "texture creation error",
+            AtlasError::Config(..) => "invalid atlas config",
+            AtlasError::ConfigValue(..) => "invalid atlas config value",
+            AtlasError::UnknownConfigKey(..) => "unknown atlas config key",
+            AtlasError::Cache(..) => "invalid atlas cache",
+            AtlasError::MissingFrame(..) => "missing frame",
+            AtlasError::MissingTile(..) => "missing tile",
+            AtlasError::NotRetained(..) => "tile not retained",
+            AtlasError::TileOutOfBounds(..) => "tile out of bounds",
+            AtlasError::InvalidAnimation(..) => "invalid animation",
+            AtlasError::DuplicateTile(..) => "duplicate tile",
+            AtlasError::DuplicateFrame(..) => "duplicate frame",
+            AtlasError::PathEscapesRoot(..) => "path escapes root",
+            AtlasError::Cancelled => "cancelled",
+            AtlasError::Multiple(..) => "multiple errors",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            AtlasError::Io(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// io::Error can't be cloned, so copies of I/O errors keep only the kind and
+// message of the original.
+impl Clone for AtlasError {
+    fn clone(&self) -> AtlasError {
+        match *self {
+            AtlasError::Io(ref path, ref err) =>
+                AtlasError::Io(path.clone(), io::Error::new(err.kind(), err.to_string())),
+            AtlasError::MissingMapFile(ref path, ref config, index) =>
+                AtlasError::MissingMapFile(path.clone(), config.clone(), index),
+            AtlasError::Image(ref path, ref err) => AtlasError::Image(path.clone(), err.clone()),
+            AtlasError::ImageTooLarge(ref path, size, limits) => AtlasError::ImageTooLarge(path.clone(), size, limits),
+            AtlasError::Pack(ref key, ref err) => AtlasError::Pack(key.clone(), err.clone()),
+            AtlasError::FrameTooLarge(ref key, size, page_size) => AtlasError::FrameTooLarge(key.clone(), size, page_size),
+            AtlasError::Texture(ref err) => AtlasError::Texture(err.clone()),
+            AtlasError::Config(ref err) => AtlasError::Config(err.clone()),
+            AtlasError::ConfigValue(ref diagnostic) => AtlasError::ConfigValue(diagnostic.clone()),
+            AtlasError::UnknownConfigKey(ref diagnostic) => AtlasError::UnknownConfigKey(diagnostic.clone()),
+            AtlasError::Cache(ref err) => AtlasError::Cache(err.clone()),
+            AtlasError::MissingFrame(ref key) => AtlasError::MissingFrame(key.clone()),
+            AtlasError::MissingTile(index) => AtlasError::MissingTile(index),
+            AtlasError::NotRetained(index) => AtlasError::NotRetained(index),
+            AtlasError::TileOutOfBounds(index, ref key, range, size) =>
+                AtlasError::TileOutOfBounds(index, key.clone(), range, size),
+            AtlasError::InvalidAnimation(index, frame_count, delay) => AtlasError::InvalidAnimation(index, frame_count, delay),
+            AtlasError::DuplicateTile(index) => AtlasError::DuplicateTile(index),
+            AtlasError::DuplicateFrame(ref key) => AtlasError::DuplicateFrame(key.clone()),
+            AtlasError::PathEscapesRoot(ref path, ref root) => AtlasError::PathEscapesRoot(path.clone(), root.clone()),
+            AtlasError::Cancelled => AtlasError::Cancelled,
+            AtlasError::Multiple(ref errors) => AtlasError::Multiple(errors.clone()),
+        }
+    }
+}
+
+impl From<toml::de::Error> for AtlasError {
+    fn from(err: toml::de::Error) -> AtlasError {
+        AtlasError::Config(err.to_string())
+    }
+}
+
+impl From<bincode::Error> for AtlasError {
+    fn from(err: bincode::Error) -> AtlasError {
+        AtlasError::Cache(err.to_string())
+    }
+}
@@ -0,0 +1,136 @@
+//! Sample frames drawn in code, and builders set up with them, enabled with
+//! the `fixtures` feature. For tests and CI jobs that need to run the whole
+//! pack, build and UV path without any art assets on disk.
+//!
+//! Every tile of a sheet is filled with `tile_color` of its index, so a test
+//! can check that a tile's UVs land on the right pixels by sampling the
+//! built page with `tile_center_pixel`.
+
+use image::{DynamicImage, GenericImage, Rgba};
+
+use backend::MemoryBackend;
+use error::AtlasResult;
+use ids::TileId;
+use texture_atlas::TextureAtlasBuilder;
+use tile_atlas::{TileAtlas, TileAtlasBuilder, TileKind};
+
+/// The number of tiles `terrain_builder` adds.
+pub const TERRAIN_TILES: u32 = 16;
+
+/// Gets the color tile `index` of a sheet is filled with. Distinct for the
+/// first 4096 indices.
+pub fn tile_color(index: u32) -> Rgba<u8> {
+    let step = |n: u32| (n % 16 * 12) as u8;
+    Rgba { data: [64 + step(index), 64 + step(index / 16), 255 - step(index / 256), 255] }
+}
+
+/// Draws a sheet of `cols` by `rows` tiles, each filled with `tile_color` of
+/// its index counting left to right, top to bottom, and outlined in a darker
+/// shade of it.
+pub fn tile_sheet(cols: u32, rows: u32, tile_size: (u32, u32)) -> DynamicImage {
+    let (tw, th) = tile_size;
+    let mut image = DynamicImage::new_rgba8(cols * tw, rows * th);
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let fill = tile_color(row * cols + col);
+            let outline = Rgba { data: [fill.data[0] / 2, fill.data[1] / 2, fill.data[2] / 2, 255] };
+            for y in 0..th {
+                for x in 0..tw {
+                    let edge = x == 0 || y == 0 || x == tw - 1 || y == th - 1;
+                    image.put_pixel(col * tw + x, row * th + y, if edge { outline } else { fill });
+                }
+            }
+        }
+    }
+
+    image
+}
+
+/// Draws a checkerboard of `cell` pixel squares in two colors.
+pub fn checkerboard(size: (u32, u32), cell: u32, a: Rgba<u8>, b: Rgba<u8>) -> DynamicImage {
+    let mut image = DynamicImage::new_rgba8(size.0, size.1);
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let color = if (x / cell + y / cell) % 2 == 0 { a } else { b };
+            image.put_pixel(x, y, color);
+        }
+    }
+    image
+}
+
+/// A builder with one 4x4 sheet of 16 pixel tiles under the key `"terrain"`,
+/// added as tiles 0 to 15 in sheet order.
+pub fn terrain_builder() -> TileAtlasBuilder {
+    let mut builder = TileAtlasBuilder::new();
+    builder.add_frame_image("terrain", tile_sheet(4, 4, (16, 16)), (16, 16)).unwrap();
+    for index in 0..TERRAIN_TILES {
+        builder.add_tile("terrain", index, (index % 4, index / 4)).unwrap();
+    }
+    builder
+}
+
+/// A builder with frames of several sizes, for exercising packing:
+///
+/// - tile 0, `"hero"`: a 4 frame animation of 24x32 tiles, 100ms a frame;
+/// - tile 1, `"chest"`: a single 32x32 tile;
+/// - tile 2, `"button"`: a single 64x16 tile.
+pub fn sprite_builder() -> TileAtlasBuilder {
+    let mut builder = TileAtlasBuilder::new();
+    builder.add_frame_image("hero", tile_sheet(4, 1, (24, 32)), (24, 32)).unwrap();
+    builder.add_frame_image("chest", tile_sheet(1, 1, (32, 32)), (32, 32)).unwrap();
+    builder.add_frame_image("button", tile_sheet(1, 1, (64, 16)), (64, 16)).unwrap();
+    builder.add_tile_with_kind("hero", 0, (0, 0), TileKind::Animated(4, 100)).unwrap();
+    builder.add_tile("chest", 1, (0, 0)).unwrap();
+    builder.add_tile("button", 2, (0, 0)).unwrap();
+    builder
+}
+
+/// A single page builder with a checkerboard under `"checker"` and two tile
+/// sheets under `"small"` and `"wide"`.
+pub fn texture_atlas_builder() -> AtlasResult<TextureAtlasBuilder> {
+    let mut builder = TextureAtlasBuilder::new();
+    let white = Rgba { data: [255, 255, 255, 255] };
+    let black = Rgba { data: [0, 0, 0, 255] };
+    builder.add_texture_image("checker", checkerboard((32, 32), 4, white, black))?;
+    builder.add_texture_image("small", tile_sheet(2, 2, (8, 8)))?;
+    builder.add_texture_image("wide", tile_sheet(4, 1, (16, 8)))?;
+    Ok(builder)
+}
+
+/// Builds `terrain_builder` into pages in main memory.
+pub fn terrain_atlas() -> TileAtlas<DynamicImage> {
+    terrain_builder().build(&MemoryBackend, None).unwrap()
+}
+
+/// Reads the page pixel at the center of `tile_type`'s UV rect as it's drawn
+/// `msecs` into its animation. For an untouched sheet from `tile_sheet`,
+/// that's `tile_color` of the tile's position on it.
+pub fn tile_center_pixel<I: Into<TileId>>(atlas: &TileAtlas<DynamicImage>, tile_type: I, msecs: u64) -> AtlasResult<Rgba<u8>> {
+    let info = atlas.try_get_tile_draw_info(tile_type, msecs)?;
+    let page = atlas.get_texture(info.page);
+    let (w, h) = GenericImage::dimensions(page);
+    let x = (info.uv_offset.0 + info.uv_scale[0] / 2.0) * w as f32;
+    let y = (info.uv_offset.1 + info.uv_scale[1] / 2.0) * h as f32;
+    Ok(page.get_pixel(x as u32, y as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_tile_colors() {
+        let atlas = terrain_atlas();
+        for index in 0..TERRAIN_TILES {
+            assert_eq!(tile_center_pixel(&atlas, index, 0).unwrap(), tile_color(index));
+        }
+
+        let sprites = sprite_builder().build(&MemoryBackend, None).unwrap();
+        assert_eq!(tile_center_pixel(&sprites, 0, 250).unwrap(), tile_color(2));
+        assert_eq!(sprites.get_tile_draw_info(2, 0).pixel_size, (64, 16));
+
+        let single = texture_atlas_builder().unwrap().build_cpu(None).unwrap();
+        assert_eq!(single.frames().len(), 3);
+    }
+}
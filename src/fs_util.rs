@@ -0,0 +1,90 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use error::{AtlasError, AtlasResult};
+
+/// Writes `data` to `path` through a temporary file in the same directory,
+/// which is then renamed over `path`. A crash partway through leaves either
+/// the old file or the new one, never a truncated mix.
+pub fn write_atomic(path: &Path, data: &[u8]) -> AtlasResult<()> {
+    let temp_path = temp_path_for(path);
+
+    let result = write_synced(&temp_path, data)
+        .and_then(|_| fs::rename(&temp_path, path).map_err(|e| AtlasError::Io(path.to_path_buf(), e)));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> AtlasResult<()> {
+    let mut file = File::create(path).map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+    file.write_all(data).map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+    file.sync_all().map_err(|e| AtlasError::Io(path.to_path_buf(), e))
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    with_suffix(path, ".tmp")
+}
+
+/// Gets a path next to `path`, named like it with `suffix` added on.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Puts the folder `staging` in place of `target`, which is moved aside
+/// first and only removed once `staging` took its place, so a failure
+/// partway through leaves the old folder or the new one whole.
+pub fn replace_dir(staging: &Path, target: &Path) -> AtlasResult<()> {
+    let old = with_suffix(target, ".old");
+    if old.exists() {
+        fs::remove_dir_all(&old).map_err(|e| AtlasError::Io(old.clone(), e))?;
+    }
+
+    let had_target = target.exists();
+    if had_target {
+        fs::rename(target, &old).map_err(|e| AtlasError::Io(target.to_path_buf(), e))?;
+    }
+
+    if let Err(e) = fs::rename(staging, target) {
+        if had_target {
+            let _ = fs::rename(&old, target);
+        }
+        return Err(AtlasError::Io(target.to_path_buf(), e));
+    }
+
+    if had_target {
+        fs::remove_dir_all(&old).map_err(|e| AtlasError::Io(old.clone(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn test_write_atomic() {
+        let path = env::temp_dir().join("texture_atlas_write_atomic.bin");
+
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+
+        let mut contents = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"new");
+        assert!(!temp_path_for(&path).exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+//! Golden-image helpers, enabled with the `golden` feature. Tiles are drawn
+//! into an offscreen target through a headless context and read back, so UV
+//! math changes can be checked against known-good images.
+//!
+//! Pages are stored compressed on the GPU, so comparisons take a per-channel
+//! tolerance instead of requiring exact matches.
+
+use std::env;
+use std::path::Path;
+
+use glium::{self, DisplayBuild, Surface};
+use glium::backend::Facade;
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::glutin::HeadlessRendererBuilder;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{MipmapsOption, RawImage2d, SrgbFormat, SrgbTexture2d};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use image::{self, ImageBuffer, RgbaImage};
+use image::imageops;
+
+use Texture2d;
+use error::{AtlasError, AtlasResult};
+use tile_atlas::{self, TileAtlas, TileIndex};
+
+/// Set this environment variable to overwrite golden files with the current
+/// output instead of comparing against them.
+pub const UPDATE_GOLDEN_VAR: &'static str = "UPDATE_GOLDEN";
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+
+implement_vertex!(Vertex, position, tex_coords);
+
+const VERTEX_SHADER: &'static str = r#"
+    #version 140
+
+    in vec2 position;
+    in vec2 tex_coords;
+    out vec2 v_tex_coords;
+
+    void main() {
+        v_tex_coords = tex_coords;
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+"#;
+
+const FRAGMENT_SHADER: &'static str = r#"
+    #version 140
+
+    uniform sampler2D tex;
+    in vec2 v_tex_coords;
+    out vec4 color;
+
+    void main() {
+        color = texture(tex, v_tex_coords);
+    }
+"#;
+
+/// Opens a headless context of the given size to render into.
+pub fn headless_display(width: u32, height: u32) -> AtlasResult<GlutinFacade> {
+    HeadlessRendererBuilder::new(width, height)
+        .build_glium()
+        .map_err(|e| AtlasError::Texture(format!("{:?}", e)))
+}
+
+/// Renders a tile as it would be drawn `msecs` into its animation, at its
+/// native pixel size.
+pub fn render_tile<F: Facade>(display: &F, atlas: &TileAtlas, tile_type: TileIndex, msecs: u64) -> AtlasResult<RgbaImage> {
+    let info = atlas.get_tile_draw_info(tile_type, msecs);
+    let texture = atlas.get_texture(info.page);
+    render_quad(display, texture, info.uv_corners(), info.pixel_size)
+}
+
+/// Renders the area of `texture` starting at `uv_offset` and spanning
+/// `uv_scale`, both in the same top-left based coordinates as `DrawInfo`.
+pub fn render_region<F: Facade>(display: &F,
+                                texture: &Texture2d,
+                                uv_offset: (f32, f32),
+                                uv_scale: [f32; 2],
+                                size: (u32, u32)) -> AtlasResult<RgbaImage> {
+    render_quad(display, texture, tile_atlas::uv_corners(uv_offset, uv_scale, false), size)
+}
+
+/// Renders a quad with the UVs of its corners in the order
+/// `DrawInfo::uv_corners` gives them.
+fn render_quad<F: Facade>(display: &F,
+                          texture: &Texture2d,
+                          corners: [(f32, f32); 4],
+                          size: (u32, u32)) -> AtlasResult<RgbaImage> {
+    let to_err = |e: &::std::fmt::Debug| AtlasError::Texture(format!("{:?}", e));
+
+    // pages are uploaded upside down, so flip the V coordinate back.
+    let tex = |i: usize| [corners[i].0, 1.0 - corners[i].1];
+
+    let vertices = [
+        Vertex { position: [-1.0,  1.0], tex_coords: tex(0) },
+        Vertex { position: [ 1.0,  1.0], tex_coords: tex(1) },
+        Vertex { position: [-1.0, -1.0], tex_coords: tex(3) },
+        Vertex { position: [ 1.0, -1.0], tex_coords: tex(2) },
+    ];
+
+    let vertex_buffer = glium::VertexBuffer::new(display, &vertices).map_err(|e| to_err(&e))?;
+    let program = glium::Program::from_source(display, VERTEX_SHADER, FRAGMENT_SHADER, None)
+        .map_err(|e| to_err(&e))?;
+
+    let target = SrgbTexture2d::empty_with_format(display,
+                                                  SrgbFormat::U8U8U8U8,
+                                                  MipmapsOption::NoMipmap,
+                                                  size.0,
+                                                  size.1)
+        .map_err(|e| to_err(&e))?;
+
+    {
+        let mut framebuffer = SimpleFrameBuffer::new(display, &target).map_err(|e| to_err(&e))?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 0.0);
+
+        let uniforms = uniform! {
+            tex: texture.sampled()
+                .magnify_filter(MagnifySamplerFilter::Nearest)
+                .minify_filter(MinifySamplerFilter::Nearest),
+        };
+
+        framebuffer.draw(&vertex_buffer,
+                         &NoIndices(PrimitiveType::TriangleStrip),
+                         &program,
+                         &uniforms,
+                         &Default::default())
+            .map_err(|e| to_err(&e))?;
+    }
+
+    let raw: RawImage2d<u8> = target.read();
+    let (width, height) = (raw.width, raw.height);
+    let image: RgbaImage = match ImageBuffer::from_raw(width, height, raw.data.into_owned()) {
+        Some(image) => image,
+        None        => return Err(AtlasError::Texture("Read back a truncated framebuffer".to_string())),
+    };
+
+    // GL reads rows bottom to top.
+    Ok(imageops::flip_vertical(&image))
+}
+
+/// How far an image is from its golden counterpart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageDiff {
+    pub dimensions_match: bool,
+    /// Pixels with any channel differing by more than the tolerance.
+    pub mismatched_pixels: usize,
+    pub max_channel_delta: u8,
+}
+
+impl ImageDiff {
+    pub fn is_match(&self) -> bool {
+        self.dimensions_match && self.mismatched_pixels == 0
+    }
+}
+
+/// Compares two images channel by channel, allowing each channel to be off
+/// by up to `tolerance`.
+pub fn compare_images(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> ImageDiff {
+    if actual.dimensions() != expected.dimensions() {
+        return ImageDiff {
+            dimensions_match: false,
+            mismatched_pixels: 0,
+            max_channel_delta: 0,
+        };
+    }
+
+    let mut mismatched_pixels = 0;
+    let mut max_channel_delta = 0;
+
+    for (a, b) in actual.pixels().zip(expected.pixels()) {
+        let delta = a.data.iter().zip(b.data.iter())
+            .map(|(&x, &y)| if x > y { x - y } else { y - x })
+            .max()
+            .unwrap_or(0);
+
+        if delta > tolerance {
+            mismatched_pixels += 1;
+        }
+        if delta > max_channel_delta {
+            max_channel_delta = delta;
+        }
+    }
+
+    ImageDiff {
+        dimensions_match: true,
+        mismatched_pixels: mismatched_pixels,
+        max_channel_delta: max_channel_delta,
+    }
+}
+
+/// Compares `actual` against the golden image at `golden_path`. If the
+/// golden file doesn't exist yet or `UPDATE_GOLDEN` is set, `actual` is
+/// written there instead. On a mismatch the output is saved next to the
+/// golden file with an `.actual.png` suffix for inspection.
+pub fn check_golden(actual: &RgbaImage, golden_path: &Path, tolerance: u8) -> AtlasResult<ImageDiff> {
+    if env::var_os(UPDATE_GOLDEN_VAR).is_some() || !golden_path.exists() {
+        actual.save(golden_path).map_err(|e| AtlasError::Io(golden_path.to_path_buf(), e))?;
+        return Ok(compare_images(actual, actual, tolerance));
+    }
+
+    let expected = image::open(golden_path)
+        .map_err(|e| AtlasError::Image(golden_path.to_path_buf(), e.to_string()))?
+        .to_rgba();
+
+    let diff = compare_images(actual, &expected, tolerance);
+
+    if !diff.is_match() {
+        let actual_path = golden_path.with_extension("actual.png");
+        actual.save(&actual_path).map_err(|e| AtlasError::Io(actual_path.clone(), e))?;
+    }
+
+    Ok(diff)
+}
+
+/// Panics unless `actual` matches the golden image at `golden_path`. Meant
+/// to be called from tests.
+pub fn assert_golden(actual: &RgbaImage, golden_path: &Path, tolerance: u8) {
+    match check_golden(actual, golden_path, tolerance) {
+        Ok(ref diff) if diff.is_match() => (),
+        Ok(diff) => panic!("{} doesn't match its golden image: {:?}", golden_path.display(), diff),
+        Err(e)   => panic!("Couldn't check {} against its golden image: {}", golden_path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn test_compare_images() {
+        let expected = RgbaImage::from_pixel(4, 4, Rgba { data: [100, 100, 100, 255] });
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 2, Rgba { data: [104, 100, 100, 255] });
+
+        assert!(compare_images(&actual, &expected, 4).is_match());
+
+        let diff = compare_images(&actual, &expected, 3);
+        assert_eq!(diff.mismatched_pixels, 1);
+        assert_eq!(diff.max_channel_delta, 4);
+
+        let smaller = RgbaImage::from_pixel(2, 2, Rgba { data: [0, 0, 0, 0] });
+        assert!(!compare_images(&smaller, &expected, 255).is_match());
+    }
+}
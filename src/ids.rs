@@ -0,0 +1,202 @@
+//! Typed ids for tiles, pages, frames and contexts, so a page index can't
+//! be passed where a tile index is expected. Each converts from and into
+//! the plain value it wraps, and the atlas methods accept anything that
+//! converts, so code passing plain `usize`s and `&str`s keeps working while
+//! it migrates.
+
+use std::borrow::Borrow;
+use std::fmt;
+
+use tile_atlas::TileIndex;
+
+/// A tile type, as given to `TileAtlasBuilder::add_tile`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TileId(pub TileIndex);
+
+/// A texture page of an atlas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PageId(pub usize);
+
+/// The key a frame was added under. Keys may be namespaced as
+/// `namespace:name`, so content packs merged into one atlas can't clash.
+/// Keys that are paths with a drive, like `C:\art\grass.png`, aren't taken
+/// to be namespaced, and neither is anything before a `:` that isn't a
+/// plain name without path separators.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FrameKey(pub String);
+
+/// A rendering context an atlas's pages are uploaded to besides the one it
+/// was built with, such as the GL context of another window. The numbering
+/// is up to the caller. See `TileAtlas::add_context`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ContextId(pub u32);
+
+/// Separates the namespace of a frame key from its name.
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+impl FrameKey {
+    pub fn namespaced(namespace: &str, name: &str) -> FrameKey {
+        FrameKey(format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name))
+    }
+
+    /// Puts `key` in `namespace` unless it already names one.
+    pub fn resolve(namespace: Option<&str>, key: &str) -> FrameKey {
+        match namespace {
+            Some(namespace) if split_namespace(key).is_none() => FrameKey::namespaced(namespace, key),
+            _ => FrameKey(key.to_string()),
+        }
+    }
+
+    pub fn namespace(&self) -> Option<&str> {
+        split_namespace(&self.0).map(|(namespace, _)| namespace)
+    }
+
+    /// Gets the key without its namespace.
+    pub fn name(&self) -> &str {
+        match split_namespace(&self.0) {
+            Some((_, name)) => name,
+            None            => &self.0,
+        }
+    }
+}
+
+/// Splits a key into its namespace and name, or gets `None` if it has no
+/// namespace. A drive letter is part of a path, not a namespace.
+fn split_namespace(key: &str) -> Option<(&str, &str)> {
+    let i = match key.find(NAMESPACE_SEPARATOR) {
+        Some(i) => i,
+        None    => return None,
+    };
+
+    let (namespace, name) = (&key[..i], &key[i + 1..]);
+    let is_path = namespace.contains(|c: char| c == '/' || c == '\\');
+    let is_drive = namespace.len() == 1 && namespace.chars().all(|c| c.is_ascii_alphabetic()) &&
+        (name.starts_with('/') || name.starts_with('\\'));
+    if namespace.is_empty() || is_path || is_drive {
+        return None;
+    }
+    Some((namespace, name))
+}
+
+impl From<TileIndex> for TileId {
+    fn from(index: TileIndex) -> TileId {
+        TileId(index)
+    }
+}
+
+impl From<TileId> for TileIndex {
+    fn from(id: TileId) -> TileIndex {
+        id.0
+    }
+}
+
+impl From<usize> for PageId {
+    fn from(index: usize) -> PageId {
+        PageId(index)
+    }
+}
+
+impl From<PageId> for usize {
+    fn from(id: PageId) -> usize {
+        id.0
+    }
+}
+
+impl From<u32> for ContextId {
+    fn from(id: u32) -> ContextId {
+        ContextId(id)
+    }
+}
+
+impl From<ContextId> for u32 {
+    fn from(id: ContextId) -> u32 {
+        id.0
+    }
+}
+
+impl<'a> From<&'a str> for FrameKey {
+    fn from(key: &'a str) -> FrameKey {
+        FrameKey(key.to_string())
+    }
+}
+
+impl From<String> for FrameKey {
+    fn from(key: String) -> FrameKey {
+        FrameKey(key)
+    }
+}
+
+impl From<FrameKey> for String {
+    fn from(key: FrameKey) -> String {
+        key.0
+    }
+}
+
+impl AsRef<str> for FrameKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for FrameKey {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TileId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tile {}", self.0)
+    }
+}
+
+impl fmt::Display for PageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "page {}", self.0)
+    }
+}
+
+impl fmt::Display for ContextId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "context {}", self.0)
+    }
+}
+
+impl fmt::Display for FrameKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_typed_and_plain_ids() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("a", TileId(3), (1, 0)).unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.get_tile_draw_info(TileId(3), 0).uv_offset, atlas.get_tile_draw_info(3, 0).uv_offset);
+        assert_eq!(atlas.get_page_dimensions(PageId(0)), atlas.get_page_dimensions(0));
+        assert_eq!(String::from(FrameKey::from("a")), "a");
+
+        let key = FrameKey::resolve(Some("mymod"), "grass.png");
+        assert_eq!(key.namespace(), Some("mymod"));
+        assert_eq!(key.name(), "grass.png");
+        assert_eq!(FrameKey::resolve(Some("mymod"), "base:grass.png").namespace(), Some("base"));
+
+        let windows = FrameKey::from("C:\\art\\grass.png");
+        assert_eq!(windows.namespace(), None);
+        assert_eq!(windows.name(), "C:\\art\\grass.png");
+        let key = FrameKey::resolve(Some("mymod"), "C:/art/grass.png");
+        assert_eq!((key.namespace(), key.name()), (Some("mymod"), "C:/art/grass.png"));
+        assert_eq!(FrameKey::from("art/v1:grass.png").namespace(), None);
+        assert_eq!(FrameKey::resolve(Some("mymod"), "base:C:\\grass.png").name(), "C:\\grass.png");
+    }
+}
@@ -1,45 +1,186 @@
 #[macro_use] extern crate serde_derive;
 extern crate bincode;
+#[cfg(feature = "blake3")]
+extern crate blake3;
+#[cfg(feature = "sha3")]
 extern crate crypto;
-extern crate glium;
+#[cfg(feature = "egui")]
+extern crate egui;
+#[cfg(feature = "glium")]
+#[macro_use] extern crate glium;
 extern crate glob;
-extern crate image;
+/// Re-exported, since frames are added as its images, so that code using
+/// this crate can name them without depending on a matching version.
+pub extern crate image;
 extern crate serde;
+#[cfg(feature = "sha2")]
+extern crate sha2;
 extern crate texture_packer;
 extern crate toml;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "wgpu")]
+extern crate wgpu;
 
+#[macro_use]
+mod trace;
+
+mod atlas;
+mod backend;
+mod block_compress;
+mod cpu_atlas;
+mod decal_atlas;
+mod decode;
+mod digest;
+#[cfg(feature = "egui")]
+mod egui_textures;
+mod error;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+#[cfg(feature = "fs")]
+mod fs_util;
+#[cfg(feature = "golden")]
+pub mod golden;
+mod ids;
+mod lint;
+#[cfg(feature = "fs")]
+mod manifest;
+mod mesher;
+mod mipmap;
+mod packing;
+pub mod rect_packing;
+mod registry;
+mod sample_match;
+mod sampler;
+#[cfg(feature = "fs")]
+mod sandbox;
+mod single_texture_atlas;
+mod sprite_layer;
+#[cfg(feature = "strict")]
+pub mod strict;
+mod streaming_upload;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "glium")]
+mod texture_array;
 mod texture_atlas;
 mod tile_atlas;
 mod tile_atlas_config;
+mod tile_registry;
 mod toml_util;
+mod usage;
+#[cfg(feature = "validate")]
+pub mod validate;
 
+pub use atlas::{Atlas, FrameUv, PageInfo};
+pub use backend::{GpuBackend, MemoryBackend, PageTexture, RawPage, TextureFn, Unflipped};
+#[cfg(feature = "glium")]
+pub use backend::{SrgbFormat, LinearFormat, CompressedLinearFormat};
+#[cfg(feature = "wgpu")]
+pub use backend::WgpuBackend;
+pub use block_compress::{BlockFormat, CompressedPage};
+pub use cpu_atlas::CpuAtlas;
+pub use decal_atlas::{DecalAtlas, DecalId, DecalMove};
+pub use decode::DecodeLimits;
+#[cfg(feature = "egui")]
+pub use egui_textures::EguiTextures;
+pub use error::{AtlasError, AtlasResult, ConfigDiagnostic};
+pub use ids::{TileId, PageId, FrameKey, ContextId};
+pub use lint::{Lint, LintOptions, PackReport};
+#[cfg(feature = "fs")]
+pub use manifest::BundleManifest;
+pub use mesher::{TilemapMesher, ChunkMesh, ChunkId, TileVertex};
+pub use packing::{PackAlgorithm, PackerConfig};
+pub use registry::{AtlasRegistry, AtlasId, FrameId};
+pub use sample_match::TileMatch;
+pub use sampler::{SamplerSettings, Filter, Wrap};
+pub use single_texture_atlas::{SingleTextureAtlas, FrameIndex};
+pub use sprite_layer::{Sprite, SpriteBatch, sort_sprites};
+pub use streaming_upload::{StreamingUpload, UploadProgress};
+#[cfg(feature = "glium")]
+pub use texture_array::TextureArrayAtlas;
 pub use texture_atlas::{TextureAtlasBuilder, TextureAtlas};
-pub use tile_atlas::{TileAtlasBuilder, TileAtlas};
-use image::GenericImage;
+pub use tile_atlas::{TileAtlasBuilder, TileAtlas, TileKind, KeyCollision, CompressionHint, AtlasTile, DrawInfo, OverlayInfo, PackJob, PackProgress, Pick, StalePages};
+#[cfg(feature = "fs")]
+pub use tile_atlas_config::{AtlasMetadata, load_metadata_only};
+pub use tile_registry::{TileRegistry, TileInfo};
+pub use usage::UsageReport;
 
+#[cfg(feature = "glium")]
 type Texture2d = glium::texture::CompressedSrgbTexture2d;
 
+/// The texture type atlases hold unless told otherwise: glium textures with
+/// the `glium` feature, or in-memory pages without it.
+#[cfg(feature = "glium")]
+pub type DefaultTexture = glium::texture::CompressedSrgbTexture2d;
+#[cfg(not(feature = "glium"))]
+pub type DefaultTexture = image::DynamicImage;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct AtlasRect {
     x: u32,
     y: u32,
     w: u32,
     h: u32,
+    #[serde(default)]
+    rotated: bool,
 }
 
-impl From<texture_packer::Rect> for AtlasRect {
-    fn from(rect: texture_packer::Rect) -> AtlasRect {
+impl AtlasRect {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> AtlasRect {
         AtlasRect {
-            x: rect.x,
-            y: rect.y,
-            w: rect.w,
-            h: rect.h,
+            x: x,
+            y: y,
+            w: w,
+            h: h,
+            rotated: false,
         }
     }
-}
 
-fn make_texture<F: glium::backend::Facade>(display: &F, image: image::DynamicImage) -> Texture2d {
-    let dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(image.to_rgba().into_raw(), dimensions);
-    Texture2d::new(display, image).unwrap()
+    /// Makes the rect of an image placed turned a quarter turn clockwise, so
+    /// that `w` and `h` are the image's height and width.
+    pub fn new_rotated(x: u32, y: u32, w: u32, h: u32) -> AtlasRect {
+        AtlasRect {
+            rotated: true,
+            ..AtlasRect::new(x, y, w, h)
+        }
+    }
+
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    pub fn w(&self) -> u32 {
+        self.w
+    }
+
+    pub fn h(&self) -> u32 {
+        self.h
+    }
+
+    /// Whether the image was placed turned a quarter turn clockwise. Its
+    /// top-left pixel is then at the top right of the rect.
+    pub fn rotated(&self) -> bool {
+        self.rotated
+    }
+
+    /// Gets the width and height of the image as it was before placing, which
+    /// are `w` and `h` swapped if it was rotated.
+    pub fn source_size(&self) -> (u32, u32) {
+        if self.rotated {
+            (self.h, self.w)
+        } else {
+            (self.w, self.h)
+        }
+    }
+
+    /// Not a `From` impl, which would make texture_packer's `Rect` part of
+    /// this crate's public API.
+    fn from_packer(rect: texture_packer::Rect) -> AtlasRect {
+        AtlasRect::new(rect.x, rect.y, rect.w, rect.h)
+    }
 }
@@ -0,0 +1,299 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+
+use image::{DynamicImage, GenericImage};
+
+/// Which checks to run on frames as they're added to a `TileAtlasBuilder`.
+/// Lints only warn; frames that fail them are still packed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LintOptions {
+    /// Warn when a frame's size isn't a multiple of its tile size.
+    pub tile_multiple: bool,
+    /// Warn when a frame has any pixel that isn't fully opaque. Meant to be
+    /// switched on while adding tilesets that are supposed to be opaque.
+    pub opaque: bool,
+    /// Warn when a frame is wider or taller than this.
+    pub max_frame_size: Option<(u32, u32)>,
+    /// Warn when a frame looks nearly the same as one of the same size added
+    /// before it, such as a sprite exported again under another name: when
+    /// the two shrunk to 8x8 thumbnails differ by at most this much per
+    /// channel on average, out of 255. These are only found once every frame
+    /// is in, by `TileAtlasBuilder::pack_report`.
+    pub near_duplicates: Option<u32>,
+}
+
+/// A problem found with a frame while adding it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lint {
+    /// Holds the frame, its size and its tile size.
+    UnevenSize(String, (u32, u32), (u32, u32)),
+    /// Holds the frame and how many of its pixels aren't fully opaque.
+    Translucent(String, usize),
+    /// Holds the frame, its size and the size it was expected to fit in.
+    Oversized(String, (u32, u32), (u32, u32)),
+    /// Holds the frame, the earlier frame it looks like and how much their
+    /// thumbnails differ. See `LintOptions::near_duplicates`.
+    NearDuplicate(String, String, u32),
+}
+
+impl Lint {
+    /// Gets the frame the lint was raised for.
+    pub fn frame(&self) -> &str {
+        match *self {
+            Lint::UnevenSize(ref key, ..) |
+            Lint::Translucent(ref key, ..) |
+            Lint::Oversized(ref key, ..) |
+            Lint::NearDuplicate(ref key, ..) => key,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Lint::UnevenSize(ref key, (w, h), (tw, th)) =>
+                write!(f, "Frame {} is {}x{}, which isn't a multiple of its {}x{} tiles", key, w, h, tw, th),
+            Lint::Translucent(ref key, count) =>
+                write!(f, "Frame {} should be opaque, but has {} translucent pixels", key, count),
+            Lint::Oversized(ref key, (w, h), (max_w, max_h)) =>
+                write!(f, "Frame {} is {}x{}, larger than the expected {}x{}", key, w, h, max_w, max_h),
+            Lint::NearDuplicate(ref key, ref other, distance) =>
+                write!(f, "Frame {} looks nearly the same as frame {} (off by {})", key, other, distance),
+        }
+    }
+}
+
+/// Everything the lints found in a builder's frames.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PackReport {
+    /// Lints, in the order their frames were added.
+    pub lints: Vec<Lint>,
+    /// The size pages are packed at, after growing to fit the frames if
+    /// `TileAtlasBuilder::set_grow_page_size` allows it.
+    pub page_size: u32,
+}
+
+impl PackReport {
+    pub fn is_clean(&self) -> bool {
+        self.lints.is_empty()
+    }
+}
+
+/// Runs the enabled checks on a frame.
+pub fn lint_frame(options: &LintOptions, key: &str, image: &DynamicImage, tile_size: (u32, u32)) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let (w, h) = image.dimensions();
+
+    if options.tile_multiple {
+        let (tw, th) = tile_size;
+        if tw == 0 || th == 0 || w % tw != 0 || h % th != 0 {
+            lints.push(Lint::UnevenSize(key.to_string(), (w, h), tile_size));
+        }
+    }
+
+    if options.opaque {
+        let count = image.pixels().filter(|&(_, _, pixel)| pixel.data[3] != 255).count();
+        if count > 0 {
+            lints.push(Lint::Translucent(key.to_string(), count));
+        }
+    }
+
+    if let Some((max_w, max_h)) = options.max_frame_size {
+        if w > max_w || h > max_h {
+            lints.push(Lint::Oversized(key.to_string(), (w, h), (max_w, max_h)));
+        }
+    }
+
+    lints
+}
+
+/// Width and height of the thumbnails frames are compared by.
+const THUMBNAIL_SIZE: u32 = 8;
+
+/// A frame shrunk to `THUMBNAIL_SIZE` squared pixels, which near duplicates
+/// are found by. Builders make one as each frame is added, so finding near
+/// duplicates doesn't shrink every frame again.
+#[derive(Clone, Debug)]
+pub struct Thumbnail {
+    /// The size of the frame it was made from.
+    size: (u32, u32),
+    cells: Vec<[u32; 4]>,
+    /// Every channel of every cell added up.
+    sum: u64,
+}
+
+impl Thumbnail {
+    pub fn new(image: &DynamicImage) -> Thumbnail {
+        let cells = thumbnail(image);
+        let sum = cells.iter().map(|cell| cell.iter().map(|&c| c as u64).sum::<u64>()).sum();
+        Thumbnail {
+            size: image.dimensions(),
+            cells: cells,
+            sum: sum,
+        }
+    }
+
+    /// Gets which bucket of similar thumbnails this falls in. Thumbnails
+    /// within `max_distance` of each other differ in `sum` by less than
+    /// `max_distance + 1` per channel, so they're never more than one bucket
+    /// apart.
+    fn bucket(&self, max_distance: u32) -> u64 {
+        let channels = self.cells.len() as u64 * 4;
+        self.sum / ((max_distance as u64 + 1) * channels)
+    }
+}
+
+/// Shrinks an image to `THUMBNAIL_SIZE` squared pixels by averaging each
+/// cell. Colors are weighted by alpha, so the color left in fully
+/// transparent pixels doesn't count.
+fn thumbnail(image: &DynamicImage) -> Vec<[u32; 4]> {
+    let (w, h) = image.dimensions();
+    let mut cells = Vec::with_capacity((THUMBNAIL_SIZE * THUMBNAIL_SIZE) as usize);
+    let span = |cell: u32, size: u32| {
+        let start = cell * size / THUMBNAIL_SIZE;
+        (start, cmp::min(cmp::max((cell + 1) * size / THUMBNAIL_SIZE, start + 1), size))
+    };
+
+    for cy in 0..THUMBNAIL_SIZE {
+        for cx in 0..THUMBNAIL_SIZE {
+            let ((x0, x1), (y0, y1)) = (span(cx, w), span(cy, h));
+            let mut sum = [0u64; 4];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let pixel = image.get_pixel(x, y).data;
+                    let alpha = pixel[3] as u64;
+                    for channel in 0..3 {
+                        sum[channel] += pixel[channel] as u64 * alpha;
+                    }
+                    sum[3] += alpha;
+                }
+            }
+
+            let count = cmp::max((x1 - x0) as u64 * (y1 - y0) as u64, 1);
+            cells.push([(sum[0] / (count * 255)) as u32,
+                        (sum[1] / (count * 255)) as u32,
+                        (sum[2] / (count * 255)) as u32,
+                        (sum[3] / count) as u32]);
+        }
+    }
+
+    cells
+}
+
+/// Gets the mean difference per channel between two thumbnails.
+fn thumbnail_distance(a: &[[u32; 4]], b: &[[u32; 4]]) -> u32 {
+    let total: u32 = a.iter().zip(b.iter())
+        .map(|(a, b)| (0..4).map(|c| (a[c] as i32 - b[c] as i32).abs() as u32).sum::<u32>())
+        .sum();
+    total / (a.len() as u32 * 4)
+}
+
+/// Finds frames that look nearly the same as one of the same size before
+/// them in `frames`, which are in the order they were added. Each frame is
+/// only reported against the first one it matches, so a run of copies
+/// doesn't raise a lint for every pair. Frames are only compared with those
+/// of the same size in the same or a neighboring bucket, which is what keeps
+/// this from comparing every pair.
+pub fn lint_near_duplicates(max_distance: u32, frames: &[(&str, &Thumbnail)]) -> Vec<Lint> {
+    let mut buckets: HashMap<((u32, u32), u64), Vec<usize>> = HashMap::new();
+    let mut lints = Vec::new();
+
+    for (i, &(key, thumbnail)) in frames.iter().enumerate() {
+        let bucket = thumbnail.bucket(max_distance);
+        let neighbors = [bucket.wrapping_sub(1), bucket, bucket + 1];
+
+        // the earliest match of any bucket, since each is in the order added.
+        let found = neighbors.iter()
+            .filter_map(|&b| buckets.get(&(thumbnail.size, b)))
+            .filter_map(|earlier| {
+                earlier.iter()
+                    .map(|&j| (j, thumbnail_distance(&thumbnail.cells, &frames[j].1.cells)))
+                    .find(|&(_, distance)| distance <= max_distance)
+            })
+            .min_by_key(|&(j, _)| j);
+
+        if let Some((j, distance)) = found {
+            lints.push(Lint::NearDuplicate(key.to_string(), frames[j].0.to_string(), distance));
+        }
+        buckets.entry((thumbnail.size, bucket)).or_insert_with(Vec::new).push(i);
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{FilterType, Rgba};
+
+    use super::*;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_lint_frame() {
+        let mut image = DynamicImage::new_rgba8(50, 48);
+        let options = LintOptions {
+            tile_multiple: true,
+            opaque: true,
+            max_frame_size: Some((32, 64)),
+            near_duplicates: None,
+        };
+
+        image.put_pixel(0, 0, Rgba { data: [0, 0, 0, 255] });
+
+        let lints = lint_frame(&options, "a", &image, (24, 24));
+        assert_eq!(lints, vec![Lint::UnevenSize("a".to_string(), (50, 48), (24, 24)),
+                               Lint::Translucent("a".to_string(), 50 * 48 - 1),
+                               Lint::Oversized("a".to_string(), (50, 48), (32, 64))]);
+
+        assert!(lint_frame(&LintOptions::default(), "a", &image, (24, 24)).is_empty());
+    }
+
+    #[test]
+    fn test_near_duplicates() {
+        let mut hero = DynamicImage::new_rgba8(16, 16);
+        for (x, y, _) in hero.clone().pixels() {
+            hero.put_pixel(x, y, Rgba { data: [200, (x * 16) as u8, 40, 255] });
+        }
+        let mut retouched = hero.clone();
+        for y in 0..4 {
+            for x in 0..4 {
+                retouched.put_pixel(x, y, Rgba { data: [0, 0, 0, 255] });
+            }
+        }
+        let mut other = DynamicImage::new_rgba8(16, 16);
+        for (x, y, _) in other.clone().pixels() {
+            other.put_pixel(x, y, Rgba { data: [0, 0, 255, 255] });
+        }
+
+        let (hero_thumbnail, retouched_thumbnail) = (Thumbnail::new(&hero), Thumbnail::new(&retouched));
+        let frames = [("hero", &hero_thumbnail), ("other", &Thumbnail::new(&other)), ("hero_old", &retouched_thumbnail),
+                      ("hero_copy", &hero_thumbnail),
+                      ("hero_big", &Thumbnail::new(&retouched.resize_exact(32, 32, FilterType::Nearest)))];
+        let lints = lint_near_duplicates(8, &frames);
+        assert_eq!(lints, vec![Lint::NearDuplicate("hero_old".to_string(), "hero".to_string(), 4),
+                               Lint::NearDuplicate("hero_copy".to_string(), "hero".to_string(), 0)]);
+        assert_eq!(lint_near_duplicates(0, &frames).len(), 1);
+
+        // grays 2 and 3 fall in neighboring buckets, and still match.
+        let gray = |value: u8| {
+            let mut image = DynamicImage::new_rgba8(16, 16);
+            for (x, y, _) in image.clone().pixels() {
+                image.put_pixel(x, y, Rgba { data: [value, value, value, 255] });
+            }
+            Thumbnail::new(&image)
+        };
+        let (dark, light) = (gray(2), gray(3));
+        assert!(dark.bucket(1) != light.bucket(1));
+        assert_eq!(lint_near_duplicates(1, &[("dark", &dark), ("light", &light)]),
+                   vec![Lint::NearDuplicate("light".to_string(), "dark".to_string(), 0)]);
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", hero.clone(), (16, 16)).unwrap();
+        builder.set_lint_options(Some(LintOptions { near_duplicates: Some(0), ..LintOptions::default() }));
+        builder.add_frame_image("b", hero, (16, 16)).unwrap();
+        assert_eq!(builder.pack_report().lints, vec![Lint::NearDuplicate("b".to_string(), "a".to_string(), 0)]);
+        builder.replace_frame("b", other).unwrap();
+        assert!(builder.pack_report().is_clean());
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use bincode;
+use glob;
+
+use digest;
+use error::{AtlasError, AtlasResult};
+use fs_util;
+
+pub const MANIFEST_FILENAME: &'static str = "manifest.bin";
+
+/// Content hashes of every file in a packed atlas folder, optionally signed
+/// with a key so prepacked atlases shipped with a game can be checked for
+/// tampering before they're uploaded.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BundleManifest {
+    pub files: BTreeMap<String, String>,
+    pub signature: Option<String>,
+}
+
+impl BundleManifest {
+    /// Hashes `cache.bin` and the page images in `dir`, signing the result
+    /// with `key` if one is given.
+    pub fn from_dir(dir: &Path, key: Option<&[u8]>) -> AtlasResult<Self> {
+        let mut files = BTreeMap::new();
+        for (name, data) in read_files(dir)? {
+            files.insert(name, digest::tagged_hash(&data));
+        }
+
+        let signature = key.map(|k| digest::tagged_hmac(k, &signed_message(&files)));
+
+        Ok(BundleManifest {
+            files: files,
+            signature: signature,
+        })
+    }
+
+    /// Checks that the contents of `dir` still match this manifest, and that
+    /// the manifest was signed with `key` if one is given. A folder that can't
+    /// be read doesn't match, and neither does one hashed or signed with an
+    /// algorithm whose feature is off.
+    pub fn verify(&self, dir: &Path, key: Option<&[u8]>) -> bool {
+        if let Some(key) = key {
+            let valid = match self.signature {
+                Some(ref signature) => digest::verify_hmac(signature, key, &signed_message(&self.files)),
+                None                => false,
+            };
+            if !valid {
+                return false;
+            }
+        }
+
+        let actual = match read_files(dir) {
+            Ok(actual) => actual,
+            Err(..)    => return false,
+        };
+
+        actual.len() == self.files.len() &&
+            actual.iter().all(|&(ref name, ref data)| {
+                self.files.get(name).map_or(false, |hash| digest::matches(hash, data))
+            })
+    }
+}
+
+pub fn write_manifest(dir: &Path, key: Option<&[u8]>) -> AtlasResult<()> {
+    let manifest = BundleManifest::from_dir(dir, key)?;
+    let data = bincode::serialize(&manifest, bincode::Infinite)?;
+    fs_util::write_atomic(&dir.join(MANIFEST_FILENAME), data.as_slice())
+}
+
+pub fn read_manifest(dir: &Path) -> Option<BundleManifest> {
+    let mut file = match File::open(dir.join(MANIFEST_FILENAME)) {
+        Ok(f) => f,
+        Err(..) => return None,
+    };
+    let mut buf = Vec::new();
+    if file.read_to_end(&mut buf).is_err() {
+        return None;
+    }
+    bincode::deserialize(buf.as_slice()).ok()
+}
+
+/// Reads every file in `dir` but the manifest, with its name.
+fn read_files(dir: &Path) -> AtlasResult<Vec<(String, Vec<u8>)>> {
+    let entries = glob::glob(&format!("{}/*", dir.display()))
+        .map_err(|e| AtlasError::Cache(e.to_string()))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        if let Ok(path) = entry {
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name.to_string(),
+                None       => continue,
+            };
+            if name == MANIFEST_FILENAME || !path.is_file() {
+                continue;
+            }
+
+            let mut file = File::open(&path).map_err(|e| AtlasError::Io(path.clone(), e))?;
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf).map_err(|e| AtlasError::Io(path.clone(), e))?;
+            files.push((name, buf));
+        }
+    }
+    Ok(files)
+}
+
+/// Gets what a manifest's signature covers: each file's name and hash.
+fn signed_message(files: &BTreeMap<String, String>) -> Vec<u8> {
+    let mut message = Vec::new();
+    for (name, hash) in files.iter() {
+        message.extend_from_slice(name.as_bytes());
+        message.push(b':');
+        message.extend_from_slice(hash.as_bytes());
+        message.push(b'\n');
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let mut dir = env::temp_dir();
+        dir.push("texture_atlas_manifest_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("0.png")).unwrap().write_all(b"page").unwrap();
+
+        let manifest = BundleManifest::from_dir(&dir, Some(&b"key"[..])).unwrap();
+        assert!(manifest.verify(&dir, Some(&b"key"[..])));
+        assert!(manifest.verify(&dir, None));
+        assert!(!manifest.verify(&dir, Some(&b"other key"[..])));
+
+        File::create(dir.join("0.png")).unwrap().write_all(b"tampered").unwrap();
+        assert!(!manifest.verify(&dir, Some(&b"key"[..])));
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use backend::PageTexture;
+use tile_atlas::{TileAtlas, TileIndex};
+
+pub type ChunkId = (i32, i32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileVertex {
+    pub position: [f32; 2],
+    pub tex_coords: [f32; 2],
+}
+
+/// Generated geometry for one chunk, with one triangle list per texture
+/// page. `generation` changes whenever the geometry is regenerated, so
+/// callers know when to re-upload their vertex buffers.
+pub struct ChunkMesh {
+    pub pages: HashMap<usize, Vec<TileVertex>>,
+    pub generation: u64,
+}
+
+struct CachedChunk {
+    tiles: Vec<TileIndex>,
+    anim_frames: Vec<u64>,
+    mesh: ChunkMesh,
+}
+
+/// Builds tilemap geometry chunk by chunk, caching the result until the
+/// chunk's tiles change or one of its animated tiles advances a frame.
+pub struct TilemapMesher {
+    chunk_size: (u32, u32),
+    tile_size: (f32, f32),
+    chunks: HashMap<ChunkId, CachedChunk>,
+    generation: u64,
+}
+
+impl TilemapMesher {
+    /// `chunk_size` is the size of a chunk in tiles, and `tile_size` the
+    /// size of a tile in world units.
+    pub fn new(chunk_size: (u32, u32), tile_size: (f32, f32)) -> Self {
+        TilemapMesher {
+            chunk_size: chunk_size,
+            tile_size: tile_size,
+            chunks: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    /// Gets the mesh for a chunk, regenerating it only if needed. `tiles`
+    /// holds the chunk's tile indices in row-major order.
+    pub fn mesh<T: PageTexture>(&mut self, atlas: &TileAtlas<T>, id: ChunkId, tiles: &[TileIndex], msecs: u64) -> &ChunkMesh {
+        assert_eq!(tiles.len(), (self.chunk_size.0 * self.chunk_size.1) as usize);
+
+        let anim_frames: Vec<u64> = tiles.iter()
+            .map(|&tile| atlas.get_animation_frame(tile, msecs))
+            .collect();
+
+        let up_to_date = match self.chunks.get(&id) {
+            Some(cached) => cached.tiles.as_slice() == tiles && cached.anim_frames == anim_frames,
+            None         => false,
+        };
+
+        if !up_to_date {
+            self.generation += 1;
+            let mesh = self.generate(atlas, id, tiles, msecs);
+            let cached = CachedChunk {
+                tiles: tiles.to_vec(),
+                anim_frames: anim_frames,
+                mesh: mesh,
+            };
+            self.chunks.insert(id, cached);
+        }
+
+        &self.chunks.get(&id).unwrap().mesh
+    }
+
+    /// Forces the chunk to be regenerated the next time it's meshed.
+    pub fn invalidate(&mut self, id: ChunkId) {
+        self.chunks.remove(&id);
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    fn generate<T: PageTexture>(&self, atlas: &TileAtlas<T>, id: ChunkId, tiles: &[TileIndex], msecs: u64) -> ChunkMesh {
+        let mut pages: HashMap<usize, Vec<TileVertex>> = HashMap::new();
+        let (cols, _) = self.chunk_size;
+        let (tw, th) = self.tile_size;
+
+        let origin_x = id.0 as f32 * self.chunk_size.0 as f32 * tw;
+        let origin_y = id.1 as f32 * self.chunk_size.1 as f32 * th;
+
+        for (i, &tile) in tiles.iter().enumerate() {
+            let info = atlas.get_tile_draw_info(tile, msecs);
+
+            // A trimmed tile only covers the part of its cell its pixels
+            // were cut from.
+            let (sw, sh) = (info.source_size.0 as f32, info.source_size.1 as f32);
+            let x = origin_x + (i as u32 % cols) as f32 * tw + info.trim_offset.0 as f32 / sw * tw;
+            let y = origin_y + (i as u32 / cols) as f32 * th + info.trim_offset.1 as f32 / sh * th;
+            let (qw, qh) = (info.pixel_size.0 as f32 / sw * tw, info.pixel_size.1 as f32 / sh * th);
+            let uvs = info.uv_corners();
+
+            let corner = |i: usize, dx: f32, dy: f32| TileVertex {
+                position: [x + dx * qw, y + dy * qh],
+                tex_coords: [uvs[i].0, uvs[i].1],
+            };
+
+            let vertices = pages.entry(info.page).or_insert_with(Vec::new);
+            vertices.push(corner(0, 0.0, 0.0));
+            vertices.push(corner(1, 1.0, 0.0));
+            vertices.push(corner(2, 1.0, 1.0));
+            vertices.push(corner(0, 0.0, 0.0));
+            vertices.push(corner(2, 1.0, 1.0));
+            vertices.push(corner(3, 0.0, 1.0));
+        }
+
+        ChunkMesh {
+            pages: pages,
+            generation: self.generation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas::{TileAtlasBuilder, TileKind};
+
+    #[test]
+    fn test_cached_chunks() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (0, 0)).unwrap();
+        builder.add_tile("sheet", 1, (1, 0)).unwrap();
+        builder.add_tile_with_kind("sheet", 2, (0, 0), TileKind::Animated(2, 100)).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        let mut mesher = TilemapMesher::new((2, 1), (1.0, 1.0));
+        let first = mesher.mesh(&atlas, (0, 0), &[0, 2], 0).generation;
+        assert_eq!(mesher.mesh(&atlas, (0, 0), &[0, 2], 50).generation, first);
+
+        // a changed tile and an animation advancing a frame each rebuild it.
+        let changed = mesher.mesh(&atlas, (0, 0), &[1, 2], 50).generation;
+        assert!(changed != first);
+        let advanced = mesher.mesh(&atlas, (0, 0), &[1, 2], 150).generation;
+        assert!(advanced != changed);
+        assert_eq!(mesher.mesh(&atlas, (0, 0), &[1, 2], 180).generation, advanced);
+        assert_eq!(mesher.mesh(&atlas, (0, 0), &[1, 2], 180).pages[&0].len(), 12);
+    }
+}
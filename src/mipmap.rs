@@ -0,0 +1,78 @@
+use std::cmp;
+
+use image::{DynamicImage, GenericImage, ImageBuffer, Rgba};
+
+/// Gets the gutter needed around each frame so that `levels` mip levels,
+/// counting the full size page, don't blend neighboring frames together.
+/// Each level halves the page, so a pixel of the smallest level covers
+/// `2^(levels - 1)` pixels of the full one.
+pub fn gutter_for_levels(levels: u32) -> u32 {
+    if levels > 1 {
+        1 << (levels - 1)
+    } else {
+        0
+    }
+}
+
+/// Gets how many levels a page `size` pixels wide can be halved into before
+/// it's 1x1, counting the full size one. Mip levels past that would only
+/// widen the gutter.
+pub fn max_levels(size: u32) -> u32 {
+    cmp::max(32 - size.leading_zeros(), 1)
+}
+
+/// Generates up to `levels - 1` mip levels below `page`, each half the size
+/// of the one before and box filtered from it. Stops early at 1x1.
+pub fn generate_chain(page: &DynamicImage, levels: u32) -> Vec<DynamicImage> {
+    trace_span!("mipmaps");
+    let mut chain = Vec::new();
+    let mut current = page.to_rgba();
+
+    for _ in 1..levels {
+        let (w, h) = current.dimensions();
+        if w == 1 && h == 1 {
+            break;
+        }
+
+        let next = downsample(&current);
+        chain.push(DynamicImage::ImageRgba8(next.clone()));
+        current = next;
+    }
+
+    chain
+}
+
+fn downsample(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (w, h) = image.dimensions();
+    let (next_w, next_h) = (cmp::max(w / 2, 1), cmp::max(h / 2, 1));
+
+    ImageBuffer::from_fn(next_w, next_h, |x, y| {
+        let mut sum = [0u32; 4];
+        for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = image.get_pixel(cmp::min(x * 2 + dx, w - 1), cmp::min(y * 2 + dy, h - 1));
+            for (total, &value) in sum.iter_mut().zip(pixel.data.iter()) {
+                *total += value as u32;
+            }
+        }
+        Rgba { data: [(sum[0] / 4) as u8, (sum[1] / 4) as u8, (sum[2] / 4) as u8, (sum[3] / 4) as u8] }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_chain() {
+        let mut page = DynamicImage::new_rgba8(8, 4);
+        page.put_pixel(0, 0, Rgba { data: [255, 255, 255, 255] });
+
+        let chain = generate_chain(&page, 5);
+        let sizes: Vec<_> = chain.iter().map(|level| level.dimensions()).collect();
+        assert_eq!(sizes, vec![(4, 2), (2, 1), (1, 1)]);
+        assert_eq!(chain[0].get_pixel(0, 0).data, [63, 63, 63, 63]);
+        assert_eq!(gutter_for_levels(3), 4);
+        assert_eq!((max_levels(64), max_levels(100), max_levels(1)), (7, 7, 1));
+        assert_eq!(gutter_for_levels(max_levels(::std::u32::MAX)), 1 << 31);
+    }
+}
@@ -0,0 +1,803 @@
+//! Placing frames on pages. Skyline packing of images goes through
+//! texture_packer, which only has that one algorithm; the others place plain
+//! rects here, and pages packed with them are composited from the rects they
+//! gave. `SkylinePacker` is the same algorithm on plain rects, for
+//! `rect_packing`, where there are no images to hand texture_packer.
+
+use std::borrow::Cow;
+use std::cmp;
+
+use image::{DynamicImage, GenericImage, Rgba};
+use texture_packer::SkylinePacker as TexturePackerSkyline;
+use texture_packer::{TexturePacker, TexturePackerConfig};
+use texture_packer::exporter::ImageExporter;
+use texture_packer::texture::Texture as PackerTexture;
+
+use AtlasRect;
+
+/// How frames are placed on a page.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackAlgorithm {
+    /// Places each frame as low as it fits along the top edge of the frames
+    /// below it. Fast, and tight for frames of similar heights, but leaves
+    /// gaps under short frames next to tall ones.
+    Skyline,
+    /// Keeps every largest free rect on the page, and places each frame in
+    /// the one it leaves the least over in. Slower, but packs mixed sizes
+    /// noticeably tighter.
+    MaxRects,
+    /// Places each frame in the corner of the free rect it fits best, and
+    /// cuts what's left into two rects along the shorter side. Frames of one
+    /// size end up on a regular grid, so tilesets that are mostly uniform
+    /// get predictable, row by row layouts.
+    Guillotine,
+    /// Places frames left to right in rows as tall as the first frame in
+    /// each, looking only at the rows' ends, so placing costs the number of
+    /// rows rather than frames. The loosest packing, but the cheapest to
+    /// continue at runtime; see `TileAtlas::insert_frame`.
+    Shelf,
+}
+
+impl Default for PackAlgorithm {
+    fn default() -> Self {
+        PackAlgorithm::Skyline
+    }
+}
+
+/// How a page is sized and packed. See `TextureAtlasBuilder::with_config`
+/// and `TileAtlasBuilder::set_packer_config`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackerConfig {
+    pub algorithm: PackAlgorithm,
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Pixels left empty between packed images.
+    pub padding: u32,
+    /// Pixels left empty between packed images and the page edges.
+    pub border_padding: u32,
+    /// Turns images that don't fit upright a quarter turn clockwise, and
+    /// places them that way if they fit then. Their rects are marked
+    /// `AtlasRect::rotated`.
+    pub allow_rotation: bool,
+    /// Draws a red outline just inside every packed image, for debugging.
+    pub outlines: bool,
+}
+
+impl Default for PackerConfig {
+    /// A 4096 pixel page packed edge to edge.
+    fn default() -> Self {
+        PackerConfig {
+            algorithm: PackAlgorithm::default(),
+            max_width: 4096,
+            max_height: 4096,
+            padding: 0,
+            border_padding: 0,
+            allow_rotation: false,
+            outlines: false,
+        }
+    }
+}
+
+impl PackerConfig {
+    fn texture_packer_config(&self) -> TexturePackerConfig {
+        TexturePackerConfig {
+            max_width: self.max_width,
+            max_height: self.max_height,
+            // Turned images are packed as turned copies instead, the same way
+            // for every algorithm.
+            allow_rotation: false,
+            texture_outlines: self.outlines,
+            trim: false,
+            texture_padding: self.padding,
+            border_padding: self.border_padding,
+            ..Default::default()
+        }
+    }
+}
+
+/// Places rects inside an area, each with room for `padding` after it.
+pub trait RectPacker {
+    fn can_pack(&self, w: u32, h: u32) -> bool;
+
+    /// Finds a place for a `w` by `h` rect, or `None` if there's no room.
+    fn pack(&mut self, w: u32, h: u32) -> Option<AtlasRect>;
+}
+
+pub struct MaxRectsPacker {
+    free: Vec<AtlasRect>,
+    padding: u32,
+}
+
+impl MaxRectsPacker {
+    /// Packs into a `width` by `height` area, keeping `border` pixels clear
+    /// at its edges. The padding after rects at the far edges may run into
+    /// the border, so a rect of the full inner size still fits.
+    pub fn new(width: u32, height: u32, border: u32, padding: u32) -> Self {
+        let inner = |size: u32| size.saturating_sub(2 * border) + padding;
+        MaxRectsPacker {
+            free: vec![AtlasRect::new(border, border, inner(width), inner(height))],
+            padding: padding,
+        }
+    }
+
+    /// Continues packing an area `rects` were already placed in, however
+    /// they were placed, with every gap they left free.
+    pub fn resume(width: u32, height: u32, border: u32, padding: u32, rects: &[AtlasRect]) -> Self {
+        let mut packer = MaxRectsPacker::new(width, height, border, padding);
+        for rect in rects.iter() {
+            packer.split_free(&AtlasRect::new(rect.x, rect.y, rect.w + padding, rect.h + padding));
+            packer.prune_free();
+        }
+        packer
+    }
+
+    /// Finds the free rect that leaves the shortest side over, breaking ties
+    /// by the longest side.
+    fn best_fit(&self, w: u32, h: u32) -> Option<usize> {
+        self.free.iter()
+            .enumerate()
+            .filter(|&(_, rect)| rect.w >= w && rect.h >= h)
+            .min_by_key(|&(_, rect)| {
+                let (dw, dh) = (rect.w - w, rect.h - h);
+                (dw.min(dh), dw.max(dh))
+            })
+            .map(|(idx, _)| idx)
+    }
+
+    /// Cuts `used` out of every free rect it overlaps, keeping the largest
+    /// rects left on each side of it.
+    fn split_free(&mut self, used: &AtlasRect) {
+        let mut free = Vec::with_capacity(self.free.len() + 4);
+
+        for rect in self.free.drain(..) {
+            if !overlaps(&rect, used) {
+                free.push(rect);
+                continue;
+            }
+
+            if used.x > rect.x {
+                free.push(AtlasRect::new(rect.x, rect.y, used.x - rect.x, rect.h));
+            }
+            if used.x + used.w < rect.x + rect.w {
+                free.push(AtlasRect::new(used.x + used.w, rect.y, rect.x + rect.w - (used.x + used.w), rect.h));
+            }
+            if used.y > rect.y {
+                free.push(AtlasRect::new(rect.x, rect.y, rect.w, used.y - rect.y));
+            }
+            if used.y + used.h < rect.y + rect.h {
+                free.push(AtlasRect::new(rect.x, used.y + used.h, rect.w, rect.y + rect.h - (used.y + used.h)));
+            }
+        }
+
+        self.free = free;
+    }
+
+    /// Drops free rects that lie inside another one.
+    fn prune_free(&mut self) {
+        let mut idx = 0;
+        while idx < self.free.len() {
+            let redundant = self.free.iter().enumerate().any(|(other, rect)| {
+                other != idx && contains(rect, &self.free[idx]) &&
+                    // Of two equal rects, keep the first.
+                    (other < idx || !contains(&self.free[idx], rect))
+            });
+
+            if redundant {
+                self.free.swap_remove(idx);
+            } else {
+                idx += 1;
+            }
+        }
+    }
+}
+
+impl RectPacker for MaxRectsPacker {
+    fn can_pack(&self, w: u32, h: u32) -> bool {
+        self.best_fit(w + self.padding, h + self.padding).is_some()
+    }
+
+    fn pack(&mut self, w: u32, h: u32) -> Option<AtlasRect> {
+        let (padded_w, padded_h) = (w + self.padding, h + self.padding);
+        let idx = match self.best_fit(padded_w, padded_h) {
+            Some(idx) => idx,
+            None      => return None,
+        };
+        let (x, y) = (self.free[idx].x, self.free[idx].y);
+
+        self.split_free(&AtlasRect::new(x, y, padded_w, padded_h));
+        self.prune_free();
+        Some(AtlasRect::new(x, y, w, h))
+    }
+}
+
+pub struct GuillotinePacker {
+    free: Vec<AtlasRect>,
+    padding: u32,
+}
+
+impl GuillotinePacker {
+    /// Packs into a `width` by `height` area the same way as
+    /// `MaxRectsPacker::new`.
+    pub fn new(width: u32, height: u32, border: u32, padding: u32) -> Self {
+        let inner = |size: u32| size.saturating_sub(2 * border) + padding;
+        GuillotinePacker {
+            free: vec![AtlasRect::new(border, border, inner(width), inner(height))],
+            padding: padding,
+        }
+    }
+
+    /// Finds the free rect with the least area left over, preferring the
+    /// topmost, then leftmost, of equally good ones.
+    fn best_fit(&self, w: u32, h: u32) -> Option<usize> {
+        self.free.iter()
+            .enumerate()
+            .filter(|&(_, rect)| rect.w >= w && rect.h >= h)
+            .min_by_key(|&(_, rect)| (rect.w as u64 * rect.h as u64 - w as u64 * h as u64, rect.y, rect.x))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Joins free rects that share a whole edge, so space freed up in rows
+    /// can take larger frames.
+    fn merge_free(&mut self) {
+        let mut merged = true;
+        while merged {
+            merged = false;
+            'search: for i in 0..self.free.len() {
+                for j in (i + 1)..self.free.len() {
+                    let joined = {
+                        let (a, b) = (&self.free[i], &self.free[j]);
+                        if a.x == b.x && a.w == b.w && (a.y + a.h == b.y || b.y + b.h == a.y) {
+                            Some(AtlasRect::new(a.x, a.y.min(b.y), a.w, a.h + b.h))
+                        } else if a.y == b.y && a.h == b.h && (a.x + a.w == b.x || b.x + b.w == a.x) {
+                            Some(AtlasRect::new(a.x.min(b.x), a.y, a.w + b.w, a.h))
+                        } else {
+                            None
+                        }
+                    };
+
+                    if let Some(joined) = joined {
+                        self.free[i] = joined;
+                        self.free.swap_remove(j);
+                        merged = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl RectPacker for GuillotinePacker {
+    fn can_pack(&self, w: u32, h: u32) -> bool {
+        self.best_fit(w + self.padding, h + self.padding).is_some()
+    }
+
+    fn pack(&mut self, w: u32, h: u32) -> Option<AtlasRect> {
+        let (padded_w, padded_h) = (w + self.padding, h + self.padding);
+        let idx = match self.best_fit(padded_w, padded_h) {
+            Some(idx) => idx,
+            None      => return None,
+        };
+        let rect = self.free.swap_remove(idx);
+
+        // Cut along the shorter leftover side, so the larger leftover stays
+        // in one piece.
+        let (right_w, below_h) = (rect.w - padded_w, rect.h - padded_h);
+        let (right, below) = if right_w < below_h {
+            (AtlasRect::new(rect.x + padded_w, rect.y, right_w, padded_h),
+             AtlasRect::new(rect.x, rect.y + padded_h, rect.w, below_h))
+        } else {
+            (AtlasRect::new(rect.x + padded_w, rect.y, right_w, rect.h),
+             AtlasRect::new(rect.x, rect.y + padded_h, padded_w, below_h))
+        };
+
+        for piece in vec![right, below] {
+            if piece.w > 0 && piece.h > 0 {
+                self.free.push(piece);
+            }
+        }
+        self.merge_free();
+
+        Some(AtlasRect::new(rect.x, rect.y, w, h))
+    }
+}
+
+/// A row of a `ShelfPacker`: the band from `y` to `y + height`, filled up to
+/// `used` from the left.
+#[derive(Clone, Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    used: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShelfPacker {
+    right: u32,
+    bottom: u32,
+    border: u32,
+    padding: u32,
+    shelves: Vec<Shelf>,
+    /// Where the next shelf starts.
+    next_y: u32,
+}
+
+impl ShelfPacker {
+    /// Packs into a `width` by `height` area the same way as
+    /// `MaxRectsPacker::new`.
+    pub fn new(width: u32, height: u32, border: u32, padding: u32) -> Self {
+        ShelfPacker {
+            right: width.saturating_sub(border) + padding,
+            bottom: height.saturating_sub(border) + padding,
+            border: border,
+            padding: padding,
+            shelves: Vec::new(),
+            next_y: border,
+        }
+    }
+
+    /// Continues packing an area `rects` were already placed in. Each top
+    /// edge of a rect starts a shelf as tall as the tallest rect there, so a
+    /// shelf packed area comes back as it was. Areas packed any other way
+    /// only get space to the right of everything across each shelf, and
+    /// below all of it.
+    pub fn resume(width: u32, height: u32, border: u32, padding: u32, rects: &[AtlasRect]) -> Self {
+        let mut packer = ShelfPacker::new(width, height, border, padding);
+
+        let mut tops: Vec<u32> = rects.iter().map(|rect| rect.y).collect();
+        tops.sort();
+        tops.dedup();
+
+        for &y in tops.iter() {
+            let height = rects.iter().filter(|rect| rect.y == y).map(|rect| rect.h + padding).max().unwrap();
+            let used = rects.iter()
+                .filter(|rect| rect.y < y + height && y < rect.y + rect.h + padding)
+                .map(|rect| rect.x + rect.w + padding)
+                .max()
+                .unwrap();
+            packer.shelves.push(Shelf { y: y, height: height, used: used });
+        }
+
+        packer.next_y = rects.iter().map(|rect| rect.y + rect.h + padding).max().unwrap_or(border);
+        packer
+    }
+
+    /// Finds the shelf with room for a padded `w` by `h` rect that wastes
+    /// the least height.
+    fn best_shelf(&self, w: u32, h: u32) -> Option<usize> {
+        self.shelves.iter()
+            .enumerate()
+            .filter(|&(_, shelf)| shelf.height >= h && shelf.used + w <= self.right)
+            .min_by_key(|&(_, shelf)| shelf.height - h)
+            .map(|(idx, _)| idx)
+    }
+
+    fn fits_new_shelf(&self, w: u32, h: u32) -> bool {
+        self.border + w <= self.right && self.next_y + h <= self.bottom
+    }
+}
+
+impl RectPacker for ShelfPacker {
+    fn can_pack(&self, w: u32, h: u32) -> bool {
+        let (w, h) = (w + self.padding, h + self.padding);
+        self.best_shelf(w, h).is_some() || self.fits_new_shelf(w, h)
+    }
+
+    fn pack(&mut self, w: u32, h: u32) -> Option<AtlasRect> {
+        let (padded_w, padded_h) = (w + self.padding, h + self.padding);
+        let (x, y) = match self.best_shelf(padded_w, padded_h) {
+            Some(idx) => (self.shelves[idx].used, self.shelves[idx].y),
+            None if self.fits_new_shelf(padded_w, padded_h) => {
+                let y = self.next_y;
+                self.shelves.push(Shelf { y: y, height: padded_h, used: self.border });
+                self.next_y += padded_h;
+                (self.border, y)
+            },
+            None => return None,
+        };
+
+        // Resumed shelves can overlap, so every one the rect crosses is
+        // filled past it.
+        for shelf in self.shelves.iter_mut() {
+            if shelf.y < y + padded_h && y < shelf.y + shelf.height {
+                shelf.used = cmp::max(shelf.used, x + padded_w);
+            }
+        }
+
+        Some(AtlasRect::new(x, y, w, h))
+    }
+}
+
+/// The top edge of a `SkylinePacker`'s placed rects from `x` to `x + width`.
+#[derive(Clone, Debug)]
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct SkylinePacker {
+    right: u32,
+    bottom: u32,
+    padding: u32,
+    /// Left to right, covering the whole width between the borders.
+    skyline: Vec<Segment>,
+}
+
+impl SkylinePacker {
+    /// Packs into a `width` by `height` area the same way as
+    /// `MaxRectsPacker::new`.
+    pub fn new(width: u32, height: u32, border: u32, padding: u32) -> Self {
+        let right = width.saturating_sub(border) + padding;
+        SkylinePacker {
+            right: right,
+            bottom: height.saturating_sub(border) + padding,
+            padding: padding,
+            skyline: vec![Segment { x: border, y: border, width: right.saturating_sub(border) }],
+        }
+    }
+
+    /// Finds where a padded `w` by `h` rect resting on the skyline ends the
+    /// lowest, preferring the leftmost of equally low places.
+    fn best_fit(&self, w: u32, h: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(u32, u32)> = None;
+        for (idx, segment) in self.skyline.iter().enumerate() {
+            let x = segment.x;
+            if x + w > self.right {
+                break;
+            }
+            let y = self.skyline[idx..].iter()
+                .take_while(|other| other.x < x + w)
+                .map(|other| other.y)
+                .max()
+                .unwrap();
+            if y + h <= self.bottom && best.map_or(true, |(bx, by)| (y, x) < (by, bx)) {
+                best = Some((x, y));
+            }
+        }
+        best
+    }
+
+    /// Raises the skyline over a placed rect.
+    fn place(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let end = x + w;
+        let mut skyline = Vec::with_capacity(self.skyline.len() + 2);
+        let mut placed = false;
+
+        for segment in self.skyline.drain(..) {
+            let segment_end = segment.x + segment.width;
+            if segment_end <= x || segment.x >= end {
+                skyline.push(segment);
+                continue;
+            }
+
+            if segment.x < x {
+                skyline.push(Segment { x: segment.x, y: segment.y, width: x - segment.x });
+            }
+            if !placed {
+                skyline.push(Segment { x: x, y: y + h, width: w });
+                placed = true;
+            }
+            if segment_end > end {
+                skyline.push(Segment { x: end, y: segment.y, width: segment_end - end });
+            }
+        }
+
+        // Join neighbours at the same height, so the search stays short.
+        let mut merged: Vec<Segment> = Vec::with_capacity(skyline.len());
+        for segment in skyline {
+            if merged.last().map_or(false, |last| last.y == segment.y) {
+                merged.last_mut().unwrap().width += segment.width;
+            } else {
+                merged.push(segment);
+            }
+        }
+        self.skyline = merged;
+    }
+}
+
+impl RectPacker for SkylinePacker {
+    fn can_pack(&self, w: u32, h: u32) -> bool {
+        self.best_fit(w + self.padding, h + self.padding).is_some()
+    }
+
+    fn pack(&mut self, w: u32, h: u32) -> Option<AtlasRect> {
+        let (padded_w, padded_h) = (w + self.padding, h + self.padding);
+        let (x, y) = match self.best_fit(padded_w, padded_h) {
+            Some(place) => place,
+            None        => return None,
+        };
+
+        self.place(x, y, padded_w, padded_h);
+        Some(AtlasRect::new(x, y, w, h))
+    }
+}
+
+/// Makes a packer of plain rects for `config`'s algorithm, area and
+/// padding.
+pub fn rect_packer(config: &PackerConfig) -> Box<dyn RectPacker> {
+    let (width, height) = (config.max_width, config.max_height);
+    let (border, padding) = (config.border_padding, config.padding);
+
+    match config.algorithm {
+        PackAlgorithm::Skyline    => Box::new(SkylinePacker::new(width, height, border, padding)),
+        PackAlgorithm::MaxRects   => Box::new(MaxRectsPacker::new(width, height, border, padding)),
+        PackAlgorithm::Guillotine => Box::new(GuillotinePacker::new(width, height, border, padding)),
+        PackAlgorithm::Shelf      => Box::new(ShelfPacker::new(width, height, border, padding)),
+    }
+}
+
+/// Makes a packer like `rect_packer` that continues around `rects`. Only
+/// shelves can carry on their own way; every other algorithm carries on as
+/// max rects, which finds all the space between rects placed any way.
+pub fn resume_rect_packer(config: &PackerConfig, rects: &[AtlasRect]) -> Box<dyn RectPacker> {
+    let (width, height) = (config.max_width, config.max_height);
+    let (border, padding) = (config.border_padding, config.padding);
+
+    match config.algorithm {
+        PackAlgorithm::Shelf => Box::new(ShelfPacker::resume(width, height, border, padding, rects)),
+        _                    => Box::new(MaxRectsPacker::resume(width, height, border, padding, rects)),
+    }
+}
+
+fn draw_outline(page: &mut DynamicImage, rect: &AtlasRect) {
+    let red = Rgba { data: [255, 0, 0, 255] };
+    for y in rect.y..rect.y + rect.h {
+        for x in rect.x..rect.x + rect.w {
+            if x == rect.x || y == rect.y || x == rect.x + rect.w - 1 || y == rect.y + rect.h - 1 {
+                page.put_pixel(x, y, red);
+            }
+        }
+    }
+}
+
+fn overlaps(a: &AtlasRect, b: &AtlasRect) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+fn contains(outer: &AtlasRect, inner: &AtlasRect) -> bool {
+    inner.x >= outer.x && inner.y >= outer.y &&
+        inner.x + inner.w <= outer.x + outer.w && inner.y + inner.h <= outer.y + outer.h
+}
+
+/// One page's packer, with the images packed onto it.
+pub struct PagePacker<'a> {
+    layout: PageLayout<'a>,
+    allow_rotation: bool,
+}
+
+enum PageLayout<'a> {
+    Skyline(TexturePacker<'a, DynamicImage, TexturePackerSkyline<Rgba<u8>>>),
+    Rects {
+        packer: Box<dyn RectPacker>,
+        border: u32,
+        outlines: bool,
+        images: Vec<(AtlasRect, Cow<'a, DynamicImage>)>,
+    },
+}
+
+impl<'a> PageLayout<'a> {
+    fn new(config: &PackerConfig) -> Self {
+        if config.algorithm == PackAlgorithm::Skyline {
+            return PageLayout::Skyline(TexturePacker::new_skyline(config.texture_packer_config()));
+        }
+
+        PageLayout::Rects {
+            packer: rect_packer(config),
+            border: config.border_padding,
+            outlines: config.outlines,
+            images: Vec::new(),
+        }
+    }
+}
+
+impl<'a> PagePacker<'a> {
+    pub fn new(config: &PackerConfig) -> Self {
+        PagePacker {
+            layout: PageLayout::new(config),
+            allow_rotation: config.allow_rotation,
+        }
+    }
+
+    pub fn can_pack(&self, image: &DynamicImage) -> bool {
+        self.fits(image, false) || (self.allow_rotation && self.fits(image, true))
+    }
+
+    fn fits(&self, image: &DynamicImage, rotated: bool) -> bool {
+        match self.layout {
+            PageLayout::Skyline(ref packer) if rotated => packer.can_pack(&image.rotate90()),
+            PageLayout::Skyline(ref packer) => packer.can_pack(image),
+            PageLayout::Rects { ref packer, .. } => {
+                let (w, h) = image.dimensions();
+                if rotated { packer.can_pack(h, w) } else { packer.can_pack(w, h) }
+            },
+        }
+    }
+
+    /// Packs an image that outlives the packer, and gets where it went.
+    pub fn pack_ref(&mut self, key: String, image: &'a DynamicImage) -> Result<AtlasRect, String> {
+        self.pack(key, Cow::Borrowed(image))
+    }
+
+    pub fn pack_own(&mut self, key: String, image: DynamicImage) -> Result<AtlasRect, String> {
+        self.pack(key, Cow::Owned(image))
+    }
+
+    fn pack(&mut self, key: String, image: Cow<'a, DynamicImage>) -> Result<AtlasRect, String> {
+        if self.allow_rotation && !self.fits(&image, false) && self.fits(&image, true) {
+            let rect = self.pack_upright(key, Cow::Owned(image.rotate90()))?;
+            return Ok(AtlasRect { rotated: true, ..rect });
+        }
+        self.pack_upright(key, image)
+    }
+
+    fn pack_upright(&mut self, key: String, image: Cow<'a, DynamicImage>) -> Result<AtlasRect, String> {
+        match self.layout {
+            PageLayout::Skyline(ref mut packer) => {
+                let packed = match image {
+                    Cow::Borrowed(image) => packer.pack_ref(key.clone(), image),
+                    Cow::Owned(image)    => packer.pack_own(key.clone(), image),
+                };
+                packed.map_err(|e| format!("{:?}", e))?;
+                Ok(AtlasRect::from_packer(packer.get_frame(&key).unwrap().frame.clone()))
+            },
+            PageLayout::Rects { ref mut packer, ref mut images, .. } => {
+                let (w, h) = image.dimensions();
+                let rect = packer.pack(w, h).ok_or_else(|| "No room left on the page".to_string())?;
+                images.push((rect.clone(), image));
+                Ok(rect)
+            },
+        }
+    }
+
+    /// Gets how wide the packed frames reach, with the border after them.
+    pub fn width(&self) -> u32 {
+        match self.layout {
+            PageLayout::Skyline(ref packer) => PackerTexture::width(packer),
+            PageLayout::Rects { ref images, border, .. } => {
+                images.iter().map(|&(ref rect, _)| rect.x + rect.w + border).max().unwrap_or(0)
+            },
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        match self.layout {
+            PageLayout::Skyline(ref packer) => PackerTexture::height(packer),
+            PageLayout::Rects { ref images, border, .. } => {
+                images.iter().map(|&(ref rect, _)| rect.y + rect.h + border).max().unwrap_or(0)
+            },
+        }
+    }
+
+    /// Draws every packed image onto a page as wide and high as they reach.
+    pub fn export(&self) -> Result<DynamicImage, String> {
+        match self.layout {
+            PageLayout::Skyline(ref packer) => ImageExporter::export(packer),
+            PageLayout::Rects { ref images, outlines, .. } => {
+                let mut page = DynamicImage::new_rgba8(self.width(), self.height());
+                for &(ref rect, ref image) in images.iter() {
+                    page.copy_from(&**image, rect.x, rect.y);
+                    if outlines {
+                        draw_outline(&mut page, rect);
+                    }
+                }
+                Ok(page)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::AtlasError;
+    use texture_atlas::TextureAtlasBuilder;
+
+    #[test]
+    fn test_max_rects() {
+        let mut packer = MaxRectsPacker::new(64, 64, 1, 2);
+        let mut placed = Vec::new();
+        for &(w, h) in [(40, 20), (20, 40), (16, 16), (16, 16), (20, 18)].iter() {
+            assert!(packer.can_pack(w, h));
+            placed.push(packer.pack(w, h).unwrap());
+        }
+
+        for (i, a) in placed.iter().enumerate() {
+            assert!(a.x >= 1 && a.y >= 1 && a.x + a.w <= 63 && a.y + a.h <= 63);
+            for b in placed.iter().skip(i + 1) {
+                let padded = AtlasRect::new(a.x, a.y, a.w + 2, a.h + 2);
+                assert!(!overlaps(&padded, b) && !overlaps(&AtlasRect::new(b.x, b.y, b.w + 2, b.h + 2), a));
+            }
+        }
+
+        assert!(!packer.can_pack(40, 40));
+        assert!(MaxRectsPacker::new(64, 64, 1, 2).can_pack(62, 62));
+
+        let mut resumed = MaxRectsPacker::resume(64, 64, 1, 2, &placed[1..]);
+        assert_eq!(resumed.pack(40, 20).map(|rect| (rect.x, rect.y)), Some((placed[0].x, placed[0].y)));
+    }
+
+    #[test]
+    fn test_guillotine_grid() {
+        let mut packer = GuillotinePacker::new(64, 64, 0, 0);
+        let mut placed: Vec<_> = (0..16).map(|_| packer.pack(16, 16).unwrap()).collect();
+        assert!(!packer.can_pack(1, 1));
+
+        placed.sort_by_key(|rect| (rect.y, rect.x));
+        for (i, rect) in placed.iter().enumerate() {
+            assert_eq!((rect.x, rect.y), ((i as u32 % 4) * 16, (i as u32 / 4) * 16));
+        }
+
+        let mut packer = GuillotinePacker::new(64, 64, 0, 0);
+        packer.pack(32, 16).unwrap();
+        packer.pack(32, 16).unwrap();
+        assert_eq!(packer.pack(64, 48).map(|rect| (rect.x, rect.y)), Some((0, 16)));
+    }
+
+    #[test]
+    fn test_shelves() {
+        let mut packer = ShelfPacker::new(64, 64, 0, 0);
+        let placed: Vec<_> = [(32, 16), (16, 8), (40, 24), (16, 16), (8, 8)].iter()
+            .map(|&(w, h)| packer.pack(w, h).map(|rect| (rect.x, rect.y)).unwrap())
+            .collect();
+        assert_eq!(placed, vec![(0, 0), (32, 0), (0, 16), (48, 0), (40, 16)]);
+
+        let rects = vec![AtlasRect::new(0, 0, 32, 16), AtlasRect::new(32, 0, 16, 8), AtlasRect::new(0, 16, 40, 24)];
+        let mut resumed = ShelfPacker::resume(64, 64, 0, 0, &rects);
+        assert_eq!(resumed.pack(16, 16).map(|rect| (rect.x, rect.y)), Some((48, 0)));
+        assert_eq!(resumed.pack(64, 24).map(|rect| (rect.x, rect.y)), Some((0, 40)));
+        assert_eq!(resumed.pack(24, 24).map(|rect| (rect.x, rect.y)), Some((40, 16)));
+        assert!(!resumed.can_pack(1, 1));
+    }
+
+    #[test]
+    fn test_skyline() {
+        let mut packer = SkylinePacker::new(64, 64, 0, 0);
+        let placed: Vec<_> = [(32, 16), (16, 32), (16, 8), (32, 8), (64, 40)].iter()
+            .map(|&(w, h)| packer.pack(w, h).map(|rect| (rect.x, rect.y)))
+            .collect();
+        assert_eq!(placed, vec![Some((0, 0)), Some((32, 0)), Some((48, 0)), Some((0, 16)), None]);
+        assert_eq!(packer.pack(16, 56).map(|rect| (rect.x, rect.y)), Some((48, 8)));
+        assert!(!packer.can_pack(48, 48));
+    }
+
+    #[test]
+    fn test_packer_config() {
+        let config = PackerConfig {
+            algorithm: PackAlgorithm::MaxRects,
+            max_width: 32,
+            max_height: 16,
+            padding: 2,
+            border_padding: 1,
+            allow_rotation: false,
+            outlines: true,
+        };
+        let mut builder = TextureAtlasBuilder::with_config(config);
+        let mut green = DynamicImage::new_rgba8(8, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                green.put_pixel(x, y, Rgba { data: [0, 255, 0, 255] });
+            }
+        }
+        builder.add_texture_image("a", green.clone()).unwrap();
+        builder.add_texture_image("b", green).unwrap();
+        {
+            let placed = |key: &str| builder.frame(key).map(|rect| (rect.x, rect.y, rect.w, rect.h));
+            assert_eq!(placed("a"), Some((1, 1, 8, 8)));
+            assert_eq!(placed("b"), Some((11, 1, 8, 8)));
+        }
+        assert_eq!(builder.free_area(), 32 * 16 - 2 * 64);
+
+        match builder.add_texture_image("wide", DynamicImage::new_rgba8(40, 8)) {
+            Err(AtlasError::FrameTooLarge(_, (40, 8), 16)) => (),
+            other => panic!("expected FrameTooLarge, got {:?}", other.map(|_| ())),
+        }
+
+        let atlas = builder.build_cpu(None).unwrap();
+        let page = atlas.get_texture();
+        assert_eq!(page.dimensions(), (20, 10));
+        assert_eq!(page.get_pixel(1, 1), Rgba { data: [255, 0, 0, 255] });
+        assert_eq!(page.get_pixel(4, 4), Rgba { data: [0, 255, 0, 255] });
+    }
+}
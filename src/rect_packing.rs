@@ -0,0 +1,170 @@
+//! Packing plain rects with the algorithms and options pages are packed
+//! with, for things that aren't images: shadow map tiles, lightmap charts,
+//! or regions of a render target. Nothing here holds pixels; a `RectAtlas`
+//! only says where each rect went.
+//!
+//! Skyline packing here is this crate's own, since texture_packer's only
+//! packs images, so it can place rects differently from how a page of
+//! images of the same sizes would be packed.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use AtlasRect;
+pub use packing::{RectPacker, SkylinePacker, MaxRectsPacker, GuillotinePacker, ShelfPacker, rect_packer, resume_rect_packer};
+use packing::{self, PackerConfig};
+
+/// An area rects are packed into one at a time, looked up by the key they
+/// were inserted under.
+pub struct RectAtlas<K> {
+    config: PackerConfig,
+    packer: Box<dyn RectPacker>,
+    placements: HashMap<K, AtlasRect>,
+    /// Whether rects were removed since the packer last saw every placement.
+    removed: bool,
+}
+
+impl<K: Hash + Eq> RectAtlas<K> {
+    /// Packs into a `max_width` by `max_height` area with `config`'s
+    /// algorithm, padding and rotation. `outlines` is ignored, having
+    /// nothing to draw on.
+    pub fn new(config: &PackerConfig) -> Self {
+        RectAtlas {
+            config: *config,
+            packer: packing::rect_packer(config),
+            placements: HashMap::new(),
+            removed: false,
+        }
+    }
+
+    /// Whether a `w` by `h` rect would fit, turned if rotation is allowed.
+    pub fn can_insert(&self, w: u32, h: u32) -> bool {
+        self.fits(&*self.packer, w, h) || (self.removed && self.fits(&*self.resumed_packer(), w, h))
+    }
+
+    fn fits(&self, packer: &dyn RectPacker, w: u32, h: u32) -> bool {
+        packer.can_pack(w, h) || (self.config.allow_rotation && packer.can_pack(h, w))
+    }
+
+    /// Places a `w` by `h` rect and gets where it went, or `None` if there's
+    /// no room left for it. With `allow_rotation`, a rect that only fits
+    /// turned is placed that way and marked `AtlasRect::rotated`.
+    ///
+    /// Inserting a key again moves it, leaving it without a place if the new
+    /// size doesn't fit.
+    pub fn insert(&mut self, key: K, w: u32, h: u32) -> Option<AtlasRect> {
+        self.remove(&key);
+
+        let mut rect = self.place(w, h);
+        if rect.is_none() && self.removed {
+            self.packer = self.resumed_packer();
+            self.removed = false;
+            rect = self.place(w, h);
+        }
+
+        if let Some(ref rect) = rect {
+            self.placements.insert(key, rect.clone());
+        }
+        rect
+    }
+
+    fn place(&mut self, w: u32, h: u32) -> Option<AtlasRect> {
+        if self.packer.can_pack(w, h) {
+            self.packer.pack(w, h)
+        } else if self.config.allow_rotation {
+            self.packer.pack(h, w).map(|rect| AtlasRect::new_rotated(rect.x, rect.y, rect.w, rect.h))
+        } else {
+            None
+        }
+    }
+
+    /// Takes out the rect inserted under `key`, and gets where it was. Its
+    /// area is packed into again once a rect doesn't fit anywhere else; see
+    /// `resume_rect_packer` for how packing carries on from then.
+    pub fn remove(&mut self, key: &K) -> Option<AtlasRect> {
+        let rect = self.placements.remove(key);
+        if rect.is_some() {
+            self.removed = true;
+        }
+        rect
+    }
+
+    /// Makes a packer that carries on around the rects placed now, sorted so
+    /// it doesn't depend on the order of the map.
+    fn resumed_packer(&self) -> Box<dyn RectPacker> {
+        let mut rects: Vec<AtlasRect> = self.placements.values().cloned().collect();
+        rects.sort_by_key(|rect| (rect.y, rect.x));
+        packing::resume_rect_packer(&self.config, &rects)
+    }
+
+    /// Gets where the rect inserted under `key` was placed.
+    pub fn placement(&self, key: &K) -> Option<&AtlasRect> {
+        self.placements.get(key)
+    }
+
+    /// Gets every placed rect by key.
+    pub fn placements(&self) -> &HashMap<K, AtlasRect> {
+        &self.placements
+    }
+
+    pub fn len(&self) -> usize {
+        self.placements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.placements.is_empty()
+    }
+
+    /// Gets how wide and high the placed rects reach, with the border after
+    /// them, the same as the size of a page packed with them would be.
+    pub fn used_size(&self) -> (u32, u32) {
+        let border = self.config.border_padding;
+        let width = self.placements.values().map(|rect| rect.x + rect.w + border).max().unwrap_or(0);
+        let height = self.placements.values().map(|rect| rect.y + rect.h + border).max().unwrap_or(0);
+        (width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packing::PackAlgorithm;
+
+    #[test]
+    fn test_rect_atlas() {
+        let config = PackerConfig {
+            algorithm: PackAlgorithm::Skyline,
+            max_width: 64,
+            max_height: 32,
+            padding: 2,
+            border_padding: 1,
+            allow_rotation: true,
+            outlines: false,
+        };
+        let mut shadows = RectAtlas::new(&config);
+        let placed = |rect: Option<AtlasRect>| rect.map(|rect| (rect.x, rect.y, rect.w, rect.h, rect.rotated));
+        assert_eq!(placed(shadows.insert("sun", 30, 30)), Some((1, 1, 30, 30, false)));
+        assert_eq!(placed(shadows.insert("lamp", 16, 20)), Some((33, 1, 16, 20, false)));
+
+        // 24 by 12 only fits turned, in the column left at the right edge.
+        assert!(shadows.can_insert(24, 12));
+        assert_eq!(placed(shadows.insert("torch", 24, 12)), Some((51, 1, 12, 24, true)));
+        assert_eq!(placed(shadows.insert("moon", 30, 30)), None);
+        assert!(shadows.placement(&"moon").is_none());
+
+        assert_eq!(shadows.len(), 3);
+        assert_eq!(shadows.placement(&"lamp").map(|rect| rect.source_size()), Some((16, 20)));
+        assert_eq!(shadows.placement(&"torch").map(|rect| rect.source_size()), Some((24, 12)));
+        assert_eq!(shadows.used_size(), (64, 32));
+
+        // Once sun is gone, moon goes in its place.
+        assert_eq!(shadows.remove(&"sun").map(|rect| (rect.x, rect.y)), Some((1, 1)));
+        assert!(shadows.can_insert(30, 30));
+        assert_eq!(placed(shadows.insert("moon", 30, 30)), Some((1, 1, 30, 30, false)));
+
+        let mut upright = RectAtlas::new(&PackerConfig { allow_rotation: false, ..config });
+        upright.insert(0, 30, 30).unwrap();
+        upright.insert(1, 16, 20).unwrap();
+        assert!(upright.insert(2, 24, 12).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use DefaultTexture;
+use backend::PageTexture;
+use tile_atlas::{AtlasFrame, TileAtlas};
+
+/// A handle to an atlas in an `AtlasRegistry`. Handles are plain copyable
+/// values, so any number of systems can hold on to one without borrowing
+/// the registry. A handle to a removed atlas stays invalid even after its
+/// slot is reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AtlasId {
+    index: u32,
+    generation: u32,
+}
+
+/// A handle to a frame of an atlas in an `AtlasRegistry`, resolved once from
+/// its key with `AtlasRegistry::frame_id`. It keeps naming the same key
+/// while the atlas changes, and stops resolving if that frame is removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FrameId {
+    atlas: AtlasId,
+    index: u32,
+}
+
+impl FrameId {
+    pub fn atlas(&self) -> AtlasId {
+        self.atlas
+    }
+}
+
+struct Entry<T> {
+    atlas: TileAtlas<T>,
+    /// Keys handed out as `FrameId`s, in the order they were first looked
+    /// up, so a `FrameId` is an index into this. Entries are never moved,
+    /// so frames added to the atlas later don't shift the others.
+    frame_keys: Vec<String>,
+    frame_indices: HashMap<String, u32>,
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Option<Entry<T>>,
+}
+
+/// Owns any number of atlases and hands out `AtlasId`s and `FrameId`s to
+/// reach them.
+pub struct AtlasRegistry<T = DefaultTexture> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T: PageTexture> AtlasRegistry<T> {
+    pub fn new() -> Self {
+        AtlasRegistry {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn insert(&mut self, atlas: TileAtlas<T>) -> AtlasId {
+        let entry = Entry {
+            atlas: atlas,
+            frame_keys: Vec::new(),
+            frame_indices: HashMap::new(),
+        };
+
+        self.len += 1;
+
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.entry = Some(entry);
+                AtlasId { index: index, generation: slot.generation }
+            },
+            None => {
+                self.slots.push(Slot { generation: 0, entry: Some(entry) });
+                AtlasId { index: self.slots.len() as u32 - 1, generation: 0 }
+            },
+        }
+    }
+
+    /// Takes an atlas out of the registry. Every handle to it, and to its
+    /// frames, stops resolving.
+    pub fn remove(&mut self, id: AtlasId) -> Option<TileAtlas<T>> {
+        if self.entry(id).is_none() {
+            return None;
+        }
+
+        let slot = &mut self.slots[id.index as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        self.len -= 1;
+        slot.entry.take().map(|entry| entry.atlas)
+    }
+
+    pub fn contains(&self, id: AtlasId) -> bool {
+        self.entry(id).is_some()
+    }
+
+    pub fn get(&self, id: AtlasId) -> Option<&TileAtlas<T>> {
+        self.entry(id).map(|entry| &entry.atlas)
+    }
+
+    pub fn get_mut(&mut self, id: AtlasId) -> Option<&mut TileAtlas<T>> {
+        self.entry_mut(id).map(|entry| &mut entry.atlas)
+    }
+
+    /// Gets a handle to the frame stored under `key` in an atlas, as it is
+    /// now, so frames added since the atlas was inserted are found too.
+    /// Looking up the same key again gives the same handle.
+    pub fn frame_id(&mut self, id: AtlasId, key: &str) -> Option<FrameId> {
+        let entry = match self.entry_mut(id) {
+            Some(entry) => entry,
+            None        => return None,
+        };
+
+        if !entry.atlas.frames().contains_key(key) {
+            return None;
+        }
+
+        if let Some(&index) = entry.frame_indices.get(key) {
+            return Some(FrameId { atlas: id, index: index });
+        }
+
+        let index = entry.frame_keys.len() as u32;
+        entry.frame_keys.push(key.to_string());
+        entry.frame_indices.insert(key.to_string(), index);
+        Some(FrameId { atlas: id, index: index })
+    }
+
+    pub fn frame(&self, id: FrameId) -> Option<&AtlasFrame> {
+        self.entry(id.atlas).and_then(|entry| {
+            entry.frame_keys.get(id.index as usize).and_then(|key| entry.atlas.frames().get(key))
+        })
+    }
+
+    /// Gets the key of a frame, or `None` if the frame was removed from its
+    /// atlas.
+    pub fn frame_key(&self, id: FrameId) -> Option<&str> {
+        self.entry(id.atlas).and_then(|entry| {
+            entry.frame_keys.get(id.index as usize)
+                .filter(|key| entry.atlas.frames().contains_key(key.as_str()))
+                .map(|key| key.as_str())
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn entry(&self, id: AtlasId) -> Option<&Entry<T>> {
+        match self.slots.get(id.index as usize) {
+            Some(slot) if slot.generation == id.generation => slot.entry.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn entry_mut(&mut self, id: AtlasId) -> Option<&mut Entry<T>> {
+        match self.slots.get_mut(id.index as usize) {
+            Some(slot) if slot.generation == id.generation => slot.entry.as_mut(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas::TileAtlasBuilder;
+
+    fn atlas(key: &str) -> TileAtlas<DynamicImage> {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image(key, DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_tile(key, 0, (0, 0)).unwrap();
+        builder.build(&MemoryBackend, None).unwrap()
+    }
+
+    #[test]
+    fn test_stale_handles() {
+        let mut registry = AtlasRegistry::new();
+        let first = registry.insert(atlas("a"));
+        let frame = registry.frame_id(first, "a").unwrap();
+        assert!(registry.frame(frame).is_some());
+        assert_eq!(registry.frame_key(frame), Some("a"));
+        assert_eq!(registry.frame_id(first, "b"), None);
+
+        assert!(registry.remove(first).is_some());
+        let second = registry.insert(atlas("a"));
+
+        assert!(first != second);
+        assert!(registry.get(first).is_none());
+        assert!(registry.frame(frame).is_none());
+        assert!(registry.get(second).is_some());
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_frames_added_later() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.set_reserve_page_space(true);
+        builder.add_frame_image("b", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+
+        let mut registry = AtlasRegistry::new();
+        let id = registry.insert(builder.build(&MemoryBackend, None).unwrap());
+        let b = registry.frame_id(id, "b").unwrap();
+        assert_eq!(registry.frame_id(id, "a"), None);
+
+        registry.get_mut(id).unwrap().insert_frame("a", &DynamicImage::new_rgba8(8, 8), (8, 8)).unwrap();
+        let a = registry.frame_id(id, "a").unwrap();
+        assert!(a != b);
+        assert_eq!(registry.frame_id(id, "b"), Some(b));
+        assert_eq!((registry.frame_key(a), registry.frame_key(b)), (Some("a"), Some("b")));
+        assert_eq!(registry.frame(a).map(|frame| frame.tile_size()), Some((8, 8)));
+    }
+}
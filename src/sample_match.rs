@@ -0,0 +1,151 @@
+//! Finding the tiles a snippet of art came from, such as a crop of a
+//! screenshot, to track down duplicated or misreferenced art in large packs.
+//! Tiles are scored by the mean squared difference of their pixels from the
+//! sample's, so exact copies score 0 and near misses still rank highly.
+
+use std::collections::HashMap;
+
+use image::{DynamicImage, GenericImage};
+
+use AtlasRect;
+use ids::TileId;
+use tile_atlas::{self, AtlasFrame};
+
+/// A tile whose pixels resemble a sample. See `CpuAtlas::match_sample`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileMatch {
+    pub tile: TileId,
+    /// Which step of the tile's animation matched, or 0 for tiles that
+    /// aren't animated.
+    pub animation_frame: u64,
+    /// The key of the frame the matching pixels are in.
+    pub frame: String,
+    /// The mean squared difference per channel, from 0 for an exact copy to
+    /// 65025.
+    pub error: f64,
+}
+
+/// Scores every step of every tile against `sample` and returns the best
+/// `limit` matches, best first. Tiles of another size than the sample are
+/// sampled at the nearest pixel, so a crop of a scaled up screenshot still
+/// matches the tile it shows. Tiles whose cells lie outside of `pages`, as
+/// with pages that don't belong to `frames`, are skipped.
+pub fn match_sample(frames: &HashMap<String, AtlasFrame>,
+                    pages: &[DynamicImage],
+                    sample: &DynamicImage,
+                    limit: usize) -> Vec<TileMatch> {
+    let mut matches = Vec::new();
+
+    for (key, frame) in frames.iter() {
+        for (&index, tile) in frame.tiles().iter() {
+            let steps = tile_atlas::tile_steps(key, frame, index, tile);
+            for (step, &(ref step_key, offset)) in steps.iter().enumerate() {
+                let step_frame = match frames.get(step_key) {
+                    Some(step_frame) => step_frame,
+                    None             => continue,
+                };
+                let rect = tile_atlas::tile_cell_rect(step_frame, offset);
+                let page = match pages.get(step_frame.texture_idx()) {
+                    Some(page) if rect_inside(&rect, page.dimensions()) => page,
+                    _ => continue,
+                };
+                matches.push(TileMatch {
+                    tile: TileId(index),
+                    animation_frame: step as u64,
+                    frame: step_key.clone(),
+                    error: mean_squared_error(page, &rect, sample),
+                });
+            }
+        }
+    }
+
+    // errors are sums of squares divided by a count above 0, so never NaN.
+    matches.sort_by(|a, b| {
+        a.error.partial_cmp(&b.error).unwrap()
+            .then_with(|| a.tile.cmp(&b.tile))
+            .then_with(|| a.animation_frame.cmp(&b.animation_frame))
+    });
+    matches.truncate(limit);
+    matches
+}
+
+fn rect_inside(rect: &AtlasRect, (w, h): (u32, u32)) -> bool {
+    rect.x + rect.w <= w && rect.y + rect.h <= h
+}
+
+fn mean_squared_error(page: &DynamicImage, rect: &AtlasRect, sample: &DynamicImage) -> f64 {
+    let (sw, sh) = sample.dimensions();
+    if sw == 0 || sh == 0 {
+        return 0.0;
+    }
+
+    let (tw, th) = rect.source_size();
+    let mut sum = 0u64;
+    for y in 0..sh {
+        for x in 0..sw {
+            let (cx, cy) = (x * tw / sw, y * th / sh);
+            let (px, py) = if rect.rotated {
+                (rect.x + rect.w - 1 - cy, rect.y + cx)
+            } else {
+                (rect.x + cx, rect.y + cy)
+            };
+            let a = page.get_pixel(px, py);
+            let b = sample.get_pixel(x, y);
+            for channel in 0..4 {
+                let diff = a.data[channel] as i64 - b.data[channel] as i64;
+                sum += (diff * diff) as u64;
+            }
+        }
+    }
+
+    sum as f64 / (sw as u64 * sh as u64 * 4) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImage, Rgba};
+
+    use super::*;
+    use tile_atlas::TileAtlasBuilder;
+
+    fn solid(size: u32, color: [u8; 4]) -> DynamicImage {
+        let mut image = DynamicImage::new_rgba8(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                image.put_pixel(x, y, Rgba { data: color });
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_match_sample() {
+        let mut sheet = DynamicImage::new_rgba8(24, 8);
+        sheet.copy_from(&solid(8, [255, 0, 0, 255]), 0, 0);
+        sheet.copy_from(&solid(8, [0, 255, 0, 255]), 8, 0);
+        sheet.copy_from(&solid(8, [255, 0, 0, 255]), 16, 0);
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", sheet, (8, 8)).unwrap();
+        for index in 0..3 {
+            builder.add_tile("sheet", index, (index as u32, 0)).unwrap();
+        }
+        let atlas = builder.build_cpu(None).unwrap();
+
+        let mut screenshot = solid(16, [250, 4, 0, 255]);
+        screenshot.put_pixel(3, 3, Rgba { data: [0, 0, 0, 255] });
+        let found = atlas.match_sample(&screenshot, 2);
+        assert_eq!(found.iter().map(|m| m.tile).collect::<Vec<_>>(), vec![TileId(0), TileId(2)]);
+        assert_eq!(found[0].error, found[1].error);
+        assert!(found[0].error > 0.0);
+
+        let exact = atlas.match_sample(&solid(8, [0, 255, 0, 255]), 1);
+        assert_eq!((exact[0].tile, exact[0].error), (TileId(1), 0.0));
+
+        // pages too small for the frames, or missing, are skipped.
+        let small = [DynamicImage::new_rgba8(12, 8)];
+        let found = match_sample(atlas.frames(), &small, &solid(8, [255, 0, 0, 255]), 3);
+        assert_eq!(found.iter().map(|m| m.tile).collect::<Vec<_>>(), vec![TileId(0)]);
+        assert!(match_sample(atlas.frames(), &[], &screenshot, 3).is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+#[cfg(feature = "glium")]
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+
+/// How a page is filtered when it's drawn larger or smaller than its size.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Keeps pixel art crisp.
+    Nearest,
+    Linear,
+    /// Linear, blending between mip levels when drawn smaller.
+    Trilinear,
+}
+
+/// What happens to UVs outside of the page.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// How an atlas's pages should be sampled. Defaults to nearest filtering
+/// with clamped UVs and no anisotropy, which is what tile and sprite atlases
+/// almost always want.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SamplerSettings {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    /// The most samples taken for anisotropic filtering, where 1 turns it
+    /// off.
+    pub anisotropy: u16,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        SamplerSettings {
+            filter: Filter::Nearest,
+            wrap: Wrap::Clamp,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl SamplerSettings {
+    pub fn linear() -> Self {
+        SamplerSettings { filter: Filter::Linear, ..Default::default() }
+    }
+
+    /// Wraps `texture` in a glium sampler with these settings, for passing
+    /// as a uniform.
+    #[cfg(feature = "glium")]
+    pub fn sample<'t, T: 't>(&self, texture: &'t T) -> Sampler<'t, T> {
+        let (minify, magnify) = match self.filter {
+            Filter::Nearest   => (MinifySamplerFilter::Nearest, MagnifySamplerFilter::Nearest),
+            Filter::Linear    => (MinifySamplerFilter::Linear, MagnifySamplerFilter::Linear),
+            Filter::Trilinear => (MinifySamplerFilter::LinearMipmapLinear, MagnifySamplerFilter::Linear),
+        };
+        let wrap = match self.wrap {
+            Wrap::Clamp  => SamplerWrapFunction::Clamp,
+            Wrap::Repeat => SamplerWrapFunction::Repeat,
+            Wrap::Mirror => SamplerWrapFunction::Mirror,
+        };
+
+        Sampler::new(texture)
+            .minify_filter(minify)
+            .magnify_filter(magnify)
+            .wrap_function(wrap)
+            .anisotropy(self.anisotropy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_builder_sampler() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        assert_eq!(builder.build(&MemoryBackend, None).unwrap().sampler().filter, Filter::Nearest);
+
+        builder.set_sampler(SamplerSettings { anisotropy: 4, ..SamplerSettings::linear() });
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+        assert_eq!(atlas.sampler().filter, Filter::Linear);
+        assert_eq!(atlas.sampler().anisotropy, 4);
+    }
+}
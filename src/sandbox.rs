@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use error::{AtlasError, AtlasResult};
+
+/// Resolves `path` relative to `root`, returning `None` if it would end up
+/// outside of `root`. Absolute paths and `..` components that climb above
+/// `root` are rejected, and symlinks are followed for paths that exist.
+pub fn resolve_in_root(root: &Path, path: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    let mut depth = 0;
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                relative.push(part);
+                depth += 1;
+            },
+            Component::ParentDir => {
+                if depth == 0 {
+                    return None;
+                }
+                relative.pop();
+                depth -= 1;
+            },
+            Component::CurDir => (),
+            Component::RootDir | Component::Prefix(..) => return None,
+        }
+    }
+
+    let resolved = root.join(relative);
+
+    if let (Ok(real_root), Ok(real_path)) = (fs::canonicalize(root), fs::canonicalize(&resolved)) {
+        if !real_path.starts_with(&real_root) {
+            return None;
+        }
+    }
+
+    Some(resolved)
+}
+
+pub fn expect_in_root(root: &Path, path: &str) -> AtlasResult<PathBuf> {
+    match resolve_in_root(root, Path::new(path)) {
+        Some(resolved) => Ok(resolved),
+        None => Err(AtlasError::PathEscapesRoot(path.to_string(), root.to_path_buf())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_in_root() {
+        let root = Path::new("mods/example");
+        assert_eq!(resolve_in_root(root, Path::new("tiles/grass.png")),
+                   Some(PathBuf::from("mods/example/tiles/grass.png")));
+        assert_eq!(resolve_in_root(root, Path::new("./tiles/../grass.png")),
+                   Some(PathBuf::from("mods/example/grass.png")));
+        assert_eq!(resolve_in_root(root, Path::new("../other/grass.png")), None);
+        assert_eq!(resolve_in_root(root, Path::new("tiles/../../grass.png")), None);
+        assert_eq!(resolve_in_root(root, Path::new("/etc/passwd")), None);
+    }
+}
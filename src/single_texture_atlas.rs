@@ -0,0 +1,46 @@
+use {AtlasRect, DefaultTexture};
+
+pub type FrameIndex = usize;
+
+/// A texture atlas for a handful of frames, such as UI elements. Frames are
+/// stored in a `Vec` and looked up by the index they were added at, so hot
+/// drawing code can resolve a key once with `index_of` and skip hashing on
+/// every draw.
+pub struct SingleTextureAtlas<T = DefaultTexture> {
+    texture: T,
+    keys: Vec<String>,
+    rects: Vec<AtlasRect>,
+}
+
+impl<T> SingleTextureAtlas<T> {
+    pub fn new(texture: T, keys: Vec<String>, rects: Vec<AtlasRect>) -> Self {
+        assert_eq!(keys.len(), rects.len());
+
+        SingleTextureAtlas {
+            texture: texture,
+            keys: keys,
+            rects: rects,
+        }
+    }
+
+    pub fn get_texture(&self) -> &T {
+        &self.texture
+    }
+
+    pub fn index_of<K: AsRef<str>>(&self, key: K) -> Option<FrameIndex> {
+        let key = key.as_ref();
+        self.keys.iter().position(|k| k == key)
+    }
+
+    pub fn get_texture_area(&self, idx: FrameIndex) -> &AtlasRect {
+        &self.rects[idx]
+    }
+
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+}
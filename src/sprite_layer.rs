@@ -0,0 +1,103 @@
+use std::cmp::Ordering;
+
+use backend::PageTexture;
+use tile_atlas::{DrawInfo, TileAtlas, TileIndex};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub position: (f32, f32),
+    pub tile: TileIndex,
+    pub layer: i32,
+}
+
+/// A run of sprites that share a texture page and can be drawn together.
+pub struct SpriteBatch {
+    pub page: usize,
+    pub layer: i32,
+    pub sprites: Vec<(Sprite, DrawInfo)>,
+}
+
+/// Sorts sprites into back-to-front draw order and splits them into
+/// batches by texture page.
+///
+/// Sprites are ordered by layer, then by descending y within a layer, so
+/// that with y pointing up, sprites lower on screen are drawn over the ones
+/// behind them. Sprites with equal keys keep their input order. Batches
+/// never reorder sprites, so a new batch starts whenever the page changes.
+pub fn sort_sprites<T: PageTexture>(atlas: &TileAtlas<T>, sprites: &[Sprite], msecs: u64) -> Vec<SpriteBatch> {
+    let mut sorted = sprites.to_vec();
+    sorted.sort_by(|a, b| {
+        a.layer.cmp(&b.layer).then_with(|| {
+            b.position.1.partial_cmp(&a.position.1).unwrap_or(Ordering::Equal)
+        })
+    });
+
+    let mut batches: Vec<SpriteBatch> = Vec::new();
+
+    for sprite in sorted.into_iter() {
+        let info = atlas.get_tile_draw_info(sprite.tile, msecs);
+
+        let continues_batch = match batches.last() {
+            Some(batch) => batch.page == info.page && batch.layer == sprite.layer,
+            None        => false,
+        };
+
+        if !continues_batch {
+            batches.push(SpriteBatch {
+                page: info.page,
+                layer: sprite.layer,
+                sprites: Vec::new(),
+            });
+        }
+
+        batches.last_mut().unwrap().sprites.push((sprite, info));
+    }
+
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_sort_sprites() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(32);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_tile("a", 0, (0, 0)).unwrap();
+        builder.add_tile("b", 1, (0, 0)).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+        let (a, b) = (atlas.get_tile_texture_idx(0), atlas.get_tile_texture_idx(1));
+        assert!(a != b);
+
+        // x numbers the sprites, to tell them apart once sorted.
+        let sprite = |n: u32, y: f32, tile: TileIndex, layer: i32| Sprite { position: (n as f32, y), tile: tile, layer: layer };
+        let sprites = [
+            sprite(0, 5.0, 0, 1),
+            sprite(1, 10.0, 0, 0),
+            sprite(2, 10.0, 0, 0),
+            sprite(3, 2.0, 1, 0),
+            sprite(4, 1.0, 0, 0),
+            sprite(5, 8.0, 0, 1),
+        ];
+        let batches = sort_sprites(&atlas, &sprites, 0);
+
+        let order: Vec<f32> = batches.iter()
+            .flat_map(|batch| batch.sprites.iter().map(|&(sprite, _)| sprite.position.0))
+            .collect();
+        assert_eq!(order, vec![1.0, 2.0, 3.0, 4.0, 5.0, 0.0]);
+
+        // the page changes twice in layer 0, then the layer changes on the
+        // same page.
+        let splits: Vec<(usize, i32, usize)> = batches.iter()
+            .map(|batch| (batch.page, batch.layer, batch.sprites.len()))
+            .collect();
+        assert_eq!(splits, vec![(a, 0, 2), (b, 0, 1), (a, 0, 1), (a, 1, 2)]);
+    }
+}
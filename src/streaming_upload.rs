@@ -0,0 +1,190 @@
+//! Uploading pages a band of rows at a time, for atlases large enough that
+//! creating every texture at once would stall a frame. Each page is created
+//! empty and filled with `GpuBackend::stream_texture`, which glium backends
+//! send through a pixel buffer object so the copy doesn't block the caller.
+
+use image::{DynamicImage, GenericImage};
+
+use backend::{GpuBackend, PageTexture, RawPage, Unflipped};
+use cpu_atlas::CpuAtlas;
+use error::{AtlasError, AtlasResult};
+use ids::PageId;
+use tile_atlas::TileAtlas;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadProgress {
+    Uploading { ready: usize, total: usize },
+    Done,
+}
+
+/// An in-progress upload of a `CpuAtlas`. Call `step` once per frame until
+/// it's done, then `finish` it into a `TileAtlas`. Pages become ready in page
+/// order, and can be drawn from as soon as they are.
+pub struct StreamingUpload<T> {
+    atlas: CpuAtlas,
+    textures: Vec<T>,
+    /// The page being filled and the next row of it to upload.
+    current: Option<(T, u32)>,
+    rows_per_step: u32,
+}
+
+impl<T: PageTexture> StreamingUpload<T> {
+    /// Starts uploading `atlas`, `rows_per_step` rows of pixels per `step`.
+    /// A 2048 pixel wide page takes 8KB per row, so 256 rows moves 2MB a
+    /// frame. Block compressed pages and pages with mipmaps are uploaded
+    /// whole in a single step.
+    pub fn new(atlas: CpuAtlas, rows_per_step: u32) -> Self {
+        assert!(rows_per_step > 0, "Need to upload at least one row per step");
+        StreamingUpload {
+            textures: Vec::with_capacity(atlas.passes()),
+            atlas: atlas,
+            current: None,
+            rows_per_step: rows_per_step,
+        }
+    }
+
+    /// Uploads the next band of rows, creating the next page's texture first
+    /// if the last one was finished.
+    pub fn step<B: GpuBackend<Texture = T>>(&mut self, backend: &B) -> AtlasResult<UploadProgress> {
+        trace_span!("stream_upload", page = self.textures.len());
+        if self.is_done() {
+            return Ok(UploadProgress::Done);
+        }
+
+        if self.atlas.flip_y() {
+            self.upload_band(backend)
+        } else {
+            self.upload_band(&Unflipped(backend))
+        }
+    }
+
+    fn upload_band<B: GpuBackend<Texture = T>>(&mut self, backend: &B) -> AtlasResult<UploadProgress> {
+        let page = self.textures.len();
+        let (mut texture, row) = match self.current.take() {
+            Some(current) => current,
+            None => {
+                if self.atlas.compressed_page(page).is_some() || !self.atlas.mipmaps(page).is_empty() {
+                    let whole = self.atlas.upload_page(backend, page)?;
+                    self.textures.push(whole);
+                    return Ok(self.progress());
+                }
+                let (w, h) = self.atlas.get_page_dimensions(page);
+                (backend.create_empty_texture(w, h)?, 0)
+            },
+        };
+
+        let (width, height) = self.atlas.get_page_dimensions(page);
+        let rows = self.rows_per_step.min(height - row);
+        let band = rgba_band(&self.atlas.pages()[page], row, rows);
+        backend.stream_texture(&mut texture, 0, row, RawPage {
+            data: &band,
+            width: width,
+            height: rows,
+        })?;
+
+        if row + rows >= height {
+            self.textures.push(texture);
+        } else {
+            self.current = Some((texture, row + rows));
+        }
+        Ok(self.progress())
+    }
+
+    pub fn progress(&self) -> UploadProgress {
+        if self.is_done() {
+            UploadProgress::Done
+        } else {
+            UploadProgress::Uploading {
+                ready: self.textures.len(),
+                total: self.atlas.passes(),
+            }
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.textures.len() >= self.atlas.passes()
+    }
+
+    pub fn is_page_ready<P: Into<PageId>>(&self, page: P) -> bool {
+        let PageId(page) = page.into();
+        page < self.textures.len()
+    }
+
+    /// Gets a page's texture if it's finished uploading.
+    pub fn page<P: Into<PageId>>(&self, page: P) -> Option<&T> {
+        let PageId(page) = page.into();
+        self.textures.get(page)
+    }
+
+    /// Gets the atlas being uploaded, whose tile queries can be used before
+    /// the pages are ready.
+    pub fn cpu_atlas(&self) -> &CpuAtlas {
+        &self.atlas
+    }
+
+    /// Turns the finished upload into an atlas. Check `is_done` first; this
+    /// fails if any page is still uploading.
+    pub fn finish(self) -> AtlasResult<TileAtlas<T>> {
+        if !self.is_done() {
+            return Err(AtlasError::Texture(format!("{} of {} pages are still uploading",
+                                                   self.atlas.passes() - self.textures.len(),
+                                                   self.atlas.passes())));
+        }
+
+        let atlas = self.atlas;
+        Ok(TileAtlas::new(atlas.locations().clone(), atlas.frames().clone(), self.textures)
+           .with_sampler(atlas.sampler())
+           .with_page_names(atlas.page_names().to_vec())
+           .with_flip_y(atlas.flip_y())
+           .with_half_texel_inset(atlas.half_texel_inset())
+           .with_mip_levels(atlas.mip_levels()))
+    }
+}
+
+/// Copies `rows` rows of `page` starting at `top` out as RGBA.
+fn rgba_band(page: &DynamicImage, top: u32, rows: u32) -> Vec<u8> {
+    let (width, _) = GenericImage::dimensions(page);
+    match *page {
+        DynamicImage::ImageRgba8(ref rgba) => {
+            let row_bytes = width as usize * 4;
+            rgba[top as usize * row_bytes..(top + rows) as usize * row_bytes].to_vec()
+        },
+        ref other => other.clone().crop(0, top, width, rows).to_rgba().into_raw(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImage, Rgba};
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_streaming_upload() {
+        let mut image = DynamicImage::new_rgba8(48, 24);
+        image.put_pixel(30, 20, Rgba { data: [255, 0, 0, 255] });
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", image, (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (1, 0)).unwrap();
+        let cpu_atlas = builder.build_cpu(None).unwrap();
+        let expected = cpu_atlas.pages()[0].clone();
+        let (_, height) = cpu_atlas.get_page_dimensions(0);
+
+        let mut upload = StreamingUpload::new(cpu_atlas, 100);
+        assert!(!upload.is_page_ready(0));
+
+        let mut steps = 0;
+        while upload.step(&MemoryBackend).unwrap() != UploadProgress::Done {
+            steps += 1;
+        }
+        assert_eq!(steps + 1, (height + 99) / 100);
+        assert!(upload.is_page_ready(0));
+
+        let atlas = upload.finish().unwrap();
+        assert_eq!(atlas.get_texture(0).raw_pixels(), expected.raw_pixels());
+        assert_eq!(atlas.get_tile_texture_size(0), (24, 24));
+    }
+}
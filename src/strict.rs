@@ -0,0 +1,139 @@
+//! Strict mode, enabled with the `strict` feature. Every entry point that can
+//! fail on bad content gets a `*_strict` variant that reports the failure as
+//! a `StrictError`. Bad content is reported as an `AtlasError` all the way
+//! down, so nothing here catches panics, and it works the same when the host
+//! is compiled with `panic = "abort"`.
+
+use std::error::Error;
+use std::fmt;
+
+use std::path::PathBuf;
+
+use backend::{GpuBackend, PageTexture};
+use error::{AtlasError, AtlasResult};
+use tile_atlas::{TileAtlas, TileAtlasBuilder, TileIndex, TileOffset};
+use tile_atlas_config;
+
+#[derive(Debug)]
+pub struct StrictError {
+    message: String,
+}
+
+impl fmt::Display for StrictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for StrictError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<AtlasError> for StrictError {
+    fn from(err: AtlasError) -> Self {
+        StrictError { message: err.to_string() }
+    }
+}
+
+pub type StrictResult<T> = Result<T, StrictError>;
+
+fn strict<T>(result: AtlasResult<T>) -> StrictResult<T> {
+    result.map_err(StrictError::from)
+}
+
+impl<T: PageTexture> TileAtlas<T> {
+    pub fn from_config_strict<B: GpuBackend<Texture = T>>(backend: &B, filename: &str) -> StrictResult<Self> {
+        strict(TileAtlas::from_config(backend, filename))
+    }
+}
+
+impl TileAtlasBuilder {
+    pub fn from_toml_strict(toml_str: &str) -> StrictResult<Self> {
+        strict(tile_atlas_config::builder_from_toml(toml_str, None))
+    }
+
+    pub fn add_frame_strict(&mut self, path_str: &str, tile_size: (u32, u32)) -> StrictResult<()> {
+        strict(self.add_frame(path_str, tile_size))
+    }
+
+    pub fn add_tile_strict(&mut self, path_str: &str, index: TileIndex, offset: TileOffset) -> StrictResult<()> {
+        strict(self.add_tile(path_str, index, offset))
+    }
+
+    pub fn build_strict<B: GpuBackend>(&self, backend: &B, packed_folder: Option<PathBuf>) -> StrictResult<TileAtlas<B::Texture>> {
+        strict(self.build(backend, packed_folder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+
+    use image::{self, DynamicImage};
+
+    use super::*;
+    use backend::MemoryBackend;
+    use tile_atlas_config::read_tile_manager_config;
+
+    fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+        let mut path = env::temp_dir();
+        path.push(name);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_malformed_configs() {
+        let configs = [
+            "",
+            "maps = 3",
+            "[[maps]]\nfile_path = 1\ntile_size = [24, 24]",
+            "[[maps]]\nfile_path = \"nonexistent.png\"\ntile_size = [24]",
+            "[[maps]]\nfile_path = \"nonexistent.png\"\ntile_size = [24, 24]\n[[tiles]]",
+            "maps = []\n[[tiles]]\natlas = \"nonexistent.png\"\noffset = [0, 0]",
+            "not = [valid toml",
+        ];
+
+        for config in configs.iter() {
+            assert!(TileAtlasBuilder::from_toml_strict(config).is_err());
+        }
+    }
+
+    #[test]
+    fn test_truncated_png() {
+        let mut data = Vec::new();
+        DynamicImage::new_rgba8(16, 16).save(&mut data, image::PNG).unwrap();
+        let len = data.len();
+        data.truncate(len / 2);
+        let path = temp_file("texture_atlas_strict_truncated.png", &data);
+
+        let mut builder = TileAtlasBuilder::new();
+        assert!(builder.add_frame_strict(path.to_str().unwrap(), (8, 8)).is_err());
+        assert!(builder.add_tile_strict(path.to_str().unwrap(), 0, (0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_cache() {
+        let path = temp_file("texture_atlas_strict_cache.bin", &[0xff, 0x00, 0x13, 0x37]);
+        assert!(strict(read_tile_manager_config(&path)).is_err());
+    }
+
+    #[test]
+    fn test_strict_build() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        assert!(builder.add_tile_strict("sheet", 0, (2, 0)).is_err());
+        assert!(builder.add_tile_strict("missing", 0, (0, 0)).is_err());
+
+        let mut oversized = TileAtlasBuilder::new();
+        oversized.add_frame_image("huge", DynamicImage::new_rgba8(64, 64), (64, 64)).unwrap();
+        assert!(oversized.add_tile_strict("huge", 0, (0, 0)).is_ok());
+        oversized.set_page_size(32);
+        assert!(oversized.build_strict(&MemoryBackend, None).is_err());
+    }
+}
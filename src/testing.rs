@@ -0,0 +1,90 @@
+//! Helpers for testing code that builds atlases, enabled with the `testing`
+//! feature. `build_headless` builds without a window or GL context, and the
+//! assertions check the layout of the result, so crates using this one can
+//! test their atlas construction in a plain `cargo test`.
+//!
+//! Tests that need real GL textures can open a context with
+//! `golden::headless_display` under the `golden` feature and `build`
+//! against it instead.
+
+use image::DynamicImage;
+
+use backend::{MemoryBackend, PageTexture};
+use error::AtlasResult;
+use ids::TileId;
+use tile_atlas::{TileAtlas, TileAtlasBuilder};
+
+/// Builds `builder` the way `build` would with a display, but keeps the
+/// pages in main memory.
+pub fn build_headless(builder: &TileAtlasBuilder) -> AtlasResult<TileAtlas<DynamicImage>> {
+    builder.build(&MemoryBackend, None)
+}
+
+/// Panics unless every frame lies inside its page.
+pub fn assert_frames_in_bounds<T: PageTexture>(atlas: &TileAtlas<T>) {
+    for (key, frame) in atlas.frames() {
+        let (w, h) = atlas.get_texture(frame.texture_idx()).dimensions();
+        let rect = frame.rect();
+        assert!(rect.x() + rect.w() <= w && rect.y() + rect.h() <= h,
+                "Frame {} at ({}, {}) of size {}x{} runs off its {}x{} page",
+                key, rect.x(), rect.y(), rect.w(), rect.h(), w, h);
+    }
+}
+
+/// Panics if any two frames on the same page overlap.
+pub fn assert_no_overlaps<T: PageTexture>(atlas: &TileAtlas<T>) {
+    let mut frames: Vec<_> = atlas.frames().iter().collect();
+    frames.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (i, &(key_a, a)) in frames.iter().enumerate() {
+        for &(key_b, b) in frames.iter().skip(i + 1) {
+            if a.texture_idx() != b.texture_idx() {
+                continue;
+            }
+
+            let (ra, rb) = (a.rect(), b.rect());
+            let overlaps = ra.x() < rb.x() + rb.w() && rb.x() < ra.x() + ra.w() &&
+                ra.y() < rb.y() + rb.h() && rb.y() < ra.y() + ra.h();
+            assert!(!overlaps, "Frames {} and {} overlap on page {}", key_a, key_b, a.texture_idx());
+        }
+    }
+}
+
+/// Panics unless `tile_type` is drawn at `size` pixels.
+pub fn assert_tile_size<T: PageTexture, I: Into<TileId>>(atlas: &TileAtlas<T>, tile_type: I, size: (u32, u32)) {
+    let TileId(tile_type) = tile_type.into();
+    let actual = atlas.try_get_tile_draw_info(tile_type, 0)
+        .unwrap_or_else(|e| panic!("Tile {} can't be drawn: {}", tile_type, e))
+        .pixel_size;
+    assert_eq!(actual, size, "Tile {} has the wrong size", tile_type);
+}
+
+/// Panics unless every tile in `tiles` is on one page, so they can be drawn
+/// in a single batch.
+pub fn assert_same_page<T: PageTexture>(atlas: &TileAtlas<T>, tiles: &[TileId]) {
+    let pages: Vec<_> = tiles.iter().map(|&tile| atlas.get_tile_texture_idx(tile)).collect();
+    if let Some(&first) = pages.first() {
+        assert!(pages.iter().all(|&page| page == first), "Tiles {:?} are spread over pages {:?}", tiles, pages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headless_layout() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(32, 32), (16, 16)).unwrap();
+        builder.add_tile("a", 0, (1, 0)).unwrap();
+        builder.add_tile("b", 1, (0, 0)).unwrap();
+
+        let atlas = build_headless(&builder).unwrap();
+        assert_frames_in_bounds(&atlas);
+        assert_no_overlaps(&atlas);
+        assert_tile_size(&atlas, 0, (24, 24));
+        assert_tile_size(&atlas, TileId(1), (16, 16));
+        assert_same_page(&atlas, &[TileId(0), TileId(1)]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use glium::backend::Facade;
+use glium::texture::{CompressedSrgbTexture2dArray, RawImage2d};
+use image::{DynamicImage, GenericImage, ImageBuffer, Rgba};
+
+use cpu_atlas::CpuAtlas;
+use error::{AtlasError, AtlasResult};
+use ids::TileId;
+use tile_atlas::{self, AtlasFrame, DrawInfo, TileAtlasBuilder, TileIndex};
+
+/// A tile atlas whose pages are the layers of one texture array, so a
+/// tilemap spanning several pages can be drawn in a single call. Pages
+/// smaller than the largest one are padded at the bottom and right, and
+/// UVs are in terms of the shared layer size, and inset by half a texel of
+/// it if the `CpuAtlas` was. The `page` of a `DrawInfo` is the layer index.
+pub struct TextureArrayAtlas {
+    locations: HashMap<TileIndex, String>,
+    frames: HashMap<String, AtlasFrame>,
+    texture: CompressedSrgbTexture2dArray,
+    layer_size: (u32, u32),
+    layers: usize,
+    half_texel_inset: bool,
+}
+
+impl TextureArrayAtlas {
+    pub fn get_texture(&self) -> &CompressedSrgbTexture2dArray {
+        &self.texture
+    }
+
+    /// Gets the width and height every layer was padded to.
+    pub fn layer_size(&self) -> (u32, u32) {
+        self.layer_size
+    }
+
+    pub fn layers(&self) -> usize {
+        self.layers
+    }
+
+    /// Whether UVs are inset by half a texel. See
+    /// `TileAtlas::set_half_texel_inset`.
+    pub fn half_texel_inset(&self) -> bool {
+        self.half_texel_inset
+    }
+
+    pub fn try_get_frame<I: Into<TileId>>(&self, tile_type: I) -> AtlasResult<&AtlasFrame> {
+        let TileId(tile_type) = tile_type.into();
+        self.locations.get(&tile_type)
+            .and_then(|key| self.frames.get(key))
+            .ok_or(AtlasError::MissingTile(tile_type))
+    }
+
+    pub fn get_tile_draw_info<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> DrawInfo {
+        self.try_get_tile_draw_info(tile_type, msecs).unwrap()
+    }
+
+    pub fn try_get_tile_draw_info<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> AtlasResult<DrawInfo> {
+        let TileId(tile_type) = tile_type.into();
+        let frame = self.try_get_frame(tile_type)?;
+        let layer_size = self.layer_size;
+        let info = tile_atlas::frame_draw_info(&self.frames, frame, tile_type, msecs, &|_| layer_size);
+        if self.half_texel_inset {
+            Ok(tile_atlas::inset_draw_info(info, &|_| layer_size))
+        } else {
+            Ok(info)
+        }
+    }
+
+    /// Gets the texture offset of a tile along with the layer it's on.
+    pub fn get_texture_offset<I: Into<TileId>>(&self, tile_type: I) -> (f32, f32, u32) {
+        self.get_texture_offset_at(tile_type, 0)
+    }
+
+    pub fn get_texture_offset_at<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> (f32, f32, u32) {
+        let info = self.get_tile_draw_info(tile_type, msecs);
+        (info.uv_offset.0, info.uv_offset.1, info.page as u32)
+    }
+}
+
+impl CpuAtlas {
+    /// Uploads every page as a layer of one texture array on `facade`,
+    /// flipped as `flip_y` says, like `upload_page` does.
+    pub fn upload_array<F: Facade>(&self, facade: &F) -> AtlasResult<TextureArrayAtlas> {
+        let layer_size = layer_size(self.pages());
+
+        let layers = self.pages().iter()
+            .map(|page| layer_image(pad_page(page, layer_size), self.flip_y()))
+            .collect();
+
+        let texture = CompressedSrgbTexture2dArray::new(facade, layers)
+            .map_err(|e| AtlasError::Texture(format!("{:?}", e)))?;
+
+        Ok(TextureArrayAtlas {
+            locations: self.locations().clone(),
+            frames: self.frames().clone(),
+            texture: texture,
+            layer_size: layer_size,
+            layers: self.pages().len(),
+            half_texel_inset: self.half_texel_inset(),
+        })
+    }
+}
+
+impl TileAtlasBuilder {
+    /// Like `build`, but uploads the pages as layers of one texture array.
+    pub fn build_array<F: Facade>(&self,
+                                  facade: &F,
+                                  packed_tex_folder: Option<PathBuf>) -> AtlasResult<TextureArrayAtlas> {
+        self.build_cpu(packed_tex_folder)?.upload_array(facade)
+    }
+}
+
+/// Gets the size every layer is padded to: that of the widest and the
+/// tallest page.
+fn layer_size(pages: &[DynamicImage]) -> (u32, u32) {
+    pages.iter().fold((1, 1), |(w, h), page| {
+        let (pw, ph) = page.dimensions();
+        (w.max(pw), h.max(ph))
+    })
+}
+
+fn pad_page(page: &DynamicImage, (w, h): (u32, u32)) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mut padded = ImageBuffer::new(w, h);
+    padded.copy_from(&page.to_rgba(), 0, 0);
+    padded
+}
+
+/// Gets a padded page ready to upload as a layer. glium reads rows from
+/// bottom to top, so flipped layers have theirs reversed.
+fn layer_image(padded: ImageBuffer<Rgba<u8>, Vec<u8>>, flip_y: bool) -> RawImage2d<'static, u8> {
+    let size = padded.dimensions();
+    if flip_y {
+        RawImage2d::from_raw_rgba_reversed(padded.into_raw(), size)
+    } else {
+        RawImage2d::from_raw_rgba(padded.into_raw(), size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImage, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn test_padded_layers() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        let mut tall = DynamicImage::new_rgba8(24, 48);
+        tall.put_pixel(23, 47, Rgba { data: [255, 0, 0, 255] });
+        builder.add_frame_image("b", tall, (24, 48)).unwrap();
+        builder.add_tile("b", 0, (0, 0)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.pages().len(), 2);
+
+        // every layer is as wide as the widest page and as tall as the
+        // tallest, with pages kept at the top left.
+        let size = layer_size(atlas.pages());
+        assert_eq!(size, (48, 48));
+        let page = atlas.get_tile_texture_idx(0);
+        let (w, h) = atlas.pages()[page].dimensions();
+        let padded = pad_page(&atlas.pages()[page], size);
+        assert_eq!(padded.dimensions(), size);
+        assert_eq!(padded.get_pixel(w - 1, h - 1), &Rgba { data: [255, 0, 0, 255] });
+        assert_eq!((w, h), (24, 48));
+        assert_eq!(padded.get_pixel(47, 0).data[3], 0);
+
+        // the red pixel is on the bottom row, which comes first only in
+        // flipped layers.
+        let red = |layer: &RawImage2d<u8>, row: usize| layer.data[(row * 48 + 23) * 4];
+        let flipped = layer_image(padded, atlas.flip_y());
+        assert_eq!((red(&flipped, 0), red(&flipped, 47)), (255, 0));
+
+        builder.set_flip_y(false);
+        let atlas = builder.build_cpu(None).unwrap();
+        let unflipped = layer_image(pad_page(&atlas.pages()[page], size), atlas.flip_y());
+        assert_eq!((red(&unflipped, 0), red(&unflipped, 47)), (0, 255));
+    }
+}
@@ -1,87 +1,321 @@
+use std::cmp;
 use std::collections::HashMap;
+#[cfg(feature = "fs")]
 use std::fs::File;
 use std::path::Path;
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
 
-use glium::backend::Facade;
-use image::{self, DynamicImage, Rgba};
-use texture_packer::SkylinePacker;
-use texture_packer::{TexturePacker, TexturePackerConfig};
-use texture_packer::importer::ImageImporter;
-use texture_packer::exporter::ImageExporter;
+#[cfg(feature = "fs")]
+use image;
+use image::{DynamicImage, GenericImage};
 
-use {AtlasRect, Texture2d, make_texture};
+use {AtlasRect, DefaultTexture};
+use backend::{self, GpuBackend, PageTexture};
+use decode;
+use sampler::SamplerSettings;
+use error::{AtlasError, AtlasResult};
+use packing::{PackAlgorithm, PackerConfig, PagePacker};
+use single_texture_atlas::SingleTextureAtlas;
+#[cfg(not(feature = "fs"))]
+use tile_atlas;
+use tile_atlas::{AtlasFrame, TileAtlas};
 
-pub struct TextureAtlas {
-    texture: Texture2d,
+pub struct TextureAtlas<T = DefaultTexture> {
+    texture: T,
     frames: HashMap<String, AtlasRect>,
+    sampler: SamplerSettings,
 }
 
-type TextureAtlasPacker<'a> = TexturePacker<'a, DynamicImage, SkylinePacker<Rgba<u8>>>;
-
 pub struct TextureAtlasBuilder<'a> {
-    packer: TextureAtlasPacker<'a>,
+    packer: PagePacker<'a>,
+    config: PackerConfig,
     frames: HashMap<String, AtlasRect>,
+    order: Vec<String>,
+    sampler: SamplerSettings,
 }
 
 impl<'a> TextureAtlasBuilder<'a> {
     pub fn new() -> Self {
-        let config = TexturePackerConfig {
-            max_width: 4096,
-            max_height: 4096,
-            allow_rotation: false,
-            texture_outlines: false,
-            trim: false,
-            texture_padding: 0,
-            ..Default::default()
+        TextureAtlasBuilder::with_capacity(0)
+    }
+
+    pub fn with_capacity(textures: usize) -> Self {
+        TextureAtlasBuilder::with_pack_algorithm(textures, PackAlgorithm::default())
+    }
+
+    /// Like `with_capacity`, placing textures with `algorithm`. Textures are
+    /// packed as they're added, so it can't be changed afterwards.
+    pub fn with_pack_algorithm(textures: usize, algorithm: PackAlgorithm) -> Self {
+        let config = PackerConfig {
+            algorithm: algorithm,
+            ..PackerConfig::default()
         };
+        TextureAtlasBuilder::with_config_and_capacity(config, textures)
+    }
 
+    /// Packs textures onto a page sized and spaced as `config` says, 4096
+    /// pixels square with no padding by default. Like the algorithm, it
+    /// can't be changed once textures have been added.
+    pub fn with_config(config: PackerConfig) -> Self {
+        TextureAtlasBuilder::with_config_and_capacity(config, 0)
+    }
+
+    fn with_config_and_capacity(config: PackerConfig, textures: usize) -> Self {
         TextureAtlasBuilder {
-            packer: TexturePacker::new_skyline(config),
-            frames: HashMap::new(),
+            packer: PagePacker::new(&config),
+            config: config,
+            frames: HashMap::with_capacity(textures),
+            order: Vec::with_capacity(textures),
+            sampler: SamplerSettings::default(),
         }
     }
 
-    pub fn add_texture(&'a mut self, texture_name: &str) -> &'a mut Self {
+    /// Sets how the built atlas's texture should be sampled.
+    pub fn set_sampler(&mut self, sampler: SamplerSettings) -> &mut Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Gets where the texture added as `texture_name` was packed.
+    pub fn frame(&self, texture_name: &str) -> Option<&AtlasRect> {
+        self.frames.get(texture_name)
+    }
+
+    /// Estimates how many pixels are left on the atlas texture, as its area
+    /// less that of the textures packed so far. Gaps the packer can't fill
+    /// aren't counted, so somewhat less than this will fit.
+    pub fn free_area(&self) -> u64 {
+        let used: u64 = self.frames.values().map(|rect| rect.w as u64 * rect.h as u64).sum();
+        (self.config.max_width as u64 * self.config.max_height as u64).saturating_sub(used)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn add_texture(&mut self, texture_name: &str) -> AtlasResult<&mut Self> {
         let path_str = format!("data/texture/{}.png", &texture_name);
         let path = Path::new(&path_str);
-        let texture = ImageImporter::import_from_file(&path).unwrap();
+        let texture = decode::import_image(&path, None)?;
 
-        self.packer.pack_own(path_str.to_string(), texture).unwrap();
+        self.pack_texture(texture_name, path_str, texture)
+    }
 
-        let rect = self.packer.get_frame(&path_str).unwrap().frame.clone();
-        self.frames.insert(texture_name.to_string(), AtlasRect::from(rect));
+    /// Like `add_texture`, for an image file that's already in memory.
+    pub fn add_texture_bytes(&mut self, texture_name: &str, data: &[u8]) -> AtlasResult<&mut Self> {
+        let texture = decode::decode_image(data, Path::new(texture_name), None)?;
+        self.pack_texture(texture_name, texture_name.to_string(), texture)
+    }
 
-        self
+    /// Like `add_texture`, for an image that's already decoded.
+    pub fn add_texture_image(&mut self, texture_name: &str, image: DynamicImage) -> AtlasResult<&mut Self> {
+        self.pack_texture(texture_name, texture_name.to_string(), image)
     }
 
-    pub fn build<F: Facade>(&self, display: &F, packed_tex_dir: Option<&str>) -> TextureAtlas {
-        let image = ImageExporter::export(&self.packer).unwrap();
+    fn pack_texture(&mut self, texture_name: &str, path_str: String, texture: DynamicImage) -> AtlasResult<&mut Self> {
+        let (w, h) = texture.dimensions();
+        let (max_w, max_h) = (self.config.max_width, self.config.max_height);
+        let fits_turned = self.config.allow_rotation && h <= max_w && w <= max_h;
+        if (w > max_w || h > max_h) && !fits_turned {
+            let page_size = cmp::min(self.config.max_width, self.config.max_height);
+            return Err(AtlasError::FrameTooLarge(texture_name.to_string(), (w, h), page_size));
+        }
 
-        if let Some(s) = packed_tex_dir {
-            let mut file = File::create(s).unwrap();
-            image.save(&mut file, image::PNG).unwrap();
+        let rect = self.packer.pack_own(path_str, texture)
+            .map_err(|e| AtlasError::Pack(texture_name.to_string(), e))?;
+        // a name added again keeps its place in `build_single`'s order.
+        if self.frames.insert(texture_name.to_string(), rect).is_none() {
+            self.order.push(texture_name.to_string());
         }
 
-        let texture = make_texture(display, image);
+        Ok(self)
+    }
 
-        let mut frames = HashMap::new();
-        for (key, frame) in self.frames.iter() {
-            frames.insert(key.clone(), frame);
+    fn export_image(&self, packed_tex_dir: Option<&str>) -> AtlasResult<DynamicImage> {
+        let image = self.packer.export()
+            .map_err(|e| AtlasError::Pack("page 0".to_string(), e))?;
+
+        if let Some(s) = packed_tex_dir {
+            save_page(s, &image)?;
         }
 
-        TextureAtlas {
+        Ok(image)
+    }
+
+    fn export_texture<B: GpuBackend>(&self, backend: &B, packed_tex_dir: Option<&str>) -> AtlasResult<B::Texture> {
+        let image = self.export_image(packed_tex_dir)?;
+        backend::upload_page(backend, &image)
+    }
+
+    pub fn build<B: GpuBackend>(&self, backend: &B, packed_tex_dir: Option<&str>) -> AtlasResult<TextureAtlas<B::Texture>> {
+        let texture = self.export_texture(backend, packed_tex_dir)?;
+
+        Ok(TextureAtlas {
             texture: texture,
             frames: self.frames.clone(),
-        }
+            sampler: self.sampler,
+        })
+    }
+
+    /// Packs the atlas without uploading it, leaving its page in main memory
+    /// for tools that only need the image and frame areas. Call `upload` on
+    /// the result to get a drawable atlas later.
+    pub fn build_cpu(&self, packed_tex_dir: Option<&str>) -> AtlasResult<TextureAtlas<DynamicImage>> {
+        let image = self.export_image(packed_tex_dir)?;
+
+        Ok(TextureAtlas {
+            texture: image,
+            frames: self.frames.clone(),
+            sampler: self.sampler,
+        })
+    }
+
+    /// Builds a `SingleTextureAtlas`, where each texture is addressed by the
+    /// order it was added in instead of by name.
+    pub fn build_single<B: GpuBackend>(&self,
+                                       backend: &B,
+                                       packed_tex_dir: Option<&str>) -> AtlasResult<SingleTextureAtlas<B::Texture>> {
+        let texture = self.export_texture(backend, packed_tex_dir)?;
+
+        let rects = self.order.iter()
+            .map(|key| self.frames.get(key).unwrap().clone())
+            .collect();
+
+        Ok(SingleTextureAtlas::new(texture, self.order.clone(), rects))
     }
 }
 
-impl TextureAtlas {
-    pub fn get_texture(&self) -> &Texture2d {
+impl<T> TextureAtlas<T> {
+    /// Makes an atlas of a texture packed elsewhere, with the areas of its
+    /// textures by key.
+    pub fn from_parts(texture: T, frames: HashMap<String, AtlasRect>, sampler: SamplerSettings) -> Self {
+        TextureAtlas {
+            texture: texture,
+            frames: frames,
+            sampler: sampler,
+        }
+    }
+
+    pub fn get_texture(&self) -> &T {
         &self.texture
     }
 
-    pub fn get_texture_area(&self, key: &str) -> &AtlasRect {
-        self.frames.get(key).unwrap()
+    pub fn get_texture_area<K: AsRef<str>>(&self, key: K) -> &AtlasRect {
+        self.try_get_texture_area(key).unwrap()
+    }
+
+    /// Like `get_texture_area`, but returns `AtlasError::MissingFrame` for an
+    /// unknown texture instead of panicking.
+    pub fn try_get_texture_area<K: AsRef<str>>(&self, key: K) -> AtlasResult<&AtlasRect> {
+        let key = key.as_ref();
+        self.frames.get(key).ok_or_else(|| AtlasError::MissingFrame(key.to_string()))
+    }
+
+    pub fn frames(&self) -> &HashMap<String, AtlasRect> {
+        &self.frames
+    }
+
+    pub fn set_sampler(&mut self, sampler: SamplerSettings) {
+        self.sampler = sampler;
+    }
+
+    /// Gets how the texture should be sampled, which is nearest filtering
+    /// unless set otherwise.
+    pub fn sampler(&self) -> SamplerSettings {
+        self.sampler
+    }
+
+    /// Gets the texture as a glium sampler configured with `sampler`, ready
+    /// to be passed as a uniform.
+    #[cfg(feature = "glium")]
+    pub fn get_sampler(&self) -> ::glium::uniforms::Sampler<T> {
+        self.sampler.sample(&self.texture)
+    }
+}
+
+impl<T: PageTexture> TextureAtlas<T> {
+    /// Gets a one page `TileAtlas` that borrows this atlas's texture, with a
+    /// frame for each texture. There are no tile types, so frames are looked
+    /// up by key through `frames`.
+    pub fn tile_atlas_view(&self) -> TileAtlas<&T> {
+        let frames = self.frames.iter()
+            .map(|(key, rect)| (key.clone(), AtlasFrame::new(0, rect.clone(), (rect.w(), rect.h()))))
+            .collect();
+
+        TileAtlas::new(HashMap::new(), frames, vec![&self.texture]).with_sampler(self.sampler)
+    }
+}
+
+#[cfg(feature = "glium")]
+impl<T: ::glium::GlObject<Id = u32>> TextureAtlas<T> {
+    /// Gets the OpenGL name of the atlas texture. See
+    /// `TileAtlas::gl_texture_id`.
+    pub fn gl_texture_id(&self) -> u32 {
+        use glium::GlObject;
+
+        self.texture.get_id()
+    }
+}
+
+impl TextureAtlas<DynamicImage> {
+    /// Creates a texture for the page with `backend`.
+    pub fn upload<B: GpuBackend>(&self, backend: &B) -> AtlasResult<TextureAtlas<B::Texture>> {
+        let texture = backend::upload_page(backend, &self.texture)?;
+
+        Ok(TextureAtlas {
+            texture: texture,
+            frames: self.frames.clone(),
+            sampler: self.sampler,
+        })
+    }
+}
+
+#[cfg(feature = "fs")]
+fn save_page(s: &str, image: &DynamicImage) -> AtlasResult<()> {
+    let mut file = File::create(s).map_err(|e| AtlasError::Io(PathBuf::from(s), e))?;
+    image.save(&mut file, image::PNG).map_err(|e| AtlasError::Image(PathBuf::from(s), e.to_string()))
+}
+
+#[cfg(not(feature = "fs"))]
+fn save_page(s: &str, _: &DynamicImage) -> AtlasResult<()> {
+    Err(tile_atlas::fs_disabled(Path::new(s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+
+    #[test]
+    fn test_try_get_texture_area() {
+        let mut builder = TextureAtlasBuilder::new();
+        builder.add_texture_image("button", DynamicImage::new_rgba8(32, 16)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+
+        assert_eq!(atlas.try_get_texture_area("button").unwrap().source_size(), (32, 16));
+        match atlas.try_get_texture_area("missing") {
+            Err(AtlasError::MissingFrame(ref key)) => assert_eq!(key, "missing"),
+            _ => panic!("Expected a missing frame error"),
+        }
+    }
+
+    #[test]
+    fn test_build_single() {
+        let mut builder = TextureAtlasBuilder::with_config(PackerConfig {
+            max_width: 64,
+            max_height: 64,
+            ..PackerConfig::default()
+        });
+        builder.add_texture_image("a", DynamicImage::new_rgba8(16, 16)).unwrap();
+        builder.add_texture_image("b", DynamicImage::new_rgba8(32, 8)).unwrap();
+        builder.add_texture_image("a", DynamicImage::new_rgba8(8, 4)).unwrap();
+        let atlas = builder.build_single(&MemoryBackend, None).unwrap();
+
+        // indices follow the order names were first added in, with the
+        // areas they have now.
+        assert_eq!(atlas.len(), 2);
+        assert_eq!((atlas.index_of("a"), atlas.index_of("b"), atlas.index_of("c")), (Some(0), Some(1), None));
+        assert_eq!(atlas.get_texture_area(0).source_size(), (8, 4));
+        assert_eq!(atlas.get_texture_area(1).source_size(), (32, 8));
     }
 }
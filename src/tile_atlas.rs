@@ -1,26 +1,69 @@
-use std::collections::HashMap;
-use std::fs::{self, File};
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(feature = "fs")]
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "fs")]
+use std::thread;
+use std::time::{Duration, Instant};
 
-use glium::backend::Facade;
-use image::{self, DynamicImage, Rgba};
-use texture_packer::{SkylinePacker, Rect};
-use texture_packer::{TexturePacker, TexturePackerConfig};
-use texture_packer::importer::ImageImporter;
-use texture_packer::exporter::ImageExporter;
+use image::{self, DynamicImage, GenericImage, ImageBuffer, Rgba};
+use serde::{Serialize, Serializer};
 
-use {AtlasRect, Texture2d, make_texture};
-use tile_atlas_config::TileAtlasConfig;
+use {AtlasRect, DefaultTexture};
+use atlas::Atlas;
+use backend::{self, GpuBackend, RawPage, Unflipped};
+use block_compress::{self, BlockFormat, CompressedPage};
+use cpu_atlas::CpuAtlas;
+use decode::{self, DecodeLimits};
+use digest;
+use error::{AtlasError, AtlasResult};
+#[cfg(feature = "fs")]
+use fs_util;
+use ids::{ContextId, PageId, TileId};
+use lint::{self, Lint, LintOptions, PackReport, Thumbnail};
+use mipmap;
+use packing::{PackAlgorithm, PackerConfig, PagePacker, RectPacker, ShelfPacker};
+use sampler::SamplerSettings;
+#[cfg(feature = "fs")]
+use sandbox;
+use texture_atlas::TextureAtlas;
+use tile_atlas_config::{self, TileAtlasConfig};
+use usage::UsageReport;
+#[cfg(all(feature = "validate", debug_assertions))]
+use validate;
 
 pub type TileOffset = (u32, u32);
 pub type TileIndex = usize;
 
 type AnimFrames = u64;
 type AnimMillisDelay = u64;
-#[derive(Serialize, Deserialize, Clone)]
+type AnimStride = u32;
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TileKind {
     Static,
+    /// Frames are laid out left to right in adjacent columns, starting at
+    /// the tile's offset.
     Animated(AnimFrames, AnimMillisDelay),
+    /// Each frame comes from its own frame key and offset, as given to
+    /// `TileAtlasBuilder::add_sequence_tile`.
+    Sequence(AnimFrames, AnimMillisDelay),
+    /// Like `Animated`, with frames `stride` columns apart instead: 2 skips
+    /// every other column, for sheets that interleave frames with unrelated
+    /// art.
+    StridedAnimated(AnimFrames, AnimMillisDelay, AnimStride),
+}
+
+/// The frame key and offset of each step of a `TileKind::Sequence` tile.
+pub type SequenceSteps = Vec<(String, TileOffset)>;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasTile {
+    pub offset: TileOffset,
+    pub kind: TileKind,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -28,219 +71,3971 @@ pub struct AtlasFrame {
     tile_size: (u32, u32),
     texture_idx: usize,
     rect: AtlasRect,
-    offsets: HashMap<TileIndex, TileOffset>,
+    padded_rect: AtlasRect,
+    #[serde(serialize_with = "serialize_sorted")]
+    tiles: HashMap<TileIndex, AtlasTile>,
+    #[serde(serialize_with = "serialize_sorted")]
+    sequences: HashMap<TileIndex, SequenceSteps>,
+    pivot: (f32, f32),
+    overlay: Option<String>,
+    class: Option<String>,
+    source_size: (u32, u32),
+    trim_offset: (u32, u32),
 }
 
 impl AtlasFrame {
-    pub fn new(texture_idx: usize, rect: Rect, tile_size: (u32, u32)) -> Self {
+    pub fn new(texture_idx: usize, rect: AtlasRect, tile_size: (u32, u32)) -> Self {
         AtlasFrame {
             tile_size: tile_size,
             texture_idx: texture_idx,
-            rect: AtlasRect::from(rect),
-            offsets: HashMap::new(),
+            rect: rect.clone(),
+            padded_rect: rect,
+            tiles: HashMap::new(),
+            sequences: HashMap::new(),
+            pivot: (0.0, 0.0),
+            overlay: None,
+            class: None,
+            source_size: tile_size,
+            trim_offset: (0, 0),
         }
     }
+
+    pub fn texture_idx(&self) -> usize {
+        self.texture_idx
+    }
+
+    /// The frame's pixels on its page.
+    pub fn rect(&self) -> &AtlasRect {
+        &self.rect
+    }
+
+    /// `rect` grown by the frame's share of the padding around it, which is
+    /// the region to copy out of the page PNG to keep any padding or
+    /// extruded edges. The same as `rect` when padding is off.
+    pub fn padded_rect(&self) -> &AtlasRect {
+        &self.padded_rect
+    }
+
+    pub fn tile_size(&self) -> (u32, u32) {
+        self.tile_size
+    }
+
+    pub fn tiles(&self) -> &HashMap<TileIndex, AtlasTile> {
+        &self.tiles
+    }
+
+    /// The frames and offsets each sequence tile in this frame steps through.
+    pub fn sequences(&self) -> &HashMap<TileIndex, SequenceSteps> {
+        &self.sequences
+    }
+
+    /// The usage class the frame was given with `set_frame_class`.
+    pub fn class(&self) -> Option<&str> {
+        self.class.as_ref().map(|c| c.as_str())
+    }
+
+    /// The size of the frame's image before transparent borders were
+    /// trimmed off, or `tile_size` if it wasn't trimmed. See
+    /// `TileAtlasBuilder::set_trim`.
+    pub fn source_size(&self) -> (u32, u32) {
+        self.source_size
+    }
+
+    /// Where the trimmed image starts in the untrimmed one.
+    pub fn trim_offset(&self) -> (u32, u32) {
+        self.trim_offset
+    }
+
+    pub fn is_trimmed(&self) -> bool {
+        self.source_size != self.tile_size
+    }
+}
+
+/// Serializes a map in the order of its keys rather than its own, which
+/// differs from run to run, so a cache of the same atlas is always written
+/// out the same.
+pub fn serialize_sorted<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where K: Ord + Serialize,
+          V: Serialize,
+          S: Serializer
+{
+    let sorted: BTreeMap<&K, &V> = map.iter().collect();
+    sorted.serialize(serializer)
+}
+
+/// Everything needed to draw a single tile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawInfo {
+    /// Index of the texture page the tile is on.
+    pub page: usize,
+    pub uv_offset: (f32, f32),
+    pub uv_scale: [f32; 2],
+    /// Whether the tile lies on the page turned a quarter turn clockwise, so
+    /// the UV rect is `pixel_size` swapped. Draw with `uv_corners` to get it
+    /// upright again.
+    pub rotated: bool,
+    pub pixel_size: (u32, u32),
+    /// The size of the tile before its frame was trimmed, which `pixel_size`
+    /// is a part of, starting `trim_offset` pixels in. The same as
+    /// `pixel_size` and `(0, 0)` for frames that weren't trimmed.
+    pub source_size: (u32, u32),
+    pub trim_offset: (u32, u32),
+    /// Anchor point of the tile, in fractions of its untrimmed size.
+    pub pivot: (f32, f32),
+    /// Where the same tile lies in the frame's overlay, if it has one.
+    pub overlay: Option<OverlayInfo>,
+}
+
+impl DrawInfo {
+    /// Gets the UVs of the tile's top-left, top-right, bottom-right and
+    /// bottom-left corners as it's drawn upright, in that order.
+    pub fn uv_corners(&self) -> [(f32, f32); 4] {
+        uv_corners(self.uv_offset, self.uv_scale, self.rotated)
+    }
+}
+
+/// What's drawn at a point on a page. See `TileAtlas::pick`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pick {
+    /// The key of the frame covering the point.
+    pub frame: String,
+    /// The tile drawn from there, if any.
+    pub tile: Option<TileId>,
+    /// Which step of the tile's animation is drawn from there, or 0 for
+    /// tiles that aren't animated.
+    pub animation_frame: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayInfo {
+    pub page: usize,
+    pub uv_offset: (f32, f32),
+    pub uv_scale: [f32; 2],
+    pub rotated: bool,
 }
 
-pub type TilePacker<'a> = TexturePacker<'a, DynamicImage, SkylinePacker<Rgba<u8>>>;
+impl OverlayInfo {
+    /// See `DrawInfo::uv_corners`.
+    pub fn uv_corners(&self) -> [(f32, f32); 4] {
+        uv_corners(self.uv_offset, self.uv_scale, self.rotated)
+    }
+}
 
-pub struct TileAtlas {
+pub struct TileAtlas<T = DefaultTexture> {
     locations: HashMap<TileIndex, String>,
     frames: HashMap<String, AtlasFrame>,
-    textures: Vec<Texture2d>,
+    textures: Vec<T>,
+    page_names: Vec<String>,
+    pending: Vec<PendingWrite>,
+    /// Lookup counts, behind a lock so lookups can count through `&self`
+    /// while the atlas is shared between threads.
+    usage: Option<Mutex<HashMap<TileIndex, u64>>>,
+    refs: HashMap<String, usize>,
+    sampler: SamplerSettings,
+    /// The pages kept in main memory for `reupload`, if they're retained.
+    retained: Option<CpuAtlas>,
+    /// The rows `insert_frame` places frames in, per page, made from the
+    /// frames on the page the first time it's called.
+    shelves: Vec<ShelfPacker>,
+    /// The pages uploaded to other contexts, with `add_context`.
+    contexts: HashMap<ContextId, ContextPages<T>>,
+    flip_y: bool,
+    half_texel_inset: bool,
+    /// How many mip levels the pages were uploaded with, counting the page.
+    mip_levels: u32,
 }
 
-pub struct TileAtlasBuilder<'a> {
+pub struct TileAtlasBuilder {
     locations: HashMap<TileIndex, String>,
-    frames: HashMap<String, AtlasFrame>,
-    packers: Vec<TilePacker<'a>>,
+    sources: HashMap<String, FrameSource>,
+    order: Vec<String>,
+    /// Only frames loaded from files are resolved against it.
+    #[cfg_attr(not(feature = "fs"), allow(dead_code))]
+    root: Option<PathBuf>,
+    limits: Option<DecodeLimits>,
+    split_oversized: bool,
+    padding: u32,
+    /// Space left at the page edges, or `None` for half the padding.
+    border_padding: Option<u32>,
+    page_size: u32,
+    /// The largest page size to grow to when a frame doesn't fit.
+    max_page_size: Option<u32>,
+    pack_algorithm: PackAlgorithm,
+    allow_rotation: bool,
+    trim: bool,
+    extrude: u32,
+    sort_by_area: bool,
+    lint_options: Option<LintOptions>,
+    lints: Vec<Lint>,
+    collect_errors: bool,
+    errors: Vec<AtlasError>,
+    failed_frames: HashSet<String>,
+    compression: Option<BlockFormat>,
+    key_collision: KeyCollision,
+    mip_levels: u32,
+    sampler: SamplerSettings,
+    debug_outlines: bool,
+    retain_pages: bool,
+    reserve_page_space: bool,
+    stale_pages: StalePages,
+    flip_y: bool,
+    half_texel_inset: bool,
+}
+
+/// A decoded frame image waiting to be packed. Packing is deferred until
+/// `build` so frames can still be removed or replaced beforehand.
+struct FrameSource {
+    image: DynamicImage,
+    tile_size: (u32, u32),
+    tiles: HashMap<TileIndex, AtlasTile>,
+    sequences: HashMap<TileIndex, SequenceSteps>,
+    pivot: (f32, f32),
+    overlay: Option<String>,
+    compression: CompressionHint,
+    class: Option<String>,
+    /// Made as the frame is added if near duplicates are being linted.
+    thumbnail: Option<Thumbnail>,
 }
 
-impl <'a> TileAtlasBuilder<'a> {
+impl TileAtlasBuilder {
     pub fn new() -> Self {
-        let mut builder = TileAtlasBuilder {
-            locations: HashMap::new(),
-            frames: HashMap::new(),
-            packers: Vec::new(),
+        TileAtlasBuilder::with_capacity(0, 0)
+    }
+
+    /// Like `new`, with the page size, padding, border, packing algorithm,
+    /// rotation and debug outlines taken from `config`. See
+    /// `set_packer_config`.
+    pub fn with_config(config: PackerConfig) -> AtlasResult<Self> {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_packer_config(config)?;
+        Ok(builder)
+    }
+
+    /// Preallocates room for the given number of frames and tiles, avoiding
+    /// rehashing while loading large configs.
+    pub fn with_capacity(frames: usize, tiles: usize) -> Self {
+        TileAtlasBuilder {
+            locations: HashMap::with_capacity(tiles),
+            sources: HashMap::with_capacity(frames),
+            order: Vec::with_capacity(frames),
+            root: None,
+            limits: Some(DecodeLimits::default()),
+            split_oversized: false,
+            padding: 0,
+            border_padding: None,
+            page_size: PAGE_SIZE,
+            max_page_size: None,
+            pack_algorithm: PackAlgorithm::default(),
+            allow_rotation: false,
+            trim: false,
+            extrude: 0,
+            sort_by_area: false,
+            lint_options: None,
+            lints: Vec::new(),
+            collect_errors: false,
+            errors: Vec::new(),
+            failed_frames: HashSet::new(),
+            compression: None,
+            key_collision: KeyCollision::KeepFirst,
+            mip_levels: 1,
+            sampler: SamplerSettings::default(),
+            debug_outlines: false,
+            retain_pages: false,
+            reserve_page_space: false,
+            stale_pages: StalePages::RemoveFolder,
+            flip_y: true,
+            half_texel_inset: false,
+        }
+    }
+
+    /// Generates `levels` mip levels for every page, counting the full size
+    /// one, for atlases drawn zoomed out. Frames get a gutter of duplicated
+    /// edge pixels wide enough that the smallest level doesn't blend them
+    /// with their neighbors, which takes the place of `set_padding` if it's
+    /// wider. A `levels` of 1, the default, generates no mipmaps. Levels past
+    /// the one where a page of the largest size would be 1x1 are ignored.
+    pub fn set_mip_levels(&mut self, levels: u32) {
+        self.mip_levels = cmp::max(levels, 1);
+    }
+
+    /// Sets how frames are placed on pages. Defaults to
+    /// `PackAlgorithm::Skyline`; `PackAlgorithm::MaxRects` usually takes
+    /// fewer pages for frames of mixed sizes, at the cost of slower packing,
+    /// and `PackAlgorithm::Guillotine` lays frames of one size out in a grid.
+    pub fn set_pack_algorithm(&mut self, algorithm: PackAlgorithm) {
+        self.pack_algorithm = algorithm;
+    }
+
+    /// Lets frames that don't fit on a page upright go on it turned a
+    /// quarter turn clockwise, so fewer pages are needed. The UVs of their
+    /// tiles are rotated to match; see `DrawInfo::uv_corners`, which renderers
+    /// must draw with for rotated tiles to come out upright.
+    pub fn set_allow_rotation(&mut self, allow: bool) {
+        self.allow_rotation = allow;
+    }
+
+    /// Crops the fully transparent borders off frames before packing them,
+    /// which saves a lot of page space for sprites drawn with room to move
+    /// in. The untrimmed size and where the rest starts in it are kept as
+    /// `DrawInfo::source_size` and `DrawInfo::trim_offset`, so renderers can
+    /// draw the trimmed tile where it was. Only frames holding a single tile
+    /// are trimmed, and none that have or are overlays, since the tiles of
+    /// the rest are found by their place in the whole image.
+    pub fn set_trim(&mut self, trim: bool) {
+        self.trim = trim;
+    }
+
+    /// Copies the edge pixels of every frame `pixels` out on each side, so
+    /// linear filtering at a frame's edge samples the frame rather than
+    /// whatever is next to it. Frames are kept far enough apart for that, in
+    /// place of `set_padding` if it's narrower. Mipmapped atlases already
+    /// extrude frames across their whole gutter. Frames inserted after the
+    /// build aren't extruded.
+    pub fn set_extrude(&mut self, pixels: u32) {
+        self.extrude = pixels;
+    }
+
+    /// Packs frames largest first, by area and then height, rather than in
+    /// the order they were added. Frames of mixed sizes usually take fewer
+    /// pages this way, but which page a frame lands on then depends on every
+    /// other frame, so it's off by default to keep existing layouts. Frames
+    /// that are trimmed are sorted by their trimmed size.
+    pub fn set_sort_by_area(&mut self, sort: bool) {
+        self.sort_by_area = sort;
+    }
+
+    /// Gets the order frames are packed in, given the size each is packed
+    /// at.
+    fn pack_order<F: Fn(&str) -> (u32, u32)>(&self, packed_size: F) -> Vec<&String> {
+        let mut order: Vec<&String> = self.order.iter().collect();
+        if self.sort_by_area {
+            // Stable, so frames of the same size keep the order they were
+            // added in.
+            order.sort_by_key(|key| {
+                let (w, h) = packed_size(key);
+                (cmp::Reverse(w as u64 * h as u64), cmp::Reverse(h))
+            });
+        }
+        order
+    }
+
+    /// Gets how many mip levels are generated: as many as `set_mip_levels`
+    /// asked for, up to the most a page of the largest size has.
+    fn mip_level_count(&self) -> u32 {
+        let page_size = cmp::max(self.page_size, self.max_page_size.unwrap_or(0));
+        cmp::min(self.mip_levels, mipmap::max_levels(page_size))
+    }
+
+    /// Gets the space left between frames, which is the largest of the
+    /// padding, the mipmap gutters on both sides and the extruded edges.
+    fn frame_padding(&self) -> u32 {
+        let gutters = mipmap::gutter_for_levels(self.mip_level_count()).saturating_mul(2);
+        cmp::max(cmp::max(self.padding, gutters), 2 * self.extrude)
+    }
+
+    /// Gets how much of the space around frames is filled with their edge
+    /// pixels, counted the same way as `frame_padding`.
+    fn extrude_padding(&self) -> u32 {
+        if self.mip_level_count() > 1 {
+            self.frame_padding()
+        } else {
+            2 * self.extrude
+        }
+    }
+
+    /// Sets what happens when a frame is added under a key that's already
+    /// taken. Defaults to `KeyCollision::KeepFirst`.
+    pub fn set_key_collision(&mut self, collision: KeyCollision) {
+        self.key_collision = collision;
+    }
+
+    /// Checks whether a frame being added under `key` should be loaded.
+    fn wants_frame(&self, key: &str) -> AtlasResult<bool> {
+        if !self.sources.contains_key(key) {
+            return Ok(true);
+        }
+
+        match self.key_collision {
+            KeyCollision::KeepFirst => Ok(false),
+            KeyCollision::Error     => Err(AtlasError::DuplicateFrame(key.to_string())),
+            KeyCollision::Override  => Ok(true),
+        }
+    }
+
+    /// Returns the index right after the highest tile index added so far,
+    /// where the tiles of another config merged into this builder start.
+    pub fn next_tile_index(&self) -> TileIndex {
+        self.locations.keys().max().map_or(0, |&index| index + 1)
+    }
+
+    /// Compresses pages to `format` on the CPU before uploading them, and
+    /// saves them compressed instead of as PNGs. Only for atlases that can
+    /// tolerate the loss in quality; pixel art usually can't.
+    pub fn set_block_compression(&mut self, format: Option<BlockFormat>) {
+        self.compression = format;
+    }
+
+    /// Draws an outline around every frame and tints each page a different
+    /// color, to see page boundaries and frame edges in game. Only for
+    /// debugging, since the outlines are drawn over the frames' edge pixels.
+    pub fn set_debug_outlines(&mut self, outlines: bool) {
+        self.debug_outlines = outlines;
+    }
+
+    /// Keeps a copy of every page in main memory after building, along with
+    /// its compressed copy if it has one, so `TileAtlas::reupload` can
+    /// restore the textures after the GL context is recreated without
+    /// packing again. Costs at least as much memory again as the pages take
+    /// up on the GPU.
+    pub fn set_retain_pages(&mut self, retain: bool) {
+        self.retain_pages = retain;
+    }
+
+    /// Makes every page the full page size, instead of only as large as the
+    /// frames on it reach, leaving room for `TileAtlas::insert_frame` to add
+    /// frames after building. Best with `PackAlgorithm::Shelf`, whose rows
+    /// the inserted frames continue.
+    pub fn set_reserve_page_space(&mut self, reserve: bool) {
+        self.reserve_page_space = reserve;
+    }
+
+    /// Sets what a build does with pages already in the folder it writes
+    /// pages to, such as the extra pages of an earlier, larger build.
+    /// Defaults to `StalePages::RemoveFolder`, which suits folders holding
+    /// nothing but pages; pick `StalePages::RemovePages` for folders shared
+    /// with other files.
+    pub fn set_stale_pages(&mut self, stale: StalePages) {
+        self.stale_pages = stale;
+    }
+
+    /// Sets whether pages are flipped upside down when uploaded to backends
+    /// that keep rows from bottom to top, like glium's. Flipped pages, the
+    /// default, put the top of each page at a V of 1, so shaders sample at
+    /// `1.0 - v`. Turning it off keeps the top row first, as wgpu does, so
+    /// `DrawInfo` UVs can be sampled as they are.
+    pub fn set_flip_y(&mut self, flip_y: bool) {
+        self.flip_y = flip_y;
+    }
+
+    /// Sets whether the built atlas insets the UVs it reports by half a
+    /// texel. See `TileAtlas::set_half_texel_inset`.
+    pub fn set_half_texel_inset(&mut self, inset: bool) {
+        self.half_texel_inset = inset;
+    }
+
+    /// Sets how the built atlas's pages should be sampled. See
+    /// `TileAtlas::sampler`.
+    pub fn set_sampler(&mut self, sampler: SamplerSettings) {
+        self.sampler = sampler;
+    }
+
+    /// Makes room for the given number of additional frames and tiles.
+    pub fn reserve(&mut self, frames: usize, tiles: usize) {
+        self.locations.reserve(tiles);
+        self.sources.reserve(frames);
+        self.order.reserve(frames);
+    }
+
+    /// Sets the size limits checked when importing frames, or disables them
+    /// with `None`.
+    pub fn set_decode_limits(&mut self, limits: Option<DecodeLimits>) {
+        self.limits = limits;
+    }
+
+    /// Leaves `padding` pixels between frames on a page, so filtering doesn't
+    /// bleed neighboring frames into each other. Each frame's `padded_rect`
+    /// then covers its half of the gap on every side.
+    pub fn set_padding(&mut self, padding: u32) {
+        self.padding = padding;
+    }
+
+    /// Sets the width and height of each page, 2048 by default. Frames that
+    /// don't fit on a page fail with `AtlasError::FrameTooLarge` unless they
+    /// can be split, when they're added and again when they're packed, so
+    /// set this before adding frames.
+    pub fn set_page_size(&mut self, page_size: u32) {
+        self.page_size = page_size;
+    }
+
+    /// Sets the page size, padding, border, packing algorithm, rotation and
+    /// debug outlines at once. Without a config, frames at the page edges
+    /// get half the padding between frames as a border. Pages are square, so
+    /// fails with `AtlasError::Config` unless `max_width` and `max_height`
+    /// are the same.
+    pub fn set_packer_config(&mut self, config: PackerConfig) -> AtlasResult<()> {
+        if config.max_width != config.max_height {
+            return Err(AtlasError::Config(format!("Pages are square, but the packer config is {}x{}",
+                                                  config.max_width, config.max_height)));
+        }
+
+        self.page_size = config.max_width;
+        self.padding = config.padding;
+        self.border_padding = Some(config.border_padding);
+        self.pack_algorithm = config.algorithm;
+        self.allow_rotation = config.allow_rotation;
+        self.debug_outlines = config.outlines;
+        Ok(())
+    }
+
+    /// Space left at the page edges: the packer config's border, or else
+    /// half the padding, so frames there get as much padding as the rest.
+    fn page_border(&self) -> u32 {
+        self.border_padding.unwrap_or_else(|| border_padding(self.frame_padding()))
+    }
+
+    /// Lets the page size grow when a frame is too large for it, instead of
+    /// failing, to the next power of two that fits every frame but no larger
+    /// than `max_page_size`, which should be the largest texture the GPU
+    /// takes (`GL_MAX_TEXTURE_SIZE`). Frames that still don't fit are split
+    /// if `set_split_oversized` is on, and fail otherwise. The size packed
+    /// with is reported in `pack_report`. Off with `None`, the default.
+    pub fn set_grow_page_size(&mut self, max_page_size: Option<u32>) {
+        self.max_page_size = max_page_size;
+    }
+
+    /// Gets the page size frames are packed with: the configured size, grown
+    /// if `set_grow_page_size` allows until every frame fits.
+    fn packing_page_size(&self) -> u32 {
+        let max = match self.max_page_size {
+            Some(max) => max,
+            None      => return self.page_size,
+        };
+
+        let border = self.page_border();
+        let fits = |page_size: u32| {
+            let usable = usable_page_size(page_size, border);
+            self.sources.values().all(|source| {
+                let (w, h) = source.image.dimensions();
+                w <= usable && h <= usable
+            })
         };
-        builder.add_packer();
-        builder
+
+        let mut page_size = self.page_size;
+        while page_size < max && !fits(page_size) {
+            page_size = cmp::min((page_size + 1).next_power_of_two(), max);
+        }
+        page_size
     }
 
-    pub fn add_tile(&mut self, path_str: &str, index: TileIndex, offset: TileOffset) {
-        let key = path_str.to_string();
-        assert!(self.frames.contains_key(&path_str.to_string()));
+    /// Sets the checks run on each frame as it's added, or turns them off
+    /// with `None`. Anything they find is listed in `pack_report`, and with
+    /// the `tracing` feature also emitted as a warning event.
+    pub fn set_lint_options(&mut self, options: Option<LintOptions>) {
+        self.lint_options = options;
 
-        {
-            let mut frame = self.frames.get_mut(&key).unwrap();
-            assert!(!frame.offsets.contains_key(&index));
-            frame.offsets.insert(index, offset);
-            self.locations.insert(index, key);
+        // frames added before near duplicates were linted need thumbnails.
+        let near_duplicates = options.and_then(|options| options.near_duplicates).is_some();
+        for source in self.sources.values_mut() {
+            if !near_duplicates {
+                source.thumbnail = None;
+            } else if source.thumbnail.is_none() {
+                source.thumbnail = Some(Thumbnail::new(&source.image));
+            }
         }
     }
 
-    pub fn add_frame(&mut self, path_string: &str, tile_size: (u32, u32)) {
-        if self.frames.contains_key(path_string) {
-            return;
+    /// Gets the lints raised for the frames added so far, followed by any
+    /// near duplicates among them.
+    pub fn pack_report(&self) -> PackReport {
+        let mut lints = self.lints.clone();
+        if let Some(max_distance) = self.lint_options.and_then(|options| options.near_duplicates) {
+            let frames: Vec<(&str, &Thumbnail)> = self.order.iter()
+                .filter_map(|key| {
+                    let thumbnail = self.sources.get(key).and_then(|source| source.thumbnail.as_ref());
+                    thumbnail.map(|thumbnail| (key.as_str(), thumbnail))
+                })
+                .collect();
+            lints.extend(lint::lint_near_duplicates(max_distance, &frames));
+        }
+
+        PackReport {
+            lints: lints,
+            page_size: self.packing_page_size(),
+        }
+    }
+
+    /// Keeps going past errors instead of stopping at the first one, so a
+    /// whole batch of broken assets can be fixed at once. Errors passed to
+    /// `record_error` are kept, frames that fail to pack are skipped, and
+    /// `build` then fails with every error at once as `AtlasError::Multiple`.
+    pub fn set_collect_errors(&mut self, collect: bool) {
+        self.collect_errors = collect;
+    }
+
+    /// Keeps `err` to be reported by `build` if errors are being collected,
+    /// or returns it otherwise. Missing frame errors for frames that already
+    /// failed to load are dropped, since they'd only repeat that failure.
+    pub fn record_error(&mut self, err: AtlasError) -> AtlasResult<()> {
+        if !self.collect_errors {
+            return Err(err);
         }
 
-        let path = Path::new(&path_string);
-        let texture = ImageImporter::import_from_file(&path).unwrap();
+        let repeated = match err {
+            AtlasError::MissingFrame(ref key) => self.failed_frames.contains(key),
+            _ => false,
+        };
+        if !repeated {
+            self.errors.push(err);
+        }
+        Ok(())
+    }
+
+    /// Like `record_error`, for an error with loading the frame `key`.
+    pub fn record_frame_error(&mut self, key: &str, err: AtlasError) -> AtlasResult<()> {
+        if self.collect_errors {
+            self.failed_frames.insert(key.to_string());
+        }
+        self.record_error(err)
+    }
+
+    /// Lets frames larger than a page be split along tile boundaries into
+    /// several frames, instead of failing to pack. Tiles keep their indices
+    /// and are moved to whichever piece holds them; animations that end up
+    /// crossing pieces become animation sequences. Frames with overlays
+    /// can't be split.
+    pub fn set_split_oversized(&mut self, split: bool) {
+        self.split_oversized = split;
+    }
+
+    /// Restricts frame paths to `root`. Paths passed to `add_frame` are then
+    /// resolved relative to `root`, and any that would escape it (through
+    /// `..`, absolute paths or symlinks) are rejected.
+    pub fn set_root(&mut self, root: &Path) {
+        self.root = Some(root.to_path_buf());
+    }
+
+    #[cfg(feature = "fs")]
+    fn resolve_path(&self, path_str: &str) -> AtlasResult<PathBuf> {
+        match self.root {
+            Some(ref root) => sandbox::expect_in_root(root, path_str),
+            None           => Ok(PathBuf::from(path_str)),
+        }
+    }
+
+    fn source_mut(&mut self, key: &str) -> AtlasResult<&mut FrameSource> {
+        self.sources.get_mut(key).ok_or_else(|| AtlasError::MissingFrame(key.to_string()))
+    }
+
+    pub fn add_tile<I: Into<TileId>>(&mut self, path_str: &str, index: I, offset: TileOffset) -> AtlasResult<()> {
+        self.add_tile_with_kind(path_str, index, offset, TileKind::Static)
+    }
+
+    pub fn add_tile_with_kind<I: Into<TileId>>(&mut self,
+                                               path_str: &str,
+                                               index: I,
+                                               offset: TileOffset,
+                                               kind: TileKind) -> AtlasResult<()> {
+        let TileId(index) = index.into();
+        match kind {
+            TileKind::Static => (),
+            TileKind::Animated(frame_count, delay) |
+            TileKind::StridedAnimated(frame_count, delay, _) => check_animation(index, frame_count, delay)?,
+            TileKind::Sequence(..) => {
+                return Err(AtlasError::Config(format!("Tile {} is a sequence, add it with add_sequence_tile", index)));
+            },
+        }
+
+        let key = path_str.to_string();
 
-        for (idx, packer) in self.packers.iter_mut().enumerate() {
-            if packer.can_pack(&texture) {
-                packer.pack_own(path_string.to_string(), texture).unwrap();
-                let rect = packer.get_frame(&path_string).unwrap().frame.clone();
-                self.frames.insert(path_string.to_string(), AtlasFrame::new(idx, rect, tile_size));
-                // cannot return self here, since self already borrowed, so
-                // cannot use builder pattern.
-                return;
+        {
+            let source = self.source_mut(&key)?;
+            if source.tiles.contains_key(&index) {
+                return Err(AtlasError::DuplicateTile(index));
             }
+            check_tile_bounds(source.tile_size, source.image.dimensions(), &key, index, offset, kind)?;
+            source.tiles.insert(index, AtlasTile { offset: offset, kind: kind });
+        }
+
+        self.locations.insert(index, key);
+        Ok(())
+    }
+
+    /// Adds an animated tile whose frames come from different frame keys, for
+    /// animations split over several source files. Each step is drawn from
+    /// the given frame and offset for `delay` milliseconds. The tile counts
+    /// as part of the first step's frame.
+    pub fn add_sequence_tile<I: Into<TileId>>(&mut self,
+                                              index: I,
+                                              steps: &[(&str, TileOffset)],
+                                              delay: u64) -> AtlasResult<()> {
+        let TileId(index) = index.into();
+        check_animation(index, steps.len() as u64, delay)?;
+
+        for &(key, offset) in steps.iter() {
+            let source = match self.sources.get(key) {
+                Some(source) => source,
+                None         => return Err(AtlasError::MissingFrame(key.to_string())),
+            };
+            check_tile_bounds(source.tile_size, source.image.dimensions(), key, index, offset, TileKind::Static)?;
         }
 
-        self.add_packer();
+        let first_key = steps[0].0.to_string();
 
         {
-            // complains that borrow doesn't last long enough
-            // len mut packer = self.newest_packer_mut();
+            let source = self.source_mut(&first_key)?;
+            if source.tiles.contains_key(&index) {
+                return Err(AtlasError::DuplicateTile(index));
+            }
 
-            let packer_idx = self.packers.len() - 1;
-            let mut packer = self.packers.get_mut(packer_idx).unwrap();
-            packer.pack_own(path_string.to_string(), texture).unwrap();
-            let rect = packer.get_frame(&path_string).unwrap().frame.clone();
-            self.frames.insert(path_string.to_string(), AtlasFrame::new(packer_idx, rect, tile_size));
+            let kind = TileKind::Sequence(steps.len() as u64, delay);
+            source.tiles.insert(index, AtlasTile { offset: steps[0].1, kind: kind });
+            source.sequences.insert(index, steps.iter().map(|&(k, o)| (k.to_string(), o)).collect());
         }
+
+        self.locations.insert(index, first_key);
+        Ok(())
     }
 
-    fn add_packer(&mut self) {
-        let config = TexturePackerConfig {
-            max_width: 2048,
-            max_height: 2048,
-            allow_rotation: false,
-            texture_outlines: false,
-            trim: false,
-            texture_padding: 0,
-            ..Default::default()
-        };
+    /// Pairs a frame with an overlay frame (a glow map, for example) laid out
+    /// on the same tile grid. `DrawInfo` then also reports where each tile
+    /// lies in the overlay, which may be packed on a different page.
+    pub fn set_frame_overlay(&mut self, key: &str, overlay_key: &str) -> AtlasResult<()> {
+        if !self.sources.contains_key(overlay_key) {
+            return Err(AtlasError::MissingFrame(overlay_key.to_string()));
+        }
+        let source = self.source_mut(key)?;
+        source.overlay = Some(overlay_key.to_string());
+        Ok(())
+    }
 
-        self.packers.push(TexturePacker::new_skyline(config));
+    /// Sets the anchor point reported in `DrawInfo` for every tile in the
+    /// frame, in fractions of the tile size.
+    pub fn set_frame_pivot(&mut self, key: &str, pivot: (f32, f32)) -> AtlasResult<()> {
+        let source = self.source_mut(key)?;
+        source.pivot = pivot;
+        Ok(())
     }
 
-    pub fn build<F: Facade>(&self, display: &F, packed_tex_folder: Option<PathBuf>) -> TileAtlas {
-        let mut textures = Vec::new();
+    /// Overrides `set_block_compression` for one frame. Frames are only
+    /// packed onto pages with others that are compressed the same way, so
+    /// UI text can stay lossless while ground tiles are compressed.
+    pub fn set_frame_compression(&mut self, key: &str, hint: CompressionHint) -> AtlasResult<()> {
+        let source = self.source_mut(key)?;
+        source.compression = hint;
+        Ok(())
+    }
 
-        if let Some(ref path_buf) = packed_tex_folder {
-            if Path::exists(path_buf.as_path()) {
-                fs::remove_dir_all(path_buf.as_path()).unwrap();
-            }
+    /// Puts a frame in a usage class, such as `"ui"`, `"world"` or
+    /// `"effects"`. Frames are only packed onto pages with others of the same
+    /// class, even when they'd fit elsewhere, so each class's pages can be
+    /// sampled and kept resident differently. See `TileAtlas::pages_with_class`.
+    pub fn set_frame_class(&mut self, key: &str, class: &str) -> AtlasResult<()> {
+        let source = self.source_mut(key)?;
+        source.class = Some(class.to_string());
+        Ok(())
+    }
+
+    /// Gets the settings of the pages a frame can be packed onto.
+    fn page_settings(&self, source: &FrameSource) -> PageSettings {
+        let compression = match source.compression {
+            CompressionHint::Default        => self.compression,
+            CompressionHint::Lossless       => None,
+            CompressionHint::Format(format) => Some(format),
+        };
 
-            fs::create_dir_all(path_buf.as_path()).unwrap();
+        PageSettings {
+            compression: compression,
+            class: source.class.clone(),
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn add_frame(&mut self, path_string: &str, tile_size: (u32, u32)) -> AtlasResult<()> {
+        self.add_frame_as(path_string, path_string, tile_size)
+    }
+
+    /// Like `add_frame`, but stores the frame under `key` instead of its
+    /// path, for namespaced keys like `mymod:grass.png`.
+    #[cfg(feature = "fs")]
+    pub fn add_frame_as(&mut self, key: &str, path_string: &str, tile_size: (u32, u32)) -> AtlasResult<()> {
+        if !self.wants_frame(key)? {
+            return Ok(());
         }
 
-        for (idx, packer) in self.packers.iter().enumerate() {
-            let image = ImageExporter::export(packer).unwrap();
+        trace_span!("decode", path = path_string);
 
-            if let Some(ref path_buf) = packed_tex_folder {
-                let mut file_path = path_buf.clone();
-                file_path.push(&format!("{}.png", idx));
+        let path = self.resolve_path(path_string)?;
+        let texture = decode::import_image(&path, self.limits.as_ref())?;
 
-                let mut file = File::create(file_path).unwrap();
+        self.insert_source(key, texture, tile_size)
+    }
 
-                image.save(&mut file, image::PNG).unwrap();
+    /// Adds several frames from files, keyed by their paths, as one change:
+    /// if any fails to load, is too large to ever pack or is too small for
+    /// the tiles an override keeps, the error is returned and none of them
+    /// are added. A key given twice is only loaded the first time.
+    #[cfg(feature = "fs")]
+    pub fn add_frames(&mut self, frames: &[(&str, (u32, u32))]) -> AtlasResult<()> {
+        let mut staged: Vec<(String, DynamicImage, (u32, u32))> = Vec::with_capacity(frames.len());
+        for &(key, tile_size) in frames.iter() {
+            if !self.wants_frame(key)? || staged.iter().any(|&(ref k, _, _)| k == key) {
+                continue;
             }
 
-            textures.push(make_texture(display, image));
+            trace_span!("decode", path = key);
+            let path = self.resolve_path(key)?;
+            let texture = decode::import_image(&path, self.limits.as_ref())?;
+            self.check_source(key, &texture, tile_size)?;
+
+            staged.push((key.to_string(), texture, tile_size));
         }
-        TileAtlas {
-            locations: self.locations.clone(),
-            frames: self.frames.clone(),
-            textures: textures,
+
+        self.insert_sources(staged)
+    }
+
+    /// Decodes the given frames across `threads` worker threads and adds them
+    /// in the order given. Only decoding is parallel; packing still happens
+    /// in `build`.
+    ///
+    /// As with `add_frames`, if any frame can't be added the first error is
+    /// returned and none of the frames are added.
+    #[cfg(feature = "fs")]
+    pub fn add_frames_parallel(&mut self, frames: &[(&str, (u32, u32))], threads: usize) -> AtlasResult<()> {
+        let mut pending: Vec<(String, PathBuf, (u32, u32))> = Vec::new();
+        for &(key, tile_size) in frames.iter() {
+            if self.wants_frame(key)? && !pending.iter().any(|&(ref k, _, _)| k == key) {
+                pending.push((key.to_string(), self.resolve_path(key)?, tile_size));
+            }
+        }
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let threads = cmp::max(threads, 1);
+        let shard_size = (pending.len() + threads - 1) / threads;
+
+        let limits = self.limits;
+
+        let handles: Vec<_> = pending.chunks(shard_size).map(|shard| {
+            let shard = shard.to_vec();
+            let first_path = shard[0].1.clone();
+            let handle = thread::spawn(move || {
+                shard.into_iter().map(|(key, path, tile_size)| {
+                    trace_span!("decode", path = key.as_str());
+                    let texture = decode::import_image(&path, limits.as_ref())?;
+                    Ok((key, texture, tile_size))
+                }).collect::<AtlasResult<Vec<_>>>()
+            });
+            (first_path, handle)
+        }).collect();
+
+        let mut decoded = Vec::with_capacity(pending.len());
+        for (first_path, handle) in handles {
+            let shard = match handle.join() {
+                Ok(shard) => shard?,
+                Err(_)    => return Err(AtlasError::Image(first_path, "the thread decoding it panicked".to_string())),
+            };
+            decoded.extend(shard);
         }
+
+        self.insert_sources(decoded)
     }
-}
 
-impl TileAtlas {
-    pub fn new(locations: HashMap<TileIndex, String>,
-               frames: HashMap<String, AtlasFrame>,
-               textures: Vec<Texture2d>) -> Self {
-        TileAtlas {
-            locations: locations,
-            frames: frames,
-            textures: textures,
+    /// Like `add_frame_as`, for an image file that's already been read into
+    /// memory, such as one fetched by a browser build without a filesystem.
+    /// The format is detected from the contents.
+    pub fn add_frame_bytes(&mut self, key: &str, data: &[u8], tile_size: (u32, u32)) -> AtlasResult<()> {
+        if !self.wants_frame(key)? {
+            return Ok(());
         }
+
+        trace_span!("decode", path = key);
+
+        let texture = decode::decode_image(data, Path::new(key), self.limits.as_ref())?;
+
+        self.insert_source(key, texture, tile_size)
     }
 
-    pub fn make_config(&self, file_hash: String) -> TileAtlasConfig {
-        TileAtlasConfig {
-            locations: self.locations.clone(),
-            frames: self.frames.clone(),
-            file_hash: file_hash,
+    /// Adds a frame from an image already in memory, stored under `key`. A
+    /// key that's already taken is handled as `set_key_collision` says.
+    pub fn add_frame_image(&mut self, key: &str, image: DynamicImage, tile_size: (u32, u32)) -> AtlasResult<()> {
+        if !self.wants_frame(key)? {
+            return Ok(());
         }
+
+        self.insert_source(key, image, tile_size)
     }
 
-    pub fn get_frame(&self, tile_type: TileIndex) -> &AtlasFrame {
-        let tex_name = self.locations.get(&tile_type).unwrap();
-        self.frames.get(tex_name).unwrap()
+    /// Fails with `AtlasError::FrameTooLarge` if `image` is too large to
+    /// ever be packed: larger than a page of the largest size the page may
+    /// grow to, less its border, unless oversized frames are split. Frames
+    /// are checked again when packing, against the page size then.
+    fn check_frame_size(&self, key: &str, image: &DynamicImage) -> AtlasResult<()> {
+        let (w, h) = image.dimensions();
+        let page_size = cmp::max(self.page_size, self.max_page_size.unwrap_or(0));
+        let usable = usable_page_size(page_size, self.page_border());
+        if (w > usable || h > usable) && !self.split_oversized {
+            return Err(AtlasError::FrameTooLarge(key.to_string(), (w, h), page_size));
+        }
+        Ok(())
     }
 
-    pub fn get_tile_texture_idx(&self, tile_type: TileIndex) -> usize {
-        self.get_frame(tile_type).texture_idx
+    /// Removes a frame along with every tile that points into it.
+    pub fn remove_frame(&mut self, key: &str) {
+        if let Some(source) = self.sources.remove(key) {
+            for index in source.tiles.keys() {
+                self.locations.remove(index);
+            }
+            self.order.retain(|k| k != key);
+            self.lints.retain(|lint| lint.frame() != key);
+
+            for other in self.sources.values_mut() {
+                if other.overlay.as_ref().map_or(false, |o| o == key) {
+                    other.overlay = None;
+                }
+
+                let broken: Vec<TileIndex> = other.sequences.iter()
+                    .filter(|&(_, steps)| steps.iter().any(|&(ref k, _)| k == key))
+                    .map(|(&index, _)| index)
+                    .collect();
+                for index in broken {
+                    other.sequences.remove(&index);
+                    other.tiles.remove(&index);
+                    self.locations.remove(&index);
+                }
+            }
+        }
     }
 
+    /// Swaps the image of an existing frame, keeping its tile size and the
+    /// tiles already registered against it. Fails, leaving the frame as it
+    /// was, if any of those tiles would lie outside of `image`.
+    pub fn replace_frame(&mut self, key: &str, image: DynamicImage) -> AtlasResult<()> {
+        let tile_size = self.source_mut(key)?.tile_size;
+        self.check_frame_tiles(key, image.dimensions(), tile_size)?;
 
-    pub fn get_tilemap_tex_ratio(&self, texture_idx: usize) -> [f32; 2] {
-        let dimensions = self.textures.get(texture_idx).unwrap().dimensions();
+        let thumbnail = self.frame_thumbnail(&image);
+        let source = self.source_mut(key)?;
+        source.image = image;
+        source.thumbnail = thumbnail;
+        Ok(())
+    }
 
-        let cols: u32 = dimensions.0 / 24;
-        let rows: u32 = dimensions.1 / 24;
-        [1.0 / cols as f32, 1.0 / rows as f32]
+    /// Makes the thumbnail a frame is compared by if near duplicates are
+    /// being linted.
+    fn frame_thumbnail(&self, image: &DynamicImage) -> Option<Thumbnail> {
+        match self.lint_options.and_then(|options| options.near_duplicates) {
+            Some(_) => Some(Thumbnail::new(image)),
+            None    => None,
+        }
     }
 
-    pub fn get_sprite_tex_ratio(&self, tile_type: TileIndex) -> [f32; 2] {
-        let frame = self.get_frame(tile_type);
-        let (sx, sy) = frame.tile_size;
+    /// Checks that every tile drawn from frame `key`, including steps of
+    /// sequence tiles, would still lie inside an image of `dimensions` cut
+    /// into tiles of `tile_size`.
+    fn check_frame_tiles(&self, key: &str, dimensions: (u32, u32), tile_size: (u32, u32)) -> AtlasResult<()> {
+        if let Some(source) = self.sources.get(key) {
+            for (&index, tile) in source.tiles.iter() {
+                check_tile_bounds(tile_size, dimensions, key, index, tile.offset, tile.kind)?;
+            }
+        }
 
-        let texture_idx = self.get_frame(tile_type).texture_idx;
-        let dimensions = self.textures.get(texture_idx).unwrap().dimensions();
+        for source in self.sources.values() {
+            for (&index, steps) in source.sequences.iter() {
+                for &(_, offset) in steps.iter().filter(|&&(ref step_key, _)| step_key == key) {
+                    check_tile_bounds(tile_size, dimensions, key, index, offset, TileKind::Static)?;
+                }
+            }
+        }
+        Ok(())
+    }
 
-        let cols: f32 = dimensions.0 as f32 / sx as f32;
-        let rows: f32 = dimensions.1 as f32 / sy as f32;
-        [1.0 / cols, 1.0 / rows]
+    /// Fails if `image` can't be added under `key`, the only ways
+    /// `insert_source` can fail.
+    fn check_source(&self, key: &str, image: &DynamicImage, tile_size: (u32, u32)) -> AtlasResult<()> {
+        self.check_frame_size(key, image)?;
+        // an override keeps the tiles already registered against the key, so
+        // they have to fit the new image.
+        self.check_frame_tiles(key, image.dimensions(), tile_size)
     }
 
-    pub fn get_tile_texture_size(&self, tile_type: TileIndex) -> (u32, u32) {
-        self.get_frame(tile_type).tile_size
+    /// Checks every staged frame before inserting any, so one that can't be
+    /// added leaves the builder as it was. Keys must not repeat.
+    #[cfg(feature = "fs")]
+    fn insert_sources(&mut self, staged: Vec<(String, DynamicImage, (u32, u32))>) -> AtlasResult<()> {
+        for &(ref key, ref texture, tile_size) in staged.iter() {
+            self.check_source(key, texture, tile_size)?;
+        }
+        for (key, texture, tile_size) in staged {
+            self.insert_source(&key, texture, tile_size)?;
+        }
+        Ok(())
     }
 
-    pub fn get_texture_offset(&self, tile_type: TileIndex) -> (f32, f32) {
-        let frame = self.get_frame(tile_type);
-        let offset = frame.offsets.get(&tile_type).unwrap();
+    fn insert_source(&mut self, key: &str, image: DynamicImage, tile_size: (u32, u32)) -> AtlasResult<()> {
+        self.check_source(key, &image, tile_size)?;
 
-        let get_tex_coords = |index: (u32, u32)| {
-            let tex_ratio = self.get_sprite_tex_ratio(tile_type);
-            let add_offset = get_add_offset(&frame.rect, &frame.tile_size);
+        self.lints.retain(|lint| lint.frame() != key);
+        if let Some(ref options) = self.lint_options {
+            for lint in lint::lint_frame(options, key, &image, tile_size) {
+                trace_warn!(frame = key, "{}", lint);
+                self.lints.push(lint);
+            }
+        }
 
-            let tx = (index.0 + add_offset.0) as f32 * tex_ratio[0];
-            let ty = (index.1 + add_offset.1) as f32 * tex_ratio[1];
+        let thumbnail = self.frame_thumbnail(&image);
+        if let Some(source) = self.sources.get_mut(key) {
+            source.image = image;
+            source.tile_size = tile_size;
+            source.thumbnail = thumbnail;
+            return Ok(());
+        }
 
-            (tx, ty)
+        let source = FrameSource {
+            image: image,
+            tile_size: tile_size,
+            tiles: HashMap::new(),
+            sequences: HashMap::new(),
+            pivot: (0.0, 0.0),
+            overlay: None,
+            compression: CompressionHint::Default,
+            class: None,
+            thumbnail: thumbnail,
         };
+        self.sources.insert(key.to_string(), source);
+        self.order.push(key.to_string());
+        Ok(())
+    }
+
+    /// Starts packing the frames added so far. The returned job can be
+    /// advanced a little at a time with `PackJob::pack_some`, so that
+    /// packing can be spread across several frames of a game loop. The job
+    /// can also be inspected as it goes, to decide whether to start another
+    /// atlas or change settings before finishing.
+    pub fn start_pack(&self) -> PackJob {
+        let page_size = self.packing_page_size();
+        let overlays: HashSet<&str> = self.sources.values()
+            .filter_map(|source| source.overlay.as_ref().map(|key| key.as_str()))
+            .collect();
+
+        // sorting by area needs every trimmed size up front, so they're only
+        // found as each frame is packed otherwise.
+        let mut trims = HashMap::new();
+        if self.trim && self.sort_by_area {
+            for (key, source) in self.sources.iter() {
+                if is_trimmable(source, overlays.contains(key.as_str())) {
+                    trims.insert(key.as_str(), trim_bounds(&source.image));
+                }
+            }
+        }
+        let order = self.pack_order(|key| match trims.get(key) {
+            Some(&Some((_, _, w, h))) => (w, h),
+            _                         => self.sources[key].image.dimensions(),
+        });
+
+        PackJob {
+            builder: self,
+            page_size: page_size,
+            order: order,
+            overlays: overlays,
+            trims: trims,
+            packers: vec![new_packer(self, page_size)],
+            page_settings: vec![None],
+            frames: HashMap::with_capacity(self.order.len()),
+            locations: self.locations.clone(),
+            splits: HashMap::new(),
+            blits: Vec::new(),
+            errors: Vec::new(),
+            next: 0,
+        }
+    }
 
-        get_tex_coords(*offset)
+    pub fn build<B: GpuBackend>(&self, backend: &B, packed_tex_folder: Option<PathBuf>) -> AtlasResult<TileAtlas<B::Texture>> {
+        self.build_cancellable(backend, packed_tex_folder, &AtomicBool::new(false))
     }
 
-    pub fn get_texture(&self, idx: usize) -> &Texture2d {
-        self.textures.get(idx).unwrap()
+    /// Like `build`, but gives up with `AtlasError::Cancelled` as soon as
+    /// `cancel` is set. Any pages already written to `packed_tex_folder` are
+    /// removed.
+    pub fn build_cancellable<B: GpuBackend>(&self,
+                                            backend: &B,
+                                            packed_tex_folder: Option<PathBuf>,
+                                            cancel: &AtomicBool) -> AtlasResult<TileAtlas<B::Texture>> {
+        self.start_pack().finish_cancellable(backend, packed_tex_folder, cancel)
     }
 
-    pub fn passes(&self) -> usize {
-        self.textures.len()
+    /// Packs the atlas without uploading it. See `CpuAtlas`.
+    pub fn build_cpu(&self, packed_tex_folder: Option<PathBuf>) -> AtlasResult<CpuAtlas> {
+        self.start_pack().finish_cpu(packed_tex_folder)
+    }
+
+    /// The first half of `build`, which never touches the GPU, so it can run
+    /// on a worker thread while the GL context stays on the main thread.
+    /// Send the result back and `upload` it there.
+    pub fn pack(&self) -> AtlasResult<CpuAtlas> {
+        self.build_cpu(None)
     }
 }
 
-fn get_add_offset(rect: &AtlasRect, tile_size: &(u32, u32)) -> (u32, u32) {
-    let ceil = |a, b| (a + b - 1) / b;
-    let cols: u32 = ceil(rect.x, tile_size.0);
-    let rows: u32 = ceil(rect.y, tile_size.1);
-    (cols, rows)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackProgress {
+    Packing { packed: usize, total: usize },
+    Done,
+}
+
+/// An in-progress pack of a `TileAtlasBuilder`'s frames.
+pub struct PackJob<'a> {
+    builder: &'a TileAtlasBuilder,
+    page_size: u32,
+    /// The builder's frames, in the order they're packed.
+    order: Vec<&'a String>,
+    /// The frames some other frame has as its overlay.
+    overlays: HashSet<&'a str>,
+    /// The trim bounds of trimmable frames, if they were found before
+    /// packing.
+    trims: HashMap<&'a str, Option<(u32, u32, u32, u32)>>,
+    packers: Vec<PagePacker<'a>>,
+    /// What every frame on each page has in common, or `None` for the first
+    /// page until a frame is packed onto it.
+    page_settings: Vec<Option<PageSettings>>,
+    frames: HashMap<String, AtlasFrame>,
+    locations: HashMap<TileIndex, String>,
+    /// Frames that were split, with the size of their pieces in tiles.
+    splits: HashMap<String, (u32, u32)>,
+    /// Where every packed frame's pixels come from.
+    blits: Vec<Blit<'a>>,
+    /// Frames that failed to pack while errors are being collected.
+    errors: Vec<AtlasError>,
+    next: usize,
+}
+
+impl<'a> PackJob<'a> {
+    /// Packs frames until `budget_ms` milliseconds have passed. At least one
+    /// frame is packed per call, so the job always makes progress.
+    ///
+    /// This reads the system clock, which `wasm32-unknown-unknown` doesn't
+    /// have, so browser builds should pack everything at once with
+    /// `finish_cpu` instead.
+    pub fn pack_some(&mut self, budget_ms: u64) -> AtlasResult<PackProgress> {
+        trace_span!("pack_some", budget_ms = budget_ms);
+        let start = Instant::now();
+        let budget = Duration::from_millis(budget_ms);
+
+        while !self.is_done() {
+            self.pack_next()?;
+
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+
+        Ok(self.progress())
+    }
+
+    pub fn progress(&self) -> PackProgress {
+        if self.is_done() {
+            PackProgress::Done
+        } else {
+            PackProgress::Packing {
+                packed: self.next,
+                total: self.order.len(),
+            }
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.order.len()
+    }
+
+    /// Gets how many pages the frames packed so far take up.
+    pub fn page_count(&self) -> usize {
+        self.packers.len()
+    }
+
+    /// Gets the size pages are packed at, which may have grown past the
+    /// builder's page size. See `TileAtlasBuilder::set_grow_page_size`.
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Estimates how many pixels are left on the pages so far, as their area
+    /// less that of the frames packed onto them. Padding and gaps the packer
+    /// can't fill aren't counted, so somewhat less than this will fit.
+    pub fn free_area(&self) -> u64 {
+        let page_area = self.page_size as u64 * self.page_size as u64;
+        let used: u64 = self.blits.iter().map(|blit| blit.rect.w as u64 * blit.rect.h as u64).sum();
+        (page_area * self.packers.len() as u64).saturating_sub(used)
+    }
+
+    /// Gets the page the frame under `key` was packed onto, or `None` if it
+    /// hasn't been packed yet. Frames split across pages give the page of
+    /// their first piece.
+    pub fn page_of(&self, key: &str) -> Option<PageId> {
+        self.frames.get(key)
+            .or_else(|| self.frames.get(&split_key(key, (0, 0))))
+            .map(|frame| PageId(frame.texture_idx))
+    }
+
+    fn pack_next(&mut self) -> AtlasResult<()> {
+        let result = self.pack_frame(self.next);
+        self.next += 1;
+
+        match result {
+            Err(e) => {
+                if self.builder.collect_errors {
+                    self.errors.push(e);
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            },
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn pack_frame(&mut self, idx: usize) -> AtlasResult<()> {
+        let builder = self.builder;
+        let key = self.order[idx];
+        trace_span!("pack", key = key.as_str());
+        let source = match builder.sources.get(key) {
+            Some(source) => source,
+            None         => return Err(AtlasError::MissingFrame(key.clone())),
+        };
+
+        let trim = if builder.trim && is_trimmable(source, self.overlays.contains(key.as_str())) {
+            match self.trims.get(key.as_str()) {
+                Some(&bounds) => bounds,
+                None          => trim_bounds(&source.image),
+            }
+        } else {
+            None
+        };
+        let trimmed = trim.map(|(x, y, w, h)| copy_region(&source.image, x, y, w, h));
+
+        let (w, h) = trimmed.as_ref().unwrap_or(&source.image).dimensions();
+        let usable = usable_page_size(self.page_size, builder.page_border());
+        if w > usable || h > usable {
+            if !builder.split_oversized {
+                return Err(AtlasError::FrameTooLarge(key.clone(), (w, h), self.page_size));
+            }
+            return self.pack_split(key, source);
+        }
+
+        let packer_idx = self.packer_for(trimmed.as_ref().unwrap_or(&source.image), builder.page_settings(source));
+
+        {
+            let packer = self.packers.get_mut(packer_idx).unwrap();
+            let rect = match trimmed {
+                Some(image) => packer.pack_own(key.clone(), image),
+                None        => packer.pack_ref(key.clone(), &source.image),
+            };
+            let rect = rect.map_err(|e| AtlasError::Pack(key.clone(), e))?;
+
+            let (origin, tile_size) = match trim {
+                Some((x, y, w, h)) => ((x, y), (w, h)),
+                None               => ((0, 0), source.tile_size),
+            };
+            let mut frame = AtlasFrame::new(packer_idx, rect, tile_size);
+            frame.source_size = source.tile_size;
+            frame.trim_offset = origin;
+            frame.padded_rect = pad_rect(&frame.rect, self.page_size, builder.frame_padding());
+            self.blits.push(Blit {
+                page: packer_idx,
+                image: &source.image,
+                origin: origin,
+                rect: frame.rect.clone(),
+            });
+            frame.tiles = source.tiles.clone();
+            frame.sequences = source.sequences.clone();
+            frame.pivot = source.pivot;
+            frame.overlay = source.overlay.clone();
+            frame.class = source.class.clone();
+            self.frames.insert(key.clone(), frame);
+        }
+
+        Ok(())
+    }
+
+    /// Gets the first page with `settings` and room for `image`, starting a
+    /// new one if none has any.
+    fn packer_for(&mut self, image: &DynamicImage, settings: PageSettings) -> usize {
+        let existing = self.packers.iter().zip(self.page_settings.iter())
+            .position(|(p, s)| s.as_ref().map_or(true, |s| *s == settings) && p.can_pack(image));
+        match existing {
+            Some(idx) => {
+                self.page_settings[idx] = Some(settings);
+                idx
+            },
+            None => {
+                self.packers.push(new_packer(self.builder, self.page_size));
+                self.page_settings.push(Some(settings));
+                self.packers.len() - 1
+            }
+        }
+    }
+
+    /// Packs a frame that's too large for a page as several pieces, each a
+    /// whole number of tiles across.
+    fn pack_split(&mut self, key: &str, source: &'a FrameSource) -> AtlasResult<()> {
+        if source.overlay.is_some() || self.overlays.contains(key) {
+            return Err(AtlasError::Pack(key.to_string(), "Frames with overlays can't be split".to_string()));
+        }
+
+        let (tw, th) = source.tile_size;
+        let usable = usable_page_size(self.page_size, self.builder.page_border());
+        let piece_tiles = (usable / cmp::max(tw, 1), usable / cmp::max(th, 1));
+        if piece_tiles.0 == 0 || piece_tiles.1 == 0 {
+            return Err(AtlasError::Pack(key.to_string(), "Tiles are larger than a page".to_string()));
+        }
+
+        let (w, h) = source.image.dimensions();
+        let (piece_w, piece_h) = (piece_tiles.0 * tw, piece_tiles.1 * th);
+
+        for py in 0..(h + piece_h - 1) / piece_h {
+            for px in 0..(w + piece_w - 1) / piece_w {
+                let (x, y) = (px * piece_w, py * piece_h);
+                let image = copy_region(&source.image, x, y, cmp::min(piece_w, w - x), cmp::min(piece_h, h - y));
+                let piece_key = split_key(key, (px, py));
+
+                let packer_idx = self.packer_for(&image, self.builder.page_settings(source));
+                let packer = self.packers.get_mut(packer_idx).unwrap();
+                let rect = packer.pack_own(piece_key.clone(), image)
+                    .map_err(|e| AtlasError::Pack(piece_key.clone(), e))?;
+
+                let mut frame = AtlasFrame::new(packer_idx, rect, source.tile_size);
+                frame.padded_rect = pad_rect(&frame.rect, self.page_size, self.builder.frame_padding());
+                self.blits.push(Blit {
+                    page: packer_idx,
+                    image: &source.image,
+                    origin: (x, y),
+                    rect: frame.rect.clone(),
+                });
+                frame.pivot = source.pivot;
+                frame.class = source.class.clone();
+                self.frames.insert(piece_key, frame);
+            }
+        }
+
+        self.splits.insert(key.to_string(), piece_tiles);
+
+        for (&index, tile) in source.tiles.iter() {
+            let (piece_key, tile, steps) = match tile.kind {
+                TileKind::Static => {
+                    let (piece_key, offset) = split_offset(key, piece_tiles, tile.offset);
+                    (piece_key, AtlasTile { offset: offset, kind: TileKind::Static }, None)
+                },
+                TileKind::Animated(frame_count, delay) |
+                TileKind::StridedAnimated(frame_count, delay, _) => {
+                    let stride = column_stride(tile.kind);
+                    let steps: SequenceSteps = (0..frame_count)
+                        .map(|i| split_offset(key, piece_tiles, (tile.offset.0 + i as u32 * stride, tile.offset.1)))
+                        .collect();
+                    let (first_key, first_offset) = steps[0].clone();
+
+                    if steps.iter().all(|&(ref k, _)| *k == first_key) {
+                        (first_key, AtlasTile { offset: first_offset, kind: tile.kind }, None)
+                    } else {
+                        let kind = TileKind::Sequence(frame_count, delay);
+                        (first_key, AtlasTile { offset: first_offset, kind: kind }, Some(steps))
+                    }
+                },
+                TileKind::Sequence(..) => {
+                    // steps are remapped once every frame is packed.
+                    let (piece_key, offset) = split_offset(key, piece_tiles, tile.offset);
+                    let steps = source.sequences.get(&index).cloned();
+                    (piece_key, AtlasTile { offset: offset, kind: tile.kind }, steps)
+                },
+            };
+
+            let frame = self.frames.get_mut(&piece_key).unwrap();
+            frame.tiles.insert(index, tile);
+            if let Some(steps) = steps {
+                frame.sequences.insert(index, steps);
+            }
+            self.locations.insert(index, piece_key);
+        }
+
+        Ok(())
+    }
+
+    /// Points sequence steps into split frames at the piece holding them.
+    fn remap_split_sequences(&mut self) {
+        if self.splits.is_empty() {
+            return;
+        }
+
+        let splits = &self.splits;
+        for frame in self.frames.values_mut() {
+            for steps in frame.sequences.values_mut() {
+                for step in steps.iter_mut() {
+                    let remapped = match splits.get(&step.0) {
+                        Some(&piece_tiles) => split_offset(&step.0, piece_tiles, step.1),
+                        None               => continue,
+                    };
+                    *step = remapped;
+                }
+            }
+        }
+    }
+
+    /// Packs any remaining frames and uploads the pages.
+    pub fn finish<B: GpuBackend>(self, backend: &B, packed_tex_folder: Option<PathBuf>) -> AtlasResult<TileAtlas<B::Texture>> {
+        self.finish_cancellable(backend, packed_tex_folder, &AtomicBool::new(false))
+    }
+
+    pub fn finish_cancellable<B: GpuBackend>(self,
+                                             backend: &B,
+                                             packed_tex_folder: Option<PathBuf>,
+                                             cancel: &AtomicBool) -> AtlasResult<TileAtlas<B::Texture>> {
+        let retain = self.builder.retain_pages;
+        let cpu_atlas = self.finish_cpu_cancellable(packed_tex_folder, cancel)?;
+        if retain {
+            cpu_atlas.upload_retained(backend)
+        } else {
+            cpu_atlas.upload(backend)
+        }
+    }
+
+    /// Packs any remaining frames and exports the pages, without uploading
+    /// them.
+    pub fn finish_cpu(self, packed_tex_folder: Option<PathBuf>) -> AtlasResult<CpuAtlas> {
+        self.finish_cpu_cancellable(packed_tex_folder, &AtomicBool::new(false))
+    }
+
+    pub fn finish_cpu_cancellable(mut self,
+                                  packed_tex_folder: Option<PathBuf>,
+                                  cancel: &AtomicBool) -> AtlasResult<CpuAtlas> {
+        while !self.is_done() {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(AtlasError::Cancelled);
+            }
+            self.pack_next()?;
+        }
+
+        if self.builder.collect_errors {
+            let mut errors = self.builder.errors.clone();
+            errors.append(&mut self.errors);
+            if !errors.is_empty() {
+                return Err(AtlasError::Multiple(errors));
+            }
+        }
+
+        self.remap_split_sequences();
+
+        trace_count!("frames", self.frames.len());
+        trace_count!("pages", self.packers.len());
+
+        if let Some(ref path_buf) = packed_tex_folder {
+            prepare_page_folder(path_buf, self.builder.stale_pages)?;
+        }
+
+        match self.export_pages(&packed_tex_folder, cancel) {
+            Ok((pages, compressed, names)) => {
+                let levels = self.builder.mip_level_count();
+                let mipmaps = if levels > 1 {
+                    pages.iter().map(|page| mipmap::generate_chain(page, levels)).collect()
+                } else {
+                    Vec::new()
+                };
+
+                Ok(CpuAtlas::new(self.locations, self.frames, pages)
+                   .with_compressed_pages(compressed)
+                   .with_mipmaps(mipmaps)
+                   .with_sampler(self.builder.sampler)
+                   .with_page_names(names)
+                   .with_flip_y(self.builder.flip_y)
+                   .with_half_texel_inset(self.builder.half_texel_inset)
+                   .with_stale_pages(self.builder.stale_pages))
+            },
+            Err(e) => {
+                remove_partial_output(&packed_tex_folder, self.builder.stale_pages);
+                Err(e)
+            }
+        }
+    }
+
+    /// Exports every page, and compresses it if the builder asks for it,
+    /// writing each to `packed_tex_folder` if given. Written pages are named
+    /// after a hash of their contents, so a page keeps its file name across
+    /// rebuilds as long as it doesn't change.
+    fn export_pages(&self,
+                    packed_tex_folder: &Option<PathBuf>,
+                    cancel: &AtomicBool) -> AtlasResult<(Vec<DynamicImage>, Vec<Option<CompressedPage>>, Vec<String>)> {
+        let mut pages = Vec::new();
+        let mut compressed = Vec::new();
+        let mut names = Vec::new();
+
+        for idx in 0..self.packers.len() {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(AtlasError::Cancelled);
+            }
+
+            let mut image = {
+                trace_span!("export", page = idx);
+                DynamicImage::ImageRgba8(self.composite_page(idx))
+            };
+
+            let extrude_padding = self.builder.extrude_padding();
+            if extrude_padding > 0 {
+                for frame in self.frames.values().filter(|frame| frame.texture_idx == idx) {
+                    let bleed = pad_rect(&frame.rect, self.page_size, extrude_padding);
+                    extrude(&mut image, &frame.rect, &bleed);
+                }
+            }
+
+            if self.builder.debug_outlines {
+                let rects: Vec<_> = self.frames.values()
+                    .filter(|frame| frame.texture_idx == idx)
+                    .map(|frame| &frame.rect)
+                    .collect();
+                draw_debug_outlines(&mut image, idx, &rects);
+            }
+
+            let format = match self.page_settings[idx] {
+                Some(ref settings) => settings.compression,
+                None               => self.builder.compression,
+            };
+
+            match format {
+                Some(format) => {
+                    let page = block_compress::compress(&image, format);
+                    if let Some(ref path_buf) = *packed_tex_folder {
+                        names.push(write_compressed_page(path_buf, &page)?);
+                    }
+                    compressed.push(Some(page));
+                },
+                None => {
+                    if let Some(ref path_buf) = *packed_tex_folder {
+                        names.push(write_page(path_buf, &image)?);
+                    }
+                    compressed.push(None);
+                },
+            }
+
+            pages.push(image);
+        }
+
+        Ok((pages, compressed, names))
+    }
+}
+
+/// A frame's pixels to copy onto a page: the area of `image` with its
+/// top-left corner at `origin` and the size of `rect`, which is where it
+/// goes on the page.
+struct Blit<'a> {
+    page: usize,
+    image: &'a DynamicImage,
+    origin: (u32, u32),
+    rect: AtlasRect,
+}
+
+impl<'a> PackJob<'a> {
+    /// Copies every frame packed onto a page into one RGBA buffer, a row at a
+    /// time where the frame is RGBA already. Gives the same page as
+    /// `PagePacker::export`, without reading it out of the packer pixel by
+    /// pixel.
+    fn composite_page(&self, idx: usize) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let packer = &self.packers[idx];
+        let (w, h) = if self.builder.reserve_page_space {
+            (self.page_size, self.page_size)
+        } else {
+            (packer.width(), packer.height())
+        };
+        let mut page: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(w, h);
+        let page_w = page.width();
+
+        for blit in self.blits.iter().filter(|blit| blit.page == idx) {
+            let (x, y) = blit.origin;
+            let rect = &blit.rect;
+
+            if rect.rotated {
+                // The image's rows become the page's columns, from the right.
+                for row in 0..rect.w {
+                    for col in 0..rect.h {
+                        page.put_pixel(rect.x + rect.w - 1 - row, rect.y + col, blit.image.get_pixel(x + col, y + row));
+                    }
+                }
+                continue;
+            }
+
+            match *blit.image {
+                DynamicImage::ImageRgba8(ref rgba) => {
+                    let image_w = rgba.width();
+                    let row_len = (rect.w * 4) as usize;
+                    let src_data: &[u8] = rgba;
+                    let data: &mut [u8] = &mut page;
+                    for row in 0..rect.h {
+                        let src = (((y + row) * image_w + x) * 4) as usize;
+                        let dst = (((rect.y + row) * page_w + rect.x) * 4) as usize;
+                        data[dst..dst + row_len].copy_from_slice(&src_data[src..src + row_len]);
+                    }
+                },
+                ref image => {
+                    for row in 0..rect.h {
+                        for col in 0..rect.w {
+                            page.put_pixel(rect.x + col, rect.y + row, image.get_pixel(x + col, y + row));
+                        }
+                    }
+                },
+            }
+        }
+
+        page
+    }
+}
+
+/// What a build does with pages already in the folder it writes pages to.
+/// See `TileAtlasBuilder::set_stale_pages`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StalePages {
+    /// Remove the folder, and everything in it, before writing.
+    RemoveFolder,
+    /// Remove only the files named like pages, keeping any others.
+    RemovePages,
+    /// Fail with `AtlasError::Io` if there are any pages, removing nothing.
+    Fail,
+}
+
+/// Gets a folder ready to write pages to, dealing with pages already in it
+/// as `stale` says.
+#[cfg(feature = "fs")]
+pub fn prepare_page_folder(folder: &Path, stale: StalePages) -> AtlasResult<()> {
+    if folder.exists() {
+        match stale {
+            StalePages::RemoveFolder => {
+                fs::remove_dir_all(folder).map_err(|e| AtlasError::Io(folder.to_path_buf(), e))?;
+            },
+            StalePages::RemovePages => {
+                for path in page_files(folder)? {
+                    fs::remove_file(&path).map_err(|e| AtlasError::Io(path.clone(), e))?;
+                }
+            },
+            StalePages::Fail => {
+                if let Some(path) = page_files(folder)?.into_iter().next() {
+                    let err = io::Error::new(io::ErrorKind::AlreadyExists, "a page of an earlier build is in the way");
+                    return Err(AtlasError::Io(path, err));
+                }
+            },
+        }
+    }
+
+    fs::create_dir_all(folder).map_err(|e| AtlasError::Io(folder.to_path_buf(), e))
+}
+
+/// Lists the files in `folder` named like `write_page` and
+/// `write_compressed_page` name pages.
+#[cfg(feature = "fs")]
+pub fn page_files(folder: &Path) -> AtlasResult<Vec<PathBuf>> {
+    let entries = fs::read_dir(folder).map_err(|e| AtlasError::Io(folder.to_path_buf(), e))?;
+
+    let mut pages = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| AtlasError::Io(folder.to_path_buf(), e))?.path();
+        if path.is_file() && is_page_file_name(&path) {
+            pages.push(path);
+        }
+    }
+    Ok(pages)
+}
+
+#[cfg(feature = "fs")]
+fn is_page_file_name(path: &Path) -> bool {
+    let stem = path.file_stem().and_then(|stem| stem.to_str());
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    match (stem, extension) {
+        (Some(stem), Some(extension)) => {
+            let hashed = stem.len() == 16 && stem.chars().all(|c| c.is_digit(16));
+            let known = [BlockFormat::Bc1, BlockFormat::Bc3].iter().any(|format| format.extension() == extension);
+            hashed && (extension == "png" || known)
+        },
+        _ => false,
+    }
+}
+
+/// Removes what a failed or cancelled build left behind: the whole folder,
+/// unless it may hold files other than pages. Errors are ignored, since
+/// this only runs while another error is already being reported.
+#[cfg(feature = "fs")]
+fn remove_partial_output(packed_tex_folder: &Option<PathBuf>, stale: StalePages) {
+    if let Some(ref path_buf) = *packed_tex_folder {
+        if !Path::exists(path_buf.as_path()) {
+            return;
+        }
+
+        match stale {
+            StalePages::RemoveFolder => {
+                let _ = fs::remove_dir_all(path_buf.as_path());
+            },
+            StalePages::RemovePages | StalePages::Fail => {
+                for path in page_files(path_buf).unwrap_or_else(|_| Vec::new()) {
+                    let _ = fs::remove_file(&path);
+                }
+            },
+        }
+    }
+}
+
+/// Saves a page into `folder`, named by `page_file_name`, and returns the
+/// name.
+#[cfg(feature = "fs")]
+pub fn write_page(folder: &Path, page: &DynamicImage) -> AtlasResult<String> {
+    let name = page_file_name(page);
+    let file_path = folder.join(&name);
+
+    let mut data = Vec::new();
+    page.save(&mut data, image::PNG).map_err(|e| AtlasError::Image(file_path.clone(), e.to_string()))?;
+    fs_util::write_atomic(&file_path, data.as_slice())?;
+
+    Ok(name)
+}
+
+/// Marks the start of a saved `CompressedPage`.
+const COMPRESSED_PAGE_MAGIC: &'static [u8; 4] = b"TABC";
+
+/// Bumped whenever the serialized layout of `CompressedPage` changes.
+const COMPRESSED_PAGE_VERSION: u32 = 1;
+
+/// Saves a compressed page into `folder`, named like `write_page` names
+/// pages but with the block format as its extension, and returns the name.
+#[cfg(feature = "fs")]
+pub fn write_compressed_page(folder: &Path, page: &CompressedPage) -> AtlasResult<String> {
+    let name = hashed_file_name((page.width, page.height), &page.data, page.format.extension());
+    let data = tile_atlas_config::encode_versioned(COMPRESSED_PAGE_MAGIC, COMPRESSED_PAGE_VERSION, page)?;
+    fs_util::write_atomic(&folder.join(&name), data.as_slice())?;
+
+    Ok(name)
+}
+
+/// Gets the error for writing to `path` without the `fs` feature.
+#[cfg(not(feature = "fs"))]
+pub fn fs_disabled(path: &Path) -> AtlasError {
+    AtlasError::Io(path.to_path_buf(), io::Error::new(io::ErrorKind::NotFound, "built without the fs feature"))
+}
+
+// Without the `fs` feature pages are only kept in memory, so asking for a
+// folder to write them to fails before any is written.
+
+#[cfg(not(feature = "fs"))]
+pub fn prepare_page_folder(folder: &Path, _: StalePages) -> AtlasResult<()> {
+    Err(fs_disabled(folder))
+}
+
+#[cfg(not(feature = "fs"))]
+fn remove_partial_output(_: &Option<PathBuf>, _: StalePages) {}
+
+#[cfg(not(feature = "fs"))]
+pub fn write_page(folder: &Path, _: &DynamicImage) -> AtlasResult<String> {
+    Err(fs_disabled(folder))
+}
+
+#[cfg(not(feature = "fs"))]
+pub fn write_compressed_page(folder: &Path, _: &CompressedPage) -> AtlasResult<String> {
+    Err(fs_disabled(folder))
+}
+
+/// Reads back a page saved by `write_compressed_page`, failing with
+/// `AtlasError::Cache` if it doesn't hold every block of the page.
+pub fn decode_compressed_page(data: &[u8]) -> AtlasResult<CompressedPage> {
+    let page: CompressedPage = tile_atlas_config::decode_versioned(COMPRESSED_PAGE_MAGIC, COMPRESSED_PAGE_VERSION, data)?;
+    page.check()?;
+    Ok(page)
+}
+
+/// Names a page after the hash of its size and pixels.
+fn page_file_name(page: &DynamicImage) -> String {
+    hashed_file_name(page.dimensions(), &page.raw_pixels(), "png")
+}
+
+fn hashed_file_name((w, h): (u32, u32), data: &[u8], extension: &str) -> String {
+    let size = format!("{}x{}:", w, h);
+    let mut name = digest::hash_hex(&[size.as_bytes(), data]);
+    name.truncate(16);
+    name.push('.');
+    name.push_str(extension);
+    name
+}
+
+/// Default width and height of each texture page.
+const PAGE_SIZE: u32 = 2048;
+
+/// How a frame's page may be compressed. See
+/// `TileAtlasBuilder::set_frame_compression`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionHint {
+    /// Compressed as set with `set_block_compression`.
+    Default,
+    /// Never block compressed, for frames like UI text that show the loss.
+    Lossless,
+    /// Compressed to this format, even if the rest of the atlas isn't.
+    Format(BlockFormat),
+}
+
+/// What every frame packed onto a page has in common.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PageSettings {
+    compression: Option<BlockFormat>,
+    class: Option<String>,
+}
+
+/// What a `TileAtlasBuilder` does with a frame added under a key it already
+/// has, such as two content packs both providing `grass.png`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyCollision {
+    /// Keep the frame that was added first.
+    KeepFirst,
+    /// Fail with `AtlasError::DuplicateFrame`.
+    Error,
+    /// Swap in the new frame's image, keeping the tiles already registered
+    /// against the key, so a pack can replace another's art.
+    Override,
+}
+
+fn new_packer<'a>(builder: &TileAtlasBuilder, page_size: u32) -> PagePacker<'a> {
+    let padding = builder.frame_padding();
+    let config = PackerConfig {
+        algorithm: builder.pack_algorithm,
+        max_width: page_size,
+        max_height: page_size,
+        padding: padding,
+        border_padding: builder.page_border(),
+        allow_rotation: builder.allow_rotation,
+        outlines: false,
+    };
+
+    PagePacker::new(&config)
+}
+
+/// Space left at the page edges, so frames there get as much padding as
+/// the rest.
+fn border_padding(padding: u32) -> u32 {
+    (padding + 1) / 2
+}
+
+/// Largest frame width or height that fits on a page inside a border of
+/// `border` pixels.
+fn usable_page_size(page_size: u32, border: u32) -> u32 {
+    page_size.saturating_sub(2 * border)
+}
+
+/// Grows a frame's rect by its share of the padding between frames.
+fn pad_rect(rect: &AtlasRect, page_size: u32, padding: u32) -> AtlasRect {
+    let before = padding / 2;
+    let after = padding - before;
+    let x = rect.x.saturating_sub(before);
+    let y = rect.y.saturating_sub(before);
+
+    AtlasRect {
+        x: x,
+        y: y,
+        w: cmp::min(rect.x + rect.w + after, page_size) - x,
+        h: cmp::min(rect.y + rect.h + after, page_size) - y,
+        rotated: rect.rotated,
+    }
+}
+
+fn split_key(key: &str, piece: (u32, u32)) -> String {
+    format!("{}#{}_{}", key, piece.0, piece.1)
+}
+
+/// Finds the piece of a split frame holding the tile at `offset`, and the
+/// tile's offset within it.
+fn split_offset(key: &str, piece_tiles: (u32, u32), offset: TileOffset) -> (String, TileOffset) {
+    let piece = (offset.0 / piece_tiles.0, offset.1 / piece_tiles.1);
+    (split_key(key, piece), (offset.0 % piece_tiles.0, offset.1 % piece_tiles.1))
+}
+
+/// Fills the part of `padded` outside of `rect` with copies of the nearest
+/// edge pixels of `rect`.
+fn extrude(page: &mut DynamicImage, rect: &AtlasRect, padded: &AtlasRect) {
+    if rect.w == 0 || rect.h == 0 {
+        return;
+    }
+
+    let (page_w, page_h) = page.dimensions();
+    let clamp = |v: u32, min: u32, max: u32| cmp::min(cmp::max(v, min), max);
+
+    for y in padded.y..cmp::min(padded.y + padded.h, page_h) {
+        for x in padded.x..cmp::min(padded.x + padded.w, page_w) {
+            let inside = x >= rect.x && x < rect.x + rect.w && y >= rect.y && y < rect.y + rect.h;
+            if !inside {
+                let pixel = page.get_pixel(clamp(x, rect.x, rect.x + rect.w - 1), clamp(y, rect.y, rect.y + rect.h - 1));
+                page.put_pixel(x, y, pixel);
+            }
+        }
+    }
+}
+
+/// Colors pages are tinted with by `set_debug_outlines`, cycled through.
+const DEBUG_PAGE_COLORS: &'static [[u8; 3]] = &[[255, 0, 255], [0, 255, 255], [255, 255, 0],
+                                                [255, 64, 0], [0, 255, 64], [64, 0, 255]];
+
+/// Tints a page a quarter of the way toward its debug color, empty space
+/// included, then outlines each of `rects` in the full color.
+fn draw_debug_outlines(page: &mut DynamicImage, idx: usize, rects: &[&AtlasRect]) {
+    let color = DEBUG_PAGE_COLORS[idx % DEBUG_PAGE_COLORS.len()];
+    let outline = Rgba { data: [color[0], color[1], color[2], 255] };
+
+    for (x, y, pixel) in page.clone().pixels() {
+        let mut tinted = pixel;
+        for (value, &tint) in tinted.data.iter_mut().zip(color.iter()) {
+            *value = ((*value as u32 * 3 + tint as u32) / 4) as u8;
+        }
+        tinted.data[3] = cmp::max(pixel.data[3], 64);
+        page.put_pixel(x, y, tinted);
+    }
+
+    for rect in rects.iter().filter(|rect| rect.w > 0 && rect.h > 0) {
+        let (right, bottom) = (rect.x + rect.w - 1, rect.y + rect.h - 1);
+        for x in rect.x..right + 1 {
+            page.put_pixel(x, rect.y, outline);
+            page.put_pixel(x, bottom, outline);
+        }
+        for y in rect.y..bottom + 1 {
+            page.put_pixel(rect.x, y, outline);
+            page.put_pixel(right, y, outline);
+        }
+    }
+}
+
+/// Whether a frame can be trimmed without moving any of its tiles. See
+/// `TileAtlasBuilder::set_trim`.
+fn is_trimmable(source: &FrameSource, is_overlay: bool) -> bool {
+    source.image.dimensions() == source.tile_size && source.overlay.is_none() && !is_overlay
+}
+
+/// Finds the smallest area of `image` holding every pixel that isn't fully
+/// transparent, or `None` if that's the whole image. An image with none left
+/// keeps its top-left pixel.
+fn trim_bounds(image: &DynamicImage) -> Option<(u32, u32, u32, u32)> {
+    let (w, h) = image.dimensions();
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in image.pixels() {
+        if pixel.data[3] == 0 {
+            continue;
+        }
+        bounds = Some(match bounds {
+            Some((x0, y0, x1, y1)) => (cmp::min(x0, x), cmp::min(y0, y), cmp::max(x1, x), cmp::max(y1, y)),
+            None                   => (x, y, x, y),
+        });
+    }
+
+    let (x0, y0, x1, y1) = bounds.unwrap_or((0, 0, 0, 0));
+    if (x0, y0, x1 + 1, y1 + 1) == (0, 0, w, h) {
+        None
+    } else {
+        Some((x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+    }
+}
+
+fn copy_region(image: &DynamicImage, x: u32, y: u32, w: u32, h: u32) -> DynamicImage {
+    let region = ImageBuffer::from_fn(w, h, |px, py| image.get_pixel(x + px, y + py));
+    DynamicImage::ImageRgba8(region)
+}
+
+impl<T: backend::PageTexture> TileAtlas<T> {
+    pub fn new(locations: HashMap<TileIndex, String>,
+               frames: HashMap<String, AtlasFrame>,
+               textures: Vec<T>) -> Self {
+        TileAtlas {
+            locations: locations,
+            frames: frames,
+            textures: textures,
+            page_names: Vec::new(),
+            pending: Vec::new(),
+            usage: None,
+            refs: HashMap::new(),
+            sampler: SamplerSettings::default(),
+            retained: None,
+            shelves: Vec::new(),
+            contexts: HashMap::new(),
+            flip_y: true,
+            half_texel_inset: false,
+            mip_levels: 1,
+        }
+    }
+
+    /// Keeps `pages` for `reupload`. See `CpuAtlas::upload_retained`.
+    pub fn with_retained_pages(mut self, pages: CpuAtlas) -> Self {
+        self.retained = Some(pages);
+        self
+    }
+
+    pub fn has_retained_pages(&self) -> bool {
+        self.retained.is_some()
+    }
+
+    /// Gets the copy of the pages kept in main memory, if they're retained.
+    /// Pixels written with `write_pixels` are drawn onto it too.
+    pub fn retained_pages(&self) -> Option<&CpuAtlas> {
+        self.retained.as_ref()
+    }
+
+    /// Records whether the pages were flipped on upload, which `flush` then
+    /// keeps to. See `TileAtlasBuilder::set_flip_y`.
+    pub fn with_flip_y(mut self, flip_y: bool) -> Self {
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn flip_y(&self) -> bool {
+        self.flip_y
+    }
+
+    /// Records how many mip levels the pages were uploaded with, counting
+    /// the page itself, so a cache made with `make_config` has them made
+    /// again when it's loaded.
+    pub fn with_mip_levels(mut self, levels: u32) -> Self {
+        self.mip_levels = levels;
+        self
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn with_half_texel_inset(mut self, inset: bool) -> Self {
+        self.half_texel_inset = inset;
+        self
+    }
+
+    /// Sets whether the UVs this atlas reports, in `DrawInfo`,
+    /// `get_texture_offset`, `get_sprite_tex_ratio` and `Atlas::frame_uv`,
+    /// are inset by half a texel on every side. Exact edge UVs are shared
+    /// with whatever is packed next to a frame, so linear filtering of a
+    /// scaled tile blends in its neighbors' texels; inset UVs stay inside.
+    /// Off by default, since nearest filtering doesn't need it.
+    pub fn set_half_texel_inset(&mut self, inset: bool) {
+        self.half_texel_inset = inset;
+    }
+
+    pub fn half_texel_inset(&self) -> bool {
+        self.half_texel_inset
+    }
+
+    /// Recreates every page's texture with `backend` from the copies kept in
+    /// main memory, such as after the GL context was lost and recreated.
+    /// Pixels written with `write_pixels` are kept, flushed or not. Fails
+    /// unless the pages were retained with
+    /// `TileAtlasBuilder::set_retain_pages`.
+    ///
+    /// Only the context the atlas was built with is recreated. Other
+    /// contexts keep their textures and queued writes; recreate any that
+    /// were lost too with `add_context`.
+    pub fn reupload<B: GpuBackend<Texture = T>>(&mut self, backend: &B) -> AtlasResult<()> {
+        let textures = match self.retained {
+            Some(ref pages) => pages.upload_pages(backend)?,
+            None            => return Err(AtlasError::Texture("Pages weren't retained, so they can't be reuploaded".to_string())),
+        };
+
+        self.textures = textures;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Uploads every page to another context with `backend`, for
+    /// applications with several windows that all draw from this atlas. The
+    /// textures are looked up with `context_texture`, by the same page
+    /// indices as `DrawInfo` gives. Adding a context again replaces its
+    /// textures and drops the writes queued for it, which the new textures
+    /// already have. Like `reupload`, this needs the pages to be retained;
+    /// use `insert_context` with `CpuAtlas::upload_pages` otherwise.
+    pub fn add_context<B, C>(&mut self, backend: &B, context: C) -> AtlasResult<()>
+        where B: GpuBackend<Texture = T>,
+              C: Into<ContextId>
+    {
+        let textures = match self.retained {
+            Some(ref pages) => pages.upload_pages(backend)?,
+            None            => return Err(AtlasError::Texture("Pages weren't retained, so they can't be uploaded to another context".to_string())),
+        };
+
+        self.contexts.insert(context.into(), ContextPages::new(textures));
+        Ok(())
+    }
+
+    /// Adds textures of every page, in page order, uploaded to another
+    /// context elsewhere. See `add_context`.
+    pub fn insert_context<C: Into<ContextId>>(&mut self, context: C, textures: Vec<T>) -> AtlasResult<()> {
+        let context = context.into();
+        if textures.len() != self.textures.len() {
+            return Err(AtlasError::Texture(format!("The atlas has {} pages, but {} has {} textures",
+                                                   self.textures.len(), context, textures.len())));
+        }
+
+        self.contexts.insert(context, ContextPages::new(textures));
+        Ok(())
+    }
+
+    /// Drops a context's textures, such as when its window closes, and gives
+    /// them back.
+    pub fn remove_context<C: Into<ContextId>>(&mut self, context: C) -> Option<Vec<T>> {
+        self.contexts.remove(&context.into()).map(|pages| pages.textures)
+    }
+
+    /// Gets the contexts added besides the one the atlas was built with, in
+    /// order.
+    pub fn contexts(&self) -> Vec<ContextId> {
+        let mut contexts: Vec<ContextId> = self.contexts.keys().cloned().collect();
+        contexts.sort();
+        contexts
+    }
+
+    /// Gets a page's texture in another context, or `None` if the context
+    /// wasn't added.
+    pub fn context_texture<C: Into<ContextId>, P: Into<PageId>>(&self, context: C, page: P) -> Option<&T> {
+        let PageId(page) = page.into();
+        self.contexts.get(&context.into()).and_then(|pages| pages.textures.get(page))
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerSettings) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn set_sampler(&mut self, sampler: SamplerSettings) {
+        self.sampler = sampler;
+    }
+
+    /// Gets how the pages should be sampled, which is nearest filtering
+    /// unless the atlas was built or loaded with other settings.
+    pub fn sampler(&self) -> SamplerSettings {
+        self.sampler
+    }
+
+    /// Records the file names the pages were saved under, in page order.
+    pub fn with_page_names(mut self, page_names: Vec<String>) -> Self {
+        self.page_names = page_names;
+        self
+    }
+
+    /// Gets the file names the pages were saved under, or nothing if they
+    /// were never saved.
+    pub fn page_names(&self) -> &[String] {
+        &self.page_names
+    }
+
+    pub fn locations(&self) -> &HashMap<TileIndex, String> {
+        &self.locations
+    }
+
+    pub fn frames(&self) -> &HashMap<String, AtlasFrame> {
+        &self.frames
+    }
+
+    /// Marks the frames holding `tile_type` as used by one more scene object.
+    pub fn retain<I: Into<TileId>>(&mut self, tile_type: I) -> AtlasResult<()> {
+        let TileId(tile_type) = tile_type.into();
+        for key in self.tile_frame_keys(tile_type)? {
+            *self.refs.entry(key).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    /// Undoes a previous `retain` of `tile_type`. Fails with
+    /// `AtlasError::NotRetained`, changing nothing, if its frames aren't
+    /// retained.
+    pub fn release<I: Into<TileId>>(&mut self, tile_type: I) -> AtlasResult<()> {
+        let TileId(tile_type) = tile_type.into();
+        let keys = self.tile_frame_keys(tile_type)?;
+        if keys.iter().any(|key| !self.refs.contains_key(key)) {
+            return Err(AtlasError::NotRetained(tile_type));
+        }
+
+        for key in keys {
+            let remove = {
+                let count = self.refs.get_mut(&key).unwrap();
+                *count -= 1;
+                *count == 0
+            };
+            if remove {
+                self.refs.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets every frame a tile is drawn from, which is more than one for
+    /// animation sequences.
+    fn tile_frame_keys(&self, tile_type: TileIndex) -> AtlasResult<Vec<String>> {
+        let key = match self.locations.get(&tile_type) {
+            Some(key) => key,
+            None      => return Err(AtlasError::MissingTile(tile_type)),
+        };
+        let mut keys = vec![key.clone()];
+
+        if let Some(steps) = self.frames.get(key).unwrap().sequences.get(&tile_type) {
+            for &(ref step_key, _) in steps.iter() {
+                if !keys.contains(step_key) {
+                    keys.push(step_key.clone());
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Gets the frames that no retained tile currently points into.
+    pub fn unreferenced_frames(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.frames.keys()
+            .filter(|key| !self.refs.contains_key(*key))
+            .map(|key| key.as_str())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Gets the texture pages with no retained frames on them, which are
+    /// safe to unload.
+    pub fn unreferenced_pages(&self) -> Vec<usize> {
+        let mut referenced = vec![false; self.textures.len()];
+        for key in self.refs.keys() {
+            referenced[self.frames.get(key).unwrap().texture_idx] = true;
+        }
+
+        referenced.iter()
+            .enumerate()
+            .filter(|&(_, &r)| !r)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Starts counting tile lookups, for finding tiles that are never drawn.
+    pub fn enable_usage_tracking(&mut self) {
+        if self.usage.is_none() {
+            self.usage = Some(Mutex::new(HashMap::new()));
+        }
+    }
+
+    /// Summarizes the lookups counted since usage tracking was enabled, or
+    /// returns `None` if it never was.
+    pub fn usage_report(&self) -> Option<UsageReport> {
+        self.usage.as_ref().map(|usage| {
+            let counts = usage.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            UsageReport::new(&self.locations, &counts)
+        })
+    }
+
+    pub fn make_config(&self, file_hash: String) -> TileAtlasConfig {
+        TileAtlasConfig {
+            locations: self.locations.clone(),
+            frames: self.frames.clone(),
+            pages: (0..self.passes()).map(|page| self.page_info(PageId(page))).collect(),
+            mip_levels: self.mip_levels,
+            sampler: self.sampler,
+            file_hash: file_hash,
+        }
+    }
+
+    pub fn get_frame<I: Into<TileId>>(&self, tile_type: I) -> &AtlasFrame {
+        self.try_get_frame(tile_type).unwrap()
+    }
+
+    /// Like `get_frame`, but returns `AtlasError::MissingTile` for an unknown
+    /// tile instead of panicking, so a placeholder can be drawn instead.
+    pub fn try_get_frame<I: Into<TileId>>(&self, tile_type: I) -> AtlasResult<&AtlasFrame> {
+        let TileId(tile_type) = tile_type.into();
+        let frame = match self.locations.get(&tile_type).and_then(|key| self.frames.get(key)) {
+            Some(frame) => frame,
+            None        => return Err(AtlasError::MissingTile(tile_type)),
+        };
+
+        if let Some(ref usage) = self.usage {
+            let mut counts = usage.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            *counts.entry(tile_type).or_insert(0) += 1;
+        }
+
+        Ok(frame)
+    }
+
+    /// Gets the page of a tile's first animation frame. The frames of an
+    /// animation sequence may be packed on different pages, so draw those
+    /// with the page from `get_tile_draw_info` for the current time.
+    pub fn get_tile_texture_idx<I: Into<TileId>>(&self, tile_type: I) -> usize {
+        self.get_frame(tile_type).texture_idx
+    }
+
+    /// Gets the page each animation frame of a tile is drawn from.
+    pub fn get_animation_pages<I: Into<TileId>>(&self, tile_type: I) -> Vec<usize> {
+        let TileId(tile_type) = tile_type.into();
+        let frame = self.get_frame(tile_type);
+        animation_pages(&self.frames, frame, tile_type)
+    }
+
+
+    pub fn get_tilemap_tex_ratio<P: Into<PageId>>(&self, texture_idx: P) -> [f32; 2] {
+        let PageId(texture_idx) = texture_idx.into();
+        let dimensions = self.page_dimensions(texture_idx);
+
+        let cols: u32 = dimensions.0 / 24;
+        let rows: u32 = dimensions.1 / 24;
+        [1.0 / cols as f32, 1.0 / rows as f32]
+    }
+
+    pub fn get_sprite_tex_ratio<I: Into<TileId>>(&self, tile_type: I) -> [f32; 2] {
+        self.sprite_tex_ratio(self.get_frame(tile_type))
+    }
+
+    fn page_dimensions(&self, texture_idx: usize) -> (u32, u32) {
+        backend::PageTexture::dimensions(self.textures.get(texture_idx).unwrap())
+    }
+
+    fn sprite_tex_ratio(&self, frame: &AtlasFrame) -> [f32; 2] {
+        let dimensions = self.page_dimensions(frame.texture_idx);
+        let ratio = frame_tex_ratio(frame, dimensions);
+        if self.half_texel_inset {
+            inset_uvs((0.0, 0.0), ratio, dimensions).1
+        } else {
+            ratio
+        }
+    }
+
+    pub fn get_tile_texture_size<I: Into<TileId>>(&self, tile_type: I) -> (u32, u32) {
+        self.get_frame(tile_type).tile_size
+    }
+
+    pub fn get_texture_offset<I: Into<TileId>>(&self, tile_type: I) -> (f32, f32) {
+        self.get_texture_offset_at(tile_type, 0)
+    }
+
+    /// Gets the texture offset of a tile `msecs` milliseconds into its
+    /// animation. Static tiles ignore `msecs`.
+    pub fn get_texture_offset_at<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> (f32, f32) {
+        self.get_tile_draw_info(tile_type, msecs).uv_offset
+    }
+
+    /// Gets which frame of its animation a tile is on after `msecs`
+    /// milliseconds. Static tiles are always on frame 0.
+    pub fn get_animation_frame<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> u64 {
+        let TileId(tile_type) = tile_type.into();
+        let frame = self.get_frame(tile_type);
+        let tile = frame.tiles.get(&tile_type).unwrap();
+        animation_frame(tile, msecs)
+    }
+
+    /// Resolves everything needed to draw a tile with a single frame lookup.
+    pub fn get_tile_draw_info<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> DrawInfo {
+        self.try_get_tile_draw_info(tile_type, msecs).unwrap()
+    }
+
+    pub fn try_get_tile_draw_info<I: Into<TileId>>(&self, tile_type: I, msecs: u64) -> AtlasResult<DrawInfo> {
+        let TileId(tile_type) = tile_type.into();
+        let frame = self.try_get_frame(tile_type)?;
+        let info = frame_draw_info(&self.frames, frame, tile_type, msecs, &|idx| self.page_dimensions(idx));
+        if self.half_texel_inset {
+            Ok(inset_draw_info(info, &|idx| self.page_dimensions(idx)))
+        } else {
+            Ok(info)
+        }
+    }
+
+    /// Appends the `DrawInfo` of every tile in `tiles` to `out`. Each
+    /// distinct tile index is only resolved once, which pays off for tilemap
+    /// chunks where the same few tiles repeat many times.
+    pub fn get_draw_infos(&self, tiles: &[TileIndex], msecs: u64, out: &mut Vec<DrawInfo>) {
+        out.reserve(tiles.len());
+
+        let mut resolved: HashMap<TileIndex, DrawInfo> = HashMap::new();
+        let mut last: Option<(TileIndex, DrawInfo)> = None;
+
+        for &tile_type in tiles.iter() {
+            let info = match last {
+                Some((last_type, info)) if last_type == tile_type => info,
+                _ => *resolved.entry(tile_type)
+                    .or_insert_with(|| self.get_tile_draw_info(tile_type, msecs)),
+            };
+
+            last = Some((tile_type, info));
+            out.push(info);
+        }
+    }
+
+    pub fn get_texture<P: Into<PageId>>(&self, idx: P) -> &T {
+        let PageId(idx) = idx.into();
+        self.textures.get(idx).unwrap()
+    }
+
+    /// Gets a page as a glium sampler configured with `sampler`, ready to be
+    /// passed as a uniform.
+    #[cfg(feature = "glium")]
+    pub fn get_sampler<P: Into<PageId>>(&self, idx: P) -> ::glium::uniforms::Sampler<T> {
+        self.sampler.sample(self.get_texture(idx))
+    }
+
+    /// Gets the pages holding frames of a usage class, in page order.
+    pub fn pages_with_class(&self, class: &str) -> Vec<PageId> {
+        pages_with_class(&self.frames, class)
+    }
+
+    /// Gets how many pixels of a page no frame or its padding covers. The
+    /// free pixels may be scattered, so see `can_fit` for whether an image
+    /// of a given size fits.
+    pub fn page_free_area<P: Into<PageId>>(&self, page: P) -> u64 {
+        let PageId(page) = page.into();
+        page_free_area(&self.frames, page, self.page_dimensions(page))
+    }
+
+    /// Finds a `w` by `h` area on any page that no frame covers, for
+    /// drawing an image added at runtime with `write_pixels`. Include any
+    /// padding the image needs in `w` and `h`.
+    ///
+    /// The area isn't reserved: until a frame covers it, every call finds it
+    /// again, so write into it before looking for the next one, or add the
+    /// image with `insert_frame`, which claims its space. Pages are cropped to
+    /// their frames, leaving little free space, unless the builder was told
+    /// to `set_reserve_page_space`.
+    pub fn find_free_rect(&self, w: u32, h: u32) -> Option<(PageId, AtlasRect)> {
+        (0..self.passes())
+            .filter_map(|page| find_free_rect(&self.frames, page, self.page_dimensions(page), w, h)
+                        .map(|rect| (PageId(page), rect)))
+            .next()
+    }
+
+    /// Checks whether a `w` by `h` image fits on a page as it is, or needs a
+    /// texture of its own.
+    pub fn can_fit(&self, w: u32, h: u32) -> bool {
+        self.find_free_rect(w, h).is_some()
+    }
+
+    /// Finds the frame and tile drawn from the pixel at `(x, y)` on a page,
+    /// for inspecting what's rendering somewhere. Points in padding or empty
+    /// space give `None`; points in a frame outside any tile give a `Pick`
+    /// without one. Of several tiles drawn from the same spot, the lowest
+    /// numbered is picked.
+    pub fn pick<P: Into<PageId>>(&self, page: P, pixel: (u32, u32)) -> Option<Pick> {
+        let PageId(page) = page.into();
+        pick(&self.frames, page, pixel)
+    }
+
+    /// Like `pick`, at a UV coordinate as `DrawInfo` gives them: from the
+    /// page's top-left corner, whatever `flip_y` is set to. A V sampled on a
+    /// flipped page is `1.0 - v` of the one to pass here.
+    pub fn pick_uv<P: Into<PageId>>(&self, page: P, uv: (f32, f32)) -> Option<Pick> {
+        let PageId(page) = page.into();
+        pick(&self.frames, page, uv_to_pixel(uv, self.page_dimensions(page)))
+    }
+
+    /// Gets a `TextureAtlas` over page `page` that borrows its texture, with
+    /// every frame on the page by key, so UI sprites packed alongside tiles
+    /// can be looked up by name without uploading them twice.
+    pub fn texture_atlas_view<P: Into<PageId>>(&self, page: P) -> TextureAtlas<&T> {
+        let PageId(page) = page.into();
+        let frames = self.frames.iter()
+            .filter(|&(_, frame)| frame.texture_idx == page)
+            .map(|(key, frame)| (key.clone(), frame.rect.clone()))
+            .collect();
+
+        TextureAtlas::from_parts(self.get_texture(page), frames, self.sampler)
+    }
+
+    pub fn passes(&self) -> usize {
+        self.textures.len()
+    }
+
+    /// Queues `image` to be drawn onto page `page` with its top-left corner
+    /// at `(x, y)`. Nothing is uploaded until `flush`, which only sends the
+    /// written areas, so frequent small updates like glyphs or minimap tiles
+    /// don't re-upload whole pages.
+    pub fn write_pixels<P: Into<PageId>>(&mut self, page: P, x: u32, y: u32, image: &DynamicImage) -> AtlasResult<()> {
+        let PageId(page) = page.into();
+        let (w, h) = image.dimensions();
+        let (page_w, page_h) = match self.textures.get(page) {
+            Some(texture) => backend::PageTexture::dimensions(texture),
+            None          => return Err(AtlasError::Texture(format!("No page {}", page))),
+        };
+        if x + w > page_w || y + h > page_h {
+            return Err(AtlasError::Texture(format!("A {}x{} write at ({}, {}) lies outside of page {}",
+                                                   w, h, x, y, page)));
+        }
+
+        if let Some(ref mut pages) = self.retained {
+            pages.write_pixels(page, x, y, image)?;
+        }
+        let write = PendingWrite {
+            page: page,
+            rect: AtlasRect::new(x, y, w, h),
+            pixels: Arc::new(image.to_rgba().into_raw()),
+        };
+        for pages in self.contexts.values_mut() {
+            queue_write(&mut pages.pending, write.clone());
+        }
+        queue_write(&mut self.pending, write);
+        Ok(())
+    }
+
+    /// Adds a frame to the atlas after it was built, for art made at runtime
+    /// like chat avatars or generated sprites, and queues its pixels to be
+    /// drawn like `write_pixels`. It goes in the first row of the first page
+    /// with room, found by a shelf packer continuing the rows the page was
+    /// packed in, so each insert only costs the number of rows.
+    ///
+    /// Pages are only as large as their frames unless the builder was told
+    /// to `set_reserve_page_space`. Inserted frames aren't padded, so include
+    /// any padding they need in `image`.
+    pub fn insert_frame(&mut self, key: &str, image: &DynamicImage, tile_size: (u32, u32)) -> AtlasResult<PageId> {
+        if self.frames.contains_key(key) {
+            return Err(AtlasError::DuplicateFrame(key.to_string()));
+        }
+
+        if self.shelves.is_empty() {
+            for page in 0..self.passes() {
+                let rects: Vec<AtlasRect> = self.frames.values()
+                    .filter(|frame| frame.texture_idx == page)
+                    .map(|frame| frame.padded_rect.clone())
+                    .collect();
+                let (w, h) = self.page_dimensions(page);
+                self.shelves.push(ShelfPacker::resume(w, h, 0, 0, &rects));
+            }
+        }
+
+        let (w, h) = image.dimensions();
+        let placed = self.shelves.iter_mut()
+            .enumerate()
+            .filter_map(|(page, shelves)| shelves.pack(w, h).map(|rect| (page, rect)))
+            .next();
+        let (page, rect) = match placed {
+            Some(placed) => placed,
+            None         => return Err(AtlasError::Pack(key.to_string(), "No room left on any page".to_string())),
+        };
+
+        self.write_pixels(page, rect.x, rect.y, image)?;
+        self.frames.insert(key.to_string(), AtlasFrame::new(page, rect, tile_size));
+        Ok(PageId(page))
+    }
+
+    /// Replaces the pixels of the frame added under `key`, such as to
+    /// hot-swap a sprite, keeping the layout of every page. `image` must be
+    /// the same size as the frame. Like `write_pixels`, nothing is uploaded
+    /// until `flush`, and mip levels below the page aren't regenerated.
+    pub fn update_frame_pixels(&mut self, key: &str, image: &DynamicImage) -> AtlasResult<()> {
+        let (page, x, y, rotated, (w, h)) = match self.frames.get(key) {
+            Some(frame) => (frame.texture_idx, frame.rect.x, frame.rect.y, frame.rect.rotated, frame.rect.source_size()),
+            None        => return Err(AtlasError::MissingFrame(key.to_string())),
+        };
+
+        let (image_w, image_h) = image.dimensions();
+        if (image_w, image_h) != (w, h) {
+            return Err(AtlasError::Texture(format!("Frame {} is {}x{}, but its new pixels are {}x{}",
+                                                   key, w, h, image_w, image_h)));
+        }
+
+        if rotated {
+            self.write_pixels(page, x, y, &image.rotate90())
+        } else {
+            self.write_pixels(page, x, y, image)
+        }
+    }
+
+    /// Gets the areas of page `page` written since the last `flush`, in the
+    /// order they were written.
+    pub fn dirty_rects<P: Into<PageId>>(&self, page: P) -> Vec<AtlasRect> {
+        let PageId(page) = page.into();
+        self.pending.iter()
+            .filter(|write| write.page == page)
+            .map(|write| write.rect.clone())
+            .collect()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Uploads every area written with `write_pixels` since the last flush.
+    /// If an upload fails, the writes after it stay queued.
+    pub fn flush<B: GpuBackend<Texture = T>>(&mut self, backend: &B) -> AtlasResult<()> {
+        let (uploaded, result) = if self.flip_y {
+            upload_writes(backend, &mut self.textures, &self.pending)
+        } else {
+            upload_writes(&Unflipped(backend), &mut self.textures, &self.pending)
+        };
+
+        self.pending.drain(..uploaded);
+        result
+    }
+
+    /// Uploads the writes made since the last flush of another context to
+    /// its textures, with that context's `backend`. Each context keeps its
+    /// own queue, so contexts can be flushed in any order and as often as
+    /// each is drawn, independently of `flush`.
+    pub fn flush_context<B, C>(&mut self, backend: &B, context: C) -> AtlasResult<()>
+        where B: GpuBackend<Texture = T>,
+              C: Into<ContextId>
+    {
+        let context = context.into();
+        let pages = match self.contexts.get_mut(&context) {
+            Some(pages) => pages,
+            None        => return Err(AtlasError::Texture(format!("No {} was added", context))),
+        };
+
+        let (uploaded, result) = if self.flip_y {
+            upload_writes(backend, &mut pages.textures, &pages.pending)
+        } else {
+            upload_writes(&Unflipped(backend), &mut pages.textures, &pages.pending)
+        };
+
+        pages.pending.drain(..uploaded);
+        result
+    }
+
+    /// Whether another context has writes it wasn't flushed with yet.
+    pub fn is_context_dirty<C: Into<ContextId>>(&self, context: C) -> bool {
+        self.contexts.get(&context.into()).map_or(false, |pages| !pages.pending.is_empty())
+    }
+}
+
+#[cfg(feature = "glium")]
+impl<T: backend::PageTexture + ::glium::GlObject<Id = u32>> TileAtlas<T> {
+    /// Gets the OpenGL name of a page's texture, for drawing it with GL code
+    /// outside of glium, whichever of the glium formats in `backend` it was
+    /// uploaded as. The atlas still owns the texture, so it mustn't be
+    /// deleted, and glium has to be told about any GL state the other code
+    /// changes.
+    pub fn gl_texture_id<P: Into<PageId>>(&self, idx: P) -> u32 {
+        use glium::GlObject;
+
+        self.get_texture(idx).get_id()
+    }
+}
+
+/// Uploads `writes` in order, stopping at the first that fails. Returns how
+/// many were uploaded.
+fn upload_writes<B: GpuBackend>(backend: &B, textures: &mut [B::Texture], writes: &[PendingWrite]) -> (usize, AtlasResult<()>) {
+    for (uploaded, write) in writes.iter().enumerate() {
+        let pixels = RawPage {
+            data: &write.pixels,
+            width: write.rect.w,
+            height: write.rect.h,
+        };
+        if let Err(e) = backend.write_texture(&mut textures[write.page], write.rect.x, write.rect.y, pixels) {
+            return (uploaded, Err(e));
+        }
+    }
+    (writes.len(), Ok(()))
+}
+
+/// Pixels queued by `TileAtlas::write_pixels` that haven't been uploaded yet.
+/// The pixels are shared between the queues of every context.
+#[derive(Clone)]
+struct PendingWrite {
+    page: usize,
+    rect: AtlasRect,
+    pixels: Arc<Vec<u8>>,
+}
+
+/// Queues a write, dropping the writes it covers completely, which no longer
+/// need uploading.
+fn queue_write(pending: &mut Vec<PendingWrite>, write: PendingWrite) {
+    pending.retain(|queued| queued.page != write.page || !rect_contains(&write.rect, &queued.rect));
+    pending.push(write);
+}
+
+/// A context's textures of every page added with `TileAtlas::add_context`,
+/// and the writes it wasn't flushed with yet.
+struct ContextPages<T> {
+    textures: Vec<T>,
+    pending: Vec<PendingWrite>,
+}
+
+impl<T> ContextPages<T> {
+    fn new(textures: Vec<T>) -> Self {
+        ContextPages {
+            textures: textures,
+            pending: Vec::new(),
+        }
+    }
+}
+
+fn rect_contains(outer: &AtlasRect, inner: &AtlasRect) -> bool {
+    inner.x >= outer.x && inner.y >= outer.y &&
+        inner.x + inner.w <= outer.x + outer.w && inner.y + inner.h <= outer.y + outer.h
+}
+
+fn frame_tex_ratio(frame: &AtlasFrame, dimensions: (u32, u32)) -> [f32; 2] {
+    let cell = tile_cell_rect(frame, (0, 0));
+    [cell.w as f32 / dimensions.0 as f32, cell.h as f32 / dimensions.1 as f32]
+}
+
+fn frame_tex_offset(frame: &AtlasFrame, tile: &AtlasTile, msecs: u64, dimensions: (u32, u32)) -> (f32, f32) {
+    let cell = tile_cell_rect(frame, animated_offset(tile, msecs));
+    (cell.x as f32 / dimensions.0 as f32, cell.y as f32 / dimensions.1 as f32)
+}
+
+/// Gets the pixels of a page of `dimensions` that no frame on it covers,
+/// counting each frame's padding as covered.
+pub fn page_free_area(frames: &HashMap<String, AtlasFrame>, page: usize, dimensions: (u32, u32)) -> u64 {
+    let used: u64 = frames.values()
+        .filter(|frame| frame.texture_idx == page)
+        .map(|frame| frame.padded_rect.w as u64 * frame.padded_rect.h as u64)
+        .sum();
+    (dimensions.0 as u64 * dimensions.1 as u64).saturating_sub(used)
+}
+
+/// Finds the topmost, then leftmost, spot on a page where a `w` by `h` area
+/// overlaps no frame. Any free spot can be slid up and left until it meets
+/// the page's edge or a frame, so only those positions are tried.
+pub fn find_free_rect(frames: &HashMap<String, AtlasFrame>,
+                      page: usize,
+                      dimensions: (u32, u32),
+                      w: u32,
+                      h: u32) -> Option<AtlasRect> {
+    let used: Vec<&AtlasRect> = frames.values()
+        .filter(|frame| frame.texture_idx == page)
+        .map(|frame| &frame.padded_rect)
+        .collect();
+
+    let mut xs: Vec<u32> = used.iter().map(|rect| rect.x + rect.w).chain(Some(0)).collect();
+    let mut ys: Vec<u32> = used.iter().map(|rect| rect.y + rect.h).chain(Some(0)).collect();
+    xs.sort();
+    xs.dedup();
+    ys.sort();
+    ys.dedup();
+
+    for &y in ys.iter() {
+        for &x in xs.iter() {
+            if x + w > dimensions.0 || y + h > dimensions.1 {
+                continue;
+            }
+
+            let candidate = AtlasRect::new(x, y, w, h);
+            if !used.iter().any(|rect| rects_overlap(rect, &candidate)) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+/// Finds what's drawn at a pixel of a page. See `TileAtlas::pick`.
+pub fn pick(frames: &HashMap<String, AtlasFrame>, page: usize, pixel: (u32, u32)) -> Option<Pick> {
+    let (x, y) = pixel;
+    let found = frames.iter().find(|&(_, frame)| {
+        let rect = &frame.rect;
+        frame.texture_idx == page && x >= rect.x && x < rect.x + rect.w && y >= rect.y && y < rect.y + rect.h
+    });
+    let (key, frame) = match found {
+        Some(found) => found,
+        None        => return None,
+    };
+
+    // Animation steps can be drawn from other frames than the one holding
+    // the tile, so every tile has to be checked.
+    let tile = frames.iter()
+        .flat_map(|(owner_key, owner)| owner.tiles.iter().map(move |(&index, tile)| (owner_key, owner, index, tile)))
+        .filter_map(|(owner_key, owner, index, tile)| {
+            tile_steps(owner_key, owner, index, tile).iter()
+                .position(|&(ref step_key, offset)| step_key == key && tile_cell_contains(frame, offset, pixel))
+                .map(|step| (index, step as u64))
+        })
+        .min();
+
+    Some(Pick {
+        frame: key.clone(),
+        tile: tile.map(|(index, _)| TileId(index)),
+        animation_frame: tile.map_or(0, |(_, step)| step),
+    })
+}
+
+/// Gets the frame key and offset of each step of a tile's animation, or of
+/// the tile itself if it isn't animated.
+pub fn tile_steps(key: &str, frame: &AtlasFrame, index: TileIndex, tile: &AtlasTile) -> SequenceSteps {
+    match tile.kind {
+        TileKind::Static => vec![(key.to_string(), tile.offset)],
+        TileKind::Animated(frame_count, _) |
+        TileKind::StridedAnimated(frame_count, _, _) => {
+            let stride = column_stride(tile.kind);
+            (0..frame_count)
+                .map(|i| (key.to_string(), (tile.offset.0 + i as u32 * stride, tile.offset.1)))
+                .collect()
+        },
+        TileKind::Sequence(..) => frame.sequences.get(&index).cloned().unwrap_or_else(Vec::new),
+    }
+}
+
+/// Gets the page pixels of the tile at `offset` of `frame`, as its UVs
+/// place it. Tiles of a rotated frame are rotated with it, so their column
+/// runs down the page and their row runs right to left.
+pub fn tile_cell_rect(frame: &AtlasFrame, offset: TileOffset) -> AtlasRect {
+    let (tw, th) = frame.tile_size;
+    if frame.rect.rotated {
+        let rect = &frame.rect;
+        return AtlasRect::new_rotated(rect.x + rect.w - (offset.1 + 1) * th, rect.y + offset.0 * tw, th, tw);
+    }
+
+    AtlasRect::new(frame.rect.x + offset.0 * tw, frame.rect.y + offset.1 * th, tw, th)
+}
+
+fn tile_cell_contains(frame: &AtlasFrame, offset: TileOffset, pixel: (u32, u32)) -> bool {
+    let cell = tile_cell_rect(frame, offset);
+    pixel.0 >= cell.x && pixel.0 < cell.x + cell.w && pixel.1 >= cell.y && pixel.1 < cell.y + cell.h
+}
+
+/// Gets the pixel a UV coordinate falls in on a page of `dimensions`.
+pub fn uv_to_pixel(uv: (f32, f32), dimensions: (u32, u32)) -> (u32, u32) {
+    let to_pixel = |coord: f32, size: u32| cmp::min((coord.max(0.0) * size as f32) as u32, size.saturating_sub(1));
+    (to_pixel(uv.0, dimensions.0), to_pixel(uv.1, dimensions.1))
+}
+
+fn rects_overlap(a: &AtlasRect, b: &AtlasRect) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+/// Gets the pages holding frames of a usage class, in page order.
+pub fn pages_with_class(frames: &HashMap<String, AtlasFrame>, class: &str) -> Vec<PageId> {
+    let mut pages: Vec<usize> = frames.values()
+        .filter(|frame| frame.class() == Some(class))
+        .map(|frame| frame.texture_idx)
+        .collect();
+    pages.sort();
+    pages.dedup();
+    pages.into_iter().map(PageId).collect()
+}
+
+/// Computes a tile's `DrawInfo` given a way to look up page sizes, so that
+/// the same math serves both GPU and CPU-only atlases. With the `validate`
+/// feature, debug builds panic if the result breaks a UV invariant.
+pub fn frame_draw_info(frames: &HashMap<String, AtlasFrame>,
+                       frame: &AtlasFrame,
+                       tile_type: TileIndex,
+                       msecs: u64,
+                       page_dimensions: &dyn Fn(usize) -> (u32, u32)) -> DrawInfo {
+    let info = unchecked_frame_draw_info(frames, frame, tile_type, msecs, page_dimensions);
+
+    #[cfg(all(feature = "validate", debug_assertions))]
+    {
+        let (frame, _) = resolve_tile(frames, frame, tile_type, msecs);
+        if let Some(reason) = validate::check_draw_info(frame, &info, page_dimensions(frame.texture_idx)) {
+            panic!("Tile {} has invalid UVs {:?}: {}", tile_type, info, reason);
+        }
+    }
+
+    info
+}
+
+/// `frame_draw_info` without the invariant checks, for the checks
+/// themselves.
+pub fn unchecked_frame_draw_info(frames: &HashMap<String, AtlasFrame>,
+                                 frame: &AtlasFrame,
+                                 tile_type: TileIndex,
+                                 msecs: u64,
+                                 page_dimensions: &dyn Fn(usize) -> (u32, u32)) -> DrawInfo {
+    let (frame, tile) = resolve_tile(frames, frame, tile_type, msecs);
+    let tile = &tile;
+
+    let overlay = frame.overlay.as_ref().map(|key| {
+        let overlay_frame = frames.get(key).unwrap();
+        let dimensions = page_dimensions(overlay_frame.texture_idx);
+        OverlayInfo {
+            page: overlay_frame.texture_idx,
+            uv_offset: frame_tex_offset(overlay_frame, tile, msecs, dimensions),
+            uv_scale: frame_tex_ratio(overlay_frame, dimensions),
+            rotated: overlay_frame.rect.rotated,
+        }
+    });
+
+    let dimensions = page_dimensions(frame.texture_idx);
+
+    DrawInfo {
+        page: frame.texture_idx,
+        uv_offset: frame_tex_offset(frame, tile, msecs, dimensions),
+        uv_scale: frame_tex_ratio(frame, dimensions),
+        rotated: frame.rect.rotated,
+        pixel_size: frame.tile_size,
+        source_size: frame.source_size,
+        trim_offset: frame.trim_offset,
+        pivot: frame.pivot,
+        overlay: overlay,
+    }
+}
+
+/// Insets the UV quads of `info` and its overlay by half a texel on every
+/// side, for atlases that don't `set_half_texel_inset` but have some draws
+/// that need it.
+pub fn inset_draw_info(info: DrawInfo, page_dimensions: &dyn Fn(usize) -> (u32, u32)) -> DrawInfo {
+    let (uv_offset, uv_scale) = inset_uvs(info.uv_offset, info.uv_scale, page_dimensions(info.page));
+    let overlay = info.overlay.map(|overlay| {
+        let (uv_offset, uv_scale) = inset_uvs(overlay.uv_offset, overlay.uv_scale, page_dimensions(overlay.page));
+        OverlayInfo {
+            uv_offset: uv_offset,
+            uv_scale: uv_scale,
+            ..overlay
+        }
+    });
+
+    DrawInfo {
+        uv_offset: uv_offset,
+        uv_scale: uv_scale,
+        overlay: overlay,
+        ..info
+    }
+}
+
+/// Gets the UVs of the corners of a quad spanning `scale` from `offset`, in
+/// the order `DrawInfo::uv_corners` gives them. For a `rotated` quad, the
+/// image's top-left corner is the quad's top-right one.
+pub fn uv_corners((u, v): (f32, f32), scale: [f32; 2], rotated: bool) -> [(f32, f32); 4] {
+    let (u1, v1) = (u + scale[0], v + scale[1]);
+    if rotated {
+        [(u1, v), (u1, v1), (u, v1), (u, v)]
+    } else {
+        [(u, v), (u1, v), (u1, v1), (u, v1)]
+    }
+}
+
+/// Moves a UV quad's corner in by half a texel of a page of `dimensions`,
+/// and shrinks it by a whole texel to match.
+pub fn inset_uvs((u, v): (f32, f32), scale: [f32; 2], dimensions: (u32, u32)) -> ((f32, f32), [f32; 2]) {
+    let texel = (1.0 / dimensions.0 as f32, 1.0 / dimensions.1 as f32);
+    ((u + texel.0 / 2.0, v + texel.1 / 2.0), [scale[0] - texel.0, scale[1] - texel.1])
+}
+
+/// Finds the frame and tile a tile is drawn from after `msecs`. For
+/// animation sequences this is the frame of the current step, with the
+/// step's offset.
+pub fn resolve_tile<'f>(frames: &'f HashMap<String, AtlasFrame>,
+                        frame: &'f AtlasFrame,
+                        tile_type: TileIndex,
+                        msecs: u64) -> (&'f AtlasFrame, AtlasTile) {
+    let tile = *frame.tiles.get(&tile_type).unwrap();
+
+    match tile.kind {
+        TileKind::Sequence(..) => {
+            let step = animation_frame(&tile, msecs) as usize;
+            let steps = frame.sequences.get(&tile_type).unwrap();
+            let (ref key, offset) = steps[step];
+            (frames.get(key).unwrap(), AtlasTile { offset: offset, kind: TileKind::Static })
+        },
+        _ => (frame, tile),
+    }
+}
+
+/// Gets the page each animation frame of a tile is drawn from, in order.
+pub fn animation_pages(frames: &HashMap<String, AtlasFrame>, frame: &AtlasFrame, tile_type: TileIndex) -> Vec<usize> {
+    match frame.sequences.get(&tile_type) {
+        Some(steps) => steps.iter().map(|&(ref key, _)| frames.get(key).unwrap().texture_idx).collect(),
+        None        => vec![frame.texture_idx],
+    }
+}
+
+fn animation_frame(tile: &AtlasTile, msecs: u64) -> u64 {
+    match tile.kind {
+        TileKind::Static => 0,
+        TileKind::Animated(frame_count, delay) |
+        TileKind::Sequence(frame_count, delay) |
+        TileKind::StridedAnimated(frame_count, delay, _) => {
+            if frame_count == 0 || delay == 0 {
+                0
+            } else {
+                (msecs / delay) % frame_count
+            }
+        },
+    }
+}
+
+/// Gets how many columns apart the frames of a tile's animation are, or 0
+/// if they aren't in columns of its own frame.
+fn column_stride(kind: TileKind) -> u32 {
+    match kind {
+        TileKind::Static | TileKind::Sequence(..) => 0,
+        TileKind::Animated(..) => 1,
+        TileKind::StridedAnimated(_, _, stride) => stride,
+    }
+}
+
+fn animated_offset(tile: &AtlasTile, msecs: u64) -> TileOffset {
+    let stride = column_stride(tile.kind);
+    let current_frame = animation_frame(tile, msecs);
+    (tile.offset.0 + current_frame as u32 * stride, tile.offset.1)
+}
+
+fn check_animation(index: TileIndex, frame_count: u64, delay: u64) -> AtlasResult<()> {
+    if frame_count == 0 || delay == 0 {
+        return Err(AtlasError::InvalidAnimation(index, frame_count, delay));
+    }
+    Ok(())
+}
+
+/// Checks that every animation frame of a tile lies inside a source image of
+/// `dimensions`, cut into tiles of `tile_size`.
+fn check_tile_bounds(tile_size: (u32, u32),
+                     dimensions: (u32, u32),
+                     key: &str,
+                     index: TileIndex,
+                     offset: TileOffset,
+                     kind: TileKind) -> AtlasResult<()> {
+    let last_column = match kind {
+        TileKind::Static | TileKind::Sequence(..) => offset.0,
+        TileKind::Animated(frame_count, _) |
+        TileKind::StridedAnimated(frame_count, _, _) => {
+            let extra = cmp::max(frame_count, 1) - 1;
+            offset.0 + extra as u32 * column_stride(kind)
+        },
+    };
+
+    let (tw, th) = tile_size;
+    let start = (offset.0 * tw, offset.1 * th);
+    let end = ((last_column + 1) * tw, (offset.1 + 1) * th);
+    let (w, h) = dimensions;
+
+    if end.0 > w || end.1 > h {
+        return Err(AtlasError::TileOutOfBounds(index, key.to_string(), (start, end), (w, h)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use backend::MemoryBackend;
+
+    #[test]
+    fn test_tile_bounds() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+
+        assert!(builder.add_tile("sheet", 0, (1, 0)).is_ok());
+        assert!(builder.add_tile_with_kind("sheet", 1, (0, 0), TileKind::Animated(2, 100)).is_ok());
+
+        match builder.add_tile("sheet", 2, (2, 0)) {
+            Err(AtlasError::TileOutOfBounds(2, ref key, range, size)) => {
+                assert_eq!(key, "sheet");
+                assert_eq!(range, ((48, 0), (72, 24)));
+                assert_eq!(size, (48, 24));
+            },
+            _ => panic!("Expected an out of bounds tile"),
+        }
+
+        assert!(builder.add_tile_with_kind("sheet", 3, (0, 0), TileKind::StridedAnimated(2, 100, 2)).is_err());
+    }
+
+    #[test]
+    fn test_sequence_tile() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("walk_0", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_frame_image("walk_1", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_sequence_tile(0, &[("walk_0", (0, 0)), ("walk_1", (1, 0))], 100).unwrap();
+
+        assert!(builder.add_sequence_tile(1, &[("walk_0", (0, 0)), ("missing", (0, 0))], 100).is_err());
+        assert!(builder.add_sequence_tile(2, &[("walk_1", (2, 0))], 100).is_err());
+
+        let atlas = builder.build_cpu(None).unwrap();
+        let first = atlas.get_tile_draw_info(0, 0);
+        let second = atlas.get_tile_draw_info(0, 100);
+
+        let walk_1 = atlas.frames().get("walk_1").unwrap();
+        let expected = frame_tex_offset(walk_1,
+                                        &AtlasTile { offset: (1, 0), kind: TileKind::Static },
+                                        0,
+                                        atlas.get_page_dimensions(walk_1.texture_idx));
+        assert_eq!(second.uv_offset, expected);
+        assert!(first.uv_offset != second.uv_offset);
+        assert_eq!(atlas.get_tile_draw_info(0, 200), first);
+
+        builder.remove_frame("walk_1");
+        assert!(builder.build_cpu(None).unwrap().locations().is_empty());
+    }
+
+    #[test]
+    fn test_replace_frame() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_frame_image("walk", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (1, 0)).unwrap();
+        builder.add_sequence_tile(1, &[("walk", (0, 0)), ("sheet", (0, 0))], 100).unwrap();
+
+        // tile 0 would lie past the edge of the smaller image.
+        match builder.replace_frame("sheet", DynamicImage::new_rgba8(24, 24)) {
+            Err(AtlasError::TileOutOfBounds(0, ref key, _, (24, 24))) => assert_eq!(key, "sheet"),
+            _ => panic!("Expected a tile out of bounds error"),
+        }
+        // the sequence step on walk would too.
+        assert!(builder.replace_frame("walk", DynamicImage::new_rgba8(24, 12)).is_err());
+        assert!(builder.replace_frame("missing", DynamicImage::new_rgba8(24, 24)).is_err());
+
+        builder.replace_frame("sheet", DynamicImage::new_rgba8(72, 24)).unwrap();
+        builder.replace_frame("walk", DynamicImage::new_rgba8(48, 24)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.frames().get("sheet").unwrap().rect().source_size(), (72, 24));
+        assert_eq!(atlas.frames().get("walk").unwrap().rect().source_size(), (48, 24));
+    }
+
+    #[test]
+    fn test_override_tile_bounds() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_key_collision(KeyCollision::Override);
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (1, 0)).unwrap();
+
+        // tile 0 lies past the edge of the override, or of its larger tiles.
+        match builder.add_frame_image("sheet", DynamicImage::new_rgba8(24, 24), (24, 24)) {
+            Err(AtlasError::TileOutOfBounds(0, ref key, _, (24, 24))) => assert_eq!(key, "sheet"),
+            _ => panic!("Expected a tile out of bounds error"),
+        }
+        assert!(builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (48, 24)).is_err());
+
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(96, 48), (48, 48)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        let sheet = atlas.frames().get("sheet").unwrap();
+        assert_eq!(sheet.rect().source_size(), (96, 48));
+        assert_eq!(sheet.tiles().get(&0).unwrap().offset, (1, 0));
+    }
+
+    #[test]
+    fn test_pack_job_inspection() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 48), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(48, 48), (24, 24)).unwrap();
+
+        let mut job = builder.start_pack();
+        assert_eq!(job.page_of("a"), None);
+        assert_eq!(job.free_area(), 64 * 64);
+
+        while !job.is_done() {
+            job.pack_some(1000).unwrap();
+        }
+        assert_eq!(job.page_count(), 2);
+        assert_eq!(job.page_of("a"), Some(PageId(0)));
+        assert_eq!(job.page_of("b"), Some(PageId(1)));
+        assert_eq!(job.free_area(), 2 * (64 * 64 - 48 * 48));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_stale_pages() {
+        let dir = ::std::env::temp_dir().join("texture_atlas_stale_pages");
+        let _ = ::std::fs::remove_dir_all(&dir);
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let stale = dir.join("0123456789abcdef.png");
+        let notes = dir.join("notes.txt");
+        ::std::fs::File::create(&stale).unwrap();
+        ::std::fs::File::create(&notes).unwrap();
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+
+        builder.set_stale_pages(StalePages::Fail);
+        assert!(builder.build_cpu(Some(dir.clone())).is_err());
+        assert!(stale.exists() && notes.exists());
+
+        builder.set_stale_pages(StalePages::RemovePages);
+        let atlas = builder.build_cpu(Some(dir.clone())).unwrap();
+        assert!(!stale.exists() && notes.exists());
+        assert!(dir.join(&atlas.page_names()[0]).exists());
+        assert_eq!(page_files(&dir).unwrap().len(), 1);
+
+        builder.set_stale_pages(StalePages::RemoveFolder);
+        builder.build_cpu(Some(dir.clone())).unwrap();
+        assert!(!notes.exists());
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_add_frames_rollback() {
+        let dir = ::std::env::temp_dir().join("texture_atlas_add_frames");
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let small = dir.join("small.png");
+        let large = dir.join("large.png");
+        DynamicImage::new_rgba8(24, 24).save(&mut ::std::fs::File::create(&small).unwrap(), ::image::PNG).unwrap();
+        DynamicImage::new_rgba8(96, 24).save(&mut ::std::fs::File::create(&large).unwrap(), ::image::PNG).unwrap();
+        let missing = dir.join("missing.png");
+        let (small, large, missing) = (small.to_str().unwrap(), large.to_str().unwrap(), missing.to_str().unwrap());
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        assert!(builder.add_frames(&[(small, (24, 24)), (missing, (24, 24))]).is_err());
+        match builder.add_frames(&[(small, (24, 24)), (large, (24, 24))]) {
+            Err(AtlasError::FrameTooLarge(ref key, (96, 24), 64)) => assert_eq!(key, large),
+            other => panic!("Expected FrameTooLarge, got {:?}", other.err()),
+        }
+        assert!(builder.add_tile(small, 0, (0, 0)).is_err());
+
+        builder.add_frames(&[(small, (24, 24)), (small, (12, 12))]).unwrap();
+        builder.add_tile(small, 0, (0, 0)).unwrap();
+        assert_eq!(builder.build_cpu(None).unwrap().get_tile_texture_size(0), (24, 24));
+
+        // large loads fine, but small's file is too narrow for the tile
+        // already registered against it, so neither is added.
+        let overriding = || {
+            let mut builder = TileAtlasBuilder::new();
+            builder.set_page_size(128);
+            builder.set_key_collision(KeyCollision::Override);
+            builder.add_frame_image(small, DynamicImage::new_rgba8(96, 24), (24, 24)).unwrap();
+            builder.add_tile(small, 0, (3, 0)).unwrap();
+            builder
+        };
+        let mut builder = overriding();
+        match builder.add_frames(&[(large, (24, 24)), (small, (24, 24))]) {
+            Err(AtlasError::TileOutOfBounds(0, ref key, _, (24, 24))) => assert_eq!(key, small),
+            other => panic!("Expected TileOutOfBounds, got {:?}", other.err()),
+        }
+        assert!(builder.add_tile(large, 1, (0, 0)).is_err());
+
+        let mut builder = overriding();
+        assert!(builder.add_frames_parallel(&[(large, (24, 24)), (small, (24, 24))], 2).is_err());
+        assert!(builder.add_tile(large, 1, (0, 0)).is_err());
+        assert!(builder.build_cpu(None).is_ok());
+    }
+
+    #[test]
+    fn test_half_texel_inset() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (1, 0)).unwrap();
+        let exact = builder.build(&MemoryBackend, None).unwrap();
+
+        builder.set_half_texel_inset(true);
+        let inset = builder.build(&MemoryBackend, None).unwrap();
+        let (w, h) = inset.get_texture(0).dimensions();
+        let texel = (1.0 / w as f32, 1.0 / h as f32);
+
+        let (u, v) = exact.get_texture_offset(0);
+        assert_eq!(inset.get_texture_offset(0), (u + texel.0 / 2.0, v + texel.1 / 2.0));
+        let ratio = exact.get_sprite_tex_ratio(0);
+        assert_eq!(inset.get_sprite_tex_ratio(0), [ratio[0] - texel.0, ratio[1] - texel.1]);
+        assert_eq!(inset.get_tile_draw_info(0, 0).uv_scale, inset.get_sprite_tex_ratio(0));
+
+        let uv = Atlas::frame_uv(&inset, "sheet").unwrap();
+        assert_eq!(uv.uv_offset, (texel.0 / 2.0, texel.1 / 2.0));
+        assert_eq!(builder.build_cpu(None).unwrap().get_texture_offset(0), inset.get_texture_offset(0));
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(72, 24), (24, 24)).unwrap();
+        builder.add_frame_image("icon", DynamicImage::new_rgba8(16, 16), (16, 16)).unwrap();
+        builder.add_tile("sheet", 0, (2, 0)).unwrap();
+        builder.add_tile_with_kind("sheet", 1, (0, 0), TileKind::Animated(2, 100)).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        let sheet = atlas.frames().get("sheet").unwrap().rect().clone();
+        let page = atlas.get_tile_texture_idx(0);
+        let walking = Pick { frame: "sheet".to_string(), tile: Some(TileId(1)), animation_frame: 1 };
+        assert_eq!(atlas.pick(page, (sheet.x() + 30, sheet.y() + 5)), Some(walking.clone()));
+        assert_eq!(atlas.pick(page, (sheet.x() + 60, sheet.y() + 23)).unwrap().tile, Some(TileId(0)));
+
+        let (w, h) = atlas.page_dimensions(page);
+        let uv = ((sheet.x() as f32 + 30.5) / w as f32, (sheet.y() as f32 + 5.5) / h as f32);
+        assert_eq!(atlas.pick_uv(page, uv), Some(walking));
+
+        let icon = atlas.frames().get("icon").unwrap();
+        let picked = atlas.pick(icon.texture_idx(), (icon.rect().x() + 8, icon.rect().y() + 8)).unwrap();
+        assert_eq!((picked.frame.as_str(), picked.tile), ("icon", None));
+        assert_eq!(atlas.pick(page, (5000, 5000)), None);
+    }
+
+    #[test]
+    fn test_insert_frame() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.set_pack_algorithm(PackAlgorithm::Shelf);
+        builder.set_reserve_page_space(true);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(32, 16), (16, 16)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(16, 24), (16, 24)).unwrap();
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+        assert_eq!(atlas.page_dimensions(0), (64, 64));
+
+        let mut avatar = DynamicImage::new_rgba8(16, 16);
+        avatar.put_pixel(0, 0, Rgba { data: [1, 2, 3, 255] });
+        assert_eq!(atlas.insert_frame("avatar", &avatar, (16, 16)).unwrap(), PageId(0));
+        assert!(atlas.insert_frame("avatar", &avatar, (16, 16)).is_err());
+        atlas.flush(&MemoryBackend).unwrap();
+
+        let rect = atlas.frames().get("avatar").unwrap().rect().clone();
+        assert_eq!((rect.x(), rect.y()), (32, 0));
+        assert_eq!(atlas.get_texture(0).get_pixel(rect.x(), rect.y()), Rgba { data: [1, 2, 3, 255] });
+
+        assert!(atlas.insert_frame("wide", &DynamicImage::new_rgba8(64, 24), (64, 24)).is_ok());
+        assert!(atlas.insert_frame("tall", &DynamicImage::new_rgba8(8, 32), (8, 32)).is_err());
+    }
+
+    #[test]
+    fn test_rotated_frames() {
+        let mut side = DynamicImage::new_rgba8(32, 16);
+        for y in 0..16 {
+            for x in 0..32 {
+                side.put_pixel(x, y, Rgba { data: [if x < 16 { 255 } else { 0 }, 0, 0, 255] });
+            }
+        }
+        side.put_pixel(0, 0, Rgba { data: [255, 255, 255, 255] });
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.set_pack_algorithm(PackAlgorithm::Shelf);
+        builder.add_frame_image("top", DynamicImage::new_rgba8(48, 64), (16, 16)).unwrap();
+        builder.add_frame_image("side", side, (16, 16)).unwrap();
+        builder.add_tile("side", 0, (0, 0)).unwrap();
+        builder.add_tile("side", 1, (1, 0)).unwrap();
+        assert_eq!(builder.build_cpu(None).unwrap().pages().len(), 2);
+
+        // Only turned does side fit in the column beside top.
+        builder.set_allow_rotation(true);
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.pages().len(), 1);
+        let rect = atlas.frames().get("side").unwrap().rect().clone();
+        assert_eq!((rect.x(), rect.y(), rect.w(), rect.h(), rect.rotated()), (48, 0, 16, 32, true));
+        assert_eq!(rect.source_size(), (32, 16));
+
+        let page = &atlas.pages()[0];
+        assert_eq!(page.get_pixel(63, 0), Rgba { data: [255, 255, 255, 255] });
+        assert_eq!(page.get_pixel(56, 24), Rgba { data: [0, 0, 0, 255] });
+
+        let info = atlas.get_tile_draw_info(0, 0);
+        assert!(info.rotated);
+        assert_eq!((info.uv_offset, info.uv_scale, info.pixel_size), ((0.75, 0.0), [0.25, 0.25], (16, 16)));
+        assert_eq!(info.uv_corners(), [(1.0, 0.0), (1.0, 0.25), (0.75, 0.25), (0.75, 0.0)]);
+        assert_eq!(atlas.get_tile_draw_info(1, 0).uv_offset, (0.75, 0.25));
+        assert_eq!(atlas.pick(0, (50, 20)).unwrap().tile, Some(TileId(1)));
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut coin = DynamicImage::new_rgba8(32, 32);
+        for y in 8..14 {
+            for x in 4..20 {
+                coin.put_pixel(x, y, Rgba { data: [255, 255, 0, 255] });
+            }
+        }
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_trim(true);
+        builder.add_frame_image("coin", coin.clone(), (32, 32)).unwrap();
+        builder.add_frame_image("sheet", coin, (16, 32)).unwrap();
+        builder.add_tile("coin", 0, (0, 0)).unwrap();
+        builder.add_tile("sheet", 1, (1, 0)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+
+        let frame = atlas.frames().get("coin").unwrap();
+        assert_eq!((frame.rect().w(), frame.rect().h()), (16, 6));
+        assert_eq!((frame.source_size(), frame.trim_offset()), ((32, 32), (4, 8)));
+        assert!(frame.is_trimmed());
+        let page = &atlas.pages()[frame.texture_idx()];
+        assert_eq!(page.get_pixel(frame.rect().x(), frame.rect().y()), Rgba { data: [255, 255, 0, 255] });
+
+        let info = atlas.get_tile_draw_info(0, 0);
+        assert_eq!((info.pixel_size, info.source_size, info.trim_offset), ((16, 6), (32, 32), (4, 8)));
+
+        // Tiles of a sheet share the frame's rect, so sheets are left alone.
+        let sheet = atlas.frames().get("sheet").unwrap();
+        assert_eq!((sheet.rect().w(), sheet.rect().h(), sheet.is_trimmed()), (32, 32, false));
+        assert_eq!(atlas.get_tile_draw_info(1, 0).trim_offset, (0, 0));
+    }
+
+    #[test]
+    fn test_sort_by_trimmed_area() {
+        let mut speck = DynamicImage::new_rgba8(48, 48);
+        for y in 10..18 {
+            for x in 10..18 {
+                speck.put_pixel(x, y, Rgba { data: [255, 255, 255, 255] });
+            }
+        }
+        let mut block = DynamicImage::new_rgba8(32, 32);
+        for (x, y, _) in block.clone().pixels() {
+            block.put_pixel(x, y, Rgba { data: [0, 0, 255, 255] });
+        }
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(128);
+        builder.set_pack_algorithm(PackAlgorithm::Shelf);
+        builder.set_sort_by_area(true);
+        builder.add_frame_image("speck", speck, (48, 48)).unwrap();
+        builder.add_frame_image("block", block, (32, 32)).unwrap();
+        let at = |atlas: &CpuAtlas, key: &str| atlas.frames().get(key).map(|frame| (frame.rect().x(), frame.rect().y()));
+
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!((at(&atlas, "speck"), at(&atlas, "block")), (Some((0, 0)), Some((48, 0))));
+
+        // trimmed down to 8x8, speck now goes after block.
+        builder.set_trim(true);
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!((at(&atlas, "block"), at(&atlas, "speck")), (Some((0, 0)), Some((32, 0))));
+        assert_eq!(atlas.frames().get("speck").unwrap().rect().source_size(), (8, 8));
+    }
+
+    #[test]
+    fn test_free_space() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 48), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(16, 16), (16, 16)).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+        let (w, h) = atlas.get_texture(0).dimensions();
+
+        // pages are cropped to their frames, leaving only the gap beside b.
+        assert_eq!(atlas.page_free_area(0), w as u64 * h as u64 - 48 * 48 - 16 * 16);
+
+        let (page, rect) = atlas.find_free_rect(8, 8).unwrap();
+        assert_eq!(page, PageId(0));
+        for frame in atlas.frames().values() {
+            assert!(!rects_overlap(&rect, frame.padded_rect()));
+        }
+        assert!(rect.x() + 8 <= w && rect.y() + 8 <= h);
+
+        // nothing is reserved, so the same area is found again.
+        let (_, again) = atlas.find_free_rect(8, 8).unwrap();
+        assert_eq!((again.x(), again.y()), (rect.x(), rect.y()));
+
+        assert!(atlas.can_fit(8, 8));
+        assert!(!atlas.can_fit(w, h));
+        assert!(!atlas.can_fit(w + 1, 1));
+    }
+
+    #[test]
+    fn test_split_oversized() {
+        let mut builder = TileAtlasBuilder::new();
+        assert!(builder.add_frame_image("sheet", DynamicImage::new_rgba8(4096, 24), (24, 24)).is_err());
+        builder.set_split_oversized(true);
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(4096, 24), (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (100, 0)).unwrap();
+        builder.add_tile_with_kind("sheet", 1, (84, 0), TileKind::Animated(2, 100)).unwrap();
+        builder.add_tile_with_kind("sheet", 2, (86, 0), TileKind::Animated(2, 100)).unwrap();
+
+        builder.set_split_oversized(false);
+        assert!(builder.build_cpu(None).is_err());
+
+        builder.set_split_oversized(true);
+        let atlas = builder.build_cpu(None).unwrap();
+
+        assert_eq!(atlas.locations().get(&0).unwrap(), "sheet#1_0");
+        assert_eq!(atlas.frames().get("sheet#1_0").unwrap().tiles().get(&0).unwrap().offset, (15, 0));
+
+        assert_eq!(atlas.locations().get(&1).unwrap(), "sheet#0_0");
+        match atlas.frames().get("sheet#0_0").unwrap().tiles().get(&1).unwrap().kind {
+            TileKind::Sequence(2, 100) => (),
+            kind => panic!("Expected a sequence, got {:?}", kind),
+        }
+
+        assert_eq!(atlas.frames().get("sheet#1_0").unwrap().tiles().get(&2).unwrap().kind,
+                   TileKind::Animated(2, 100));
+    }
+
+    #[test]
+    fn test_padded_rect() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_padding(4);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        for frame in atlas.frames().values() {
+            let (rect, padded) = (frame.rect(), frame.padded_rect());
+            assert!(rect.x() >= 2 && rect.y() >= 2);
+            assert_eq!((padded.x(), padded.y()), (rect.x() - 2, rect.y() - 2));
+            assert_eq!((padded.w(), padded.h()), (rect.w() + 4, rect.h() + 4));
+        }
+    }
+
+    #[test]
+    fn test_truncated_compressed_page() {
+        let mut page = block_compress::compress(&DynamicImage::new_rgba8(8, 8), BlockFormat::Bc1);
+        page.data.truncate(8);
+        let data = tile_atlas_config::encode_versioned(COMPRESSED_PAGE_MAGIC, COMPRESSED_PAGE_VERSION, &page).unwrap();
+        match decode_compressed_page(&data) {
+            Err(AtlasError::Cache(..)) => (),
+            _ => panic!("expected a truncated page to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_packer_config() {
+        let config = PackerConfig { max_width: 64, max_height: 32, ..PackerConfig::default() };
+        assert!(TileAtlasBuilder::with_config(config).is_err());
+
+        let config = PackerConfig { max_width: 64, max_height: 64, border_padding: 8, ..PackerConfig::default() };
+        let mut builder = TileAtlasBuilder::with_config(config).unwrap();
+        builder.add_frame_image("fits", DynamicImage::new_rgba8(48, 48), (48, 48)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        let rect = atlas.frames().get("fits").unwrap().rect().clone();
+        assert_eq!((rect.x(), rect.y()), (8, 8));
+
+        let mut builder = TileAtlasBuilder::with_config(config).unwrap();
+        match builder.add_frame_image("wide", DynamicImage::new_rgba8(49, 48), (49, 48)) {
+            Err(AtlasError::FrameTooLarge(ref key, _, 64)) => assert_eq!(key, "wide"),
+            _ => panic!("expected the border to leave no room for wide"),
+        }
+    }
+
+    #[test]
+    fn test_padded_uvs() {
+        let mut sheet = DynamicImage::new_rgba8(48, 24);
+        for (x, y, _) in sheet.clone().pixels() {
+            sheet.put_pixel(x, y, Rgba { data: if x < 24 { [255, 0, 0, 255] } else { [0, 255, 0, 255] } });
+        }
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_padding(4);
+        builder.add_frame_image("sheet", sheet, (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (1, 0)).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        let rect = atlas.frames().get("sheet").unwrap().rect().clone();
+        let (w, h) = atlas.get_texture(0).dimensions();
+        let info = atlas.get_tile_draw_info(0, 0);
+        assert_eq!(info.uv_offset, ((rect.x() + 24) as f32 / w as f32, rect.y() as f32 / h as f32));
+        assert_eq!(info.uv_scale, [24.0 / w as f32, 24.0 / h as f32]);
+
+        let center = uv_to_pixel((info.uv_offset.0 + info.uv_scale[0] / 2.0, info.uv_offset.1 + info.uv_scale[1] / 2.0), (w, h));
+        assert_eq!(atlas.get_texture(0).get_pixel(center.0, center.1), Rgba { data: [0, 255, 0, 255] });
+    }
+
+    #[test]
+    fn test_composite_page() {
+        let mut rgba = DynamicImage::new_rgba8(24, 48);
+        let mut rgb = DynamicImage::new_rgb8(48, 24);
+        for (x, y, _) in rgba.clone().pixels() {
+            rgba.put_pixel(x, y, Rgba { data: [x as u8, y as u8, 7, 255] });
+        }
+        for (x, y, _) in rgb.clone().pixels() {
+            rgb.put_pixel(x, y, Rgba { data: [y as u8, x as u8, 9, 255] });
+        }
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_padding(2);
+        builder.add_frame_image("a", rgba, (24, 24)).unwrap();
+        builder.add_frame_image("b", rgb, (24, 24)).unwrap();
+
+        let mut job = builder.start_pack();
+        job.pack_some(1000).unwrap();
+        let exported = job.packers[0].export().unwrap();
+        assert_eq!(job.composite_page(0).into_raw(), exported.to_rgba().into_raw());
+
+        builder.set_pack_algorithm(PackAlgorithm::MaxRects);
+        let mut job = builder.start_pack();
+        job.pack_some(1000).unwrap();
+        let exported = job.packers[0].export().unwrap();
+        assert_eq!(job.composite_page(0).into_raw(), exported.to_rgba().into_raw());
+    }
+
+    #[test]
+    fn test_compression_hints() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_block_compression(Some(BlockFormat::Bc1));
+        builder.add_frame_image("ground", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_frame_image("text", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.set_frame_compression("text", CompressionHint::Lossless).unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        let ground = atlas.frames().get("ground").unwrap().texture_idx();
+        let text = atlas.frames().get("text").unwrap().texture_idx();
+        assert!(ground != text);
+        assert_eq!(atlas.compressed_page(ground).unwrap().format, BlockFormat::Bc1);
+        assert!(atlas.compressed_page(text).is_none());
+    }
+
+    #[test]
+    fn test_usage_classes() {
+        let mut builder = TileAtlasBuilder::new();
+        for key in &["button", "grass", "explosion", "label"] {
+            builder.add_frame_image(key, DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        }
+        builder.set_frame_class("button", "ui").unwrap();
+        builder.set_frame_class("label", "ui").unwrap();
+        builder.set_frame_class("explosion", "effects").unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        let page_of = |key: &str| atlas.frames().get(key).unwrap().texture_idx();
+        assert_eq!(page_of("button"), page_of("label"));
+        assert!(page_of("button") != page_of("grass"));
+        assert!(page_of("explosion") != page_of("grass"));
+        assert_eq!(atlas.pages_with_class("ui"), vec![PageId(page_of("button"))]);
+    }
+
+    #[test]
+    fn test_debug_outlines() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_debug_outlines(true);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        let rect = atlas.frames().get("a").unwrap().rect().clone();
+        let page = &atlas.pages()[0];
+        assert_eq!(page.get_pixel(rect.x(), rect.y()).data, [255, 0, 255, 255]);
+        assert_eq!(page.get_pixel(rect.x() + 1, rect.y() + 1).data, [63, 0, 63, 64]);
+    }
+
+    #[test]
+    fn test_mipmap_gutters() {
+        let mut frame = DynamicImage::new_rgba8(24, 24);
+        for (x, y, _) in frame.clone().pixels() {
+            frame.put_pixel(x, y, Rgba { data: [255, 0, 0, 255] });
+        }
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_mip_levels(3);
+        builder.add_frame_image("a", frame, (24, 24)).unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        let padded = atlas.frames().get("a").unwrap().padded_rect().clone();
+        assert_eq!((padded.w(), padded.h()), (32, 32));
+        assert_eq!(atlas.pages()[0].get_pixel(padded.x(), padded.y()).data, [255, 0, 0, 255]);
+        assert_eq!(atlas.mipmaps(0).len(), 2);
+
+        // far more levels than a page has only widen the gutter as far as
+        // the last level would, leaving no room for the frame.
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.set_mip_levels(40);
+        match builder.add_frame_image("a", DynamicImage::new_rgba8(8, 8), (8, 8)) {
+            Err(AtlasError::FrameTooLarge(_, (8, 8), 64)) => (),
+            other => panic!("Expected FrameTooLarge, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_extrude() {
+        let mut frame = DynamicImage::new_rgba8(8, 8);
+        for (x, y, _) in frame.clone().pixels() {
+            frame.put_pixel(x, y, Rgba { data: [255, 0, 0, 255] });
+        }
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_padding(8);
+        builder.set_extrude(1);
+        builder.add_frame_image("a", frame.clone(), (8, 8)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        let rect = atlas.frames().get("a").unwrap().rect().clone();
+        let page = &atlas.pages()[0];
+        assert_eq!(page.get_pixel(rect.x() - 1, rect.y() - 1).data, [255, 0, 0, 255]);
+        assert_eq!(page.get_pixel(rect.x() - 2, rect.y()).data, [0, 0, 0, 0]);
+
+        // Without padding, frames are spread apart to make room.
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_extrude(2);
+        builder.add_frame_image("a", frame, (8, 8)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        let (rect, padded) = {
+            let frame = atlas.frames().get("a").unwrap();
+            (frame.rect().clone(), frame.padded_rect().clone())
+        };
+        assert_eq!((rect.x(), rect.y(), padded.w(), padded.h()), (2, 2, 12, 12));
+        assert_eq!(atlas.pages()[0].get_pixel(0, 0).data, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_sort_by_area() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        builder.set_pack_algorithm(PackAlgorithm::Shelf);
+        for (i, &(w, h)) in [(48, 32), (16, 16), (32, 16), (32, 32)].iter().enumerate() {
+            builder.add_frame_image(&i.to_string(), DynamicImage::new_rgba8(w, h), (w, h)).unwrap();
+        }
+        assert_eq!(builder.build_cpu(None).unwrap().pages().len(), 2);
+
+        builder.set_sort_by_area(true);
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.pages().len(), 1);
+        let at = |key: &str| atlas.frames().get(key).map(|frame| (frame.rect().x(), frame.rect().y()));
+        assert_eq!((at("3"), at("2"), at("1")), (Some((0, 32)), Some((32, 32)), Some((48, 0))));
+    }
+
+    #[test]
+    fn test_frame_too_large() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        match builder.add_frame_image("wide", DynamicImage::new_rgba8(96, 24), (24, 24)) {
+            Err(AtlasError::FrameTooLarge(key, size, page_size)) => {
+                assert_eq!(key, "wide");
+                assert_eq!(size, (96, 24));
+                assert_eq!(page_size, 64);
+            },
+            _ => panic!("Expected a frame too large error"),
+        }
+        let mut png = Vec::new();
+        DynamicImage::new_rgba8(96, 24).save(&mut png, ::image::PNG).unwrap();
+        match builder.add_frame_bytes("wide", &png, (24, 24)) {
+            Err(AtlasError::FrameTooLarge(_, (96, 24), 64)) => (),
+            _ => panic!("Expected a frame too large error"),
+        }
+        assert!(builder.add_tile("wide", 0, (0, 0)).is_err());
+
+        // a frame that fit when added is checked again against the page size
+        // it's packed with.
+        builder.set_page_size(128);
+        builder.add_frame_image("wide", DynamicImage::new_rgba8(96, 24), (24, 24)).unwrap();
+        assert!(builder.build_cpu(None).is_ok());
+        builder.set_page_size(64);
+        match builder.build_cpu(None) {
+            Err(AtlasError::FrameTooLarge(ref key, (96, 24), 64)) => assert_eq!(key, "wide"),
+            _ => panic!("Expected a frame too large error"),
+        }
+    }
+
+    #[test]
+    fn test_grow_page_size() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_page_size(64);
+        assert!(builder.add_frame_image("wide", DynamicImage::new_rgba8(96, 24), (24, 24)).is_err());
+        builder.set_grow_page_size(Some(4096));
+        builder.add_frame_image("wide", DynamicImage::new_rgba8(96, 24), (24, 24)).unwrap();
+        builder.add_frame_image("small", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+
+        builder.set_grow_page_size(None);
+        assert_eq!(builder.pack_report().page_size, 64);
+
+        builder.set_grow_page_size(Some(100));
+        assert_eq!(builder.pack_report().page_size, 100);
+
+        builder.set_grow_page_size(Some(4096));
+        assert_eq!(builder.pack_report().page_size, 128);
+        assert_eq!(builder.start_pack().page_size(), 128);
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.passes(), 1);
+
+        builder.set_grow_page_size(Some(80));
+        match builder.build_cpu(None) {
+            Err(AtlasError::FrameTooLarge(_, _, page_size)) => assert_eq!(page_size, 80),
+            _ => panic!("Expected a frame too large error"),
+        }
+    }
+
+    #[test]
+    fn test_flush_dirty_rects() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (0, 0)).unwrap();
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        let mut small = DynamicImage::new_rgba8(2, 2);
+        small.put_pixel(1, 1, Rgba { data: [255, 0, 0, 255] });
+        atlas.write_pixels(0, 4, 4, &small).unwrap();
+        atlas.write_pixels(0, 8, 4, &small).unwrap();
+        assert!(atlas.write_pixels(0, 1 << 20, 0, &small).is_err());
+
+        // covers the first write, so only two areas are left to upload.
+        atlas.write_pixels(0, 3, 3, &DynamicImage::new_rgba8(4, 4)).unwrap();
+        assert_eq!(atlas.dirty_rects(0).len(), 2);
+
+        atlas.flush(&MemoryBackend).unwrap();
+        assert!(!atlas.is_dirty());
+        assert_eq!(atlas.get_texture(0).get_pixel(9, 5), Rgba { data: [255, 0, 0, 255] });
+        assert_eq!(atlas.get_texture(0).get_pixel(5, 5), Rgba { data: [0, 0, 0, 0] });
+    }
+
+    #[test]
+    fn test_update_frame_pixels() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        let mut swapped = DynamicImage::new_rgba8(24, 24);
+        swapped.put_pixel(2, 3, Rgba { data: [0, 255, 0, 255] });
+        atlas.update_frame_pixels("b", &swapped).unwrap();
+        assert!(atlas.update_frame_pixels("b", &DynamicImage::new_rgba8(8, 8)).is_err());
+        assert!(atlas.update_frame_pixels("c", &swapped).is_err());
+        atlas.flush(&MemoryBackend).unwrap();
+
+        let (page, x, y) = {
+            let b = atlas.frames().get("b").unwrap();
+            (b.texture_idx(), b.rect().x(), b.rect().y())
+        };
+        assert_eq!(atlas.get_texture(page).get_pixel(x + 2, y + 3), Rgba { data: [0, 255, 0, 255] });
+    }
+
+    #[test]
+    fn test_try_get_lookups() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("a", 0, (1, 0)).unwrap();
+        let cpu_atlas = builder.build_cpu(None).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        // unknown tiles give an error naming them instead of panicking.
+        match atlas.try_get_frame(7) {
+            Err(AtlasError::MissingTile(7)) => (),
+            _ => panic!("Expected a missing tile error"),
+        }
+        assert!(atlas.try_get_tile_draw_info(7, 0).is_err());
+        match cpu_atlas.try_get_tile_draw_info(7, 0) {
+            Err(AtlasError::MissingTile(7)) => (),
+            _ => panic!("Expected a missing tile error"),
+        }
+
+        assert_eq!(atlas.try_get_frame(0).unwrap().rect().x(), atlas.get_frame(0).rect().x());
+        assert_eq!(atlas.try_get_tile_draw_info(0, 0).unwrap(), atlas.get_tile_draw_info(0, 0));
+        assert_eq!(cpu_atlas.try_get_frame(0).unwrap().tile_size(), (24, 24));
+    }
+
+    #[test]
+    fn test_retain_release() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_frame_image("b", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_tile("a", 0, (0, 0)).unwrap();
+        builder.add_sequence_tile(1, &[("b", (0, 0)), ("a", (0, 0))], 100).unwrap();
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        atlas.retain(0).unwrap();
+        assert_eq!(atlas.unreferenced_frames(), vec!["b"]);
+        match atlas.release(1) {
+            Err(AtlasError::NotRetained(1)) => (),
+            _ => panic!("Expected a not retained error"),
+        }
+        assert_eq!(atlas.unreferenced_frames(), vec!["b"]);
+        assert!(atlas.retain(7).is_err());
+
+        atlas.retain(1).unwrap();
+        atlas.release(0).unwrap();
+        assert!(atlas.unreferenced_frames().is_empty());
+        atlas.release(1).unwrap();
+        assert_eq!(atlas.unreferenced_frames(), vec!["a", "b"]);
+        assert!(atlas.release(0).is_err());
+    }
+
+    #[test]
+    fn test_usage_tracking_threads() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("a", 0, (0, 0)).unwrap();
+        builder.add_tile("a", 1, (1, 0)).unwrap();
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+        atlas.enable_usage_tracking();
+
+        // lookups from several threads at once all count.
+        let atlas = Arc::new(atlas);
+        let handles: Vec<_> = (0..4).map(|_| {
+            let atlas = atlas.clone();
+            ::std::thread::spawn(move || for _ in 0..25 {
+                atlas.get_frame(0);
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let report = atlas.usage_report().unwrap();
+        assert_eq!(report.frames, vec![("a".to_string(), 100)]);
+        assert_eq!(report.unused_tiles, vec![1]);
+    }
+
+    #[test]
+    fn test_shared_page_views() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("button", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        builder.add_frame_image("grass", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        let atlas = builder.build(&MemoryBackend, None).unwrap();
+
+        let view = atlas.texture_atlas_view(0);
+        assert!(::std::ptr::eq(*view.get_texture(), atlas.get_texture(0)));
+        assert_eq!(view.get_texture_area("button").x(), atlas.frames().get("button").unwrap().rect().x());
+
+        let tiles = view.tile_atlas_view();
+        assert_eq!(tiles.frames().get("grass").unwrap().rect().y(), view.get_texture_area("grass").y());
+        assert_eq!(tiles.passes(), 1);
+    }
+
+    #[test]
+    fn test_reupload_retained_pages() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+        assert!(atlas.reupload(&MemoryBackend).is_err());
+
+        builder.set_retain_pages(true);
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+        let mut dot = DynamicImage::new_rgba8(1, 1);
+        dot.put_pixel(0, 0, Rgba { data: [0, 0, 255, 255] });
+        atlas.write_pixels(0, 5, 5, &dot).unwrap();
+
+        atlas.reupload(&MemoryBackend).unwrap();
+        assert!(!atlas.is_dirty());
+        assert_eq!(atlas.get_texture(0).get_pixel(5, 5), Rgba { data: [0, 0, 255, 255] });
+    }
+
+    #[test]
+    fn test_contexts() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+        assert!(atlas.add_context(&MemoryBackend, 1).is_err());
+        assert!(atlas.insert_context(1, Vec::new()).is_err());
+
+        builder.set_retain_pages(true);
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+        atlas.add_context(&MemoryBackend, ContextId(2)).unwrap();
+        atlas.add_context(&MemoryBackend, 1).unwrap();
+        assert_eq!(atlas.contexts(), vec![ContextId(1), ContextId(2)]);
+        assert!(atlas.context_texture(3, 0).is_none());
+
+        let mut dot = DynamicImage::new_rgba8(1, 1);
+        dot.put_pixel(0, 0, Rgba { data: [0, 0, 255, 255] });
+        atlas.write_pixels(0, 5, 5, &dot).unwrap();
+        atlas.flush(&MemoryBackend).unwrap();
+        atlas.flush_context(&MemoryBackend, 1).unwrap();
+        assert!(atlas.flush_context(&MemoryBackend, 3).is_err());
+
+        // context 2 keeps its writes queued until it's flushed itself.
+        let blue = Rgba { data: [0, 0, 255, 255] };
+        assert_eq!(atlas.get_texture(0).get_pixel(5, 5), blue);
+        assert_eq!(atlas.context_texture(1, 0).unwrap().get_pixel(5, 5), blue);
+        assert!(!atlas.is_context_dirty(1));
+        assert!(atlas.is_context_dirty(2));
+        assert!(atlas.context_texture(2, 0).unwrap().get_pixel(5, 5) != blue);
+        atlas.flush_context(&MemoryBackend, 2).unwrap();
+        assert!(!atlas.is_context_dirty(2));
+        assert_eq!(atlas.context_texture(2, 0).unwrap().get_pixel(5, 5), blue);
+
+        // reuploading the main context leaves the others as they were.
+        atlas.write_pixels(0, 6, 6, &dot).unwrap();
+        atlas.reupload(&MemoryBackend).unwrap();
+        assert!(!atlas.is_dirty());
+        assert!(atlas.is_context_dirty(1));
+        atlas.add_context(&MemoryBackend, 1).unwrap();
+        assert!(!atlas.is_context_dirty(1));
+        assert_eq!(atlas.context_texture(1, 0).unwrap().get_pixel(6, 6), blue);
+
+        assert_eq!(atlas.remove_context(2).map(|textures| textures.len()), Some(1));
+        assert_eq!(atlas.contexts(), vec![ContextId(1)]);
+    }
+
+    #[test]
+    fn test_page_file_name() {
+        let page = DynamicImage::new_rgba8(64, 64);
+        assert_eq!(page_file_name(&page), page_file_name(&page.clone()));
+        assert!(page_file_name(&page).ends_with(".png"));
+
+        let mut changed = page.clone();
+        changed.put_pixel(3, 3, Rgba { data: [255, 0, 0, 255] });
+        assert!(page_file_name(&page) != page_file_name(&changed));
+        assert!(page_file_name(&page) != page_file_name(&DynamicImage::new_rgba8(32, 128)));
+    }
+
+    #[test]
+    fn test_invalid_animation() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+
+        assert!(builder.add_tile_with_kind("sheet", 0, (0, 0), TileKind::Animated(0, 100)).is_err());
+        assert!(builder.add_tile_with_kind("sheet", 1, (0, 0), TileKind::Animated(2, 0)).is_err());
+        assert!(builder.add_sequence_tile(2, &[], 100).is_err());
+        assert!(builder.add_sequence_tile(3, &[("sheet", (0, 0))], 0).is_err());
+
+        let tile = AtlasTile { offset: (0, 0), kind: TileKind::Animated(2, 0) };
+        assert_eq!(animation_frame(&tile, 1000), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_cancelled_export() {
+        let folder = ::std::env::temp_dir().join("texture_atlas_cancelled_export");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let stale_page = folder.join("0123456789abcdef.png");
+        fs::write(&stale_page, b"stale").unwrap();
+        fs::write(folder.join("notes.txt"), b"keep").unwrap();
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.set_stale_pages(StalePages::RemovePages);
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+
+        // packed, then cancelled before the first page is exported.
+        let mut job = builder.start_pack();
+        while !job.is_done() {
+            job.pack_next().unwrap();
+        }
+        match job.finish_cpu_cancellable(Some(folder.clone()), &AtomicBool::new(true)) {
+            Err(AtlasError::Cancelled) => (),
+            _ => panic!("expected the export to be cancelled"),
+        }
+
+        assert!(!stale_page.exists());
+        assert!(page_files(&folder).unwrap().is_empty());
+        assert!(folder.join("notes.txt").exists());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
 }
@@ -1,142 +1,1297 @@
 use std::collections::HashMap;
-use std::fs::File;
+#[cfg(feature = "fs")]
+use std::fs::{self, File};
 use std::path::{Path, PathBuf};
-use std::io::{Read, Write};
+use std::io;
+#[cfg(feature = "fs")]
+use std::io::Read;
+#[cfg(feature = "fs")]
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use atlas::PageInfo;
 use bincode;
-use crypto::digest::Digest;
-use crypto::sha3::Sha3;
-use glium::backend::Facade;
-use glob;
-use image;
-use toml::Value;
+#[cfg(feature = "fs")]
+use image::{self, DynamicImage, GenericImage};
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "fs")]
+use backend::{self, GpuBackend};
+use block_compress::BlockFormat;
+#[cfg(feature = "fs")]
+use block_compress::{self, CompressedPage};
+#[cfg(feature = "fs")]
+use cpu_atlas::CpuAtlas;
+use digest;
+use error::{AtlasError, AtlasResult};
+#[cfg(feature = "fs")]
+use fs_util;
+use ids::{FrameKey, TileId};
+#[cfg(feature = "fs")]
+use manifest;
+#[cfg(feature = "fs")]
+use mipmap;
+use packing::PackAlgorithm;
+#[cfg(feature = "fs")]
+use sandbox;
+use sampler::SamplerSettings;
 use tile_atlas::*;
-use toml_util;
-
-use {make_texture};
+use toml_util::{self, ConfigSource, TableEntry};
 
 #[derive(Serialize, Deserialize)]
 pub struct TileAtlasConfig {
+    #[serde(serialize_with = "serialize_sorted")]
     pub locations: HashMap<TileIndex, String>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub frames: HashMap<String, AtlasFrame>,
+    /// The page images, in page order.
+    pub pages: Vec<PageInfo>,
+    /// How many mip levels the pages had, counting the page itself. Only
+    /// the pages are saved, so the levels below are made again on loading.
+    pub mip_levels: u32,
+    pub sampler: SamplerSettings,
     pub file_hash: String,
 }
 
+#[cfg(feature = "fs")]
 pub fn get_config_cache_path(config_name: &str) -> PathBuf {
     let cache_filepath_str = format!("data/.packed/{}", config_name);
     PathBuf::from(&cache_filepath_str)
 }
 
-pub fn load_tile_manager_config(config_name: &str) -> TileAtlasConfig {
+#[cfg(feature = "fs")]
+pub fn load_tile_manager_config(config_name: &str) -> AtlasResult<TileAtlasConfig> {
     let mut path = get_config_cache_path(config_name);
     path.push("cache.bin");
 
-    let mut file = File::open(path).unwrap();
+    read_tile_manager_config(&path)
+}
+
+/// Marks the start of a serialized `TileAtlasConfig`.
+const CACHE_MAGIC: &'static [u8; 4] = b"TACF";
+
+/// Bumped whenever the serialized layout of `TileAtlasConfig` changes, so
+/// caches written by older versions are rebuilt instead of misread.
+const CACHE_VERSION: u32 = 6;
+
+#[cfg(feature = "fs")]
+pub fn read_tile_manager_config(path: &Path) -> AtlasResult<TileAtlasConfig> {
+    let mut file = File::open(path).map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
     let mut buf = Vec::new();
-    file.read_to_end(&mut buf).unwrap();
-    bincode::deserialize(buf.as_slice()).unwrap()
+    file.read_to_end(&mut buf).map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+    decode_config(buf.as_slice())
 }
 
-pub fn write_tile_manager_config(config: &TileAtlasConfig, config_name: &str) {
-    let mut path = get_config_cache_path(config_name);
-    path.push("cache.bin");
+#[cfg(feature = "fs")]
+fn encode_config(config: &TileAtlasConfig) -> AtlasResult<Vec<u8>> {
+    encode_versioned(CACHE_MAGIC, CACHE_VERSION, config)
+}
+
+#[cfg(feature = "fs")]
+fn decode_config(data: &[u8]) -> AtlasResult<TileAtlasConfig> {
+    decode_versioned(CACHE_MAGIC, CACHE_VERSION, data)
+}
+
+/// Serializes `value` after a header of `magic` and a little-endian
+/// `version`.
+pub fn encode_versioned<T: Serialize>(magic: &[u8; 4], version: u32, value: &T) -> AtlasResult<Vec<u8>> {
+    let mut data = magic.to_vec();
+    data.extend_from_slice(&[version as u8,
+                             (version >> 8) as u8,
+                             (version >> 16) as u8,
+                             (version >> 24) as u8]);
+    data.extend(bincode::serialize(value, bincode::Infinite)?);
+    Ok(data)
+}
+
+/// Reads back what `encode_versioned` wrote, failing unless the header
+/// matches `magic` and `version`.
+pub fn decode_versioned<T: Deserialize>(magic: &[u8; 4], version: u32, data: &[u8]) -> AtlasResult<T> {
+    let header_len = magic.len() + 4;
+    if data.len() < header_len || data[..magic.len()] != magic[..] {
+        return Err(AtlasError::Cache("Missing cache header".to_string()));
+    }
+
+    let v = &data[magic.len()..header_len];
+    let found = v[0] as u32 | (v[1] as u32) << 8 | (v[2] as u32) << 16 | (v[3] as u32) << 24;
+    if found != version {
+        return Err(AtlasError::Cache(format!("Cache has version {}, expected {}", found, version)));
+    }
+
+    Ok(bincode::deserialize(&data[header_len..])?)
+}
+
+#[cfg(feature = "fs")]
+pub fn write_tile_manager_config(config: &TileAtlasConfig, config_name: &str) -> AtlasResult<()> {
+    write_config_in(&get_config_cache_path(config_name), config)
+}
 
-    let data = bincode::serialize(config, bincode::Infinite).unwrap();
-    let mut file = File::create(path).unwrap();
-    file.write(data.as_slice()).unwrap();
+#[cfg(feature = "fs")]
+fn write_config_in(folder: &Path, config: &TileAtlasConfig) -> AtlasResult<()> {
+    let data = encode_config(config)?;
+    fs_util::write_atomic(&folder.join("cache.bin"), data.as_slice())
 }
 
-fn hash_str(s: &str) -> String {
-    let mut hasher = Sha3::sha3_256();
-    hasher.input_str(s);
-    hasher.result_str()
+/// Settings shared by the `from_config*` entry points.
+#[cfg(feature = "fs")]
+struct LoadOptions<'a> {
+    root: Option<&'a Path>,
+    signing_key: Option<&'a [u8]>,
+    reject_unknown_keys: bool,
+    collect_errors: bool,
+    profile: Option<&'a str>,
+    retain_pages: bool,
+    cancel: &'a AtomicBool,
 }
 
-impl TileAtlas {
-    pub fn from_config<F: Facade>(display: &F, filename: &str) -> Self {
-        let toml_str = toml_util::toml_string_from_file(filename);
+#[cfg(feature = "fs")]
+impl<'a> LoadOptions<'a> {
+    fn new(cancel: &'a AtomicBool) -> Self {
+        LoadOptions {
+            root: None,
+            signing_key: None,
+            reject_unknown_keys: false,
+            collect_errors: false,
+            profile: None,
+            retain_pages: false,
+            cancel: cancel,
+        }
+    }
+}
+
+/// A page read back from the cache, which is saved either as an image or
+/// block compressed.
+#[cfg(feature = "fs")]
+enum CachedPage {
+    Image(DynamicImage),
+    Compressed(CompressedPage),
+}
+
+#[cfg(feature = "fs")]
+impl CachedPage {
+    fn read(path: &Path) -> AtlasResult<CachedPage> {
+        if path.extension().map_or(false, |ext| ext == "png") {
+            let page = image::open(path).map_err(|e| AtlasError::Image(path.to_path_buf(), e.to_string()))?;
+            return Ok(CachedPage::Image(page));
+        }
+
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+        Ok(CachedPage::Compressed(decode_compressed_page(&data)?))
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        match *self {
+            CachedPage::Image(ref image)     => image.dimensions(),
+            CachedPage::Compressed(ref page) => (page.width, page.height),
+        }
+    }
+}
+
+/// Puts the pages read from a cache back together into the atlas they were
+/// saved from, with the mip levels below them made again. Compressed pages
+/// are decoded too, since a `CpuAtlas` keeps the pixels of every page, but
+/// are still uploaded compressed.
+#[cfg(feature = "fs")]
+fn cached_atlas(config: TileAtlasConfig, pages: Vec<CachedPage>) -> AtlasResult<CpuAtlas> {
+    let mut images = Vec::with_capacity(pages.len());
+    let mut compressed = Vec::with_capacity(pages.len());
+    for page in pages {
+        match page {
+            CachedPage::Image(image) => {
+                images.push(image);
+                compressed.push(None);
+            },
+            CachedPage::Compressed(page) => {
+                images.push(block_compress::decompress(&page)?);
+                compressed.push(Some(page));
+            },
+        }
+    }
+
+    let mipmaps = if config.mip_levels > 1 {
+        images.iter().map(|page| mipmap::generate_chain(page, config.mip_levels)).collect()
+    } else {
+        Vec::new()
+    };
+
+    let names = config.pages.into_iter().filter_map(|info| info.file_name).collect();
+    Ok(CpuAtlas::new(config.locations, config.frames, images)
+       .with_compressed_pages(compressed)
+       .with_mipmaps(mipmaps)
+       .with_sampler(config.sampler)
+       .with_page_names(names))
+}
+
+/// Reads every page `config` lists from the cache folder, checking that
+/// each one exists and is large enough to hold the frames packed on it.
+#[cfg(feature = "fs")]
+fn read_cached_pages(cache_path: &Path, config: &TileAtlasConfig, cancel: &AtomicBool) -> AtlasResult<Vec<CachedPage>> {
+    let page_count = config.frames.values()
+        .map(|frame| frame.texture_idx() + 1)
+        .max()
+        .unwrap_or(0);
+
+    if config.pages.len() < page_count {
+        return Err(AtlasError::Cache(format!("Expected {} pages, but only {} are listed", page_count, config.pages.len())));
+    }
+
+    let mut pages = Vec::with_capacity(page_count);
+
+    for info in config.pages.iter() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(AtlasError::Cancelled);
+        }
+
+        let path = match info.file_name {
+            Some(ref name) => cache_path.join(name),
+            None           => return Err(AtlasError::Cache(format!("Page {} has no file name", info.page))),
+        };
+        if !path.exists() {
+            return Err(AtlasError::Io(path, io::Error::new(io::ErrorKind::NotFound, "page not found")));
+        }
+
+        let page = CachedPage::read(&path)?;
+        if page.dimensions() != (info.width, info.height) {
+            return Err(AtlasError::Cache(format!("Page {} is {:?}, but was saved as {}x{}",
+                                                 info.page, page.dimensions(), info.width, info.height)));
+        }
+        pages.push(page);
+    }
+
+    for (key, frame) in config.frames.iter() {
+        let (w, h) = pages[frame.texture_idx()].dimensions();
+        let rect = frame.rect();
+        if rect.x + rect.w > w || rect.y + rect.h > h {
+            return Err(AtlasError::Cache(format!("Frame {} lies outside of page {}", key, frame.texture_idx())));
+        }
+
+        for steps in frame.sequences().values() {
+            if let Some(&(ref step_key, _)) = steps.iter().find(|&&(ref k, _)| !config.frames.contains_key(k)) {
+                return Err(AtlasError::Cache(format!("A sequence in frame {} steps into missing frame {}", key, step_key)));
+            }
+        }
+    }
+
+    for (index, key) in config.locations.iter() {
+        if !config.frames.get(key).map_or(false, |frame| frame.tiles().contains_key(index)) {
+            return Err(AtlasError::Cache(format!("Tile {} is listed in frame {}, which doesn't hold it", index, key)));
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Hashes a config, tagged with the algorithm used. See `digest`.
+pub fn hash_str(s: &str) -> String {
+    digest::tagged_hash(s.as_bytes())
+}
+
+/// Checks a hash from `hash_str` against `s`.
+pub fn hash_matches(recorded: &str, s: &str) -> bool {
+    digest::matches(recorded, s.as_bytes())
+}
+
+/// Parses an atlas config and loads every frame and tile it lists into a
+/// builder, without touching the GPU. If `root` is given, frame paths are
+/// resolved inside it and may not escape it.
+pub fn builder_from_toml(toml_str: &str, root: Option<&Path>) -> AtlasResult<TileAtlasBuilder> {
+    builder_from_config(toml_str, None, root, None, false, false)
+}
+
+/// Like `builder_from_toml`, but fails with `AtlasError::UnknownConfigKey`
+/// on any key the config format doesn't use, which catches typos like
+/// `tile_sizee`.
+pub fn builder_from_toml_strict_keys(toml_str: &str, root: Option<&Path>) -> AtlasResult<TileAtlasBuilder> {
+    builder_from_config(toml_str, None, root, None, true, false)
+}
+
+/// Like `builder_from_toml`, but keeps going past broken maps and tiles.
+/// The returned builder collects errors (see
+/// `TileAtlasBuilder::set_collect_errors`), so everything wrong with the
+/// config is reported together once it's built.
+pub fn builder_from_toml_collecting_errors(toml_str: &str, root: Option<&Path>) -> AtlasResult<TileAtlasBuilder> {
+    builder_from_config(toml_str, None, root, None, false, true)
+}
+
+const ROOT_KEYS: &'static [&'static str] = &["maps", "tiles", "namespace", "profiles"];
+const MAP_KEYS: &'static [&'static str] = &["file_path", "tile_size", "pivot", "overlay", "compression", "class"];
+const TILE_KEYS: &'static [&'static str] = &["atlas", "offset", "animation", "stride", "sequence", "delay",
+                                             "name", "tags", "collision"];
+const SEQUENCE_STEP_KEYS: &'static [&'static str] = &["atlas", "offset"];
+const PROFILE_KEYS: &'static [&'static str] = &["page_size", "padding", "mip_levels", "compression", "outlines",
+                                                "packer", "rotation", "trim", "extrude", "sort_by_area"];
+
+/// Turns a missing image error into one that points at the config entry
+/// that listed the image.
+fn missing_map_file(err: AtlasError, config_path: Option<&Path>, index: usize) -> AtlasError {
+    match err {
+        AtlasError::Io(path, err) => {
+            if err.kind() == io::ErrorKind::NotFound {
+                AtlasError::MissingMapFile(path, config_path.map(|p| p.to_path_buf()), index)
+            } else {
+                AtlasError::Io(path, err)
+            }
+        },
+        err => err,
+    }
+}
 
-        let packed_folder = Path::new(filename).file_stem().unwrap().to_str().unwrap();
+fn builder_from_config(toml_str: &str,
+                       config_path: Option<&Path>,
+                       root: Option<&Path>,
+                       profile: Option<&str>,
+                       reject_unknown_keys: bool,
+                       collect_errors: bool) -> AtlasResult<TileAtlasBuilder> {
+    let mut builder = TileAtlasBuilder::new();
+    builder.set_collect_errors(collect_errors);
+    if let Some(root) = root {
+        builder.set_root(root);
+    }
+
+    if let Some(profile) = profile {
+        apply_profile(&mut builder, toml_str, config_path, profile, reject_unknown_keys)?;
+    }
+
+    load_config_into(&mut builder, toml_str, config_path, None, reject_unknown_keys)?;
+    Ok(builder)
+}
+
+/// Applies the build settings of a profile, so one config can be built
+/// small and plain while developing and compressed for shipping. Settings a
+/// profile leaves out keep the builder's defaults.
+fn apply_profile(builder: &mut TileAtlasBuilder,
+                 toml_str: &str,
+                 config_path: Option<&Path>,
+                 profile: &str,
+                 reject_unknown_keys: bool) -> AtlasResult<()> {
+    let mut source = ConfigSource::new(toml_str, config_path);
+    source.set_reject_unknown_keys(reject_unknown_keys);
+    let val = source.parse()?;
+    let config = source.root(&val);
+
+    let settings = match config.get_table("profiles")? {
+        Some(profiles) => profiles.get_table(profile)?,
+        None           => None,
+    };
+    let settings = match settings {
+        Some(settings) => settings,
+        None           => return Err(AtlasError::Config(format!("No profile named \"{}\"", profile))),
+    };
+    settings.check_keys(PROFILE_KEYS)?;
+
+    if let Some(page_size) = settings.get("page_size", "an integer")? {
+        builder.set_page_size(page_size);
+    }
+    if let Some(padding) = settings.get("padding", "an integer")? {
+        builder.set_padding(padding);
+    }
+    if let Some(levels) = settings.get("mip_levels", "an integer")? {
+        builder.set_mip_levels(levels);
+    }
+    if let Some(outlines) = settings.get("outlines", "a boolean")? {
+        builder.set_debug_outlines(outlines);
+    }
+    if let Some(compression) = settings.get::<String>("compression", COMPRESSION_EXPECTED)? {
+        builder.set_block_compression(parse_compression(&compression)?);
+    }
+    if let Some(packer) = settings.get::<String>("packer", PACKER_EXPECTED)? {
+        builder.set_pack_algorithm(parse_packer(&packer)?);
+    }
+    if let Some(rotation) = settings.get("rotation", "a boolean")? {
+        builder.set_allow_rotation(rotation);
+    }
+    if let Some(trim) = settings.get("trim", "a boolean")? {
+        builder.set_trim(trim);
+    }
+    if let Some(extrude) = settings.get("extrude", "an integer")? {
+        builder.set_extrude(extrude);
+    }
+    if let Some(sort) = settings.get("sort_by_area", "a boolean")? {
+        builder.set_sort_by_area(sort);
+    }
+
+    Ok(())
+}
+
+const COMPRESSION_EXPECTED: &'static str = "\"bc1\", \"bc3\" or \"none\"";
+
+/// Parses the `compression` of a profile or map.
+fn parse_compression(compression: &str) -> AtlasResult<Option<BlockFormat>> {
+    match compression {
+        "bc1"  => Ok(Some(BlockFormat::Bc1)),
+        "bc3"  => Ok(Some(BlockFormat::Bc3)),
+        "none" => Ok(None),
+        _      => Err(AtlasError::Config(format!("Unknown compression \"{}\", expected {}", compression, COMPRESSION_EXPECTED))),
+    }
+}
+
+const PACKER_EXPECTED: &'static str = "\"skyline\", \"maxrects\", \"guillotine\" or \"shelf\"";
+
+fn parse_packer(packer: &str) -> AtlasResult<PackAlgorithm> {
+    match packer {
+        "skyline"    => Ok(PackAlgorithm::Skyline),
+        "maxrects"   => Ok(PackAlgorithm::MaxRects),
+        "guillotine" => Ok(PackAlgorithm::Guillotine),
+        "shelf"      => Ok(PackAlgorithm::Shelf),
+        _            => Err(AtlasError::Config(format!("Unknown packer \"{}\", expected {}", packer, PACKER_EXPECTED))),
+    }
+}
+
+/// Gets the hash a cached build is checked against. Builds of other profiles
+/// hash differently, so switching profiles rebuilds the atlas.
+#[cfg(any(feature = "fs", test))]
+fn build_hash(toml_str: &str, profile: Option<&str>) -> String {
+    hash_str(&build_hash_input(toml_str, profile))
+}
+
+#[cfg(feature = "fs")]
+fn build_hash_matches(recorded: &str, toml_str: &str, profile: Option<&str>) -> bool {
+    hash_matches(recorded, &build_hash_input(toml_str, profile))
+}
+
+fn build_hash_input(toml_str: &str, profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("{}\n# profile: {}", toml_str, profile),
+        None          => toml_str.to_string(),
+    }
+}
+
+impl TileAtlasBuilder {
+    /// Adds every frame and tile of another config to this builder, for
+    /// merging content packs into one atlas. Its tiles are numbered from
+    /// `next_tile_index`, and frame keys that clash with ones already added
+    /// are handled as set with `set_key_collision`. Packs should set a
+    /// `namespace` to keep their frame keys apart.
+    pub fn add_toml(&mut self, toml_str: &str, config_path: Option<&Path>) -> AtlasResult<()> {
+        load_config_into(self, toml_str, config_path, None, false)
+    }
+
+    /// Like `add_toml`, but reads the images the config lists from `files`,
+    /// keyed by each map's `file_path`, instead of from disk. For platforms
+    /// without a filesystem, like the browser, where the images are fetched
+    /// some other way.
+    pub fn add_toml_in_memory(&mut self, toml_str: &str, files: &HashMap<String, Vec<u8>>) -> AtlasResult<()> {
+        load_config_into(self, toml_str, None, Some(files), false)
+    }
+}
+
+/// Loads a config's maps and tiles into `builder`. If the config sets a
+/// `namespace`, its frames are added under `namespace:file_path`, and frame
+/// references without a namespace of their own resolve inside it.
+fn load_config_into(builder: &mut TileAtlasBuilder,
+                    toml_str: &str,
+                    config_path: Option<&Path>,
+                    files: Option<&HashMap<String, Vec<u8>>>,
+                    reject_unknown_keys: bool) -> AtlasResult<()> {
+    let mut source = ConfigSource::new(toml_str, config_path);
+    source.set_reject_unknown_keys(reject_unknown_keys);
+    let val = source.parse()?;
+    let config = source.root(&val);
+    config.check_keys(ROOT_KEYS)?;
+
+    let namespace: Option<String> = config.get("namespace", "a string")?;
+    let namespace = namespace.as_ref().map(|n| n.as_str());
+
+    let mut idx = builder.next_tile_index();
+
+    let maps = config.expect_entries("maps")?;
+    let tiles = config.expect_entries("tiles")?;
+    builder.reserve(maps.len(), tiles.len());
+
+    for map in maps.iter() {
+        if let Err(e) = add_map(builder, map, config_path, files, namespace) {
+            match map.get::<String>("file_path", "a string") {
+                Ok(Some(file_path)) => builder.record_frame_error(&frame_key(namespace, &file_path), e)?,
+                _                   => builder.record_error(e)?,
+            }
+        }
+    }
+
+    // overlays can point at any map, so link them once every map is added.
+    for map in maps.iter() {
+        if let Err(e) = link_overlay(builder, map, namespace) {
+            builder.record_error(e)?;
+        }
+    }
+
+    for tile in tiles.iter() {
+        if let Err(e) = add_tile(builder, tile, idx, namespace) {
+            builder.record_error(e)?;
+        }
+        idx += 1;
+    }
+
+    Ok(())
+}
+
+fn frame_key(namespace: Option<&str>, key: &str) -> String {
+    FrameKey::resolve(namespace, key).into()
+}
+
+fn add_map(builder: &mut TileAtlasBuilder,
+           map: &TableEntry,
+           config_path: Option<&Path>,
+           files: Option<&HashMap<String, Vec<u8>>>,
+           namespace: Option<&str>) -> AtlasResult<()> {
+    map.check_keys(MAP_KEYS)?;
+    let file_path: String = map.expect("file_path", "a string")?;
+    let tile_size: [u32; 2] = map.expect("tile_size", "an array of two integers")?;
+    let key = frame_key(namespace, &file_path);
+    let tile_size = (tile_size[0], tile_size[1]);
+    let added = match files {
+        Some(files) => match files.get(&file_path) {
+            Some(data) => builder.add_frame_bytes(&key, data, tile_size),
+            None       => Err(AtlasError::Io(PathBuf::from(&file_path),
+                                             io::Error::new(io::ErrorKind::NotFound, "not among the given files"))),
+        },
+        None => add_map_file(builder, &key, &file_path, tile_size),
+    };
+    added.map_err(|e| missing_map_file(e, config_path, map.index()))?;
+
+    if let Some(pivot) = map.get::<[f32; 2]>("pivot", "an array of two floats")? {
+        builder.set_frame_pivot(&key, (pivot[0], pivot[1]))?;
+    }
+
+    if let Some(compression) = map.get::<String>("compression", COMPRESSION_EXPECTED)? {
+        let hint = match parse_compression(&compression)? {
+            Some(format) => CompressionHint::Format(format),
+            None         => CompressionHint::Lossless,
+        };
+        builder.set_frame_compression(&key, hint)?;
+    }
+
+    if let Some(class) = map.get::<String>("class", "a string")? {
+        builder.set_frame_class(&key, &class)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "fs")]
+fn add_map_file(builder: &mut TileAtlasBuilder, key: &str, file_path: &str, tile_size: (u32, u32)) -> AtlasResult<()> {
+    builder.add_frame_as(key, file_path, tile_size)
+}
+
+#[cfg(not(feature = "fs"))]
+fn add_map_file(_: &mut TileAtlasBuilder, _: &str, file_path: &str, _: (u32, u32)) -> AtlasResult<()> {
+    Err(fs_disabled(Path::new(file_path)))
+}
+
+fn link_overlay(builder: &mut TileAtlasBuilder, map: &TableEntry, namespace: Option<&str>) -> AtlasResult<()> {
+    if let Some(overlay) = map.get::<String>("overlay", "a string")? {
+        let file_path: String = map.expect("file_path", "a string")?;
+        builder.set_frame_overlay(&frame_key(namespace, &file_path), &frame_key(namespace, &overlay))?;
+    }
+
+    Ok(())
+}
+
+fn add_tile(builder: &mut TileAtlasBuilder, tile: &TableEntry, idx: TileIndex, namespace: Option<&str>) -> AtlasResult<()> {
+    tile.check_keys(TILE_KEYS)?;
+
+    if tile.has("sequence") {
+        return add_sequence_tile(builder, tile, idx, namespace);
+    }
+
+    let atlas: String = tile.expect("atlas", "a string")?;
+    let offset: [u32; 2] = tile.expect("offset", "an array of two integers")?;
+    let offset = (offset[0], offset[1]);
+
+    let kind = match tile.get::<[u64; 2]>("animation", "an array of two integers")? {
+        Some(animation) => {
+            match tile.get("stride", "an integer")? {
+                Some(stride) => TileKind::StridedAnimated(animation[0], animation[1], stride),
+                None         => TileKind::Animated(animation[0], animation[1]),
+            }
+        },
+        None => TileKind::Static,
+    };
+
+    builder.add_tile_with_kind(&frame_key(namespace, &atlas), idx, offset, kind)
+}
+
+/// Adds a tile whose animation frames are listed one by one, each with its
+/// own atlas and offset:
+///
+/// ```toml
+/// [[tiles]]
+/// sequence = [{ atlas = "walk_0.png", offset = [0, 0] },
+///             { atlas = "walk_1.png", offset = [0, 0] }]
+/// delay = 100
+/// ```
+fn add_sequence_tile(builder: &mut TileAtlasBuilder,
+                     tile: &TableEntry,
+                     idx: TileIndex,
+                     namespace: Option<&str>) -> AtlasResult<()> {
+    let steps = tile.expect_entries("sequence")?;
+    let delay: u64 = tile.expect("delay", "an integer")?;
+
+    let mut parsed: Vec<(String, TileOffset)> = Vec::with_capacity(steps.len());
+    for step in steps.iter() {
+        step.check_keys(SEQUENCE_STEP_KEYS)?;
+        let atlas: String = step.expect("atlas", "a string")?;
+        let offset: [u32; 2] = step.expect("offset", "an array of two integers")?;
+        parsed.push((frame_key(namespace, &atlas), (offset[0], offset[1])));
+    }
+
+    let steps: Vec<(&str, TileOffset)> = parsed.iter().map(|&(ref k, o)| (k.as_str(), o)).collect();
+    builder.add_sequence_tile(idx, &steps, delay)
+}
+
+#[cfg(feature = "fs")]
+impl<T: backend::PageTexture> TileAtlas<T> {
+    pub fn from_config<B: GpuBackend<Texture = T>>(backend: &B, filename: &str) -> AtlasResult<Self> {
+        TileAtlas::from_config_cancellable(backend, filename, &AtomicBool::new(false))
+    }
+
+    /// Like `from_config`, but fails with `AtlasError::Cancelled` if `cancel`
+    /// is set while the atlas is being loaded or rebuilt. A rebuild first
+    /// removes the cache's listing of the earlier build, and a cancelled one
+    /// removes the pages it wrote, so the next run starts from scratch
+    /// instead of loading a partial cache, however the folder was written.
+    pub fn from_config_cancellable<B: GpuBackend<Texture = T>>(backend: &B, filename: &str, cancel: &AtomicBool) -> AtlasResult<Self> {
+        TileAtlas::load_config(backend, filename, &LoadOptions::new(cancel))
+    }
+
+    /// Like `from_config`, but fails with `AtlasError::UnknownConfigKey` if
+    /// the config has keys the format doesn't use. Keys are only checked
+    /// when the atlas is rebuilt from the config, not when the cache is used.
+    pub fn from_config_strict_keys<B: GpuBackend<Texture = T>>(backend: &B, filename: &str) -> AtlasResult<Self> {
+        let cancel = AtomicBool::new(false);
+        let options = LoadOptions { reject_unknown_keys: true, ..LoadOptions::new(&cancel) };
+        TileAtlas::load_config(backend, filename, &options)
+    }
+
+    /// Like `from_config`, but if the atlas has to be rebuilt, reports every
+    /// broken map and tile in the config together as `AtlasError::Multiple`
+    /// instead of stopping at the first.
+    pub fn from_config_collecting_errors<B: GpuBackend<Texture = T>>(backend: &B, filename: &str) -> AtlasResult<Self> {
+        let cancel = AtomicBool::new(false);
+        let options = LoadOptions { collect_errors: true, ..LoadOptions::new(&cancel) };
+        TileAtlas::load_config(backend, filename, &options)
+    }
+
+    /// Like `from_config`, for configs that should only reference files under
+    /// `root`, such as ones shipped by mods. `filename` and every frame path
+    /// in it are resolved relative to `root`.
+    pub fn from_config_sandboxed<B: GpuBackend<Texture = T>>(backend: &B, filename: &str, root: &Path) -> AtlasResult<Self> {
+        let config_path = sandbox::expect_in_root(root, filename)?;
+        let config_path = config_path.to_string_lossy();
+
+        let cancel = AtomicBool::new(false);
+        let options = LoadOptions { root: Some(root), ..LoadOptions::new(&cancel) };
+        TileAtlas::load_config(backend, &config_path, &options)
+    }
+
+    /// Like `from_config`, but only uses the cached atlas if its manifest was
+    /// signed with `key` and every hashed file is unchanged. Otherwise the
+    /// atlas is rebuilt from the config and signed again.
+    pub fn from_config_signed<B: GpuBackend<Texture = T>>(backend: &B, filename: &str, key: &[u8]) -> AtlasResult<Self> {
+        let cancel = AtomicBool::new(false);
+        let options = LoadOptions { signing_key: Some(key), ..LoadOptions::new(&cancel) };
+        TileAtlas::load_config(backend, filename, &options)
+    }
+
+    /// Like `from_config`, but builds with the settings of the config's
+    /// profile named `profile`, which is a table under `[profiles]`:
+    ///
+    /// ```toml
+    /// [profiles.debug]
+    /// page_size = 1024
+    /// outlines = true
+    ///
+    /// [profiles.release]
+    /// page_size = 4096
+    /// mip_levels = 4
+    /// compression = "bc3"
+    /// ```
+    ///
+    /// A profile can set `page_size`, `padding`, `mip_levels`, `outlines`
+    /// (see `TileAtlasBuilder::set_debug_outlines`), `rotation` (see
+    /// `TileAtlasBuilder::set_allow_rotation`), `trim` (see
+    /// `TileAtlasBuilder::set_trim`), `extrude` (see
+    /// `TileAtlasBuilder::set_extrude`), `sort_by_area` (see
+    /// `TileAtlasBuilder::set_sort_by_area`), `compression`, which is one of
+    /// `"bc1"`, `"bc3"` or `"none"`, and `packer`, which is one of
+    /// `"skyline"`, `"maxrects"`, `"guillotine"` or `"shelf"`. The cache only
+    /// holds one build at a time, so switching profiles rebuilds the atlas.
+    pub fn from_config_with_profile<B: GpuBackend<Texture = T>>(backend: &B, filename: &str, profile: &str) -> AtlasResult<Self> {
+        let cancel = AtomicBool::new(false);
+        let options = LoadOptions { profile: Some(profile), ..LoadOptions::new(&cancel) };
+        TileAtlas::load_config(backend, filename, &options)
+    }
+
+    /// Like `from_config`, but keeps the pages in main memory for `reupload`
+    /// and `add_context`, as `TileAtlasBuilder::set_retain_pages` does,
+    /// whether the atlas is rebuilt or loaded from the cache.
+    pub fn from_config_retained<B: GpuBackend<Texture = T>>(backend: &B, filename: &str) -> AtlasResult<Self> {
+        let cancel = AtomicBool::new(false);
+        let options = LoadOptions { retain_pages: true, ..LoadOptions::new(&cancel) };
+        TileAtlas::load_config(backend, filename, &options)
+    }
+
+    fn load_config<B: GpuBackend<Texture = T>>(backend: &B, filename: &str, options: &LoadOptions) -> AtlasResult<Self> {
+        let toml_str = toml_util::toml_string_from_file(filename)?;
+
+        let packed_folder = packed_folder_name(filename)?;
         let cache_filepath = get_config_cache_path(packed_folder);
 
         if !Path::exists(cache_filepath.as_path()) {
-            return TileAtlas::build_from_toml(display, packed_folder, &toml_str);
+            return TileAtlas::build_from_toml(backend, filename, packed_folder, &toml_str, options);
         }
 
         // check if tile definitions were changed and only repack textures if
         // so, saving startup time.
 
-        let cached_config = load_tile_manager_config(packed_folder);
+        let cached_config = match load_tile_manager_config(packed_folder) {
+            Ok(config) => config,
+            Err(e) => {
+                trace_warn!("couldn't read cached tile atlas config at {}/cache.bin, rebuilding: {}",
+                            cache_filepath.display(), e);
+                return TileAtlas::build_from_toml(backend, filename, packed_folder, &toml_str, options);
+            },
+        };
 
-        let hash = hash_str(&toml_str);
+        if !build_hash_matches(&cached_config.file_hash, &toml_str, options.profile) {
+            return TileAtlas::build_from_toml(backend, filename, packed_folder, &toml_str, options);
+        }
 
-        if cached_config.file_hash != hash {
-            return TileAtlas::build_from_toml(display, packed_folder, &toml_str);
+        if options.signing_key.is_some() {
+            let verified = match manifest::read_manifest(&cache_filepath) {
+                Some(m) => m.verify(&cache_filepath, options.signing_key),
+                None    => false,
+            };
+
+            if !verified {
+                trace_warn!("cached tile atlas at {} failed verification, rebuilding", cache_filepath.display());
+                return TileAtlas::build_from_toml(backend, filename, packed_folder, &toml_str, options);
+            }
         }
 
         println!("Using cached tile atlas config at {}/cache.bin", cache_filepath.display());
 
-        let mut textures = Vec::new();
+        let pages = match read_cached_pages(&cache_filepath, &cached_config, options.cancel) {
+            Ok(pages) => pages,
+            Err(AtlasError::Cancelled) => return Err(AtlasError::Cancelled),
+            Err(e) => {
+                trace_warn!("cached tile atlas pages at {} are missing or damaged, rebuilding: {}",
+                            cache_filepath.display(), e);
+                return TileAtlas::build_from_toml(backend, filename, packed_folder, &toml_str, options);
+            },
+        };
 
-        for entry in glob::glob(&format!("{}/*.png", cache_filepath.display())).unwrap() {
-            match entry {
-                Ok(path) => {
-                    let image = image::open(&path).unwrap();
-                    let texture = make_texture(display, image);
-                    textures.push(texture);
-                },
-                Err(..) => (),
-            }
+        let atlas = cached_atlas(cached_config, pages)?;
+        if options.retain_pages {
+            atlas.upload_retained(backend)
+        } else {
+            atlas.upload(backend)
         }
-
-        TileAtlas::new(cached_config.locations, cached_config.frames, textures)
     }
 
-    fn build_from_toml<F: Facade>(display: &F, packed_folder: &str, toml_str: &str) -> Self {
+    fn build_from_toml<B: GpuBackend<Texture = T>>(backend: &B,
+                                  config_path: &str,
+                                  packed_folder: &str,
+                                  toml_str: &str,
+                                  options: &LoadOptions) -> AtlasResult<Self> {
         println!("Rebuilding tile atlas config \"{}\"", packed_folder);
 
-        let val = toml_util::toml_value_from_string(toml_str);
+        let mut builder = builder_from_config(toml_str,
+                                              Some(Path::new(config_path)),
+                                              options.root,
+                                              options.profile,
+                                              options.reject_unknown_keys,
+                                              options.collect_errors)?;
+        builder.set_retain_pages(options.retain_pages);
+
+        let hash = build_hash(toml_str, options.profile);
+
+        let packed_path = get_config_cache_path(packed_folder);
+        remove_cache_listing(&packed_path)?;
+
+        let atlas = builder.build_cancellable(backend, Some(packed_path.clone()), options.cancel)?;
+
+        let config = atlas.make_config(hash);
+        write_tile_manager_config(&config, packed_folder)?;
+        manifest::write_manifest(&packed_path, options.signing_key)?;
+
+        Ok(atlas)
+    }
+}
+
+/// Removes the files listing a cached build, so pages a rebuild removes or
+/// only partly writes are never loaded as a cache.
+#[cfg(feature = "fs")]
+fn remove_cache_listing(folder: &Path) -> AtlasResult<()> {
+    for name in ["cache.bin", manifest::MANIFEST_FILENAME].iter() {
+        let path = folder.join(name);
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| AtlasError::Io(path.clone(), e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "fs")]
+impl CpuAtlas {
+    /// Saves this atlas as the cached build of the config at `filename`, so
+    /// the next `TileAtlas::from_config` of it loads this atlas instead of
+    /// rebuilding. Meant for atlases built from a config's builder with
+    /// generated frames added on top, which would otherwise have to be
+    /// generated again on every run.
+    ///
+    /// The cache is still only used while the config is unchanged. If `key`
+    /// is given, the bundle is signed for `from_config_signed`. The bundle is
+    /// written to a folder next to the cache and only then moved into place,
+    /// so a failed save leaves the earlier cache as it was. Pages of the
+    /// earlier cache are dealt with as `stale_pages` says.
+    pub fn save_to_cache(&self, filename: &str, key: Option<&[u8]>) -> AtlasResult<()> {
+        save_cache(filename, key, self, self.locations(), self.frames())
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<T: backend::PageTexture> TileAtlas<T> {
+    /// Like `CpuAtlas::save_to_cache`, for an atlas that frames were added
+    /// to with `insert_frame`, or pixels drawn onto with `write_pixels`,
+    /// after it was built. Textures can't be read back, so this fails unless
+    /// the pages were retained with `TileAtlasBuilder::set_retain_pages`.
+    pub fn save_to_cache(&self, filename: &str, key: Option<&[u8]>) -> AtlasResult<()> {
+        match self.retained_pages() {
+            Some(pages) => save_cache(filename, key, pages, self.locations(), self.frames()),
+            None        => Err(AtlasError::Texture("Pages weren't retained, so they can't be saved".to_string())),
+        }
+    }
+}
+
+/// Writes the pages of `atlas`, with `locations` and `frames`, as the cached
+/// build of the config at `filename`. See `CpuAtlas::save_to_cache`.
+#[cfg(feature = "fs")]
+fn save_cache(filename: &str,
+              key: Option<&[u8]>,
+              atlas: &CpuAtlas,
+              locations: &HashMap<TileIndex, String>,
+              frames: &HashMap<String, AtlasFrame>) -> AtlasResult<()> {
+    let toml_str = toml_util::toml_string_from_file(filename)?;
+    let packed_folder = packed_folder_name(filename)?;
+    let packed_path = get_config_cache_path(packed_folder);
+    let stale = atlas.stale_pages();
+
+    // fail before writing anything if the earlier pages are in the way.
+    if stale == StalePages::Fail {
+        prepare_page_folder(&packed_path, stale)?;
+    }
+
+    let staging = fs_util::with_suffix(&packed_path, ".saving");
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| AtlasError::Io(staging.clone(), e))?;
+    }
+    fs::create_dir_all(&staging).map_err(|e| AtlasError::Io(staging.clone(), e))?;
+
+    let written = write_cache_bundle(&staging, key, atlas, locations, frames, &toml_str);
+    if let Err(e) = written {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    match stale {
+        StalePages::RemoveFolder => fs_util::replace_dir(&staging, &packed_path),
+        StalePages::RemovePages | StalePages::Fail => {
+            // the folder may hold other files, so only the bundle's own
+            // files are moved in.
+            prepare_page_folder(&packed_path, stale)?;
+            let entries = fs::read_dir(&staging).map_err(|e| AtlasError::Io(staging.clone(), e))?;
+            for entry in entries {
+                let path = entry.map_err(|e| AtlasError::Io(staging.clone(), e))?.path();
+                if let Some(name) = path.file_name() {
+                    fs::rename(&path, packed_path.join(name)).map_err(|e| AtlasError::Io(path.clone(), e))?;
+                }
+            }
+            fs::remove_dir(&staging).map_err(|e| AtlasError::Io(staging.clone(), e))?;
+            manifest::write_manifest(&packed_path, key)
+        },
+    }
+}
+
+#[cfg(feature = "fs")]
+fn write_cache_bundle(folder: &Path,
+                      key: Option<&[u8]>,
+                      atlas: &CpuAtlas,
+                      locations: &HashMap<TileIndex, String>,
+                      frames: &HashMap<String, AtlasFrame>,
+                      toml_str: &str) -> AtlasResult<()> {
+    let mut pages = Vec::with_capacity(atlas.pages().len());
+    for (idx, page) in atlas.pages().iter().enumerate() {
+        let name = match atlas.compressed_page(idx) {
+            Some(compressed) => write_compressed_page(folder, compressed)?,
+            None             => write_page(folder, page)?,
+        };
+        pages.push(PageInfo { file_name: Some(name), ..atlas.page_info(idx) });
+    }
+
+    let config = TileAtlasConfig {
+        locations: locations.clone(),
+        frames: frames.clone(),
+        pages: pages,
+        mip_levels: atlas.mip_levels(),
+        sampler: atlas.sampler(),
+        file_hash: hash_str(toml_str),
+    };
+    write_config_in(folder, &config)?;
+    manifest::write_manifest(folder, key)
+}
+
+/// The frames and tiles of a cached atlas, and the files and sizes of its
+/// pages, without decoding the pages.
+#[cfg(feature = "fs")]
+pub struct AtlasMetadata {
+    locations: HashMap<TileIndex, String>,
+    frames: HashMap<String, AtlasFrame>,
+    pages: Vec<PageInfo>,
+}
+
+#[cfg(feature = "fs")]
+impl AtlasMetadata {
+    pub fn locations(&self) -> &HashMap<TileIndex, String> {
+        &self.locations
+    }
+
+    pub fn frames(&self) -> &HashMap<String, AtlasFrame> {
+        &self.frames
+    }
+
+    pub fn pages(&self) -> &[PageInfo] {
+        &self.pages
+    }
+
+    /// Gets the page the frame added under `key` is on.
+    pub fn frame_page_info(&self, key: &str) -> Option<&PageInfo> {
+        self.frames.get(key).and_then(|frame| self.pages.get(frame.texture_idx()))
+    }
+
+    pub fn try_get_frame<I: Into<TileId>>(&self, tile_type: I) -> AtlasResult<&AtlasFrame> {
+        let TileId(tile_type) = tile_type.into();
+        self.locations.get(&tile_type)
+            .and_then(|key| self.frames.get(key))
+            .ok_or(AtlasError::MissingTile(tile_type))
+    }
+
+    pub fn try_get_tile<I: Into<TileId>>(&self, tile_type: I) -> AtlasResult<&AtlasTile> {
+        let TileId(tile_type) = tile_type.into();
+        self.try_get_frame(tile_type)
+            .and_then(|frame| frame.tiles().get(&tile_type).ok_or(AtlasError::MissingTile(tile_type)))
+    }
+}
+
+/// Reads the frames and tiles of the cached build of the config at
+/// `filename`, without decoding any pages or needing a renderer. Meant for
+/// servers that share configs with a client but never draw anything.
+///
+/// Nothing is rebuilt, since that would mean decoding every frame, so this
+/// fails if the cache is missing or older than the config.
+#[cfg(feature = "fs")]
+pub fn load_metadata_only(filename: &str) -> AtlasResult<AtlasMetadata> {
+    let toml_str = toml_util::toml_string_from_file(filename)?;
+    let packed_folder = packed_folder_name(filename)?;
+    let config = load_tile_manager_config(packed_folder)?;
+
+    if !hash_matches(&config.file_hash, &toml_str) {
+        return Err(AtlasError::Cache(format!("Cached atlas of {} is out of date", filename)));
+    }
+
+    Ok(AtlasMetadata {
+        locations: config.locations,
+        frames: config.frames,
+        pages: config.pages,
+    })
+}
 
-        let mut idx = 0;
+/// Gets the name of the folder a config's atlas is cached in.
+#[cfg(feature = "fs")]
+fn packed_folder_name(filename: &str) -> AtlasResult<&str> {
+    match Path::new(filename).file_stem().and_then(|s| s.to_str()) {
+        Some(stem) => Ok(stem),
+        None       => Err(AtlasError::Config(format!("Invalid config filename {}", filename))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "fs")]
+    use std::io::Write;
+
+    use image::{self, DynamicImage};
+
+    use super::*;
+    use backend::MemoryBackend;
+    use ids::PageId;
 
+    /// Writes a config named `name` to the temp folder, listing one 24x24
+    /// map with a tile on it, and gets its path. Its cache goes in
+    /// `data/.packed/name`.
+    #[cfg(feature = "fs")]
+    fn temp_config(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir();
+        let image_path = dir.join(format!("{}.png", name));
+        DynamicImage::new_rgba8(24, 24).save(&mut File::create(&image_path).unwrap(), image::PNG).unwrap();
+
+        let config_path = dir.join(format!("{}.toml", name));
+        let config = format!("[[maps]]\nfile_path = \"{0}\"\ntile_size = [24, 24]\n\
+                              [[tiles]]\natlas = \"{0}\"\noffset = [0, 0]\n",
+                             image_path.display());
+        File::create(&config_path).unwrap().write_all(config.as_bytes()).unwrap();
+        config_path
+    }
+
+    #[test]
+    fn test_missing_map_file() {
+        let config = "tiles = []\n[[maps]]\nfile_path = \"nonexistent.png\"\ntile_size = [24, 24]";
+
+        match builder_from_config(config, Some(Path::new("tiles.toml")), None, None, false, false) {
+            Err(AtlasError::MissingMapFile(path, config, index)) => {
+                assert_eq!(path, PathBuf::from("nonexistent.png"));
+                assert_eq!(config, Some(PathBuf::from("tiles.toml")));
+                assert_eq!(index, 0);
+            },
+            _ => panic!("Expected a missing map file error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_cache_header() {
+        let config = TileAtlasConfig {
+            locations: HashMap::new(),
+            frames: HashMap::new(),
+            pages: vec![PageInfo {
+                page: PageId(0),
+                file_name: Some("0123456789abcdef.png".to_string()),
+                width: 2048,
+                height: 1024,
+            }],
+            mip_levels: 1,
+            sampler: SamplerSettings::default(),
+            file_hash: "hash".to_string(),
+        };
+
+        let mut data = encode_config(&config).unwrap();
+        assert_eq!(decode_config(&data).unwrap().pages, config.pages);
+
+        data[CACHE_MAGIC.len()] += 1;
+        assert!(decode_config(&data).is_err());
+
+        let unversioned = bincode::serialize(&config, bincode::Infinite).unwrap();
+        assert!(decode_config(&unversioned).is_err());
+    }
+
+    #[test]
+    fn test_reproducible_cache() {
         let mut builder = TileAtlasBuilder::new();
+        for index in 0..32 {
+            let key = format!("frame_{}", index);
+            builder.add_frame_image(&key, DynamicImage::new_rgba8(8 + index % 5, 8), (8, 8)).unwrap();
+            builder.add_tile(&key, index as usize, (0, 0)).unwrap();
+        }
 
-        let maps = match toml_util::expect_value_in_table(&val, "maps") {
-            Value::Array(array) => array,
-            _                   => panic!("Atlas config array wasn't an array."),
+        // Each build's maps iterate in their own order.
+        let encode = |builder: &TileAtlasBuilder| {
+            let atlas = builder.build(&MemoryBackend, None).unwrap();
+            bincode::serialize(&atlas.make_config("hash".to_string()), bincode::Infinite).unwrap()
         };
+        assert!(encode(&builder) == encode(&builder));
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let config = "[[maps]]\nfile_path = \"missing_a.png\"\ntile_size = [24, 24]\n\
+                      [[maps]]\nfile_path = \"missing_b.png\"\ntile_size = [24, 24]\n\
+                      [[tiles]]\natlas = \"missing_a.png\"\noffset = [0, 0]\n\
+                      [[tiles]]\natlas = \"missing_b.png\"\noffset = \"0, 0\"\n";
+
+        let builder = builder_from_config(config, None, None, None, false, true).unwrap();
+        match builder.build_cpu(None) {
+            Err(AtlasError::Multiple(errors)) => {
+                assert_eq!(errors.len(), 3);
+                match errors[0] {
+                    AtlasError::MissingMapFile(ref path, _, 0) => assert_eq!(*path, PathBuf::from("missing_a.png")),
+                    _ => panic!("Expected a missing map file error, got {}", errors[0]),
+                }
+                match errors[2] {
+                    AtlasError::ConfigValue(ref diagnostic) => assert_eq!(diagnostic.key, "offset"),
+                    _ => panic!("Expected a config value error, got {}", errors[2]),
+                }
+            },
+            _ => panic!("Expected every error to be collected"),
+        }
+
+        assert!(builder_from_config(config, None, None, None, false, false).is_err());
+    }
 
-        for map in maps.iter() {
-            let file_path: String = toml_util::expect_value_in_table(&map, "file_path");
-            let tile_size: [u32; 2] = toml_util::expect_value_in_table(&map, "tile_size");
-            builder.add_frame(&file_path, (tile_size[0], tile_size[1]));
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_namespaces() {
+        let path = ::std::env::temp_dir().join("texture_atlas_namespaces.png");
+        {
+            let mut file = File::create(&path).unwrap();
+            DynamicImage::new_rgba8(24, 24).save(&mut file, image::PNG).unwrap();
         }
 
-        let tiles = match toml_util::expect_value_in_table(&val, "tiles") {
-            Value::Array(array) => array,
-            _                   => panic!("Atlas config array wasn't an array."),
+        let config = |namespace: &str| {
+            format!("namespace = \"{}\"\n\
+                     [[maps]]\nfile_path = \"{}\"\ntile_size = [24, 24]\n\
+                     [[tiles]]\natlas = \"{}\"\noffset = [0, 0]\n",
+                    namespace, path.display(), path.display())
         };
 
-        for tile in tiles.iter() {
-            let atlas: String = toml_util::expect_value_in_table(&tile, "atlas");
-            let offset: [u32; 2] = toml_util::expect_value_in_table(&tile, "offset");
-            let offset = (offset[0], offset[1]);
+        let mut builder = builder_from_config(&config("base"), None, None, None, false, false).unwrap();
+        builder.add_toml(&config("mymod"), None).unwrap();
 
-            builder.add_tile(&atlas, idx, offset);
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.locations().get(&0), Some(&format!("base:{}", path.display())));
+        assert_eq!(atlas.locations().get(&1), Some(&format!("mymod:{}", path.display())));
 
-            idx += 1;
+        builder.set_key_collision(KeyCollision::Error);
+        match builder.add_toml(&config("base"), None) {
+            Err(AtlasError::DuplicateFrame(key)) => assert_eq!(key, format!("base:{}", path.display())),
+            _ => panic!("Expected a duplicate frame error"),
         }
+    }
 
-        let hash = hash_str(toml_str);
+    #[test]
+    fn test_profiles() {
+        let config = "maps = []\ntiles = []\n\
+                      [profiles.debug]\npage_size = 512\n\
+                      [profiles.release]\nmip_levels = 3\ncompression = \"bc3\"\n";
 
-        let packed_path = get_config_cache_path(packed_folder);
+        let mut builder = builder_from_config(config, None, None, Some("debug"), false, false).unwrap();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        assert!(builder.build_cpu(None).unwrap().compressed_page(0).is_none());
 
-        let atlas = builder.build(display, Some(packed_path));
+        let mut builder = builder_from_config(config, None, None, Some("release"), false, false).unwrap();
+        builder.add_frame_image("a", DynamicImage::new_rgba8(24, 24), (24, 24)).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        assert!(atlas.compressed_page(0).is_some());
+        assert_eq!(atlas.mipmaps(0).len(), 2);
 
-        let config = atlas.make_config(hash);
-        write_tile_manager_config(&config, packed_folder);
+        assert!(builder_from_config(config, None, None, Some("profiling"), false, false).is_err());
+        assert!(build_hash(config, Some("debug")) != build_hash(config, Some("release")));
+    }
+
+    #[test]
+    fn test_in_memory_config() {
+        let config = "[[maps]]\nfile_path = \"a.png\"\ntile_size = [24, 24]\n\
+                      [[tiles]]\natlas = \"a.png\"\noffset = [1, 0]\n";
+
+        let mut png = Vec::new();
+        DynamicImage::new_rgba8(48, 24).save(&mut png, image::PNG).unwrap();
+        let mut files = HashMap::new();
+        files.insert("a.png".to_string(), png);
+
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_toml_in_memory(config, &files).unwrap();
+        let atlas = builder.build_cpu(None).unwrap();
+        assert_eq!(atlas.get_tile_texture_size(0), (24, 24));
+
+        match TileAtlasBuilder::new().add_toml_in_memory(config, &HashMap::new()) {
+            Err(AtlasError::MissingMapFile(path, None, 0)) => assert_eq!(path, PathBuf::from("a.png")),
+            _ => panic!("expected a missing map file"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_save_to_cache() {
+        let config_path = temp_config("texture_atlas_save_to_cache");
+        let filename = config_path.to_str().unwrap();
+        let toml_str = toml_util::toml_string_from_file(filename).unwrap();
+        let packed_path = get_config_cache_path("texture_atlas_save_to_cache");
+
+        let mut builder = builder_from_config(&toml_str, Some(&config_path), None, None, false, false).unwrap();
+        assert!(builder.build(&MemoryBackend, None).unwrap().save_to_cache(filename, None).is_err());
+
+        builder.set_reserve_page_space(true);
+        builder.set_retain_pages(true);
+        let mut atlas = builder.build(&MemoryBackend, None).unwrap();
+        let mut avatar = DynamicImage::new_rgba8(8, 8);
+        avatar.put_pixel(0, 0, image::Rgba { data: [1, 2, 3, 255] });
+        atlas.insert_frame("avatar", &avatar, (8, 8)).unwrap();
+        atlas.save_to_cache(filename, None).unwrap();
+        assert!(!fs_util::with_suffix(&packed_path, ".saving").exists());
+
+        // the inserted frame can only have come from the cache.
+        let loaded = TileAtlas::from_config(&MemoryBackend, filename).unwrap();
+        let rect = loaded.frames().get("avatar").unwrap().rect().clone();
+        assert_eq!(loaded.get_texture(0).get_pixel(rect.x(), rect.y()), image::Rgba { data: [1, 2, 3, 255] });
+        assert!(loaded.locations().contains_key(&0));
+
+        // saving over the cache replaces it whole.
+        atlas.save_to_cache(filename, None).unwrap();
+        assert!(!fs_util::with_suffix(&packed_path, ".old").exists());
+        assert_eq!(page_files(&packed_path).unwrap().len(), 1);
+
+        fs::remove_dir_all(&packed_path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_cached_mipmaps() {
+        let config_path = temp_config("texture_atlas_cached_mipmaps");
+        let filename = config_path.to_str().unwrap();
+        let toml_str = toml_util::toml_string_from_file(filename).unwrap();
+        let packed_path = get_config_cache_path("texture_atlas_cached_mipmaps");
+
+        let mut builder = builder_from_config(&toml_str, Some(&config_path), None, None, false, false).unwrap();
+        builder.set_mip_levels(3);
+        builder.set_sampler(SamplerSettings::linear());
+        builder.build_cpu(None).unwrap().save_to_cache(filename, None).unwrap();
+
+        let loaded = TileAtlas::from_config(&MemoryBackend, filename).unwrap();
+        assert_eq!(loaded.mip_levels(), 3);
+        assert_eq!(loaded.sampler(), SamplerSettings::linear());
+
+        fs::remove_dir_all(&packed_path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_config_retained() {
+        let config_path = temp_config("texture_atlas_config_retained");
+        let filename = config_path.to_str().unwrap();
+        let packed_path = get_config_cache_path("texture_atlas_config_retained");
+        if packed_path.exists() {
+            fs::remove_dir_all(&packed_path).unwrap();
+        }
+
+        // rebuilt, then loaded from the cache.
+        for _ in 0..2 {
+            let mut atlas = TileAtlas::from_config_retained(&MemoryBackend, filename).unwrap();
+            assert!(atlas.has_retained_pages());
+            assert!(atlas.reupload(&MemoryBackend).is_ok());
+        }
+        assert!(!TileAtlas::from_config(&MemoryBackend, filename).unwrap().has_retained_pages());
+
+        fs::remove_dir_all(&packed_path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_cancelled_rebuild() {
+        let config_path = temp_config("texture_atlas_cancelled_rebuild");
+        let filename = config_path.to_str().unwrap();
+        let packed_path = get_config_cache_path("texture_atlas_cancelled_rebuild");
+        TileAtlas::from_config(&MemoryBackend, filename).unwrap();
+        assert!(packed_path.join("cache.bin").exists());
+
+        // a changed config is rebuilt, which is cancelled.
+        File::create(&config_path).unwrap().write_all(b"maps = []\ntiles = []\n").unwrap();
+        match TileAtlas::from_config_cancellable(&MemoryBackend, filename, &AtomicBool::new(true)) {
+            Err(AtlasError::Cancelled) => (),
+            _ => panic!("expected the rebuild to be cancelled"),
+        }
+        assert!(!packed_path.join("cache.bin").exists());
+        assert!(!packed_path.join(manifest::MANIFEST_FILENAME).exists());
 
-        atlas
+        let _ = fs::remove_dir_all(&packed_path);
     }
 }
@@ -0,0 +1,149 @@
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::Read;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use error::AtlasResult;
+#[cfg(feature = "fs")]
+use error::AtlasError;
+#[cfg(feature = "fs")]
+use fs_util;
+use ids::TileId;
+use tile_atlas::TileIndex;
+use tile_atlas_config::{self, hash_matches, hash_str};
+use toml_util::{self, ConfigSource};
+
+/// What a tile type means to the game, read from the optional `name`,
+/// `tags` and `collision` keys of its `[[tiles]]` entry:
+///
+/// ```toml
+/// [[tiles]]
+/// atlas = "terrain.png"
+/// offset = [2, 0]
+/// name = "water"
+/// tags = ["liquid", "swimmable"]
+/// collision = "blocks_walking"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TileInfo {
+    pub index: TileIndex,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub collision: Option<String>,
+}
+
+/// Every tile a config defines, indexed exactly as a `TileAtlas` built from
+/// the same config indexes them, but without any frames or pages. Game
+/// servers can load this instead of an atlas to agree with clients on tile
+/// semantics.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TileRegistry {
+    tiles: Vec<TileInfo>,
+    file_hash: String,
+}
+
+/// Marks the start of a serialized `TileRegistry`.
+const REGISTRY_MAGIC: &'static [u8; 4] = b"TREG";
+
+/// Bumped whenever the serialized layout of `TileRegistry` changes.
+const REGISTRY_VERSION: u32 = 1;
+
+impl TileRegistry {
+    #[cfg(feature = "fs")]
+    pub fn from_config(filename: &str) -> AtlasResult<Self> {
+        let toml_str = toml_util::toml_string_from_file(filename)?;
+        TileRegistry::from_toml(&toml_str)
+    }
+
+    pub fn from_toml(toml_str: &str) -> AtlasResult<Self> {
+        let source = ConfigSource::new(toml_str, None);
+        let val = source.parse()?;
+        let config = source.root(&val);
+
+        // tile indices count every entry, the same as when building an atlas.
+        let entries = config.expect_entries("tiles")?;
+        let mut tiles = Vec::with_capacity(entries.len());
+        for (idx, tile) in entries.iter().enumerate() {
+            tiles.push(TileInfo {
+                index: idx,
+                name: tile.get("name", "a string")?,
+                tags: tile.get("tags", "an array of strings")?.unwrap_or_default(),
+                collision: tile.get("collision", "a string")?,
+            });
+        }
+
+        Ok(TileRegistry {
+            tiles: tiles,
+            file_hash: hash_str(toml_str),
+        })
+    }
+
+    pub fn tiles(&self) -> &[TileInfo] {
+        &self.tiles
+    }
+
+    pub fn get<I: Into<TileId>>(&self, tile_type: I) -> Option<&TileInfo> {
+        let TileId(tile_type) = tile_type.into();
+        self.tiles.get(tile_type)
+    }
+
+    /// Finds the tile with the given `name`.
+    pub fn find(&self, name: &str) -> Option<&TileInfo> {
+        self.tiles.iter().find(|tile| tile.name.as_ref().map_or(false, |n| n == name))
+    }
+
+    /// Checks that this registry was made from exactly `toml_str`, so that
+    /// its indices match an atlas built from it.
+    pub fn matches_config(&self, toml_str: &str) -> bool {
+        hash_matches(&self.file_hash, toml_str)
+    }
+
+    pub fn encode(&self) -> AtlasResult<Vec<u8>> {
+        tile_atlas_config::encode_versioned(REGISTRY_MAGIC, REGISTRY_VERSION, self)
+    }
+
+    pub fn decode(data: &[u8]) -> AtlasResult<Self> {
+        tile_atlas_config::decode_versioned(REGISTRY_MAGIC, REGISTRY_VERSION, data)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn write(&self, path: &Path) -> AtlasResult<()> {
+        let data = self.encode()?;
+        fs_util::write_atomic(path, data.as_slice())
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn read(path: &Path) -> AtlasResult<Self> {
+        let mut file = File::open(path).map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+        TileRegistry::decode(buf.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_round_trip() {
+        let config = "maps = []\n\
+                      [[tiles]]\natlas = \"a.png\"\noffset = [0, 0]\nname = \"grass\"\n\
+                      [[tiles]]\natlas = \"a.png\"\noffset = [1, 0]\nname = \"wall\"\n\
+                      tags = [\"stone\"]\ncollision = \"solid\"\n";
+
+        let registry = TileRegistry::from_toml(config).unwrap();
+        let wall = registry.find("wall").unwrap();
+        assert_eq!(wall.index, 1);
+        assert_eq!(wall.tags, vec!["stone".to_string()]);
+        assert_eq!(wall.collision, Some("solid".to_string()));
+        assert_eq!(registry.get(0).unwrap().name, Some("grass".to_string()));
+
+        let decoded = TileRegistry::decode(&registry.encode().unwrap()).unwrap();
+        assert_eq!(decoded, registry);
+        assert!(decoded.matches_config(config));
+        assert!(!decoded.matches_config("maps = []\ntiles = []\n"));
+    }
+}
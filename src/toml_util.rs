@@ -1,23 +1,25 @@
+#[cfg(feature = "fs")]
 use std::fs::File;
+#[cfg(feature = "fs")]
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde::de::Deserialize;
 use toml::Value;
 
-pub fn toml_string_from_file(filename: &str) -> String {
+use error::{AtlasError, AtlasResult, ConfigDiagnostic};
+
+#[cfg(feature = "fs")]
+pub fn toml_string_from_file(filename: &str) -> AtlasResult<String> {
     let path = PathBuf::from(filename);
-    let mut file = match File::open(&path) {
-        Ok(f) => f,
-        Err(..) => panic!("Cannot open TOML file {}", path.display()),
-    };
+    let mut file = File::open(&path).map_err(|e| AtlasError::Io(path.clone(), e))?;
     let mut data = String::new();
-    file.read_to_string(&mut data).expect("Can't read TOML file!");
-    data
+    file.read_to_string(&mut data).map_err(|e| AtlasError::Io(path.clone(), e))?;
+    Ok(data)
 }
 
-pub fn toml_value_from_string(data: &str) -> Value {
-    data.parse::<Value>().expect("Invalid TOML!")
+pub fn toml_value_from_string(data: &str) -> AtlasResult<Value> {
+    Ok(data.parse::<Value>()?)
 }
 
 pub fn get_value_in_table<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
@@ -34,10 +36,210 @@ pub fn get_value_in_table<'a>(value: &'a Value, key: &str) -> Option<&'a Value>
     }
 }
 
-pub fn expect_value_in_table<'a, T: Deserialize>(value: &'a Value, key: &str) -> T {
-    match get_value_in_table(value,  key) {
-        Some(v) => v.clone().try_into::<T>().unwrap(),
-        None    => panic!("Expected value {} couldn't be parsed in table!", key),
+/// The text of a TOML config, kept so that errors about its values can
+/// point at the line they came from.
+pub struct ConfigSource<'a> {
+    text: &'a str,
+    file: Option<&'a Path>,
+    reject_unknown_keys: bool,
+}
+
+impl<'a> ConfigSource<'a> {
+    pub fn new(text: &'a str, file: Option<&'a Path>) -> Self {
+        ConfigSource {
+            text: text,
+            file: file,
+            reject_unknown_keys: false,
+        }
+    }
+
+    /// Makes `TableEntry::check_keys` fail on keys it wasn't told about,
+    /// instead of ignoring them.
+    pub fn set_reject_unknown_keys(&mut self, reject: bool) {
+        self.reject_unknown_keys = reject;
+    }
+
+    pub fn parse(&self) -> AtlasResult<Value> {
+        toml_value_from_string(self.text).map_err(|e| {
+            match (self.file, e) {
+                (Some(file), AtlasError::Config(err)) => AtlasError::Config(format!("{}: {}", file.display(), err)),
+                (_, e) => e,
+            }
+        })
+    }
+
+    /// Reads keys out of the top level table.
+    pub fn root(&'a self, value: &'a Value) -> TableEntry<'a> {
+        TableEntry {
+            source: self,
+            value: value,
+            entry: None,
+            anchor: None,
+        }
+    }
+
+    /// Finds the line and column of `key`, in the `index`th entry of the
+    /// array of tables named `table` if one is given. Falls back to the
+    /// entry's header if the key isn't there.
+    fn locate(&self, entry: Option<(&str, usize)>, key: &str) -> Option<(usize, usize)> {
+        let mut current: Option<(&str, usize)> = None;
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        let mut header = None;
+
+        for (line_idx, line) in self.text.lines().enumerate() {
+            let trimmed = line.trim_left();
+            let column = line.len() - trimmed.len() + 1;
+
+            if trimmed.starts_with('[') {
+                let name = trimmed.trim_matches(|c: char| c == '[' || c == ']' || c == ' ');
+                let name = name.split(']').next().unwrap_or(name).trim();
+                let count = match counts.iter().position(|&(n, _)| n == name) {
+                    Some(pos) => { counts[pos].1 += 1; counts[pos].1 }
+                    None      => { counts.push((name, 1)); 1 }
+                };
+                current = Some((name, count - 1));
+                if current == entry {
+                    header = Some((line_idx + 1, column));
+                }
+                continue;
+            }
+
+            if current != entry {
+                continue;
+            }
+
+            if trimmed.starts_with(key) && trimmed[key.len()..].trim_left().starts_with('=') {
+                return Some((line_idx + 1, column));
+            }
+        }
+
+        header
+    }
+}
+
+/// A table in a config, such as one `[[maps]]` entry, whose values are read
+/// with errors that say where they went wrong.
+pub struct TableEntry<'a> {
+    source: &'a ConfigSource<'a>,
+    value: &'a Value,
+    entry: Option<(&'static str, usize)>,
+    /// For tables nested inside a value, the key holding them, which is
+    /// what errors point at.
+    anchor: Option<String>,
+}
+
+impl<'a> TableEntry<'a> {
+    pub fn has(&self, key: &str) -> bool {
+        get_value_in_table(self.value, key).is_some()
+    }
+
+    /// Gets the value of `key`, which should be `expected`, as in "an array
+    /// of two integers".
+    pub fn expect<T: Deserialize>(&self, key: &str, expected: &str) -> AtlasResult<T> {
+        match get_value_in_table(self.value, key) {
+            Some(v) => v.clone().try_into::<T>().map_err(|_| {
+                self.error(key, expected, Some(v.type_str()))
+            }),
+            None    => Err(self.error(key, expected, None)),
+        }
+    }
+
+    /// Like `expect`, but a missing key gives `None` instead of an error.
+    pub fn get<T: Deserialize>(&self, key: &str, expected: &str) -> AtlasResult<Option<T>> {
+        if self.has(key) {
+            self.expect(key, expected).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Gets the array of tables under `key`. Top level arrays become
+    /// entries of their own, while nested ones stay part of this entry.
+    pub fn expect_entries(&self, key: &'static str) -> AtlasResult<Vec<TableEntry<'a>>> {
+        let expected = "an array of tables";
+        let array = match get_value_in_table(self.value, key) {
+            Some(&Value::Array(ref array)) => array,
+            Some(v) => return Err(self.error(key, expected, Some(v.type_str()))),
+            None    => return Err(self.error(key, expected, None)),
+        };
+
+        let is_root = self.entry.is_none();
+
+        Ok(array.iter().enumerate().map(|(idx, value)| {
+            TableEntry {
+                source: self.source,
+                value: value,
+                entry: if is_root { Some((key, idx)) } else { self.entry },
+                anchor: if is_root { None } else { Some(format!("{}[{}]", key, idx)) },
+            }
+        }).collect())
+    }
+
+    /// Gets the table under `key`, such as `[profiles.release]`, if there is
+    /// one.
+    pub fn get_table(&self, key: &str) -> AtlasResult<Option<TableEntry<'a>>> {
+        let name = match self.anchor {
+            Some(ref anchor) => format!("{}.{}", anchor, key),
+            None             => key.to_string(),
+        };
+
+        match get_value_in_table(self.value, key) {
+            Some(value @ &Value::Table(_)) => Ok(Some(TableEntry {
+                source: self.source,
+                value: value,
+                entry: self.entry,
+                anchor: Some(name),
+            })),
+            Some(v) => Err(self.error(key, "a table", Some(v.type_str()))),
+            None    => Ok(None),
+        }
+    }
+
+    /// With unknown keys rejected, fails on the first key in this table
+    /// that isn't in `valid`.
+    pub fn check_keys(&self, valid: &[&str]) -> AtlasResult<()> {
+        if !self.source.reject_unknown_keys {
+            return Ok(());
+        }
+
+        let table = match *self.value {
+            Value::Table(ref table) => table,
+            _                       => return Ok(()),
+        };
+
+        let mut keys: Vec<&String> = table.keys().collect();
+        keys.sort();
+
+        match keys.into_iter().find(|key| !valid.contains(&key.as_str())) {
+            Some(key) => {
+                let expected = format!("one of {}", valid.join(", "));
+                match self.error(key, &expected, None) {
+                    AtlasError::ConfigValue(diagnostic) => Err(AtlasError::UnknownConfigKey(diagnostic)),
+                    err => Err(err),
+                }
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.entry.map(|(_, idx)| idx).unwrap_or(0)
+    }
+
+    fn error(&self, key: &str, expected: &str, found: Option<&str>) -> AtlasError {
+        let (name, located_key) = match self.anchor {
+            Some(ref anchor) => (format!("{}.{}", anchor, key), anchor.split('[').next().unwrap_or(key).to_string()),
+            None             => (key.to_string(), key.to_string()),
+        };
+
+        AtlasError::ConfigValue(ConfigDiagnostic {
+            file: self.source.file.map(|f| f.to_path_buf()),
+            position: self.source.locate(self.entry, &located_key),
+            entry: self.entry.map(|(table, idx)| (table.to_string(), idx)),
+            key: name,
+            expected: expected.to_string(),
+            found: found.map(|f| f.to_string()),
+        })
     }
 }
 
@@ -56,12 +258,61 @@ pub fn get_toml_value<T: Deserialize>(value: &Value, table_name: &str, key: &str
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_diagnostics() {
+        let text = "[[maps]]\nfile_path = \"a.png\"\ntile_size = [24, 24]\n\n[[maps]]\nfile_path = \"b.png\"\n  tile_size = \"24\"\n";
+        let source = ConfigSource::new(text, Some(Path::new("tiles.toml")));
+        let value = source.parse().unwrap();
+        let maps = source.root(&value).expect_entries("maps").unwrap();
+
+        assert!(maps[0].expect::<[u32; 2]>("tile_size", "an array of two integers").is_ok());
+
+        match maps[1].expect::<[u32; 2]>("tile_size", "an array of two integers") {
+            Err(AtlasError::ConfigValue(diagnostic)) => {
+                assert_eq!(diagnostic.position, Some((7, 3)));
+                assert_eq!(diagnostic.entry, Some(("maps".to_string(), 1)));
+                assert_eq!(diagnostic.found, Some("string".to_string()));
+            },
+            _ => panic!("Expected a diagnostic"),
+        }
+
+        match maps[1].expect::<String>("overlay", "a string") {
+            Err(AtlasError::ConfigValue(diagnostic)) => {
+                assert_eq!(diagnostic.position, Some((5, 1)));
+                assert_eq!(diagnostic.found, None);
+            },
+            _ => panic!("Expected a diagnostic"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let text = "[[maps]]\nfile_path = \"a.png\"\ntile_sizee = [24, 24]\n";
+        let mut source = ConfigSource::new(text, None);
+        let value = source.parse().unwrap();
+
+        {
+            let maps = source.root(&value).expect_entries("maps").unwrap();
+            assert!(maps[0].check_keys(&["file_path", "tile_size"]).is_ok());
+        }
+
+        source.set_reject_unknown_keys(true);
+        let maps = source.root(&value).expect_entries("maps").unwrap();
+        match maps[0].check_keys(&["file_path", "tile_size"]) {
+            Err(AtlasError::UnknownConfigKey(diagnostic)) => {
+                assert_eq!(diagnostic.key, "tile_sizee");
+                assert_eq!(diagnostic.position, Some((3, 1)));
+            },
+            _ => panic!("Expected an unknown key error"),
+        }
+    }
+
     #[test]
     fn test_get() {
         let val = toml_value_from_string("
 [table]
 thing=1
-dood=true");
+dood=true").unwrap();
         let res: Option<i32> = get_toml_value(&val, "table", "thing");
         assert!(res.is_some());
         let res: Option<bool> = get_toml_value(&val, "table", "none");
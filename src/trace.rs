@@ -0,0 +1,51 @@
+//! Profiling hooks. With the `tracing` feature enabled these open `tracing`
+//! spans and emit counter and warning events; otherwise they expand to
+//! nothing, but for warnings still using what they're given, so a value
+//! only kept to warn about doesn't go unused.
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($name:expr) => {
+        let _trace_span = ::tracing::info_span!($name).entered();
+    };
+    ($name:expr, $($field:tt)+) => {
+        let _trace_span = ::tracing::info_span!($name, $($field)+).entered();
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($args:tt)*) => {};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_count {
+    ($name:expr, $value:expr) => {
+        ::tracing::info!(counter = $name, value = $value as u64);
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_count {
+    ($name:expr, $value:expr) => {};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_warn {
+    ($($args:tt)+) => {
+        ::tracing::warn!($($args)+);
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_warn {
+    ($field:ident = $value:expr, $($rest:tt)+) => {
+        let _ = &$value;
+        trace_warn!($($rest)+);
+    };
+    ($($args:tt)+) => {
+        if false {
+            let _ = format!($($args)+);
+        }
+    };
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use tile_atlas::TileIndex;
+
+/// How often each tile and frame of an atlas was looked up while usage
+/// tracking was enabled. Anything listed as unused was never drawn and is a
+/// candidate for cutting from the atlas.
+pub struct UsageReport {
+    /// Lookup counts per frame, most used first.
+    pub frames: Vec<(String, u64)>,
+    pub unused_frames: Vec<String>,
+    pub unused_tiles: Vec<TileIndex>,
+}
+
+impl UsageReport {
+    pub fn new(locations: &HashMap<TileIndex, String>, counts: &HashMap<TileIndex, u64>) -> Self {
+        let mut frame_counts: HashMap<String, u64> = HashMap::new();
+
+        for (index, key) in locations.iter() {
+            let count = counts.get(index).cloned().unwrap_or(0);
+            *frame_counts.entry(key.clone()).or_insert(0) += count;
+        }
+
+        let mut unused_frames: Vec<String> = frame_counts.iter()
+            .filter(|&(_, &count)| count == 0)
+            .map(|(key, _)| key.clone())
+            .collect();
+        unused_frames.sort();
+
+        let mut unused_tiles: Vec<TileIndex> = locations.keys()
+            .filter(|index| !counts.contains_key(*index))
+            .cloned()
+            .collect();
+        unused_tiles.sort();
+
+        let mut frames: Vec<(String, u64)> = frame_counts.into_iter().collect();
+        frames.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        UsageReport {
+            frames: frames,
+            unused_frames: unused_frames,
+            unused_tiles: unused_tiles,
+        }
+    }
+
+    /// Writes the per-frame counts as CSV.
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(file, "frame,lookups")?;
+        for &(ref key, count) in self.frames.iter() {
+            writeln!(file, "{},{}", key, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut locations = HashMap::new();
+        locations.insert(0, "a.png".to_string());
+        locations.insert(1, "a.png".to_string());
+        locations.insert(2, "b.png".to_string());
+
+        let mut counts = HashMap::new();
+        counts.insert(1, 5);
+
+        let report = UsageReport::new(&locations, &counts);
+        assert_eq!(report.frames, vec![("a.png".to_string(), 5), ("b.png".to_string(), 0)]);
+        assert_eq!(report.unused_frames, vec!["b.png".to_string()]);
+        assert_eq!(report.unused_tiles, vec![0, 2]);
+    }
+}
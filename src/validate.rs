@@ -0,0 +1,196 @@
+//! UV invariant checks, enabled with the `validate` feature. Every tile's UV
+//! quad should lie inside its frame's rect on the page and inside [0, 1].
+//!
+//! With the feature on, debug builds also panic as soon as a tile that
+//! breaks these is looked up.
+
+use cpu_atlas::CpuAtlas;
+use error::AtlasResult;
+use tile_atlas::{self, AtlasFrame, DrawInfo, TileIndex, TileKind};
+use tile_atlas_config;
+use toml_util;
+
+/// Slack for float rounding when comparing UV coordinates.
+const EPSILON: f32 = 1.0e-5;
+
+/// A tile whose UV quad breaks an invariant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UvViolation {
+    pub tile: TileIndex,
+    pub frame: String,
+    /// The animation frame the quad was computed for.
+    pub animation_frame: u64,
+    pub uv_min: (f32, f32),
+    pub uv_max: (f32, f32),
+    pub reason: &'static str,
+}
+
+/// Checks a single tile's `DrawInfo` against the frame it was computed from,
+/// on a page of size `dimensions`. Returns why it's invalid, if it is.
+pub fn check_draw_info(frame: &AtlasFrame, info: &DrawInfo, dimensions: (u32, u32)) -> Option<&'static str> {
+    let (min, max) = uv_bounds(info);
+
+    if min.0 < -EPSILON || min.1 < -EPSILON || max.0 > 1.0 + EPSILON || max.1 > 1.0 + EPSILON {
+        return Some("UV quad leaves the [0, 1] range");
+    }
+
+    let rect = frame.rect();
+    let (w, h) = (dimensions.0 as f32, dimensions.1 as f32);
+    let rect_min = (rect.x as f32 / w, rect.y as f32 / h);
+    let rect_max = ((rect.x + rect.w) as f32 / w, (rect.y + rect.h) as f32 / h);
+
+    if min.0 < rect_min.0 - EPSILON || min.1 < rect_min.1 - EPSILON ||
+        max.0 > rect_max.0 + EPSILON || max.1 > rect_max.1 + EPSILON {
+        return Some("UV quad leaves the frame's rect");
+    }
+
+    if info.rotated != rect.rotated {
+        return Some("UV quad is turned differently than the frame's rect");
+    }
+
+    None
+}
+
+/// Checks every animation frame of every tile in `atlas`, including that
+/// each frame of an animation sequence reports the page it's actually on.
+pub fn check_cpu_atlas(atlas: &CpuAtlas) -> Vec<UvViolation> {
+    let mut tiles: Vec<(&TileIndex, &String)> = atlas.locations().iter().collect();
+    tiles.sort();
+
+    let mut violations = Vec::new();
+
+    for (&tile_type, key) in tiles {
+        let frame = match atlas.frames().get(key) {
+            Some(frame) => frame,
+            None        => continue,
+        };
+        let tile = match frame.tiles().get(&tile_type) {
+            Some(tile) => tile,
+            None       => continue,
+        };
+        let (frame_count, delay) = match tile.kind {
+            TileKind::Static => (1, 0),
+            TileKind::Animated(frame_count, delay) |
+            TileKind::StridedAnimated(frame_count, delay, _) |
+            TileKind::Sequence(frame_count, delay) => {
+                if frame_count == 0 || delay == 0 {
+                    violations.push(UvViolation {
+                        tile: tile_type,
+                        frame: key.clone(),
+                        animation_frame: 0,
+                        uv_min: (0.0, 0.0),
+                        uv_max: (0.0, 0.0),
+                        reason: "animation has no frames or no delay",
+                    });
+                    continue;
+                }
+                (frame_count, delay)
+            },
+        };
+
+        for animation_frame in 0..frame_count {
+            let msecs = animation_frame * delay;
+            let info = tile_atlas::unchecked_frame_draw_info(atlas.frames(),
+                                                             frame,
+                                                             tile_type,
+                                                             msecs,
+                                                             &|idx| atlas.get_page_dimensions(idx));
+
+            let (step_frame, _) = tile_atlas::resolve_tile(atlas.frames(), frame, tile_type, msecs);
+            let dimensions = atlas.get_page_dimensions(step_frame.texture_idx());
+
+            let reason = if info.page != step_frame.texture_idx() {
+                Some("draw info points at a different page than the frame it samples")
+            } else {
+                check_draw_info(step_frame, &info, dimensions)
+            };
+
+            if let Some(reason) = reason {
+                let (uv_min, uv_max) = uv_bounds(&info);
+                violations.push(UvViolation {
+                    tile: tile_type,
+                    frame: key.clone(),
+                    animation_frame: animation_frame,
+                    uv_min: uv_min,
+                    uv_max: uv_max,
+                    reason: reason,
+                });
+            }
+        }
+    }
+
+    violations
+}
+
+/// Gets the tiles whose animation frames are spread over more than one page.
+/// These draw correctly, but only if the page is looked up again each time
+/// the animation advances.
+pub fn cross_page_tiles(atlas: &CpuAtlas) -> Vec<TileIndex> {
+    let mut tiles: Vec<TileIndex> = atlas.locations().keys()
+        .cloned()
+        .filter(|&tile_type| {
+            let pages = atlas.get_animation_pages(tile_type);
+            pages.iter().any(|&page| page != pages[0])
+        })
+        .collect();
+    tiles.sort();
+    tiles
+}
+
+/// Packs the atlas config at `filename` in memory and checks every tile in
+/// it, without touching the GPU or the cache.
+pub fn validate_config(filename: &str) -> AtlasResult<Vec<UvViolation>> {
+    let toml_str = toml_util::toml_string_from_file(filename)?;
+    let builder = tile_atlas_config::builder_from_toml(&toml_str, None)?;
+    let atlas = builder.build_cpu(None)?;
+    Ok(check_cpu_atlas(&atlas))
+}
+
+fn uv_bounds(info: &DrawInfo) -> ((f32, f32), (f32, f32)) {
+    let min = info.uv_offset;
+    let max = (min.0 + info.uv_scale[0], min.1 + info.uv_scale[1]);
+    (min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use AtlasRect;
+    use tile_atlas::TileAtlasBuilder;
+
+    #[test]
+    fn test_check_draw_info() {
+        let frame = AtlasFrame::new(0, AtlasRect::new(16, 0, 48, 24), (24, 24));
+        let mut info = DrawInfo {
+            page: 0,
+            uv_offset: (16.0 / 128.0, 0.0),
+            uv_scale: [24.0 / 128.0, 24.0 / 128.0],
+            rotated: false,
+            pixel_size: (24, 24),
+            source_size: (24, 24),
+            trim_offset: (0, 0),
+            pivot: (0.0, 0.0),
+            overlay: None,
+        };
+        assert_eq!(check_draw_info(&frame, &info, (128, 128)), None);
+
+        info.uv_offset = (48.0 / 128.0, 0.0);
+        assert!(check_draw_info(&frame, &info, (128, 128)).is_some());
+
+        info.uv_offset = (120.0 / 128.0, 0.0);
+        assert!(check_draw_info(&frame, &info, (128, 128)).is_some());
+    }
+
+    #[test]
+    fn test_check_cpu_atlas() {
+        let mut builder = TileAtlasBuilder::new();
+        builder.add_frame_image("sheet", DynamicImage::new_rgba8(48, 24), (24, 24)).unwrap();
+        builder.add_tile("sheet", 0, (0, 0)).unwrap();
+        builder.add_tile("sheet", 1, (1, 0)).unwrap();
+
+        let atlas = builder.build_cpu(None).unwrap();
+        assert!(check_cpu_atlas(&atlas).is_empty());
+    }
+}